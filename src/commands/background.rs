@@ -0,0 +1,395 @@
+use std::{
+    env,
+    fs::{self, File},
+    io,
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use tabled::{Table, Tabled, settings::Style};
+
+use crate::{
+    commands::run::{self, Prepared, RunOptions},
+    core::{
+        background::{BackgroundRun, Registry},
+        config::{Config, OutputFormat},
+        history::History,
+        logs::{self, rotated_path},
+    },
+    utils::{
+        cli::is_interactive,
+        duration::format_duration,
+        shell,
+        time::{self, format_timestamp},
+    },
+};
+
+/// 等待后台 rune 进程接管登记的最长时间
+const START_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// `rune logs -f` 检查新输出的间隔
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(200);
+
+/// `rune kill` 可以发送的信号，rune 会把它转发给脚本的进程组
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum KillSignal {
+    /// SIGTERM
+    #[default]
+    Term,
+    /// SIGINT
+    Int,
+    /// SIGHUP
+    Hup,
+}
+
+impl KillSignal {
+    fn name(self) -> &'static str {
+        match self {
+            KillSignal::Term => "SIGTERM",
+            KillSignal::Int => "SIGINT",
+            KillSignal::Hup => "SIGHUP",
+        }
+    }
+}
+
+#[derive(Debug, Tabled)]
+struct RunListInfo {
+    #[tabled(rename = "ID")]
+    id: u64,
+    #[tabled(rename = "PID")]
+    pid: String,
+    #[tabled(rename = "Script")]
+    script: String,
+    #[tabled(rename = "Arguments")]
+    args: String,
+    #[tabled(rename = "Started")]
+    started: String,
+    #[tabled(rename = "Running")]
+    running: String,
+    #[tabled(rename = "Log")]
+    log: String,
+}
+
+/// 在后台启动脚本，立即返回
+///
+/// 先在前台解析脚本和插件（需要时提示选择），再启动脱离终端的 `rune run` 进程。
+/// 该进程登记运行、把输出写入日志，并在结束时像前台运行一样记录历史
+pub fn detach(options: RunOptions) -> Result<()> {
+    let interactive = !options.no_input && is_interactive();
+    let invocation = match run::prepare(&options, interactive)? {
+        Prepared::Help(usage) => {
+            print!("{}", usage);
+            return Ok(());
+        }
+        Prepared::Ready(invocation) => invocation,
+        Prepared::Workflow(found) => {
+            bail!(
+                "Workflow '{}' cannot be run with --detach",
+                found.script.name
+            )
+        }
+    };
+    run::check_trust(
+        &invocation.repo,
+        &invocation.script,
+        invocation.trust,
+        invocation.signature.as_ref(),
+        options.allow_unsigned,
+    )?;
+
+    let registry = Registry::open()?;
+    let cwd = env::current_dir().context("Failed to get current directory")?;
+    let id = registry.register(
+        BackgroundRun {
+            id: 0,
+            pid: 0,
+            started: time::now(),
+            script: invocation.script.name.clone(),
+            args: options.args.clone(),
+            cwd: cwd.clone(),
+            log: None,
+        },
+        History::load()?.next_id(),
+    )?;
+
+    let mut args = vec![
+        "run".to_string(),
+        "--no-input".to_string(),
+        "--background-id".to_string(),
+        id.to_string(),
+        "--repo".to_string(),
+        invocation.repo.name(),
+        "--plugin".to_string(),
+        invocation.plugin.name.clone(),
+        "--grace-period".to_string(),
+        options.grace_period.to_string(),
+    ];
+    if let Some(extension) = &options.extension {
+        args.extend(["--extension".to_string(), extension.clone()]);
+    }
+    args.extend(run::control_args(&options));
+    args.extend(run::isolation_args(
+        &options.limits,
+        options.sandbox.as_ref(),
+    ));
+    if options.allow_unsigned {
+        args.push("--allow-unsigned".to_string());
+    }
+    if options.no_history {
+        args.push("--no-history".to_string());
+    }
+    args.push(options.name.clone());
+    if !options.args.is_empty() {
+        args.push("--".to_string());
+        args.extend(options.args.iter().cloned());
+    }
+
+    let result = spawn_detached(&registry, id, &args, &cwd);
+    if !matches!(result, Ok(Some(_))) {
+        registry.remove(id);
+    }
+    let Some(pid) = result? else {
+        return Ok(());
+    };
+    println!(
+        "Started background run #{} (PID {}): {}",
+        id,
+        pid,
+        command_line(&invocation.script.name, &options.args)
+    );
+    println!(
+        "Follow its output with 'rune logs -f {}', stop it with 'rune kill {}'",
+        id, id
+    );
+    Ok(())
+}
+
+/// 启动后台 rune 进程并等待它接管登记，返回其 PID；接管前就已结束时返回 None
+#[cfg(unix)]
+fn spawn_detached(
+    registry: &Registry,
+    id: u64,
+    args: &[String],
+    cwd: &Path,
+) -> Result<Option<u32>> {
+    use std::os::unix::process::CommandExt;
+
+    let exe = env::current_exe().context("Failed to locate the rune executable")?;
+    let stderr_path = registry.stderr_path(id);
+    let stderr = File::create(&stderr_path)
+        .with_context(|| format!("Failed to create {}", stderr_path.display()))?;
+    let mut command = Command::new(exe);
+    command
+        .args(args)
+        .current_dir(cwd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(stderr);
+    // SAFETY: setsid 是异步信号安全的；新会话使其脱离终端，关闭终端不会终止运行
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut child = command
+        .spawn()
+        .context("Failed to start the background rune process")?;
+
+    let started = Instant::now();
+    loop {
+        if let Some(run) = registry.get(id)
+            && run.pid != 0
+        {
+            return Ok(Some(run.pid));
+        }
+        if let Some(status) = child
+            .try_wait()
+            .context("Failed to wait for the background rune process")?
+        {
+            // 接管前失败时错误信息留在 .err 文件中；接管后结束会删除该文件
+            let message = fs::read_to_string(&stderr_path).unwrap_or_default();
+            if message.trim().is_empty() {
+                println!(
+                    "Background run #{} already finished ({})",
+                    id,
+                    match status.code() {
+                        Some(code) => format!("exit {}", code),
+                        None => "terminated by a signal".to_string(),
+                    }
+                );
+                return Ok(None);
+            }
+            let message = message.trim();
+            bail!(
+                "Background run #{} failed to start: {}",
+                id,
+                message.strip_prefix("Error: ").unwrap_or(message)
+            );
+        }
+        if started.elapsed() > START_TIMEOUT {
+            let _ = child.kill();
+            bail!("Background run #{} did not start in time", id);
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[cfg(not(unix))]
+fn spawn_detached(
+    _registry: &Registry,
+    _id: u64,
+    _args: &[String],
+    _cwd: &Path,
+) -> Result<Option<u32>> {
+    bail!("--detach is only supported on Unix")
+}
+
+/// 列出仍在运行的后台运行
+pub fn ps(plain: bool) -> Result<()> {
+    let runs = Registry::open()?.list();
+    if runs.is_empty() {
+        println!("No background runs");
+        return Ok(());
+    }
+    let plain = plain || Config::load()?.output.format == OutputFormat::Plain;
+    if plain {
+        for run in &runs {
+            println!(
+                "{}\t{}\t{}",
+                run.id,
+                run.pid,
+                command_line(&run.script, &run.args)
+            );
+        }
+        return Ok(());
+    }
+
+    let now = time::now();
+    let rows: Vec<RunListInfo> = runs
+        .into_iter()
+        .map(|run| RunListInfo {
+            id: run.id,
+            pid: match run.pid {
+                0 => "starting".to_string(),
+                pid => pid.to_string(),
+            },
+            script: run.script,
+            args: shell::join(&run.args),
+            started: format_timestamp(run.started),
+            running: format_duration(Duration::from_secs(now.saturating_sub(run.started))),
+            log: run
+                .log
+                .map(|log| log.display().to_string())
+                .unwrap_or_default(),
+        })
+        .collect();
+    let mut table = Table::new(rows);
+    table.with(Style::rounded());
+    println!("{}", table);
+    Ok(())
+}
+
+/// 向后台运行发送信号
+///
+/// 信号发给后台 rune 进程的进程组，rune 再转发给脚本并在宽限期后强制终止，
+/// 因此运行仍会记录历史
+pub fn kill(id: u64, signal: KillSignal) -> Result<()> {
+    let registry = Registry::open()?;
+    let Some(run) = registry.get(id) else {
+        bail!("No background run #{} (see 'rune ps')", id);
+    };
+    if run.pid == 0 {
+        bail!("Background run #{} is still starting; try again", id);
+    }
+    send_signal(run.pid, signal)
+        .with_context(|| format!("Failed to signal background run #{}", id))?;
+    println!(
+        "Sent {} to background run #{} ({})",
+        signal.name(),
+        id,
+        command_line(&run.script, &run.args)
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+fn send_signal(pid: u32, signal: KillSignal) -> io::Result<()> {
+    let signal = match signal {
+        KillSignal::Term => libc::SIGTERM,
+        KillSignal::Int => libc::SIGINT,
+        KillSignal::Hup => libc::SIGHUP,
+    };
+    crate::core::signal::kill_group(pid as libc::pid_t, signal)
+}
+
+#[cfg(not(unix))]
+fn send_signal(_pid: u32, _signal: KillSignal) -> io::Result<()> {
+    Err(io::Error::other("signals are only supported on Unix"))
+}
+
+/// 要查看日志的后台运行：指定编号时为该运行，否则为最近启动的运行
+pub(crate) fn live_run(id: Option<u64>) -> Result<Option<BackgroundRun>> {
+    let registry = Registry::open()?;
+    Ok(match id {
+        Some(id) => registry.get(id),
+        None => registry.list().pop(),
+    })
+}
+
+/// 输出后台运行的日志，`follow` 为 true 时持续输出新内容直到运行结束
+pub(crate) fn logs(run: BackgroundRun, path: bool, follow: bool) -> Result<()> {
+    let registry = Registry::open()?;
+    let id = run.id;
+    // 运行刚启动时还没有日志文件
+    let log = match run.log {
+        Some(log) => log,
+        None => loop {
+            thread::sleep(FOLLOW_INTERVAL);
+            match registry.get(id) {
+                Some(run) => {
+                    if let Some(log) = run.log {
+                        break log;
+                    }
+                }
+                None => bail!(
+                    "Background run #{} finished before writing a log (see 'rune history')",
+                    id
+                ),
+            }
+        },
+    };
+    if path {
+        println!("{}", log.display());
+        return Ok(());
+    }
+
+    let mut stdout = io::stdout().lock();
+    let rotated = rotated_path(&log);
+    if rotated.exists() {
+        logs::copy_since(&rotated, 0, &mut stdout)?;
+    }
+    let mut offset = 0;
+    loop {
+        // 先检查是否仍在运行再读取，结束前写入的最后一部分输出不会丢失
+        let live = follow && registry.get(id).is_some();
+        offset = logs::copy_since(&log, offset, &mut stdout)?;
+        if !live {
+            return Ok(());
+        }
+        thread::sleep(FOLLOW_INTERVAL);
+    }
+}
+
+/// 后台运行的命令行
+fn command_line(script: &str, args: &[String]) -> String {
+    let mut words = vec![script.to_string()];
+    words.extend(args.iter().cloned());
+    shell::join(&words)
+}
@@ -0,0 +1,64 @@
+use std::{env, path::PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    core::config::{self, Config, find_project_config, project_config_target},
+    utils::fs::get_config_path,
+};
+
+/// 显示生效的配置项
+pub fn get(key: String) -> Result<()> {
+    println!("{}", Config::load()?.get(&key)?);
+    Ok(())
+}
+
+/// 修改或删除配置项
+///
+/// 默认写入全局配置文件，`project` 为 true 时写入项目的 `.rune/config.toml`
+pub fn set(key: String, value: Option<String>, unset: bool, project: bool) -> Result<()> {
+    if project {
+        config::check_project_key(&key)?;
+    }
+    let path = target_path(project)?;
+    if unset {
+        config::set_value(&path, &key, None)?;
+        println!("Removed '{}' from {}", key, path.display());
+    } else {
+        let value = value.context("Missing value (use '--unset' to remove the key)")?;
+        config::set_value(&path, &key, Some(&value))?;
+        println!("Set '{}' to '{}' in {}", key, value, path.display());
+    }
+    Ok(())
+}
+
+/// 列出所有生效的配置项
+pub fn list() -> Result<()> {
+    for (key, value) in Config::load()?.entries()? {
+        println!("{} = {}", key, value);
+    }
+    Ok(())
+}
+
+/// 显示配置文件路径
+pub fn path(project: bool) -> Result<()> {
+    if project {
+        let cwd = env::current_dir().context("Failed to get current directory")?;
+        let path = find_project_config(&cwd)
+            .context("No project config found (create '.rune/config.toml' in your project)")?;
+        println!("{}", path.display());
+    } else {
+        println!("{}", get_config_path()?.display());
+    }
+    Ok(())
+}
+
+/// `rune config set` 写入的文件
+fn target_path(project: bool) -> Result<PathBuf> {
+    if project {
+        let cwd = env::current_dir().context("Failed to get current directory")?;
+        project_config_target(&cwd)
+    } else {
+        get_config_path()
+    }
+}
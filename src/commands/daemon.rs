@@ -0,0 +1,393 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    env, fmt, fs,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    commands::{
+        run,
+        run_many::{PREFIX_COLORS, describe_outcome},
+    },
+    core::{
+        executor::{CommandExecutor, ExitOutcome},
+        schedule::{Clock, Fire, MISSED_GRACE, OverlapPolicy, Schedule, ScheduleState, Schedules},
+    },
+    utils::{
+        cli::{Color, paint, warn},
+        duration::format_duration,
+        fs::{get_schedule_state_path, get_schedules_path},
+        time::format_timestamp,
+    },
+};
+
+/// 检查定时任务、终止信号和运行结果的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// 两次检查的实际间隔超过该值时视为系统休眠过，期间的计划时间算作错过
+const SUSPEND_GAP: Duration = Duration::from_secs(30);
+
+/// `rune daemon` 的选项
+#[derive(Debug, Clone, Copy)]
+pub struct DaemonOptions {
+    /// 使用从该时间开始的模拟时钟（Unix 时间戳，秒）
+    pub clock: Option<u64>,
+    /// 模拟时钟的倍速
+    pub speed: f64,
+    /// 运行完当前到期的任务后退出
+    pub once: bool,
+}
+
+/// 一次正在进行的运行
+struct Run {
+    cancel: Arc<AtomicBool>,
+    thread: JoinHandle<Result<ExitOutcome>>,
+    started: Instant,
+}
+
+/// 守护进程中每个定时任务的状态
+struct Slot {
+    schedule: Schedule,
+    /// 已经处理到的时间
+    last: u64,
+    running: Vec<Run>,
+    /// 等待运行的计划时间
+    queue: VecDeque<Fire>,
+    /// 任务已被删除，等正在进行的运行结束后移除
+    removed: bool,
+}
+
+/// 在前台运行调度器，直到收到终止信号
+///
+/// 每次运行都通过 `rune run` 子进程执行，因此会像手动运行一样记录历史和日志。
+/// 使用模拟时钟时不读取也不更新运行进度
+pub fn daemon(options: DaemonOptions) -> Result<ExitOutcome> {
+    let clock = match options.clock {
+        Some(start) => Clock::simulated(start, options.speed),
+        None => Clock::system(),
+    };
+    let exe = env::current_exe().context("Failed to locate the rune executable")?;
+    let schedules_path = get_schedules_path()?;
+    let state_path = get_schedule_state_path()?;
+    let mut state = if clock.is_simulated() {
+        ScheduleState::default()
+    } else {
+        ScheduleState::load_from(&state_path)
+    };
+
+    let start = clock.now();
+    let mut modified = modified_time(&schedules_path);
+    let mut slots = BTreeMap::new();
+    for schedule in Schedules::load()?.schedules() {
+        // 模拟时钟从开始的那一分钟起触发；否则从上次处理到的时间（或添加时间）起计算错过的运行
+        let last = if clock.is_simulated() {
+            start.saturating_sub(1)
+        } else {
+            state.last(schedule.id).unwrap_or(schedule.created)
+        };
+        slots.insert(schedule.id, Slot::new(schedule.clone(), last));
+    }
+    match options.clock {
+        Some(_) => log(
+            start,
+            format!(
+                "Daemon started with {} schedule(s) (simulated clock at {}x speed)",
+                slots.len(),
+                options.speed
+            ),
+        ),
+        None => log(
+            start,
+            format!("Daemon started with {} schedule(s)", slots.len()),
+        ),
+    }
+
+    #[cfg(unix)]
+    let guard = crate::core::signal::ForwardGuard::install()
+        .map_err(|e| anyhow::anyhow!(e).context("安装信号处理器失败"))?;
+    #[allow(unused_mut)]
+    let mut interrupted: Option<i32> = None;
+    let mut previous: Option<(u64, Instant)> = None;
+    loop {
+        #[cfg(unix)]
+        if let Some(signal) = guard.take_pending()
+            && interrupted.is_none()
+        {
+            // 正在进行的运行由执行器转发信号，这里只停止触发新的运行
+            interrupted = Some(signal);
+            for slot in slots.values_mut() {
+                slot.queue.clear();
+            }
+            let running: usize = slots.values().map(|slot| slot.running.len()).sum();
+            if running > 0 {
+                log(
+                    clock.now(),
+                    format!("Stopping; waiting for {} running script(s)", running),
+                );
+            }
+        }
+
+        let now = clock.now();
+        if interrupted.is_none() {
+            let current = modified_time(&schedules_path);
+            if current != modified {
+                modified = current;
+                reload(&mut slots, now);
+            }
+
+            // 正常运行时两次检查之间的计划时间都算按时（模拟时钟倍速运行时间隔可能很长）
+            let grace = match previous {
+                Some((then, instant)) if instant.elapsed() < SUSPEND_GAP => {
+                    now.saturating_sub(then) + MISSED_GRACE
+                }
+                _ => MISSED_GRACE,
+            };
+            previous = Some((now, Instant::now()));
+            let mut fired = false;
+            for slot in slots.values_mut().filter(|slot| !slot.removed) {
+                let due = slot.schedule.due(slot.last, now, grace);
+                slot.last = now;
+                if due.dropped > 0 {
+                    log(
+                        now,
+                        format!(
+                            "{}: skipped {} missed run(s)",
+                            slot.schedule.label(),
+                            due.dropped
+                        ),
+                    );
+                }
+                for fire in due.fires {
+                    fired = true;
+                    slot.enqueue(fire, now);
+                }
+            }
+            if fired && !clock.is_simulated() {
+                save_state(&mut state, &slots, &state_path);
+            }
+        }
+
+        for (index, slot) in slots.values_mut().enumerate() {
+            slot.reap(now);
+            if interrupted.is_none() {
+                slot.start_ready(&exe, index, now);
+            }
+        }
+        slots.retain(|_, slot| !(slot.removed && slot.running.is_empty()));
+
+        let idle = slots
+            .values()
+            .all(|slot| slot.running.is_empty() && slot.queue.is_empty());
+        if idle && (interrupted.is_some() || options.once) {
+            break;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+
+    if !clock.is_simulated() {
+        save_state(&mut state, &slots, &state_path);
+    }
+    match interrupted {
+        Some(signal) => Ok(ExitOutcome::Signaled(signal)),
+        None => Ok(ExitOutcome::SUCCESS),
+    }
+}
+
+impl Slot {
+    fn new(schedule: Schedule, last: u64) -> Self {
+        Self {
+            schedule,
+            last,
+            running: Vec::new(),
+            queue: VecDeque::new(),
+            removed: false,
+        }
+    }
+
+    /// 按重叠策略安排一次到期的运行
+    fn enqueue(&mut self, fire: Fire, now: u64) {
+        // 补跑的运行依次进行，不受重叠策略影响
+        let busy = !self.running.is_empty() || self.queue.iter().any(|queued| !queued.missed);
+        if fire.missed || !busy {
+            self.queue.push_back(fire);
+            return;
+        }
+        let label = self.schedule.label();
+        match self.schedule.overlap {
+            OverlapPolicy::Skip => log(
+                now,
+                format!("{}: skipped, previous run still active", label),
+            ),
+            OverlapPolicy::Queue if self.queue.iter().any(|queued| !queued.missed) => {
+                log(now, format!("{}: skipped, a run is already queued", label))
+            }
+            OverlapPolicy::Queue => {
+                log(
+                    now,
+                    format!("{}: queued until the previous run finishes", label),
+                );
+                self.queue.push_back(fire);
+            }
+            OverlapPolicy::Parallel => self.queue.push_back(fire),
+            OverlapPolicy::Replace => {
+                log(now, format!("{}: stopping the previous run", label));
+                for run in &self.running {
+                    run.cancel.store(true, Ordering::SeqCst);
+                }
+                self.queue.push_back(fire);
+            }
+        }
+    }
+
+    /// 启动可以开始的运行
+    fn start_ready(&mut self, exe: &Path, index: usize, now: u64) {
+        while let Some(fire) = self.queue.front().copied() {
+            let parallel = self.schedule.overlap == OverlapPolicy::Parallel && !fire.missed;
+            if !self.running.is_empty() && !parallel {
+                break;
+            }
+            self.queue.pop_front();
+            let mut message = format!("{}: started", self.schedule.label());
+            if fire.missed {
+                message.push_str(&format!(
+                    " (missed run scheduled at {})",
+                    format_timestamp(fire.at)
+                ));
+            }
+            log(now, message);
+            self.running.push(spawn(exe, &self.schedule, fire, index));
+        }
+    }
+
+    /// 回收结束的运行并报告结果
+    fn reap(&mut self, now: u64) {
+        let (finished, running): (Vec<Run>, Vec<Run>) = self
+            .running
+            .drain(..)
+            .partition(|run| run.thread.is_finished());
+        self.running = running;
+        for run in finished {
+            let elapsed = format_duration(run.started.elapsed());
+            let label = self.schedule.label();
+            match run.thread.join() {
+                Ok(Ok(outcome)) if outcome.success() => log(
+                    now,
+                    format!("{}: {} ({})", label, paint("ok", Color::Green), elapsed),
+                ),
+                Ok(Ok(outcome)) => log(
+                    now,
+                    format!(
+                        "{}: {} ({})",
+                        label,
+                        paint(&describe_outcome(outcome), Color::Red),
+                        elapsed
+                    ),
+                ),
+                Ok(Err(e)) => log(
+                    now,
+                    format!("{}: {} {:#}", label, paint("error:", Color::Red), e),
+                ),
+                Err(_) => log(now, format!("{}: {}", label, paint("panicked", Color::Red))),
+            }
+        }
+    }
+}
+
+/// 在子进程中运行一次定时任务
+fn spawn(exe: &Path, schedule: &Schedule, fire: Fire, index: usize) -> Run {
+    let mut args = vec!["run".to_string(), "--no-input".to_string()];
+    if let Some(repo) = &schedule.repo {
+        args.push("--repo".to_string());
+        args.push(repo.clone());
+    }
+    args.extend(run::isolation_args(
+        &schedule.limits,
+        schedule.sandbox.as_ref(),
+    ));
+    args.push(schedule.script.clone());
+    if !schedule.args.is_empty() {
+        args.push("--".to_string());
+        args.extend(schedule.args.iter().cloned());
+    }
+    let prefix = paint(
+        &format!("{} |", schedule.label()),
+        PREFIX_COLORS[index % PREFIX_COLORS.len()],
+    );
+    let cancel = Arc::new(AtomicBool::new(false));
+    let executor = CommandExecutor::new(exe.to_string_lossy())
+        .args(args)
+        .env("RUNE_SCHEDULE_ID", schedule.id.to_string())
+        .env("RUNE_SCHEDULED_AT", fire.at.to_string())
+        .interactive(false)
+        .prefix_output(format!("{} ", prefix))
+        .cancel_flag(Arc::clone(&cancel));
+    Run {
+        cancel,
+        thread: std::thread::spawn(move || executor.status()),
+        started: Instant::now(),
+    }
+}
+
+/// 重新加载定时任务，新任务从现在开始计算
+fn reload(slots: &mut BTreeMap<u64, Slot>, now: u64) {
+    let schedules = match Schedules::load() {
+        Ok(schedules) => schedules,
+        Err(e) => {
+            warn(format!("Failed to reload schedules: {:#}", e));
+            return;
+        }
+    };
+    for slot in slots.values_mut() {
+        slot.removed = true;
+    }
+    for schedule in schedules.schedules() {
+        match slots.get_mut(&schedule.id) {
+            Some(slot) => {
+                slot.schedule = schedule.clone();
+                slot.removed = false;
+            }
+            None => {
+                slots.insert(schedule.id, Slot::new(schedule.clone(), now));
+            }
+        }
+    }
+    for slot in slots.values_mut().filter(|slot| slot.removed) {
+        slot.queue.clear();
+    }
+    log(
+        now,
+        format!(
+            "Schedules reloaded: {} schedule(s)",
+            schedules.schedules().len()
+        ),
+    );
+}
+
+/// 保存每个任务处理到的时间
+fn save_state(state: &mut ScheduleState, slots: &BTreeMap<u64, Slot>, path: &Path) {
+    state.retain(|id| slots.contains_key(&id));
+    for (&id, slot) in slots {
+        state.set_last(id, slot.last);
+    }
+    if let Err(e) = state.save_to(path) {
+        warn(format!("Failed to save schedule state: {:#}", e));
+    }
+}
+
+/// 定时任务文件的修改时间，文件不存在时为 None
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// 输出带时间的守护进程日志
+fn log(now: u64, message: impl fmt::Display) {
+    eprintln!("{} {}", paint(&format_timestamp(now), Color::Dim), message);
+}
@@ -0,0 +1,338 @@
+use std::{env, time::Duration};
+
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use tabled::{Table, Tabled, settings::Style};
+
+use crate::{
+    commands::{
+        background,
+        run::{self, RunOptions},
+        workflow::WorkflowOptions,
+    },
+    core::{
+        config::{Config, OutputFormat},
+        executor::{DEFAULT_GRACE_PERIOD, ExitOutcome},
+        history::{History, HistoryEntry},
+        logs::read_log,
+    },
+    utils::{
+        cli::prompt_confirm,
+        duration::format_duration,
+        shell,
+        time::{format_timestamp, parse_time},
+    },
+};
+
+/// 按退出状态过滤历史
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StatusFilter {
+    /// 成功退出的运行
+    Ok,
+    /// 非零退出或被信号终止的运行
+    Failed,
+}
+
+/// 历史过滤条件
+#[derive(Debug, Default)]
+pub struct HistoryFilter {
+    /// 脚本名称
+    pub script: Option<String>,
+    /// 退出状态
+    pub status: Option<StatusFilter>,
+    /// 起始时间
+    pub since: Option<String>,
+    /// 截止时间
+    pub until: Option<String>,
+    /// 最多显示的条数，0 表示不限制
+    pub limit: usize,
+}
+
+#[derive(Debug, Tabled)]
+struct HistoryListInfo {
+    #[tabled(rename = "ID")]
+    id: u64,
+    #[tabled(rename = "Time")]
+    time: String,
+    #[tabled(rename = "Script")]
+    script: String,
+    #[tabled(rename = "Arguments")]
+    args: String,
+    #[tabled(rename = "Plugin")]
+    plugin: String,
+    #[tabled(rename = "Status")]
+    status: String,
+    #[tabled(rename = "Duration")]
+    duration: String,
+    #[tabled(rename = "User")]
+    user: String,
+    #[tabled(rename = "Directory")]
+    cwd: String,
+}
+
+/// 列出运行历史
+pub fn list(filter: HistoryFilter, plain: bool) -> Result<()> {
+    let since = filter.since.as_deref().map(parse_time).transpose()?;
+    let until = filter.until.as_deref().map(parse_time).transpose()?;
+    let history = History::load()?;
+    let mut entries: Vec<&HistoryEntry> = history
+        .entries()
+        .iter()
+        .filter(|e| {
+            filter
+                .script
+                .as_deref()
+                .is_none_or(|s| e.script == s || file_name(e) == s)
+        })
+        .filter(|e| match filter.status {
+            Some(StatusFilter::Ok) => e.success(),
+            Some(StatusFilter::Failed) => !e.success(),
+            None => true,
+        })
+        .filter(|e| since.is_none_or(|t| e.timestamp >= t))
+        .filter(|e| until.is_none_or(|t| e.timestamp <= t))
+        .collect();
+    if filter.limit > 0 && entries.len() > filter.limit {
+        entries.drain(..entries.len() - filter.limit);
+    }
+
+    if entries.is_empty() {
+        println!("No history found");
+        return Ok(());
+    }
+    let plain = plain || Config::load()?.output.format == OutputFormat::Plain;
+    if plain {
+        for entry in entries {
+            println!(
+                "{}\t{}\t{}\t{}",
+                entry.id,
+                format_timestamp(entry.timestamp),
+                entry.status(),
+                command_line(entry)
+            );
+        }
+        return Ok(());
+    }
+
+    let rows: Vec<HistoryListInfo> = entries
+        .into_iter()
+        .map(|entry| HistoryListInfo {
+            id: entry.id,
+            time: format_timestamp(entry.timestamp),
+            script: file_name(entry),
+            args: shell::join(&entry.args),
+            plugin: entry.plugin.clone(),
+            status: entry.status(),
+            duration: format_duration(Duration::from_millis(entry.duration_ms)),
+            user: entry.user.clone(),
+            cwd: entry.cwd.display().to_string(),
+        })
+        .collect();
+    let mut table = Table::new(rows);
+    table.with(Style::rounded());
+    println!("{}", table);
+    Ok(())
+}
+
+/// 清空运行历史
+pub fn clear(yes: bool) -> Result<()> {
+    if !yes
+        && Config::load()?.confirm.remove
+        && !prompt_confirm("Do you want to clear the run history?", false)?
+    {
+        println!("Operation cancelled");
+        return Ok(());
+    }
+    History::load()?.clear()?;
+    println!("Run history cleared");
+    Ok(())
+}
+
+/// 以相同的脚本、插件、参数和工作目录重新运行历史中的一条记录
+///
+/// `id` 为 None 时重新运行最近一条；`here` 为 true 时在当前目录运行
+pub fn rerun(id: Option<u64>, here: bool, dry_run: bool, no_input: bool) -> Result<ExitOutcome> {
+    let history = History::load()?;
+    let entry = history.get(id).cloned().with_context(|| match id {
+        Some(id) => format!("No run with ID {} in history (see 'rune history')", id),
+        None => "Run history is empty".to_string(),
+    })?;
+
+    if !here {
+        if entry.cwd.is_dir() {
+            env::set_current_dir(&entry.cwd).with_context(|| {
+                format!("Failed to change directory to {}", entry.cwd.display())
+            })?;
+        } else {
+            eprintln!(
+                "Directory {} no longer exists; running in the current directory",
+                entry.cwd.display()
+            );
+        }
+    }
+    eprintln!("Rerunning #{}: {}", entry.id, command_line(&entry));
+
+    let options = rerun_options(entry, no_input);
+    if dry_run {
+        run::explain(options, false, WorkflowOptions::default())?;
+        return Ok(ExitOutcome::SUCCESS);
+    }
+    run::run(options, WorkflowOptions::default())
+}
+
+/// 与记录相同的运行选项：脚本、插件、参数，以及命令行指定的超时、重试、日志、锁、
+/// 资源限制和沙箱设置
+fn rerun_options(entry: HistoryEntry, no_input: bool) -> RunOptions {
+    let settings = entry.settings;
+    RunOptions {
+        name: entry.script,
+        extension: (!entry.extension.is_empty()).then_some(entry.extension),
+        plugin: Some(entry.plugin),
+        repo: Some(entry.repo),
+        grace_period: DEFAULT_GRACE_PERIOD.as_secs(),
+        no_input,
+        no_history: false,
+        log: settings.log_output,
+        timeout: settings.timeout_ms.map(Duration::from_millis),
+        retries: settings.retries,
+        retry_delay: settings.retry_delay_ms.map(Duration::from_millis),
+        backoff: settings.backoff,
+        lock: settings.lock,
+        lock_name: settings.lock_name,
+        lock_wait: settings.lock_wait,
+        limits: entry.limits,
+        sandbox: entry.sandbox,
+        allow_unsigned: false,
+        background: None,
+        args: entry.args,
+    }
+}
+
+/// 显示一次运行的输出日志
+///
+/// `id` 为 None 时显示最近一次记录了日志的运行（`follow` 时优先选择最近启动的后台运行）；
+/// `path` 为 true 时只打印日志文件路径。仍在后台运行的脚本还没有历史记录，从登记中查找
+pub fn logs(id: Option<u64>, path: bool, follow: bool) -> Result<()> {
+    let live = if id.is_some() || follow {
+        background::live_run(id)?
+    } else {
+        None
+    };
+    if let Some(run) = live {
+        return background::logs(run, path, follow);
+    }
+    let history = History::load()?;
+    let entry = match id {
+        Some(id) => history.get(Some(id)).with_context(|| {
+            format!("No run with ID {} in history (see 'rune history')", id)
+        })?,
+        None => history
+            .entries()
+            .iter()
+            .rev()
+            .find(|e| e.log.is_some())
+            .context("No logged runs in history (run scripts with 'rune run --log' or set 'logs.enabled')")?,
+    };
+    let log = entry.log.as_deref().with_context(|| {
+        format!(
+            "Run #{} was not logged (run scripts with 'rune run --log' or set 'logs.enabled')",
+            entry.id
+        )
+    })?;
+    if !log.exists() {
+        bail!(
+            "Log file for run #{} no longer exists: {}",
+            entry.id,
+            log.display()
+        );
+    }
+
+    if path {
+        println!("{}", log.display());
+        return Ok(());
+    }
+    print!("{}", read_log(log)?);
+    Ok(())
+}
+
+/// 带扩展名的脚本名称
+fn file_name(entry: &HistoryEntry) -> String {
+    if entry.extension.is_empty() {
+        entry.script.clone()
+    } else {
+        format!("{}.{}", entry.script, entry.extension)
+    }
+}
+
+/// 与记录等价的 `rune run` 命令
+fn command_line(entry: &HistoryEntry) -> String {
+    let options = rerun_options(entry.clone(), false);
+    let mut words = run::control_args(&options);
+    match options.log {
+        Some(true) => words.push("--log".to_string()),
+        Some(false) => words.push("--no-log".to_string()),
+        None => {}
+    }
+    words.extend(run::isolation_args(
+        &options.limits,
+        options.sandbox.as_ref(),
+    ));
+    words.push(options.name);
+    let mut line = format!("rune run {}", shell::join(&words));
+    if !options.args.is_empty() {
+        line.push_str(" -- ");
+        line.push_str(&shell::join(&options.args));
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::lock::LockWait;
+
+    fn entry(settings: &str) -> HistoryEntry {
+        serde_json::from_str(&format!(
+            r#"{{"id":3,"timestamp":100,"script":"deploy","extension":"sh","repo":"user","plugin":"bash","args":["a b"],"cwd":"/tmp","duration_ms":10,"exit_code":0{}}}"#,
+            settings
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_rerun_keeps_settings() {
+        let entry = entry(
+            r#","timeout_ms":30000,"retries":2,"retry_delay_ms":1500,"backoff":2.0,"log_output":true,"lock":true,"lock_name":"db","lock_wait":"300000ms","limits":{"mem":"2G"}"#,
+        );
+        assert_eq!(
+            command_line(&entry),
+            "rune run --timeout 30s --retries 2 --retry-delay 1500ms --backoff 2 --lock \
+             --lock-name db --lock-wait=5m --log --limit mem=2G deploy -- 'a b'"
+        );
+
+        let options = rerun_options(entry, true);
+        assert_eq!(options.timeout, Some(Duration::from_secs(30)));
+        assert_eq!(options.retries, Some(2));
+        assert_eq!(options.retry_delay, Some(Duration::from_millis(1500)));
+        assert_eq!(options.backoff, Some(2.0));
+        assert_eq!(options.log, Some(true));
+        assert!(options.lock);
+        assert_eq!(options.lock_name.as_deref(), Some("db"));
+        assert_eq!(
+            options.lock_wait,
+            Some(LockWait::Timeout(Duration::from_secs(300)))
+        );
+        assert!(!options.limits.is_empty());
+        assert_eq!(options.args, ["a b"]);
+    }
+
+    #[test]
+    fn test_rerun_without_settings() {
+        let entry = entry("");
+        assert_eq!(command_line(&entry), "rune run deploy -- 'a b'");
+        let options = rerun_options(entry, false);
+        assert_eq!(options.timeout, None);
+        assert_eq!(options.log, None);
+        assert!(!options.lock && options.lock_wait.is_none());
+    }
+}
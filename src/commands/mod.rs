@@ -1,9 +1,49 @@
+pub mod background;
+pub mod config;
+pub mod daemon;
+pub mod history;
 pub mod plugin;
 pub mod run;
+pub mod run_many;
+pub mod schedule;
 pub mod script;
+pub mod sign;
+pub mod trust;
+pub mod watch;
+pub mod workflow;
 
+use crate::{
+    commands::{background::KillSignal, history::StatusFilter},
+    core::{
+        config::OutputFormat,
+        executor::RetryPolicy,
+        limits::ResourceLimits,
+        schedule::{MissedPolicy, OverlapPolicy},
+    },
+    utils::duration::parse_duration,
+};
 use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
+
+/// 解析 `--backoff` 的值
+fn parse_backoff(input: &str) -> anyhow::Result<f64> {
+    let backoff = input
+        .parse()
+        .map_err(|_| anyhow::anyhow!("'{}' is not a number", input))?;
+    RetryPolicy::check_backoff(backoff)?;
+    Ok(backoff)
+}
+
+/// 解析 `rune daemon --speed` 的值
+fn parse_speed(input: &str) -> anyhow::Result<f64> {
+    let speed: f64 = input
+        .parse()
+        .map_err(|_| anyhow::anyhow!("'{}' is not a number", input))?;
+    if !(speed.is_finite() && speed > 0.0) {
+        anyhow::bail!("Speed must be greater than 0");
+    }
+    Ok(speed)
+}
 
 /// A powerful script runner
 #[derive(Parser, Debug)]
@@ -15,25 +55,335 @@ pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
 }
+// 只在启动时解析一次，变体大小差异无关紧要
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     /// Run a script with optional arguments
-    #[command(visible_alias = "r")]
+    #[command(visible_alias = "r", disable_help_flag = true)]
     Run {
         /// Name of the script to run
-        name: String,
+        #[arg(required_unless_present = "help")]
+        name: Option<String>,
         /// Specify script extension
         #[arg(short = 'x', long)]
         extension: Option<String>,
         /// Specify plugin to use (overrides auto-detection)
         #[arg(short = 'p', long)]
         plugin: Option<String>,
+        /// Only look for the script in this repository (project, user or a shared repo name)
+        #[arg(short = 'r', long)]
+        repo: Option<String>,
+        /// Seconds to wait after forwarding a termination signal before sending SIGKILL
+        #[arg(long, value_name = "SECONDS", default_value_t = 10)]
+        grace_period: u64,
+        /// Never prompt for input; fail if the plugin cannot be determined automatically
+        #[arg(long)]
+        no_input: bool,
+        /// Print the resolved invocation instead of running the script (same as 'rune explain')
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+        /// Do not record this run in the history
+        #[arg(long)]
+        no_history: bool,
+        /// Also write the script's output to a log file (see 'rune logs')
+        #[arg(long, conflicts_with = "no_log")]
+        log: bool,
+        /// Do not write a log file even if 'logs.enabled' is set
+        #[arg(long)]
+        no_log: bool,
+        /// Terminate the script after this long, e.g. 30s or 5m (exit code 124)
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        timeout: Option<Duration>,
+        /// Retry a failed or timed-out run up to this many times
+        #[arg(long, value_name = "N")]
+        retries: Option<u32>,
+        /// Time to wait before the first retry (default 1s)
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        retry_delay: Option<Duration>,
+        /// Multiply the retry delay by this factor after each retry (default 1)
+        #[arg(long, value_name = "FACTOR", value_parser = parse_backoff)]
+        backoff: Option<f64>,
+        /// Refuse to start while another run of this script holds its lock
+        #[arg(long)]
+        lock: bool,
+        /// Lock name to use instead of the script name (implies --lock)
+        #[arg(long, value_name = "NAME")]
+        lock_name: Option<String>,
+        /// Wait for the lock instead of failing, at most DURATION if given as --lock-wait=5m (implies --lock)
+        #[arg(
+            long,
+            value_name = "DURATION",
+            num_args = 0..=1,
+            require_equals = true,
+            value_parser = parse_duration
+        )]
+        lock_wait: Option<Option<Duration>>,
+        /// Resource limits for the script, e.g. mem=2G,cpu=60s,nofile=1024,nproc=64 (Unix only)
+        #[arg(long, value_name = "LIMITS", value_parser = ResourceLimits::parse)]
+        limit: Vec<ResourceLimits>,
+        /// Run the script in a sandbox: read-only filesystem and a private /tmp (Linux only)
+        #[arg(long)]
+        sandbox: bool,
+        /// Let the sandboxed script write to this path, relative to its working directory (implies --sandbox)
+        #[arg(long, value_name = "PATH")]
+        sandbox_write: Vec<String>,
+        /// Deny network access to the sandboxed script (implies --sandbox)
+        #[arg(long)]
+        no_network: bool,
+        /// Run a script whose signature is missing or invalid (it still needs 'rune trust')
+        #[arg(long)]
+        allow_unsigned: bool,
+        /// Run comma-separated scripts or workflow steps concurrently, at most N at a time (0 runs all at once)
+        #[arg(short = 'j', long, value_name = "N")]
+        parallel: Option<usize>,
+        /// When running several scripts or a workflow, stop everything as soon as one fails
+        #[arg(long)]
+        fail_fast: bool,
+        /// Resume a workflow at this step, also running every step that depends on it
+        #[arg(long, value_name = "STEP", conflicts_with = "only")]
+        from_step: Option<String>,
+        /// Only run these workflow steps (comma-separated), treating their dependencies as done
+        #[arg(long, value_name = "STEP", value_delimiter = ',')]
+        only: Vec<String>,
+        /// Re-run the script whenever it or one of these files or directories changes
+        #[arg(
+            short = 'w',
+            long,
+            value_name = "PATH",
+            num_args = 0..,
+            value_delimiter = ',',
+            conflicts_with_all = ["from_step", "only"]
+        )]
+        watch: Option<Vec<PathBuf>>,
+        /// Clear the screen before each run in watch mode
+        #[arg(long, requires = "watch")]
+        clear: bool,
+        /// Wait this long for changes to settle before re-running (default 200ms)
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration, requires = "watch")]
+        debounce: Option<Duration>,
+        /// Run the script in the background, writing its output to a log (see 'rune ps')
+        #[arg(
+            short = 'd',
+            long,
+            conflicts_with_all = ["dry_run", "no_log", "watch", "from_step", "only"]
+        )]
+        detach: bool,
+        /// Run as the background process started by --detach (internal)
+        #[arg(long, value_name = "ID", hide = true)]
+        background_id: Option<u64>,
+        /// Print help (with a script name, print the script's usage)
+        #[arg(short = 'h', long)]
+        help: bool,
 
         /// Arguments to pass to the script (use -- to separate from rune args)
         #[arg(last = true)]
         args: Vec<String>,
     },
 
+    /// Run several scripts concurrently and summarize the results
+    RunMany {
+        /// Names of the scripts to run
+        #[arg(required = true)]
+        names: Vec<String>,
+        /// Only look for the scripts in this repository (project, user or a shared repo name)
+        #[arg(short = 'r', long)]
+        repo: Option<String>,
+        /// Maximum number of scripts running at the same time (0 runs all at once)
+        #[arg(short = 'j', long, value_name = "N", default_value_t = 0)]
+        parallel: usize,
+        /// Stop all scripts as soon as one fails; by default every script runs to completion
+        #[arg(long)]
+        fail_fast: bool,
+        /// Seconds to wait after forwarding a termination signal before sending SIGKILL
+        #[arg(long, value_name = "SECONDS", default_value_t = 10)]
+        grace_period: u64,
+        /// Never prompt for input; fail if a plugin cannot be determined automatically
+        #[arg(long)]
+        no_input: bool,
+        /// Do not record these runs in the history
+        #[arg(long)]
+        no_history: bool,
+        /// Also write each script's output to a log file (see 'rune logs')
+        #[arg(long, conflicts_with = "no_log")]
+        log: bool,
+        /// Do not write log files even if 'logs.enabled' is set
+        #[arg(long)]
+        no_log: bool,
+        /// Terminate a script after this long, e.g. 30s or 5m
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        timeout: Option<Duration>,
+        /// Retry a failed or timed-out script up to this many times
+        #[arg(long, value_name = "N")]
+        retries: Option<u32>,
+        /// Resource limits for every script, e.g. mem=2G,cpu=60s (Unix only)
+        #[arg(long, value_name = "LIMITS", value_parser = ResourceLimits::parse)]
+        limit: Vec<ResourceLimits>,
+        /// Run every script in a sandbox: read-only filesystem and a private /tmp (Linux only)
+        #[arg(long)]
+        sandbox: bool,
+        /// Let the sandboxed scripts write to this path, relative to their working directory (implies --sandbox)
+        #[arg(long, value_name = "PATH")]
+        sandbox_write: Vec<String>,
+        /// Deny network access to the sandboxed scripts (implies --sandbox)
+        #[arg(long)]
+        no_network: bool,
+        /// Run scripts whose signature is missing or invalid (they still need 'rune trust')
+        #[arg(long)]
+        allow_unsigned: bool,
+
+        /// Arguments to pass to every script (use -- to separate from rune args)
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+
+    /// Show how a script would be run (plugin, executor, arguments, environment) without running it
+    #[command(visible_alias = "x")]
+    Explain {
+        /// Name of the script
+        name: String,
+        /// Specify script extension
+        #[arg(short = 'x', long)]
+        extension: Option<String>,
+        /// Specify plugin to use (overrides auto-detection)
+        #[arg(short = 'p', long)]
+        plugin: Option<String>,
+        /// Only look for the script in this repository (project, user or a shared repo name)
+        #[arg(short = 'r', long)]
+        repo: Option<String>,
+        /// Print a shell-escaped command line that can be copied and run
+        #[arg(long)]
+        shell: bool,
+
+        /// Arguments that would be passed to the script
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+
+    /// Allow a script from a project or shared repository to run; changing the script revokes it
+    Trust {
+        /// Name of the script
+        name: String,
+        /// Specify script extension
+        #[arg(short = 'x', long)]
+        extension: Option<String>,
+        /// Only look for the script in this repository (project, user or a shared repo name)
+        #[arg(short = 'r', long)]
+        repo: Option<String>,
+        /// Revoke trust in the script instead
+        #[arg(long)]
+        revoke: bool,
+    },
+
+    /// Sign scripts or plugin files with your signing key, writing <file>.sig next to each
+    Sign {
+        /// Script names or file paths to sign
+        #[arg(required_unless_present_any = ["generate", "show_key"])]
+        targets: Vec<String>,
+        /// Signing key file (defaults to 'signing.key' in the config directory)
+        #[arg(short, long, value_name = "FILE")]
+        key: Option<PathBuf>,
+        /// Generate a new signing key instead
+        #[arg(long, conflicts_with = "targets")]
+        generate: bool,
+        /// Signer name stored in the new key, shown to people verifying signatures
+        #[arg(long, requires = "generate")]
+        name: Option<String>,
+        /// Print the public key to add to 'signing.trusted_keys'
+        #[arg(long, conflicts_with = "targets")]
+        show_key: bool,
+    },
+
+    /// Show previous runs
+    History {
+        /// Only show runs of this script
+        #[arg(short, long)]
+        script: Option<String>,
+        /// Only show successful or failed runs
+        #[arg(long)]
+        status: Option<StatusFilter>,
+        /// Only show runs since this time (e.g. "2h", "7d" or "2024-05-01")
+        #[arg(long, value_name = "TIME")]
+        since: Option<String>,
+        /// Only show runs until this time (e.g. "1d" or "2024-05-31 18:00")
+        #[arg(long, value_name = "TIME")]
+        until: Option<String>,
+        /// Number of most recent runs to show (0 shows all)
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        /// Display in plain text format (one per line)
+        #[arg(short = '1', long)]
+        plain: bool,
+        /// Delete the whole history
+        #[arg(long, conflicts_with_all = ["script", "status", "since", "until", "plain"])]
+        clear: bool,
+        /// Skip confirmation prompt when clearing
+        #[arg(short, long, requires = "clear")]
+        yes: bool,
+    },
+
+    /// Run a script from the history again with the same plugin, arguments and directory
+    Rerun {
+        /// ID of the run to repeat (see 'rune history'); defaults to the most recent run
+        id: Option<u64>,
+        /// Run in the current directory instead of the recorded one
+        #[arg(long)]
+        here: bool,
+        /// Print the resolved invocation instead of running the script
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+        /// Never prompt for input
+        #[arg(long)]
+        no_input: bool,
+    },
+
+    /// Show the output log of a run
+    Logs {
+        /// ID of the run (see 'rune history' or 'rune ps'); defaults to the most recent logged run
+        id: Option<u64>,
+        /// Only print the path of the log file
+        #[arg(long)]
+        path: bool,
+        /// Keep printing new output until the background run finishes
+        #[arg(short = 'f', long, conflicts_with = "path")]
+        follow: bool,
+    },
+
+    /// List scripts running in the background (started with 'rune run --detach')
+    Ps {
+        /// Display in plain text format (one per line)
+        #[arg(short = '1', long)]
+        plain: bool,
+    },
+
+    /// Stop a background run; the script gets the grace period before being killed
+    Kill {
+        /// ID of the background run (see 'rune ps')
+        id: u64,
+        /// Signal to send
+        #[arg(short, long, value_enum, default_value_t = KillSignal::Term)]
+        signal: KillSignal,
+    },
+
+    /// Run scripts on a cron schedule (add, list, remove); schedules fire while 'rune daemon' runs
+    Schedule {
+        #[command(subcommand)]
+        command: ScheduleCommands,
+    },
+
+    /// Run the scheduler in the foreground, firing schedules until interrupted
+    Daemon {
+        /// Pretend the daemon starts at this local time (e.g. "2024-05-01 02:59"); for testing schedules
+        #[arg(long, value_name = "TIME")]
+        clock: Option<String>,
+        /// Make the simulated clock run this many times faster than real time
+        #[arg(long, value_name = "FACTOR", default_value_t = 1.0, requires = "clock", value_parser = parse_speed)]
+        speed: f64,
+        /// Run the schedules that are due now (including missed runs), wait for them and exit
+        #[arg(long)]
+        once: bool,
+    },
+
     /// Manage scripts (add, remove, list, create new)
     #[command(visible_alias = "s")]
     Script {
@@ -47,7 +397,69 @@ pub enum Commands {
         #[command(subcommand)]
         command: PluginCommands,
     },
+
+    /// Show or change configuration (get, set, list, path)
+    #[command(visible_alias = "c")]
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
 }
+#[derive(Subcommand, Debug)]
+pub enum ScheduleCommands {
+    /// Run a script on a cron schedule, e.g. rune schedule add backup "0 3 * * *" -- --full
+    #[command(visible_alias = "a")]
+    Add {
+        /// Name of the script to run
+        script: String,
+        /// When to run: minute hour day month weekday, or @hourly, @daily, @weekly, @monthly, @yearly
+        cron: String,
+        /// Only look for the script in this repository
+        #[arg(short = 'r', long)]
+        repo: Option<String>,
+        /// What to do with runs missed while the daemon was not running
+        #[arg(long, value_name = "POLICY", default_value = "skip")]
+        missed: MissedPolicy,
+        /// What to do when the previous run is still active
+        #[arg(long, value_name = "POLICY", default_value = "skip")]
+        overlap: OverlapPolicy,
+        /// Resource limits for each run, e.g. mem=2G,cpu=60s (Unix only)
+        #[arg(long, value_name = "LIMITS", value_parser = ResourceLimits::parse)]
+        limit: Vec<ResourceLimits>,
+        /// Run the script in a sandbox (Linux only)
+        #[arg(long)]
+        sandbox: bool,
+        /// Let the sandboxed script write to this path (implies --sandbox)
+        #[arg(long, value_name = "PATH")]
+        sandbox_write: Vec<String>,
+        /// Deny network access to the sandboxed script (implies --sandbox)
+        #[arg(long)]
+        no_network: bool,
+
+        /// Arguments to pass to the script (use -- to separate from rune args)
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+
+    /// List schedules and their next run
+    #[command(visible_aliases = ["ls", "l"])]
+    List {
+        /// Display in plain text format (one per line)
+        #[arg(short = '1', long)]
+        plain: bool,
+    },
+
+    /// Remove a schedule by ID
+    #[command(visible_aliases = ["rm", "delete"])]
+    Remove {
+        /// ID of the schedule (see 'rune schedule list')
+        id: u64,
+        /// Skip confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 pub enum ScriptCommands {
     /// Add a script from file path
@@ -59,6 +471,14 @@ pub enum ScriptCommands {
         /// Force overwrite if script already exists
         #[arg(short, long)]
         force: bool,
+
+        /// Namespace (subdirectory) to add the script into, e.g. "db/"
+        #[arg(long, value_name = "NAMESPACE")]
+        into: Option<String>,
+
+        /// Repository to add the script to (project or user; defaults to user)
+        #[arg(short = 'r', long)]
+        repo: Option<String>,
     },
 
     /// Remove a script by name
@@ -74,6 +494,10 @@ pub enum ScriptCommands {
         /// Specify script extension
         #[arg(short = 'x', long)]
         extension: Option<String>,
+
+        /// Only look for the script in this repository
+        #[arg(short = 'r', long)]
+        repo: Option<String>,
     },
 
     /// List all available scripts
@@ -82,6 +506,31 @@ pub enum ScriptCommands {
         /// Display in plain text format (one per line)
         #[arg(short = '1', long)]
         plain: bool,
+
+        /// Display scripts as a tree grouped by namespace
+        #[arg(short, long, conflicts_with = "plain")]
+        tree: bool,
+
+        /// Output format (defaults to 'output.format' in the config)
+        #[arg(long, conflicts_with_all = ["plain", "tree"])]
+        format: Option<OutputFormat>,
+
+        /// Only list scripts from this repository
+        #[arg(short = 'r', long)]
+        repo: Option<String>,
+    },
+
+    /// Show script details and metadata
+    #[command(visible_aliases = ["i"])]
+    Info {
+        /// Name of the script
+        name: String,
+        /// Specify script extension
+        #[arg(short = 'x', long)]
+        extension: Option<String>,
+        /// Only look for the script in this repository
+        #[arg(short = 'r', long)]
+        repo: Option<String>,
     },
 
     /// Create a new script from template
@@ -89,6 +538,9 @@ pub enum ScriptCommands {
     New {
         /// Name for the new script
         name: String,
+        /// Repository to add the script to (project or user; defaults to user)
+        #[arg(short = 'r', long)]
+        repo: Option<String>,
     },
     #[command(visible_alias = "e")]
     Edit {
@@ -97,6 +549,22 @@ pub enum ScriptCommands {
         /// Specify script extension
         #[arg(short = 'x', long)]
         extension: Option<String>,
+        /// Only look for the script in this repository
+        #[arg(short = 'r', long)]
+        repo: Option<String>,
+    },
+    /// Pin the plugin used to run a script (show the current pin if no plugin is given)
+    Pin {
+        /// Name of the script
+        name: String,
+        /// Plugin to always use for this script
+        plugin: Option<String>,
+        /// Specify script extension
+        #[arg(short = 'x', long)]
+        extension: Option<String>,
+        /// Remove the pin
+        #[arg(long, conflicts_with = "plugin")]
+        unset: bool,
     },
 }
 
@@ -111,6 +579,10 @@ pub enum PluginCommands {
         /// Force overwrite if plugin already exists
         #[arg(short, long)]
         force: bool,
+
+        /// Add the plugin even if its signature is missing or invalid
+        #[arg(long)]
+        allow_unsigned: bool,
     },
 
     /// Remove a plugin by name
@@ -130,6 +602,10 @@ pub enum PluginCommands {
         /// Display in plain text format (one per line)
         #[arg(short = '1', long)]
         plain: bool,
+
+        /// Output format (defaults to 'output.format' in the config; tree is shown as a table)
+        #[arg(long, conflicts_with = "plain")]
+        format: Option<OutputFormat>,
     },
     /// Show plugin info
     #[command(visible_aliases = ["i"])]
@@ -137,6 +613,16 @@ pub enum PluginCommands {
         /// Name of the plugin
         name: String,
     },
+    /// Set the default plugin for an extension (show the current default if no plugin is given)
+    Default {
+        /// File extension (without the leading dot)
+        extension: String,
+        /// Plugin to use by default for this extension
+        plugin: Option<String>,
+        /// Remove the default
+        #[arg(long, conflicts_with = "plugin")]
+        unset: bool,
+    },
     /// Export plugin
     #[command(visible_aliases = ["e"])]
     Export {
@@ -149,3 +635,35 @@ pub enum PluginCommands {
         format: Option<String>,
     },
 }
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommands {
+    /// Print the effective value of a config key
+    Get {
+        /// Dotted key, e.g. "editor" or "plugins.defaults.py"
+        key: String,
+    },
+    /// Set a config key in the config file
+    Set {
+        /// Dotted key, e.g. "editor" or "plugins.defaults.py"
+        key: String,
+        /// New value
+        #[arg(required_unless_present = "unset")]
+        value: Option<String>,
+        /// Remove the key from the config file
+        #[arg(long, conflicts_with = "value")]
+        unset: bool,
+        /// Write to the project config (.rune/config.toml) instead of the global one
+        #[arg(long)]
+        project: bool,
+    },
+    /// List all effective config values
+    #[command(visible_aliases = ["ls", "l"])]
+    List,
+    /// Print the path of the config file
+    Path {
+        /// Print the project config path instead
+        #[arg(long)]
+        project: bool,
+    },
+}
@@ -4,8 +4,16 @@ use anyhow::{Context, Result, anyhow, bail};
 use tabled::{Table, Tabled, settings::Style};
 
 use crate::{
-    core::plugin::{Plugin, PluginDatabase},
-    utils::cli::prompt_confirm,
+    core::{
+        config::{Config, OutputFormat},
+        plugin::{Plugin, PluginDatabase},
+        preferences::PluginPreferences,
+        signing::{self, Verification},
+    },
+    utils::{
+        cli::{Color, paint, prompt_confirm, warn},
+        duration::format_duration,
+    },
 };
 
 #[derive(Debug, Tabled)]
@@ -18,7 +26,7 @@ struct PluginListInfo {
     extension: String,
 }
 
-pub fn add(path: PathBuf, force: bool) -> Result<()> {
+pub fn add(path: PathBuf, force: bool, allow_unsigned: bool) -> Result<()> {
     // 检查源文件是否存在
     if !path.exists() {
         bail!("Target plugin does not exist:{}", path.display());
@@ -34,12 +42,12 @@ pub fn add(path: PathBuf, force: bool) -> Result<()> {
         .and_then(|s| s.to_str())
         .map(|s| s.to_lowercase());
 
-    let plugin = match extension.as_deref() {
+    let mut plugin = match extension.as_deref() {
         Some("toml") => {
             // 尝试解析为 TOML，如果失败则检查是否实际上是 YAML
             Plugin::from_toml(&content).or_else(|toml_err| {
                 if let Ok(yaml_plugin) = Plugin::from_yaml(&content) {
-                    eprintln!("警告: 文件扩展名为 .toml，但内容看起来是 YAML 格式");
+                    warn("文件扩展名为 .toml，但内容看起来是 YAML 格式");
                     eprintln!("已成功解析为 YAML，但建议将文件重命名为 .yaml 或 .yml 扩展名");
                     Ok(yaml_plugin)
                 } else {
@@ -54,7 +62,7 @@ pub fn add(path: PathBuf, force: bool) -> Result<()> {
             // 尝试解析为 YAML，如果失败则检查是否实际上是 TOML
             Plugin::from_yaml(&content).or_else(|yaml_err| {
                 if let Ok(toml_plugin) = Plugin::from_toml(&content) {
-                    eprintln!("警告: 文件扩展名为 .yaml/.yml，但内容看起来是 TOML 格式");
+                    warn("文件扩展名为 .yaml/.yml，但内容看起来是 TOML 格式");
                     eprintln!("已成功解析为 TOML，但建议将文件重命名为 .toml 扩展名");
                     Ok(toml_plugin)
                 } else {
@@ -83,9 +91,26 @@ pub fn add(path: PathBuf, force: bool) -> Result<()> {
     };
 
     plugin.validate()?;
+    // 签名者只来自签名验证，不采用插件文件中的声明
+    plugin.signer = None;
+    let config = Config::load()?;
+    if config.signing.enforced() {
+        match signing::verify_file(&path, &signing::file_name(&path), &config.signing)? {
+            Verification::Verified(signer) => plugin.signer = Some(signer),
+            verification if allow_unsigned => warn(format!(
+                "插件文件未通过签名验证（{}），因 --allow-unsigned 仍然添加",
+                verification
+            )),
+            verification => bail!(
+                "Plugin file '{}' failed signature verification: {}\nAsk its author to sign it with 'rune sign', or pass --allow-unsigned to add it anyway",
+                path.display(),
+                verification
+            ),
+        }
+    }
     let mut pdb = PluginDatabase::load()?;
     if pdb.get_plugin(&plugin.name).is_some() {
-        if !force {
+        if !force && config.confirm.overwrite {
             let message = format!(
                 "Plugin '{}' already exists. Do you want to overwrite it?",
                 plugin.name
@@ -105,6 +130,9 @@ pub fn add(path: PathBuf, force: bool) -> Result<()> {
     }
     println!("  Executor: {}", plugin.executor);
     println!("  Extensions: {}", plugin.extensions.join(", "));
+    if let Some(signer) = &plugin.signer {
+        println!("  Signed by: {}", signer);
+    }
     pdb.save()?;
     Ok(())
 }
@@ -115,7 +143,7 @@ pub fn remove(name: String, yes: bool) -> Result<()> {
         anyhow::bail!("Plugin '{}' not found", name);
     }
     // 确认删除
-    if !yes {
+    if !yes && Config::load()?.confirm.remove {
         let message = format!("Do you want to remove plugin '{}'?", name);
         if !prompt_confirm(&message, false)? {
             println!("Operation cancelled");
@@ -130,16 +158,31 @@ pub fn remove(name: String, yes: bool) -> Result<()> {
     // 保存数据库
     pdb.save()?;
 
+    // 清理指向该插件的默认设置和脚本固定
+    let mut prefs = PluginPreferences::load()?;
+    let before = prefs.clone();
+    prefs.extensions.retain(|_, plugin| *plugin != name);
+    prefs.scripts.retain(|_, plugin| *plugin != name);
+    if prefs != before {
+        prefs.save()?;
+    }
+
     Ok(())
 }
-pub fn list(plain: bool) -> Result<()> {
+pub fn list(plain: bool, format: Option<OutputFormat>) -> Result<()> {
+    // 命令行选项优先于配置中的默认格式；插件没有树形显示，按表格输出
+    let format = match (plain, format) {
+        (true, _) => OutputFormat::Plain,
+        (_, Some(format)) => format,
+        _ => Config::load()?.output.format,
+    };
     let pdb = PluginDatabase::load()?;
     let plugins: Vec<_> = pdb.all_plugins().collect();
     if plugins.is_empty() {
         println!("No plugins installed");
         return Ok(());
     }
-    if plain {
+    if format == OutputFormat::Plain {
         for plugin in plugins {
             println!("{}", plugin.name);
         }
@@ -147,12 +190,10 @@ pub fn list(plain: bool) -> Result<()> {
         let scripts: Vec<PluginListInfo> = plugins
             .iter()
             .enumerate()
-            .filter_map(|(index, entry)| {
-                Some(PluginListInfo {
-                    index,
-                    name: entry.name.clone(),
-                    extension: format!("[{}]", entry.extensions.join(",").clone()),
-                })
+            .map(|(index, entry)| PluginListInfo {
+                index,
+                name: entry.name.clone(),
+                extension: format!("[{}]", entry.extensions.join(",").clone()),
             })
             .collect();
         let mut table = Table::new(scripts);
@@ -176,17 +217,64 @@ pub fn info(name: String) -> Result<()> {
     if !plugin.description.is_empty() {
         println!("Description: {}", plugin.description);
     }
+    match &plugin.signer {
+        Some(signer) => println!("Signer: {}", signer),
+        None if Config::load()?.signing.enforced() => println!("Signer: none (not verified)"),
+        None => {}
+    }
     println!();
     println!("Executor: {}", plugin.executor);
     println!("Extensions: {}", plugin.extensions.join(", "));
+    if !plugin.interpreters.is_empty() {
+        println!("Interpreters: {}", plugin.interpreters.join(", "));
+    }
     println!("Argument Template: {}", plugin.arg_template.join(" "));
+    println!("Working Directory: {}", plugin.cwd);
+    if let Some(comment) = &plugin.comment {
+        println!("Comment Prefix: {}", comment);
+    }
+    if let Some(timeout) = plugin.timeout() {
+        println!("Timeout: {}", format_duration(timeout));
+    }
+    if let Some(retries) = plugin.retries {
+        println!("Retries: {}", retries);
+    }
+    if let Some(delay) = plugin.retry_delay() {
+        println!("Retry Delay: {}", format_duration(delay));
+    }
+    if let Some(backoff) = plugin.backoff {
+        println!("Backoff: x{}", backoff);
+    }
+    if !plugin.limits.is_empty() {
+        println!("Limits: {}", plugin.limits);
+    }
+    if let Some(sandbox) = &plugin.sandbox {
+        println!("Sandbox: {}", sandbox);
+    }
+    if !plugin.inherit_env {
+        println!("Inherit Environment: no");
+    }
+    if !plugin.env.is_empty() {
+        println!();
+        println!("Environment:");
+        for (key, value) in &plugin.env {
+            println!("  {}={}", key, value);
+        }
+    }
+    if !plugin.env_remove.is_empty() {
+        println!("Removed Environment: {}", plugin.env_remove.join(", "));
+    }
 
     if !plugin.requires.is_empty() {
         println!();
         println!("Requirements:");
         for req in &plugin.requires {
             let available = which::which(req).is_ok();
-            let status = if available { "✓" } else { "✗" };
+            let status = if available {
+                paint("✓", Color::Green)
+            } else {
+                paint("✗", Color::Red)
+            };
             let status_text = if available { "installed" } else { "not found" };
             println!("  {} {} ({})", status, req, status_text);
         }
@@ -196,9 +284,9 @@ pub fn info(name: String) -> Result<()> {
     println!();
     let executor_available = which::which(&plugin.executor).is_ok();
     if executor_available {
-        println!("Status: ✓ Ready to use");
+        println!("Status: {} Ready to use", paint("✓", Color::Green));
     } else {
-        println!("Status: ✗ Executor not found in PATH");
+        println!("Status: {} Executor not found in PATH", paint("✗", Color::Red));
         println!(
             "        Please install '{}' to use this plugin",
             plugin.executor
@@ -207,11 +295,63 @@ pub fn info(name: String) -> Result<()> {
 
     Ok(())
 }
+/// 设置、查看或移除扩展名的默认插件
+pub fn default(extension: String, plugin: Option<String>, unset: bool) -> Result<()> {
+    let extension = extension.trim_start_matches('.').to_string();
+    let mut prefs = PluginPreferences::load()?;
+    // 配置文件中的默认插件优先于这里记住的选择
+    let configured = Config::load()?.default_plugin(&extension).map(String::from);
+
+    if unset {
+        if prefs.extensions.remove(&extension).is_none() {
+            bail!("No default plugin set for '.{}'", extension);
+        }
+        prefs.save()?;
+        println!("Default plugin for '.{}' removed", extension);
+        return Ok(());
+    }
+
+    let Some(plugin) = plugin else {
+        match (&configured, prefs.default_for_extension(&extension)) {
+            (Some(plugin), _) => println!("{} (from config)", plugin),
+            (None, Some(plugin)) => println!("{}", plugin),
+            (None, None) => println!("No default plugin set for '.{}'", extension),
+        }
+        return Ok(());
+    };
+
+    let db = PluginDatabase::load()?;
+    let found = db
+        .get_plugin(&plugin)
+        .ok_or_else(|| anyhow!("Plugin '{}' not found", plugin))?;
+    if !found.extensions.contains(&extension) {
+        bail!(
+            "Plugin '{}' does not support '.{}' extension.\nSupported extensions: {}",
+            plugin,
+            extension,
+            found.extensions.join(", ")
+        );
+    }
+
+    prefs.extensions.insert(extension.clone(), plugin.clone());
+    prefs.save()?;
+    println!("Default plugin for '.{}' set to '{}'", extension, plugin);
+    if let Some(configured) = configured.filter(|c| *c != plugin) {
+        warn(format!(
+            "配置中的 'plugins.defaults.{}' 为 '{}'，将优先使用",
+            extension, configured
+        ));
+    }
+    Ok(())
+}
 pub fn export(name: String, output: Option<PathBuf>, format: Option<String>) -> Result<()> {
     let db = PluginDatabase::load()?;
-    let plugin = db
+    let mut plugin = db
         .get_plugin(&name)
-        .ok_or_else(|| anyhow!("Plugin '{}' not found", name))?;
+        .ok_or_else(|| anyhow!("Plugin '{}' not found", name))?
+        .clone();
+    // 签名者由添加时的验证决定，导出的文件需要重新签名
+    plugin.signer = None;
 
     // 确定输出格式
     let output_format = if let Some(fmt) = format {
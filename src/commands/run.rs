@@ -1,73 +1,783 @@
 use std::io::{self, Write};
-use std::path::Path;
+use std::{
+    env, fmt,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
 
 use crate::{
+    commands::workflow::{self, WorkflowOptions},
     core::{
-        executor::CommandExecutor,
-        plugin::{Plugin, PluginDatabase},
-        script::{Script, load_scripts},
+        background::Registry,
+        config::Config,
+        executor::{CommandExecutor, DEFAULT_RETRY_DELAY, ExitOutcome, RetryPolicy},
+        history::{History, HistoryEntry, RunSettings},
+        limits::ResourceLimits,
+        lock::{LockWait, ScriptLock},
+        logs,
+        metadata::ScriptMetadata,
+        params::{self, ResolvedArgs},
+        plugin::{Plugin, PluginDatabase, WorkingDir},
+        preferences::PluginPreferences,
+        repo::{Repo, RepoScript, RepoSet},
+        sandbox::SandboxPolicy,
+        script::Script,
+        signing::{self, Verification},
+        template::{self, TemplateContext},
+        trust::{self, Trust},
+        workflow::Workflow,
+    },
+    utils::{
+        cli::{is_interactive, prompt_confirm, warn},
+        duration::{format_duration, parse_duration},
+        fs::{get_locks_path, get_logs_path},
+        shell, time,
     },
-    utils::fs::get_script_path,
 };
 
-pub fn run(
-    name: String,
-    extension: Option<String>,
-    plugin_name: Option<String>,
-    args: Vec<String>,
+/// `rune run` 和 `rune explain` 的选项
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    /// 脚本名称
+    pub name: String,
+    /// 脚本扩展名
+    pub extension: Option<String>,
+    /// 指定使用的插件
+    pub plugin: Option<String>,
+    /// 只在该仓库中查找脚本
+    pub repo: Option<String>,
+    /// 转发终止信号后等待的秒数
+    pub grace_period: u64,
+    /// 不提示用户输入
+    pub no_input: bool,
+    /// 不记录运行历史
+    pub no_history: bool,
+    /// 是否把输出写入日志文件，None 表示按配置 `logs.enabled`
+    pub log: Option<bool>,
+    /// 运行超时时间，覆盖脚本元数据和插件的设置
+    pub timeout: Option<Duration>,
+    /// 失败后的重试次数，覆盖脚本元数据和插件的设置
+    pub retries: Option<u32>,
+    /// 第一次重试前的等待时间
+    pub retry_delay: Option<Duration>,
+    /// 每次重试后等待时间的倍数
+    pub backoff: Option<f64>,
+    /// 禁止同时运行多个实例
+    pub lock: bool,
+    /// 单实例锁的名称，设置后隐含 `lock`
+    pub lock_name: Option<String>,
+    /// 锁被占用时的等待方式，设置后隐含 `lock`
+    pub lock_wait: Option<LockWait>,
+    /// 资源限制，逐项覆盖脚本元数据和插件的设置
+    pub limits: ResourceLimits,
+    /// 沙箱策略，只能收紧插件和脚本元数据的策略；None 表示由它们决定
+    pub sandbox: Option<SandboxPolicy>,
+    /// 签名缺失或无效时仍然允许运行（仍需通过 `rune trust` 信任）
+    pub allow_unsigned: bool,
+    /// 后台运行的编号，只用于 `rune run --detach` 启动的 rune 进程
+    pub background: Option<u64>,
+    /// 传递给脚本的参数
+    pub args: Vec<String>,
+}
+
+impl RunOptions {
+    /// 记录在运行历史中的命令行设置
+    pub fn settings(&self) -> RunSettings {
+        RunSettings {
+            timeout_ms: self.timeout.map(|timeout| timeout.as_millis() as u64),
+            retries: self.retries,
+            retry_delay_ms: self.retry_delay.map(|delay| delay.as_millis() as u64),
+            backoff: self.backoff,
+            log_output: self.log,
+            lock: self.lock,
+            lock_name: self.lock_name.clone(),
+            lock_wait: self.lock_wait,
+        }
+    }
+}
+
+/// 选择插件的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PluginChoice {
+    /// 通过 `--plugin` 指定
+    Flag,
+    /// 通过 `rune script pin` 固定
+    Pinned,
+    /// 脚本元数据中声明
+    Metadata,
+    /// 唯一支持该扩展名的插件
+    Extension(String),
+    /// 唯一支持 shebang 解释器的插件
+    Interpreter(String),
+    /// 多个插件支持该扩展名，只有一个与 shebang 匹配
+    Shebang(String),
+    /// 配置中 `plugins.defaults` 指定的默认插件
+    ConfigDefault(String),
+    /// `rune plugin default` 记住的默认插件
+    Remembered(String),
+    /// 用户交互选择
+    Prompt,
+}
+
+impl fmt::Display for PluginChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PluginChoice::Flag => write!(f, "selected with --plugin"),
+            PluginChoice::Pinned => write!(f, "pinned with 'rune script pin'"),
+            PluginChoice::Metadata => write!(f, "declared in the script's metadata"),
+            PluginChoice::Extension(ext) => write!(f, "only plugin supporting '.{}'", ext),
+            PluginChoice::Interpreter(interpreter) => {
+                write!(f, "only plugin supporting interpreter '{}'", interpreter)
+            }
+            PluginChoice::Shebang(interpreter) => {
+                write!(f, "matches the shebang interpreter '{}'", interpreter)
+            }
+            PluginChoice::ConfigDefault(ext) => {
+                write!(f, "default for '.{}' (plugins.defaults in config)", ext)
+            }
+            PluginChoice::Remembered(ext) => {
+                write!(f, "default for '.{}' (set with 'rune plugin default')", ext)
+            }
+            PluginChoice::Prompt => write!(f, "selected interactively"),
+        }
+    }
+}
+
+/// 解析完成、可以执行的脚本调用
+pub struct Invocation {
+    /// 脚本所在的仓库
+    pub repo: Repo,
+    /// 脚本
+    pub script: Script,
+    /// 使用的插件
+    pub plugin: Plugin,
+    /// 选择该插件的原因
+    pub choice: PluginChoice,
+    /// 配置好参数、环境和工作目录的执行器
+    pub executor: CommandExecutor,
+    /// 运行前需要获取的单实例锁
+    pub lock: Option<LockSpec>,
+    /// 脚本的信任状态
+    pub trust: Trust,
+    /// 脚本签名的验证结果，不需要验证时为 None
+    pub signature: Option<Verification>,
+}
+
+/// 单实例锁的设置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockSpec {
+    /// 锁名称，默认为脚本名称
+    pub name: String,
+    /// 锁被占用时的等待方式
+    pub wait: LockWait,
+}
+
+/// 解析脚本调用的结果
+pub(crate) enum Prepared {
+    /// 用户请求了脚本的用法说明
+    Help(String),
+    /// 可以执行的调用
+    Ready(Box<Invocation>),
+    /// 脚本是工作流，由 [`workflow`](crate::commands::workflow) 运行
+    Workflow(Box<RepoScript>),
+}
+
+/// 同时运行多个脚本时，串行写入运行历史
+static HISTORY_LOCK: Mutex<()> = Mutex::new(());
+
+/// 运行脚本或工作流，`steps` 只用于工作流
+pub fn run(options: RunOptions, steps: WorkflowOptions) -> Result<ExitOutcome> {
+    let interactive = !options.no_input && is_interactive();
+    match prepare(&options, interactive)? {
+        Prepared::Help(usage) => {
+            print!("{}", usage);
+            Ok(ExitOutcome::SUCCESS)
+        }
+        Prepared::Ready(_) if steps.selects_steps() => {
+            bail!("--from-step and --only can only be used with workflows")
+        }
+        Prepared::Ready(invocation) => execute(&invocation, &options, &Config::load()?),
+        Prepared::Workflow(found) => workflow::run(*found, &options, steps),
+    }
+}
+
+/// 执行脚本，返回子进程的退出结果，由调用方决定退出码
+///
+/// 依次获取单实例锁、运行脚本、清理旧日志并记录运行历史
+pub(crate) fn execute(
+    invocation: &Invocation,
+    options: &RunOptions,
+    config: &Config,
+) -> Result<ExitOutcome> {
+    check_trust(
+        &invocation.repo,
+        &invocation.script,
+        invocation.trust,
+        invocation.signature.as_ref(),
+        options.allow_unsigned,
+    )?;
+    // 锁在脚本（包括所有重试）结束后释放；rune 被强制结束时由脚本继续持有
+    let lock = match &invocation.lock {
+        Some(lock) => Some(ScriptLock::acquire(
+            &get_locks_path()?,
+            &lock.name,
+            &invocation.script.name,
+            lock.wait,
+        )?),
+        None => None,
+    };
+    // 登记在历史记录写入后才删除，`rune logs <id>` 在运行结束前后都能找到日志
+    let _background = match options.background {
+        Some(id) => Some(Registry::open()?.attach(id, invocation.executor.get_log_file())?),
+        None => None,
+    };
+    let started = time::now();
+    let clock = Instant::now();
+    let outcome = match &lock {
+        Some(lock) => invocation.executor.clone().hold_lock(lock).status()?,
+        None => invocation.executor.status()?,
+    };
+    if invocation.executor.get_log_file().is_some()
+        && let Err(e) =
+            get_logs_path().and_then(|dir| logs::prune(&dir, config.logs.max_files))
+    {
+        warn(format!("Failed to remove old logs: {:#}", e));
+    }
+    if config.history.enabled && !options.no_history {
+        // 历史写入失败不影响脚本的退出码
+        let mut entry = history_entry(options, invocation, started, clock.elapsed(), outcome);
+        let _guard = HISTORY_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = config.history.retention().and_then(|retention| {
+            let mut history = History::load()?;
+            // 后台运行使用启动时预留的编号，其他运行跳过仍在后台运行的编号
+            entry.id = match options.background {
+                Some(id) => id,
+                None => history.next_id().max(Registry::open()?.next_id()),
+            };
+            history.record(entry, retention, time::now())
+        }) {
+            warn(format!("Failed to record run history: {:#}", e));
+        }
+    }
+    Ok(outcome)
+}
+
+/// 重现超时、重试和单实例锁设置的 `rune run` 参数，用于后台运行和重新运行
+pub fn control_args(options: &RunOptions) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(timeout) = options.timeout {
+        args.extend(["--timeout".to_string(), duration_arg(timeout)]);
+    }
+    if let Some(retries) = options.retries {
+        args.extend(["--retries".to_string(), retries.to_string()]);
+    }
+    if let Some(delay) = options.retry_delay {
+        args.extend(["--retry-delay".to_string(), duration_arg(delay)]);
+    }
+    if let Some(backoff) = options.backoff {
+        args.extend(["--backoff".to_string(), backoff.to_string()]);
+    }
+    if options.lock {
+        args.push("--lock".to_string());
+    }
+    if let Some(name) = &options.lock_name {
+        args.extend(["--lock-name".to_string(), name.clone()]);
+    }
+    match options.lock_wait {
+        Some(LockWait::Timeout(wait)) => args.push(format!("--lock-wait={}", duration_arg(wait))),
+        Some(LockWait::Forever) => args.push("--lock-wait".to_string()),
+        Some(LockWait::Fail) if !options.lock => args.push("--lock".to_string()),
+        _ => {}
+    }
+    args
+}
+
+/// 时长参数，无法用 `1h30m` 这样的写法精确表示时使用毫秒
+fn duration_arg(duration: Duration) -> String {
+    let text = format_duration(duration);
+    if parse_duration(&text).is_ok_and(|parsed| parsed == duration) {
+        text
+    } else {
+        format!("{}ms", duration.as_millis())
+    }
+}
+
+/// 重现资源限制和沙箱策略的 `rune run` 参数，用于后台运行、定时任务和重新运行
+pub fn isolation_args(limits: &ResourceLimits, sandbox: Option<&SandboxPolicy>) -> Vec<String> {
+    let mut args = Vec::new();
+    if !limits.is_empty() {
+        args.extend(["--limit".to_string(), limits.to_string()]);
+    }
+    if let Some(sandbox) = sandbox {
+        args.push("--sandbox".to_string());
+        for path in &sandbox.writable {
+            args.extend(["--sandbox-write".to_string(), path.clone()]);
+        }
+        if sandbox.network == Some(false) {
+            args.push("--no-network".to_string());
+        }
+    }
+    args
+}
+
+/// 生成运行历史记录
+fn history_entry(
+    options: &RunOptions,
+    invocation: &Invocation,
+    started: u64,
+    elapsed: Duration,
+    outcome: ExitOutcome,
+) -> HistoryEntry {
+    let executor = &invocation.executor;
+    let program = which::which(executor.get_program())
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| executor.get_program().to_string());
+    let mut entry = HistoryEntry {
+        id: 0,
+        timestamp: started,
+        script: invocation.script.name.clone(),
+        extension: invocation.script.extension.clone(),
+        repo: invocation.repo.name(),
+        plugin: invocation.plugin.name.clone(),
+        args: options.args.clone(),
+        argv: std::iter::once(program)
+            .chain(executor.get_args().iter().cloned())
+            .collect(),
+        cwd: env::current_dir().unwrap_or_default(),
+        duration_ms: elapsed.as_millis() as u64,
+        exit_code: 0,
+        signal: None,
+        timed_out: false,
+        user: env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .unwrap_or_default(),
+        log: executor.get_log_file().map(Path::to_path_buf),
+        limits: options.limits,
+        sandbox: options.sandbox.clone(),
+        settings: options.settings(),
+    };
+    entry.set_outcome(outcome);
+    entry
+}
+
+/// 拒绝运行未信任或信任后被修改的脚本
+///
+/// 配置了受信任的公钥时，有效的签名代替 `rune trust`；签名缺失或无效时拒绝运行，
+/// 除非 `allow_unsigned` 为 true，此时按信任记录检查
+pub(crate) fn check_trust(
+    repo: &Repo,
+    script: &Script,
+    trust: Trust,
+    signature: Option<&Verification>,
+    allow_unsigned: bool,
 ) -> Result<()> {
-    // 1. 查找脚本
-    let script = find_script(&name, extension.as_deref())?;
+    match signature {
+        Some(Verification::Verified(_)) => return Ok(()),
+        Some(verification) if !allow_unsigned => bail!(
+            "Script '{}' from repository '{}' ({}) failed signature verification: {}\nAsk its author to sign it with 'rune sign', or pass --allow-unsigned to run it anyway",
+            script.name,
+            repo.name(),
+            script.path.display(),
+            verification
+        ),
+        _ => {}
+    }
+    match trust {
+        Trust::Untrusted => bail!(
+            "Script '{}' from repository '{}' is not trusted ({})\nReview it, then run 'rune trust {}' to allow it to run",
+            script.name,
+            repo.name(),
+            script.path.display(),
+            script.name
+        ),
+        Trust::Changed => bail!(
+            "Script '{}' from repository '{}' has changed since it was trusted ({})\nReview the changes, then run 'rune trust {}' again",
+            script.name,
+            repo.name(),
+            script.path.display(),
+            script.name
+        ),
+        Trust::Implicit | Trust::Trusted => Ok(()),
+    }
+}
+
+/// 打印解析后的调用而不执行
+///
+/// `shell` 为 true 时输出可以直接粘贴到 shell 中执行的命令行
+pub fn explain(options: RunOptions, shell: bool, steps: WorkflowOptions) -> Result<()> {
+    // 不提示选择插件，也不记住任何选择
+    let invocation = match prepare(&options, false)? {
+        Prepared::Help(usage) => {
+            print!("{}", usage);
+            return Ok(());
+        }
+        Prepared::Ready(_) if steps.selects_steps() => {
+            bail!("--from-step and --only can only be used with workflows")
+        }
+        Prepared::Ready(invocation) => invocation,
+        Prepared::Workflow(found) => return workflow::explain(*found, &options, shell, steps),
+    };
+    if shell {
+        println!("{}", shell_command(&invocation));
+    } else {
+        print!("{}", describe(&invocation));
+    }
+    Ok(())
+}
+
+/// 查找脚本、选择插件并构建执行器
+pub(crate) fn prepare(options: &RunOptions, interactive: bool) -> Result<Prepared> {
+    // 1. 按仓库优先级查找脚本，工作流交给调用方处理
+    let found = RepoSet::discover()?.find(
+        &options.name,
+        options.extension.as_deref(),
+        options.repo.as_deref(),
+    )?;
+    if found.script.is_workflow() {
+        return Ok(Prepared::Workflow(Box::new(found)));
+    }
+    let RepoScript { repo, script, .. } = found;
+    let trust = trust::status(&repo, &script.path)?;
+    let signature = signing::status(&repo, &script)?;
 
     // 2. 加载插件数据库
     let db = PluginDatabase::load()?;
 
-    // 3. 根据是否指定插件名称，选择不同的加载方式
-    let plugin = if let Some(name) = plugin_name {
+    // 3. 读取脚本开头注释中的元数据
+    let metadata = ScriptMetadata::for_script(&script, &db)?;
+
+    // 脚本声明了参数时，先验证用户输入并填充默认值，再启动解释器
+    let resolved = if metadata.args.is_empty() {
+        ResolvedArgs {
+            args: options.args.clone(),
+            env: Vec::new(),
+        }
+    } else {
+        if params::wants_help(&options.args) {
+            return Ok(Prepared::Help(script_usage(&script, &metadata)));
+        }
+        params::resolve(&metadata.args, metadata.arg_style, &options.args).with_context(|| {
+            format!(
+                "Invalid arguments for script '{}' (see 'rune run {} --help')",
+                script.name, script.name
+            )
+        })?
+    };
+
+    // 4. 根据是否指定插件名称，选择不同的加载方式；
+    //    未指定时依次使用脚本固定的插件和元数据中声明的插件
+    let plugin_name = options.plugin.as_deref();
+    let prefs = PluginPreferences::load()?;
+    let pinned = prefs.pinned_for_script(&script.file_name());
+    let declared = metadata.plugin.as_deref();
+    let (plugin, choice) = if let Some(name) = plugin_name.or(pinned).or(declared) {
+        let choice = if plugin_name.is_some() {
+            PluginChoice::Flag
+        } else if pinned.is_some() {
+            PluginChoice::Pinned
+        } else {
+            PluginChoice::Metadata
+        };
         // 用户指定了插件名称，加载并验证是否支持该扩展名
-        let plugin = load_plugin_by_name(&db, &name)?;
+        let plugin = load_plugin_by_name(&db, name).map_err(|e| match choice {
+            PluginChoice::Pinned => e.context(format!(
+                "Script '{}' is pinned to plugin '{}'; use 'rune script pin {} --unset' to clear it",
+                script.name, name, script.name
+            )),
+            PluginChoice::Metadata => e.context(format!(
+                "Script '{}' declares plugin '{}' in its metadata",
+                script.name, name
+            )),
+            _ => e,
+        })?;
 
-        // 验证插件是否支持该脚本的扩展名
-        if !plugin.extensions.contains(&script.extension) {
-            anyhow::bail!(
-                "Plugin '{}' does not support '.{}' extension.\nSupported extensions: {}",
-                plugin.name,
-                script.extension,
-                plugin.extensions.join(", ")
-            );
+        // 验证插件是否支持该脚本的扩展名或 shebang 解释器
+        let supports_interpreter = script
+            .interpreter
+            .as_deref()
+            .is_some_and(|i| plugin.matches_interpreter(i));
+        if !plugin.extensions.contains(&script.extension) && !supports_interpreter {
+            match &script.interpreter {
+                Some(interpreter) if script.extension.is_empty() => anyhow::bail!(
+                    "Plugin '{}' does not support interpreter '{}'.",
+                    plugin.name,
+                    interpreter
+                ),
+                _ => anyhow::bail!(
+                    "Plugin '{}' does not support '.{}' extension.\nSupported extensions: {}",
+                    plugin.name,
+                    script.extension,
+                    plugin.extensions.join(", ")
+                ),
+            }
         }
 
-        plugin
+        (plugin, choice)
     } else {
-        // 未指定插件，根据扩展名查找（可能需要交互选择）
-        load_plugin_for_extension(&db, &script.extension)?
+        // 未指定插件，根据扩展名和 shebang 查找（可能需要交互选择）
+        let config = Config::load()?;
+        load_plugin_for_script(&db, &script, &config, &prefs, interactive)?
     };
 
-    // 4. 构建命令参数
-    let cmd_args = build_command_args(&plugin, &script.path, &args)?;
-
-    // 5. 执行脚本
-    CommandExecutor::new(&plugin.executor)
+    // 5. 构建命令参数和运行环境
+    let ctx = TemplateContext {
+        file: &script.path,
+        repo: &repo.path,
+        args: &resolved.args,
+    };
+    let cmd_args = build_command_args(&plugin, &ctx)?;
+    let executor = CommandExecutor::new(&plugin.executor)
         .args(cmd_args)
-        .execute()?;
+        .grace_period(Duration::from_secs(options.grace_period));
+    let mut executor = apply_environment(executor, &plugin, &ctx)?;
+    executor = executor.envs(resolved.env);
+    for (key, value) in &metadata.env {
+        let value = template::expand_value(value, &ctx).map_err(|e| {
+            anyhow::anyhow!("Invalid value for '{}' in script metadata: {}", key, e)
+        })?;
+        executor = executor.env(key, value);
+    }
+    // 命令行选项优先，其次是脚本元数据，最后是插件
+    if let Some(timeout) = options
+        .timeout
+        .or_else(|| metadata.timeout())
+        .or_else(|| plugin.timeout())
+    {
+        executor = executor.timeout(timeout);
+    }
+    executor = executor.retry(RetryPolicy {
+        retries: options
+            .retries
+            .or(metadata.retries)
+            .or(plugin.retries)
+            .unwrap_or(0),
+        delay: options
+            .retry_delay
+            .or_else(|| metadata.retry_delay())
+            .or_else(|| plugin.retry_delay())
+            .unwrap_or(DEFAULT_RETRY_DELAY),
+        backoff: options
+            .backoff
+            .or(metadata.backoff)
+            .or(plugin.backoff)
+            .unwrap_or(1.0),
+    });
+    executor = executor.limits(options.limits.or(metadata.limits).or(plugin.limits));
+    // 任一处声明沙箱即在沙箱中运行；元数据和命令行只能在插件策略的基础上收紧
+    if let Some(policy) = [&plugin.sandbox, &metadata.sandbox, &options.sandbox]
+        .into_iter()
+        .flatten()
+        .cloned()
+        .reduce(SandboxPolicy::tighten)
+    {
+        let sandbox = policy.resolve(&ctx, executor.get_current_dir())?;
+        sandbox.check_visible(&script.path, "Script")?;
+        if let Some(dir) = executor.get_current_dir() {
+            sandbox.check_visible(dir, "Working directory")?;
+        }
+        for path in &sandbox.writable {
+            sandbox.check_visible(path, "Writable path")?;
+        }
+        executor = executor.sandbox(sandbox);
+    }
+    let lock = (options.lock
+        || options.lock_name.is_some()
+        || options.lock_wait.is_some()
+        || metadata.exclusive
+        || metadata.lock.is_some())
+    .then(|| LockSpec {
+        name: options
+            .lock_name
+            .clone()
+            .or_else(|| metadata.lock.clone())
+            .unwrap_or_else(|| script.name.clone()),
+        wait: options
+            .lock_wait
+            .or_else(|| metadata.lock_wait())
+            .unwrap_or_default(),
+    });
+    let config = Config::load()?;
+    // 后台运行的输出只能通过日志查看
+    if options.background.is_some() || options.log.unwrap_or(config.logs.enabled) {
+        let path = logs::new_log_path(&get_logs_path()?, &script.name, time::now());
+        executor = executor.log_file(path, config.logs.max_size()?);
+    }
 
-    Ok(())
+    Ok(Prepared::Ready(Box::new(Invocation {
+        repo,
+        script,
+        plugin,
+        choice,
+        executor,
+        lock,
+        trust,
+        signature,
+    })))
 }
 
-/// 查找脚本
-fn find_script(name: &str, extension: Option<&str>) -> Result<Script> {
-    let script_path = get_script_path()?;
-    let scripts = load_scripts(script_path)?;
+/// 生成调用的可读说明
+fn describe(invocation: &Invocation) -> String {
+    let Invocation {
+        repo,
+        script,
+        plugin,
+        choice,
+        executor,
+        lock,
+        trust,
+        signature,
+    } = invocation;
+    let mut out = String::new();
+    let mut line = |text: String| {
+        out.push_str(&text);
+        out.push('\n');
+    };
 
-    scripts
-        .into_iter()
-        .find(|s| s.name == name && extension.map_or(true, |ext| s.extension == ext))
-        .ok_or_else(|| match extension {
-            Some(ext) => anyhow::anyhow!("Script '{}.{}' not found", name, ext),
-            None => anyhow::anyhow!("Script '{}' not found", name),
-        })
+    line(format!("Script: {}", script.path.display()));
+    line(format!("Repository: {}", repo.name()));
+    match (signature, trust) {
+        (Some(signature), _) => line(format!("Signature: {}", signature)),
+        (None, Trust::Implicit) => {}
+        (None, Trust::Trusted) => line(format!("Trust: {}", trust)),
+        (None, Trust::Untrusted | Trust::Changed) => line(format!(
+            "Trust: {} (run 'rune trust {}' to allow it)",
+            trust, script.name
+        )),
+    }
+    line(format!("Plugin: {} ({})", plugin.name, choice));
+    match which::which(executor.get_program()) {
+        Ok(path) => line(format!("Executor: {}", path.display())),
+        Err(_) => line(format!(
+            "Executor: {} (not found in PATH)",
+            executor.get_program()
+        )),
+    }
+    line("Arguments:".to_string());
+    for (i, arg) in executor.get_args().iter().enumerate() {
+        line(format!("  [{}] {}", i, shell::quote(arg)));
+    }
+    match executor.get_current_dir() {
+        Some(dir) => line(format!("Working Directory: {}", dir.display())),
+        None => line("Working Directory: (current directory)".to_string()),
+    }
+    if let Some(timeout) = executor.get_timeout() {
+        line(format!("Timeout: {}", format_duration(timeout)));
+    }
+    let retry = executor.get_retry();
+    if retry.retries > 0 {
+        let mut text = format!(
+            "Retries: {} (delay {}",
+            retry.retries,
+            format_duration(retry.delay)
+        );
+        if retry.backoff != 1.0 {
+            text.push_str(&format!(", backoff x{}", retry.backoff));
+        }
+        text.push(')');
+        line(text);
+    }
+    let limits = executor.get_limits();
+    if !limits.is_empty() {
+        line(format!("Limits: {}", limits));
+    }
+    if let Some(sandbox) = executor.get_sandbox() {
+        line(format!("Sandbox: {}", sandbox));
+    }
+    if let Some(lock) = lock {
+        line(format!("Lock: {} ({})", lock.name, lock.wait));
+    }
+    if let Some(path) = executor.get_log_file() {
+        line(format!("Log File: {}", path.display()));
+    }
+
+    let clear = executor.get_env_clear();
+    let removed = executor.get_env_remove();
+    let set = executor.get_envs();
+    if clear || !removed.is_empty() || !set.is_empty() {
+        line(String::new());
+        line("Environment:".to_string());
+        if clear {
+            line("  (inherited environment cleared)".to_string());
+        }
+        for key in removed {
+            line(format!("  -{}", key));
+        }
+        for (key, value) in set {
+            line(format!("  +{}={}", key, shell::quote(value)));
+        }
+    }
+    line(String::new());
+    line(format!("Command: {}", shell_command(invocation)));
+    out
+}
+
+/// 生成可以粘贴到 shell 中执行的命令行
+///
+/// 环境变量的改动通过 `env` 表达，工作目录通过 `cd` 表达
+pub(crate) fn shell_command(invocation: &Invocation) -> String {
+    let executor = &invocation.executor;
+    let program = which::which(executor.get_program())
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| executor.get_program().to_string());
+
+    let mut words = Vec::new();
+    let clear = executor.get_env_clear();
+    let removed = executor.get_env_remove();
+    let set = executor.get_envs();
+    if clear || !removed.is_empty() || !set.is_empty() {
+        words.push("env".to_string());
+        if clear {
+            words.push("-i".to_string());
+        }
+        for key in removed {
+            words.push("-u".to_string());
+            words.push(shell::quote(key));
+        }
+        for (key, value) in set {
+            words.push(format!("{}={}", key, shell::quote(value)));
+        }
+    }
+    words.push(shell::quote(&program));
+    words.push(shell::join(executor.get_args()));
+
+    let command = words.join(" ");
+    match executor.get_current_dir() {
+        Some(dir) => format!(
+            "cd {} && {}",
+            shell::quote(&dir.to_string_lossy()),
+            command
+        ),
+        None => command,
+    }
+}
+
+/// 打印脚本的用法说明
+pub fn help(name: String, extension: Option<String>, repo: Option<String>) -> Result<()> {
+    let script = RepoSet::discover()?
+        .find(&name, extension.as_deref(), repo.as_deref())?
+        .script;
+    if script.is_workflow() {
+        print!("{}", workflow::usage(&script, &Workflow::load(&script.path)?));
+        return Ok(());
+    }
+    let db = PluginDatabase::load()?;
+    let metadata = ScriptMetadata::for_script(&script, &db)?;
+    print!("{}", script_usage(&script, &metadata));
+    if metadata.args.is_empty() {
+        println!(
+            "\nThis script does not declare any arguments; \
+            everything after '--' is passed to it unchanged."
+        );
+    }
+    Ok(())
+}
+
+/// 生成脚本的用法说明
+fn script_usage(script: &Script, metadata: &ScriptMetadata) -> String {
+    params::usage(&script.name, &metadata.description, &metadata.args)
 }
 
 /// 根据插件名称加载插件
@@ -81,33 +791,177 @@ fn load_plugin_by_name(db: &PluginDatabase, name: &str) -> Result<Plugin> {
     })
 }
 
-/// 根据扩展名查找插件，如果有多个匹配则提示用户选择
-fn load_plugin_for_extension(db: &PluginDatabase, extension: &str) -> Result<Plugin> {
-    let matches = db.find_all_by_extension(extension);
+/// 根据扩展名和 shebang 查找插件，如果有多个匹配则提示用户选择
+///
+/// 扩展名只匹配到一个插件时直接使用；匹配到多个时用 shebang 中的解释器消除歧义，
+/// 仍有多个时使用该扩展名的默认插件（配置优先于记住的选择）；没有扩展名或扩展名没有匹配的插件时回退到 shebang。
+/// 非交互模式下无法确定插件时返回错误而不是读取 stdin
+fn load_plugin_for_script(
+    db: &PluginDatabase,
+    script: &Script,
+    config: &Config,
+    prefs: &PluginPreferences,
+    interactive: bool,
+) -> Result<(Plugin, PluginChoice)> {
+    let by_extension = if script.extension.is_empty() {
+        Vec::new()
+    } else {
+        db.find_all_by_extension(&script.extension)
+    };
+    let by_interpreter = script
+        .interpreter
+        .as_deref()
+        .map(|i| db.find_all_by_interpreter(i))
+        .unwrap_or_default();
+
+    let interpreter = script.interpreter.clone().unwrap_or_default();
+    // `single` 是只剩一个匹配时选择它的原因
+    let (matches, subject, single) = match by_extension.len() {
+        0 => (
+            by_interpreter,
+            script
+                .interpreter
+                .as_ref()
+                .map(|i| format!("interpreter '{}'", i)),
+            PluginChoice::Interpreter(interpreter),
+        ),
+        1 => (
+            by_extension,
+            None,
+            PluginChoice::Extension(script.extension.clone()),
+        ),
+        _ => {
+            // 多个插件支持该扩展名，优先选择与 shebang 匹配的插件
+            let narrowed: Vec<&Plugin> = by_extension
+                .iter()
+                .filter(|p| by_interpreter.iter().any(|q| q.name == p.name))
+                .copied()
+                .collect();
+            let matches = if narrowed.is_empty() {
+                by_extension
+            } else {
+                narrowed
+            };
+            (
+                matches,
+                Some(format!("'.{}' extension", script.extension)),
+                PluginChoice::Shebang(interpreter),
+            )
+        }
+    };
 
     match matches.len() {
-        0 => {
-            anyhow::bail!(
+        0 => match &script.interpreter {
+            Some(interpreter) if script.extension.is_empty() => anyhow::bail!(
+                "No plugin found for interpreter '{}'.\n\
+                Use 'rune plugin list' to see available plugins or \
+                'rune plugin add <source>' to add a new plugin.",
+                interpreter
+            ),
+            Some(interpreter) => anyhow::bail!(
+                "No plugin found for extension '.{}' or interpreter '{}'.\n\
+                Use 'rune plugin list' to see available plugins or \
+                'rune plugin add <source>' to add a new plugin.",
+                script.extension,
+                interpreter
+            ),
+            None => anyhow::bail!(
                 "No plugin found for extension '.{}'.\n\
                 Use 'rune plugin list' to see available plugins or \
                 'rune plugin add <source>' to add a new plugin.",
-                extension
-            )
-        }
+                script.extension
+            ),
+        },
         1 => {
             // 只有一个匹配，直接使用
-            Ok(matches[0].clone())
+            Ok((matches[0].clone(), single))
         }
         _ => {
-            // 多个匹配，提示用户选择
-            select_plugin_interactive(&matches, extension)
+            // 多个匹配，优先使用记住的默认插件
+            let extension = &script.extension;
+            let defaults = [
+                (
+                    config.default_plugin(extension),
+                    PluginChoice::ConfigDefault(extension.clone()),
+                ),
+                (
+                    prefs.default_for_extension(extension),
+                    PluginChoice::Remembered(extension.clone()),
+                ),
+            ];
+            for (default, choice) in defaults {
+                if let Some(plugin) = default.and_then(|d| matches.iter().find(|p| p.name == d)) {
+                    return Ok(((*plugin).clone(), choice));
+                }
+            }
+
+            let subject = subject.unwrap_or_default();
+            if !interactive {
+                let names: Vec<&str> = matches.iter().map(|p| p.name.as_str()).collect();
+                let remember = if script.extension.is_empty() {
+                    format!("'rune script pin {} <plugin>'", script.name)
+                } else {
+                    format!("'rune plugin default {} <plugin>'", script.extension)
+                };
+                anyhow::bail!(
+                    "Multiple plugins support {}: {}.\n\
+                    Cannot prompt in non-interactive mode; use '--plugin <name>' \
+                    or remember a choice with {}.",
+                    subject,
+                    names.join(", "),
+                    remember
+                );
+            }
+
+            // 提示用户选择，并询问是否记住选择
+            let selected = select_plugin_interactive(&matches, &subject)?;
+            remember_selection(script, &selected)?;
+            Ok((selected, PluginChoice::Prompt))
         }
     }
 }
 
+/// 询问用户是否记住插件选择
+///
+/// 有扩展名时记为该扩展名的默认插件，否则固定到该脚本
+fn remember_selection(script: &Script, plugin: &Plugin) -> Result<()> {
+    let (message, key) = if script.extension.is_empty() {
+        (
+            format!("Always use '{}' for script '{}'?", plugin.name, script.name),
+            None,
+        )
+    } else {
+        (
+            format!(
+                "Always use '{}' for '.{}' scripts?",
+                plugin.name, script.extension
+            ),
+            Some(script.extension.clone()),
+        )
+    };
+    if !prompt_confirm(&message, false)? {
+        return Ok(());
+    }
+
+    let mut prefs = PluginPreferences::load()?;
+    match key {
+        Some(extension) => {
+            prefs.extensions.insert(extension, plugin.name.clone());
+        }
+        None => {
+            prefs.scripts.insert(script.file_name(), plugin.name.clone());
+        }
+    }
+    prefs.save()?;
+    println!("✓ Choice saved\n");
+    Ok(())
+}
+
 /// 交互式选择插件
-fn select_plugin_interactive(plugins: &[&Plugin], extension: &str) -> Result<Plugin> {
-    println!("\n⚠️  Multiple plugins support '.{}' extension:", extension);
+///
+/// `subject` 描述冲突的来源，如 "'.py' extension" 或 "interpreter 'python3'"
+fn select_plugin_interactive(plugins: &[&Plugin], subject: &str) -> Result<Plugin> {
+    println!("\n⚠️  Multiple plugins support {}:", subject);
     println!();
 
     for (i, plugin) in plugins.iter().enumerate() {
@@ -144,25 +998,120 @@ fn select_plugin_interactive(plugins: &[&Plugin], extension: &str) -> Result<Plu
 }
 
 /// 构建命令参数
-fn build_command_args(
+///
+/// 展开插件的参数模板；模板不包含 `{args}` 时用户参数追加在末尾
+fn build_command_args(plugin: &Plugin, ctx: &TemplateContext) -> Result<Vec<String>> {
+    template::expand(&plugin.arg_template, ctx)
+        .map_err(|e| anyhow::anyhow!("Invalid argument template for plugin '{}': {}", plugin.name, e))
+}
+
+/// 应用插件声明的环境变量和工作目录
+fn apply_environment(
+    mut executor: CommandExecutor,
     plugin: &Plugin,
-    script_path: &Path,
-    user_args: &[String],
-) -> Result<Vec<String>> {
-    let script_path_str = script_path
-        .to_str()
-        .ok_or_else(|| anyhow::anyhow!("Invalid script path: non-UTF8 characters"))?;
+    ctx: &TemplateContext,
+) -> Result<CommandExecutor> {
+    if !plugin.inherit_env {
+        executor = executor.env_clear();
+    }
+    for key in &plugin.env_remove {
+        executor = executor.env_remove(key);
+    }
+    for (key, value) in &plugin.env {
+        let value = template::expand_value(value, ctx).map_err(|e| {
+            anyhow::anyhow!("Invalid value for '{}' in plugin '{}': {}", key, plugin.name, e)
+        })?;
+        executor = executor.env(key, value);
+    }
+
+    match &plugin.cwd {
+        WorkingDir::Caller => {}
+        WorkingDir::Script => {
+            if let Some(dir) = ctx.file.parent() {
+                executor = executor.current_dir(dir);
+            }
+        }
+        WorkingDir::Path(path) => {
+            let dir = template::expand_value(path, ctx).map_err(|e| {
+                anyhow::anyhow!("Invalid working directory in plugin '{}': {}", plugin.name, e)
+            })?;
+            executor = executor.current_dir(dir);
+        }
+    }
+    Ok(executor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::repo::Layer;
+    use std::path::PathBuf;
 
-    let mut cmd_args = Vec::new();
+    fn shared() -> (Repo, Script) {
+        let repo = Repo {
+            layer: Layer::Shared("team".to_string()),
+            path: PathBuf::from("/srv/team"),
+        };
+        let script = Script {
+            name: "deploy".to_string(),
+            extension: "sh".to_string(),
+            path: PathBuf::from("/srv/team/deploy.sh"),
+            interpreter: None,
+        };
+        (repo, script)
+    }
 
-    // 处理参数模板
-    for template in &plugin.arg_template {
-        let arg = template.replace("{file}", script_path_str);
-        cmd_args.push(arg);
+    fn check(trust: Trust, signature: Option<Verification>, allow_unsigned: bool) -> Result<()> {
+        let (repo, script) = shared();
+        check_trust(&repo, &script, trust, signature.as_ref(), allow_unsigned)
     }
 
-    // 添加用户参数
-    cmd_args.extend(user_args.iter().cloned());
+    #[test]
+    fn test_check_trust_without_signing() {
+        assert!(check(Trust::Implicit, None, false).is_ok());
+        assert!(check(Trust::Trusted, None, false).is_ok());
+
+        let err = check(Trust::Untrusted, None, false)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Script 'deploy' from repository 'team' is not trusted"));
+        assert!(err.contains("rune trust deploy"));
+        let err = check(Trust::Changed, None, false).unwrap_err().to_string();
+        assert!(err.contains("has changed since it was trusted"));
+
+        // --allow-unsigned 不影响信任检查
+        assert!(check(Trust::Untrusted, None, true).is_err());
+        assert!(check(Trust::Changed, None, true).is_err());
+    }
 
-    Ok(cmd_args)
+    #[test]
+    fn test_check_trust_with_signing() {
+        let verified = || Some(Verification::Verified("alice".to_string()));
+        let invalid = || Some(Verification::Invalid("the file was modified".to_string()));
+
+        // 有效的签名代替 rune trust
+        assert!(check(Trust::Untrusted, verified(), false).is_ok());
+        assert!(check(Trust::Changed, verified(), false).is_ok());
+
+        // 签名缺失或无效时即使已信任也拒绝运行
+        for signature in [Some(Verification::Unsigned), invalid()] {
+            let err = check(Trust::Trusted, signature, false)
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains("failed signature verification"));
+            assert!(err.contains("--allow-unsigned"));
+        }
+
+        // --allow-unsigned 时改为按信任记录检查
+        assert!(check(Trust::Trusted, invalid(), true).is_ok());
+        assert!(check(Trust::Implicit, Some(Verification::Unsigned), true).is_ok());
+        let err = check(Trust::Untrusted, invalid(), true)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("is not trusted"));
+        let err = check(Trust::Changed, Some(Verification::Unsigned), true)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("has changed since it was trusted"));
+    }
 }
@@ -0,0 +1,467 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        Arc, Condvar, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, bail};
+use tabled::{builder::Builder, settings::Style};
+
+use crate::{
+    commands::run::{self, Invocation, Prepared, RunOptions},
+    core::{config::Config, executor::ExitOutcome, workflow::When},
+    utils::{
+        cli::{Color, is_interactive, paint},
+        duration::format_duration,
+    },
+};
+
+/// 输出前缀轮流使用的颜色
+pub(crate) const PREFIX_COLORS: [Color; 5] = [
+    Color::Cyan,
+    Color::Magenta,
+    Color::Blue,
+    Color::Yellow,
+    Color::Green,
+];
+
+/// 同时运行多个脚本的选项
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ManyOptions {
+    /// 最多同时运行的脚本数，0 表示全部同时运行
+    pub jobs: usize,
+    /// 有脚本失败时取消其余脚本
+    pub fail_fast: bool,
+}
+
+/// 调度运行的一个脚本
+pub(crate) struct Job {
+    /// 在输出前缀和汇总表中显示的名称
+    pub label: String,
+    /// 运行选项，用于记录运行历史
+    pub options: RunOptions,
+    /// 解析完成的调用
+    pub invocation: Invocation,
+    /// 需要先完成的任务下标
+    pub needs: Vec<usize>,
+    /// 根据依赖任务的结果决定是否运行
+    pub when: When,
+    /// 运行条件是否满足，不满足时跳过
+    pub enabled: bool,
+}
+
+/// 单个脚本的运行结果
+enum JobStatus {
+    /// 脚本运行结束
+    Finished(ExitOutcome),
+    /// 因其他脚本失败或收到终止信号而被终止
+    Cancelled,
+    /// 未能启动（如锁被占用）
+    Error(String),
+    /// 没有运行，附带原因
+    Skipped(String),
+}
+
+impl JobStatus {
+    fn success(&self) -> bool {
+        matches!(self, JobStatus::Finished(outcome) if outcome.success())
+    }
+
+    /// 运行了但没有成功
+    fn failed(&self) -> bool {
+        !self.success() && !matches!(self, JobStatus::Skipped(_))
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            JobStatus::Finished(outcome) if outcome.success() => "ok".to_string(),
+            JobStatus::Finished(outcome) => describe_outcome(*outcome),
+            JobStatus::Cancelled => "cancelled".to_string(),
+            JobStatus::Error(message) => format!("error: {}", message),
+            JobStatus::Skipped(reason) if reason.is_empty() => "skipped".to_string(),
+            JobStatus::Skipped(reason) => format!("skipped ({})", reason),
+        }
+    }
+}
+
+/// 任务的调度状态
+enum State {
+    Pending,
+    Running,
+    Done(JobStatus, Duration),
+}
+
+/// 调度器下一步要做的事
+enum Next {
+    Run(usize),
+    Skip(usize, String),
+    Wait,
+    Finished,
+}
+
+/// 同时运行多个脚本
+///
+/// 每个脚本的输出逐行加上带颜色的脚本名称前缀，全部结束后打印汇总表。
+/// 返回第一个失败脚本的退出码，全部成功时返回成功
+pub fn run_many(names: Vec<String>, options: RunOptions, many: ManyOptions) -> Result<ExitOutcome> {
+    let mut seen = HashSet::new();
+    for name in &names {
+        if !seen.insert(name) {
+            bail!("Script '{}' is listed more than once", name);
+        }
+    }
+    if names.is_empty() {
+        bail!("No scripts to run");
+    }
+
+    // 先解析所有脚本，任何一个无法运行时都不启动
+    let interactive = !options.no_input && is_interactive();
+    let mut jobs = Vec::with_capacity(names.len());
+    for name in names {
+        let options = RunOptions {
+            name,
+            ..options.clone()
+        };
+        let invocation = match run::prepare(&options, interactive)? {
+            Prepared::Ready(invocation) => *invocation,
+            Prepared::Help(_) => bail!(
+                "Script usage is not available when running several scripts; use 'rune run {} --help'",
+                options.name
+            ),
+            Prepared::Workflow(_) => bail!(
+                "'{}' is a workflow; run it on its own with 'rune run {}'",
+                options.name,
+                options.name
+            ),
+        };
+        jobs.push(Job {
+            label: options.name.clone(),
+            options,
+            invocation,
+            needs: Vec::new(),
+            when: When::Success,
+            enabled: true,
+        });
+    }
+    run_jobs(jobs, many, "Script")
+}
+
+/// 按依赖关系运行任务，依赖关系允许时同时运行
+///
+/// 依赖任务失败或被跳过时，`when: success` 的任务也会被跳过。
+/// `header` 为汇总表第一列的标题
+pub(crate) fn run_jobs(jobs: Vec<Job>, many: ManyOptions, header: &str) -> Result<ExitOutcome> {
+    let config = Config::load()?;
+    let cancel = Arc::new(AtomicBool::new(false));
+    let width = jobs
+        .iter()
+        .map(|job| job.label.chars().count())
+        .max()
+        .unwrap_or(0);
+    let jobs: Vec<Job> = jobs
+        .into_iter()
+        .enumerate()
+        .map(|(i, mut job)| {
+            let prefix = paint(
+                &format!("{:<width$} |", job.label),
+                PREFIX_COLORS[i % PREFIX_COLORS.len()],
+            );
+            job.invocation.executor = job
+                .invocation
+                .executor
+                .prefix_output(format!("{} ", prefix))
+                .cancel_flag(Arc::clone(&cancel));
+            job
+        })
+        .collect();
+
+    let workers = match many.jobs {
+        0 => jobs.len(),
+        n => n.min(jobs.len()),
+    };
+    let board = Mutex::new(jobs.iter().map(|_| State::Pending).collect::<Vec<_>>());
+    let changed = Condvar::new();
+    #[cfg(unix)]
+    let guard = crate::core::signal::ForwardGuard::install()
+        .map_err(|e| anyhow::anyhow!(e).context("安装信号处理器失败"))?;
+    #[allow(unused_mut)]
+    let mut interrupted: Option<i32> = None;
+
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| worker(&jobs, &board, &changed, &cancel, &config, many.fail_fast))
+            })
+            .collect();
+        // 收到终止信号后不再启动新的脚本，正在运行的脚本由执行器转发信号
+        while !handles.iter().all(|handle| handle.is_finished()) {
+            #[cfg(unix)]
+            if let Some(signal) = guard.take_pending() {
+                interrupted.get_or_insert(signal);
+                cancel.store(true, Ordering::SeqCst);
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    });
+
+    let states = board.into_inner().unwrap_or_else(|e| e.into_inner());
+    let mut table = Builder::default();
+    table.push_record([header, "Status", "Duration"]);
+    let mut outcome = ExitOutcome::SUCCESS;
+    for (job, state) in jobs.iter().zip(states) {
+        let (status, duration) = match state {
+            State::Done(status @ JobStatus::Skipped(_), _) => (status, "-".to_string()),
+            State::Done(status, elapsed) => (status, format_duration(elapsed)),
+            State::Pending | State::Running => (JobStatus::Skipped(String::new()), "-".to_string()),
+        };
+        if outcome.success() {
+            match &status {
+                JobStatus::Finished(failed) if !failed.success() => {
+                    outcome = ExitOutcome::Exited(failed.code())
+                }
+                JobStatus::Error(_) => outcome = ExitOutcome::Exited(1),
+                _ => {}
+            }
+        }
+        table.push_record([job.label.clone(), status.describe(), duration]);
+    }
+
+    let mut table = table.build();
+    table.with(Style::rounded());
+    eprintln!("{}", table);
+
+    match interrupted {
+        Some(signal) => Ok(ExitOutcome::Signaled(signal)),
+        None => Ok(outcome),
+    }
+}
+
+/// 不断取出可以运行的任务，直到所有任务结束或被取消
+fn worker(
+    jobs: &[Job],
+    board: &Mutex<Vec<State>>,
+    changed: &Condvar,
+    cancel: &AtomicBool,
+    config: &Config,
+    fail_fast: bool,
+) {
+    let mut states = board.lock().unwrap_or_else(|e| e.into_inner());
+    loop {
+        if cancel.load(Ordering::SeqCst) {
+            changed.notify_all();
+            return;
+        }
+        match next(jobs, &states) {
+            Next::Run(index) => {
+                states[index] = State::Running;
+                drop(states);
+
+                let job = &jobs[index];
+                let clock = Instant::now();
+                let result = run::execute(&job.invocation, &job.options, config);
+                let elapsed = clock.elapsed();
+                let status = match result {
+                    Ok(outcome) if outcome.success() => JobStatus::Finished(outcome),
+                    // 取消标志在脚本结束前已置位，失败是由取消导致的
+                    Ok(_) if cancel.load(Ordering::SeqCst) => JobStatus::Cancelled,
+                    Ok(outcome) => JobStatus::Finished(outcome),
+                    Err(e) => JobStatus::Error(format!("{:#}", e)),
+                };
+                if fail_fast && !status.success() {
+                    cancel.store(true, Ordering::SeqCst);
+                }
+
+                states = board.lock().unwrap_or_else(|e| e.into_inner());
+                states[index] = State::Done(status, elapsed);
+                changed.notify_all();
+            }
+            Next::Skip(index, reason) => {
+                states[index] = State::Done(JobStatus::Skipped(reason), Duration::ZERO);
+                changed.notify_all();
+            }
+            Next::Wait => {
+                // 定时醒来检查取消标志
+                states = changed
+                    .wait_timeout(states, Duration::from_millis(50))
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0;
+            }
+            Next::Finished => return,
+        }
+    }
+}
+
+/// 找出第一个依赖已全部结束的任务，决定运行还是跳过
+fn next(jobs: &[Job], states: &[State]) -> Next {
+    let mut waiting = false;
+    for (index, job) in jobs.iter().enumerate() {
+        match states[index] {
+            State::Pending => {}
+            State::Running => {
+                waiting = true;
+                continue;
+            }
+            State::Done(..) => continue,
+        }
+        let needs: Vec<(usize, &JobStatus)> = job
+            .needs
+            .iter()
+            .filter_map(|&need| match &states[need] {
+                State::Done(status, _) => Some((need, status)),
+                _ => None,
+            })
+            .collect();
+        if needs.len() < job.needs.len() {
+            waiting = true;
+            continue;
+        }
+
+        let failed = needs.iter().find(|(_, status)| status.failed());
+        let skipped = needs
+            .iter()
+            .find(|(_, status)| matches!(status, JobStatus::Skipped(_)));
+        let reason = match job.when {
+            When::Success => failed
+                .map(|(need, _)| format!("{} failed", jobs[*need].label))
+                .or_else(|| skipped.map(|(need, _)| format!("{} skipped", jobs[*need].label))),
+            When::Failure if failed.is_none() => Some("nothing failed".to_string()),
+            When::Failure | When::Always => None,
+        };
+        if let Some(reason) = reason {
+            return Next::Skip(index, reason);
+        }
+        if !job.enabled {
+            return Next::Skip(index, "condition not met".to_string());
+        }
+        return Next::Run(index);
+    }
+    if waiting { Next::Wait } else { Next::Finished }
+}
+
+/// 运行结果的简短描述，与运行历史中的状态一致
+pub(crate) fn describe_outcome(outcome: ExitOutcome) -> String {
+    match outcome {
+        ExitOutcome::Exited(code) => format!("exit {}", code),
+        ExitOutcome::Signaled(signal) => format!("signal {}", signal),
+        ExitOutcome::TimedOut => "timeout".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::run::PluginChoice,
+        core::{
+            executor::{CommandExecutor, DEFAULT_GRACE_PERIOD},
+            limits::ResourceLimits,
+            plugin::Plugin,
+            repo::{Layer, Repo},
+            script::Script,
+            trust::Trust,
+        },
+    };
+    use serial_test::serial;
+    use std::path::PathBuf;
+
+    /// 用 `sh -c` 运行 `command` 的任务，不记录历史和日志
+    fn job(label: &str, command: &str) -> Job {
+        let options = RunOptions {
+            name: label.to_string(),
+            extension: None,
+            plugin: None,
+            repo: None,
+            grace_period: DEFAULT_GRACE_PERIOD.as_secs(),
+            no_input: true,
+            no_history: true,
+            log: Some(false),
+            timeout: None,
+            retries: None,
+            retry_delay: None,
+            backoff: None,
+            lock: false,
+            lock_name: None,
+            lock_wait: None,
+            limits: ResourceLimits::default(),
+            sandbox: None,
+            allow_unsigned: false,
+            background: None,
+            args: Vec::new(),
+        };
+        let invocation = Invocation {
+            repo: Repo {
+                layer: Layer::User,
+                path: PathBuf::from("/scripts"),
+            },
+            script: Script {
+                name: label.to_string(),
+                extension: "sh".to_string(),
+                path: PathBuf::from(format!("/scripts/{}.sh", label)),
+                interpreter: None,
+            },
+            plugin: Plugin::default(),
+            choice: PluginChoice::Flag,
+            executor: CommandExecutor::new("sh")
+                .args(["-c", command])
+                .interactive(false),
+            lock: None,
+            trust: Trust::Implicit,
+            signature: None,
+        };
+        Job {
+            label: label.to_string(),
+            options,
+            invocation,
+            needs: Vec::new(),
+            when: When::Success,
+            enabled: true,
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[serial]
+    fn test_run_jobs_fail_fast_cancels_siblings() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let marker = temp_dir.path().join("finished");
+        let slow = format!("sleep 5; touch '{}'", marker.display());
+        let jobs = vec![job("slow", &slow), job("broken", "sleep 0.2; exit 7")];
+        let many = ManyOptions {
+            jobs: 0,
+            fail_fast: true,
+        };
+
+        let started = Instant::now();
+        let outcome = run_jobs(jobs, many, "Script").unwrap();
+        // 被取消的脚本不影响退出码，返回失败脚本的退出码
+        assert_eq!(outcome, ExitOutcome::Exited(7));
+        assert!(started.elapsed() < Duration::from_secs(4));
+        assert!(!marker.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[serial]
+    fn test_run_jobs_returns_first_failure() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let marker = temp_dir.path().join("finished");
+        let last = format!("touch '{}'", marker.display());
+        // 不启用 fail-fast 时全部运行完，返回列表中第一个失败脚本的退出码
+        let jobs = vec![
+            job("ok", "true"),
+            job("late", "sleep 0.3; exit 3"),
+            job("early", "exit 5"),
+            job("last", &last),
+        ];
+        let outcome = run_jobs(jobs, ManyOptions::default(), "Script").unwrap();
+        assert_eq!(outcome, ExitOutcome::Exited(3));
+        assert!(marker.exists());
+
+        let jobs = vec![job("ok", "true"), job("fine", "exit 0")];
+        let outcome = run_jobs(jobs, ManyOptions::default(), "Script").unwrap();
+        assert_eq!(outcome, ExitOutcome::SUCCESS);
+    }
+}
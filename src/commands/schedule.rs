@@ -0,0 +1,193 @@
+use anyhow::{Result, bail};
+use tabled::{Table, Tabled, settings::Style};
+
+use crate::{
+    commands::run::{self, Prepared, RunOptions},
+    core::{
+        config::{Config, OutputFormat},
+        cron::CronExpr,
+        executor::DEFAULT_GRACE_PERIOD,
+        limits::ResourceLimits,
+        sandbox::SandboxPolicy,
+        schedule::{MissedPolicy, OverlapPolicy, Schedule, Schedules},
+    },
+    utils::{
+        cli::prompt_confirm,
+        shell,
+        time::{self, format_timestamp},
+    },
+};
+
+#[derive(Debug, Tabled)]
+struct ScheduleListInfo {
+    #[tabled(rename = "ID")]
+    id: u64,
+    #[tabled(rename = "Script")]
+    script: String,
+    #[tabled(rename = "Arguments")]
+    args: String,
+    #[tabled(rename = "Schedule")]
+    cron: String,
+    #[tabled(rename = "Next Run")]
+    next: String,
+    #[tabled(rename = "Missed")]
+    missed: String,
+    #[tabled(rename = "Overlap")]
+    overlap: String,
+}
+
+/// `rune schedule add` 的选项
+#[derive(Debug, Clone, Default)]
+pub struct ScheduleOptions {
+    /// 错过的运行的处理方式
+    pub missed: MissedPolicy,
+    /// 上一次运行尚未结束时的处理方式
+    pub overlap: OverlapPolicy,
+    /// 资源限制，同 `rune run --limit`
+    pub limits: ResourceLimits,
+    /// 沙箱策略，同 `rune run --sandbox`
+    pub sandbox: Option<SandboxPolicy>,
+}
+
+/// 添加定时任务
+///
+/// 添加前先解析脚本和插件，避免到了运行时间才发现无法运行
+pub fn add(
+    script: String,
+    cron: String,
+    repo: Option<String>,
+    options: ScheduleOptions,
+    args: Vec<String>,
+) -> Result<()> {
+    let cron = CronExpr::parse(&cron)?;
+    let run_options = RunOptions {
+        name: script.clone(),
+        extension: None,
+        plugin: None,
+        repo: repo.clone(),
+        grace_period: DEFAULT_GRACE_PERIOD.as_secs(),
+        no_input: true,
+        no_history: true,
+        log: None,
+        timeout: None,
+        retries: None,
+        retry_delay: None,
+        backoff: None,
+        lock: false,
+        lock_name: None,
+        lock_wait: None,
+        limits: options.limits,
+        sandbox: options.sandbox.clone(),
+        allow_unsigned: false,
+        background: None,
+        args: args.clone(),
+    };
+    if let Prepared::Help(_) = run::prepare(&run_options, false)? {
+        bail!("Scheduled runs cannot pass '--help' to '{}'", script);
+    }
+
+    let mut schedules = Schedules::load()?;
+    let id = schedules.add(Schedule {
+        id: 0,
+        script,
+        cron,
+        args,
+        repo,
+        missed: options.missed,
+        overlap: options.overlap,
+        created: time::now(),
+        limits: options.limits,
+        sandbox: options.sandbox,
+    });
+    schedules.save()?;
+    let schedule = &schedules.schedules()[schedules.schedules().len() - 1];
+    println!(
+        "Schedule #{} added: {} at '{}' (next run: {})",
+        id,
+        command_line(schedule),
+        schedule.cron,
+        next_run(schedule)
+    );
+    println!("Schedules only fire while 'rune daemon' is running");
+    Ok(())
+}
+
+/// 列出定时任务
+pub fn list(plain: bool) -> Result<()> {
+    let schedules = Schedules::load()?;
+    if schedules.schedules().is_empty() {
+        println!("No schedules found");
+        return Ok(());
+    }
+    let plain = plain || Config::load()?.output.format == OutputFormat::Plain;
+    if plain {
+        for schedule in schedules.schedules() {
+            println!(
+                "{}\t{}\t{}",
+                schedule.id,
+                schedule.cron,
+                command_line(schedule)
+            );
+        }
+        return Ok(());
+    }
+
+    let rows: Vec<ScheduleListInfo> = schedules
+        .schedules()
+        .iter()
+        .map(|schedule| ScheduleListInfo {
+            id: schedule.id,
+            script: match &schedule.repo {
+                Some(repo) => format!("{} ({})", schedule.script, repo),
+                None => schedule.script.clone(),
+            },
+            args: shell::join(&schedule.args),
+            cron: schedule.cron.to_string(),
+            next: next_run(schedule),
+            missed: schedule.missed.to_string(),
+            overlap: schedule.overlap.to_string(),
+        })
+        .collect();
+    let mut table = Table::new(rows);
+    table.with(Style::rounded());
+    println!("{}", table);
+    Ok(())
+}
+
+/// 删除定时任务
+pub fn remove(id: u64, yes: bool) -> Result<()> {
+    let mut schedules = Schedules::load()?;
+    let Some(schedule) = schedules.schedules().iter().find(|s| s.id == id) else {
+        bail!("Schedule #{} not found (see 'rune schedule list')", id);
+    };
+    if !yes && Config::load()?.confirm.remove {
+        let message = format!(
+            "Do you want to remove schedule #{} ({})?",
+            id,
+            command_line(schedule)
+        );
+        if !prompt_confirm(&message, false)? {
+            println!("Operation cancelled");
+            return Ok(());
+        }
+    }
+    schedules.remove(id)?;
+    schedules.save()?;
+    println!("Schedule #{} removed successfully", id);
+    Ok(())
+}
+
+/// 定时任务运行的命令行
+fn command_line(schedule: &Schedule) -> String {
+    let mut words = vec![schedule.script.clone()];
+    words.extend(schedule.args.iter().cloned());
+    shell::join(&words)
+}
+
+/// 下一次运行时间的描述
+fn next_run(schedule: &Schedule) -> String {
+    schedule
+        .next_after(time::now())
+        .map(format_timestamp)
+        .unwrap_or_else(|| "never".to_string())
+}
@@ -1,42 +1,214 @@
 use crate::{
-    core::{executor::CommandExecutor, script::load_scripts},
-    utils::{cli::prompt_confirm, fs::get_script_path},
+    core::{
+        config::{Config, OutputFormat},
+        executor::CommandExecutor,
+        metadata::ScriptMetadata,
+        plugin::PluginDatabase,
+        preferences::PluginPreferences,
+        repo::{RepoScript, RepoSet},
+        script::{Script, namespace_dir},
+        signing::{self, Verification},
+        trust::{self, Trust, TrustStore},
+    },
+    utils::{
+        cli::{Color, paint, prompt_confirm, warn},
+        duration::format_duration,
+    },
 };
 use anyhow::{Context, Result, bail};
-use std::{env, fs, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
 use tabled::{Table, Tabled, settings::Style};
 
 #[derive(Debug, Tabled)]
 struct ScriptListInfo {
     #[tabled(rename = "Index")]
     index: usize,
+    #[tabled(rename = "Repository")]
+    repo: String,
+    #[tabled(rename = "Namespace")]
+    namespace: String,
     #[tabled(rename = "Name")]
     name: String,
     #[tabled(rename = "Extension")]
     extension: String,
+    #[tabled(rename = "Description")]
+    description: String,
+    #[tabled(rename = "Tags")]
+    tags: String,
+}
+
+/// 树形显示中的目录节点
+#[derive(Default)]
+struct TreeNode {
+    /// 子命名空间
+    children: BTreeMap<String, TreeNode>,
+    /// 该命名空间下的脚本（文件名, 描述）
+    scripts: Vec<(String, String)>,
 }
 
-pub fn list(plain: bool) -> Result<()> {
-    let script_path = get_script_path().context("Failed to get script path")?;
-    let scripts = load_scripts(script_path)?;
+impl TreeNode {
+    fn insert(&mut self, namespace: &str, file_name: String, description: String) {
+        let mut node = self;
+        for part in namespace.split('/').filter(|p| !p.is_empty()) {
+            node = node.children.entry(part.to_string()).or_default();
+        }
+        node.scripts.push((file_name, description));
+    }
+
+    fn render(&self, prefix: &str, out: &mut String) {
+        let total = self.children.len() + self.scripts.len();
+        let entries = self
+            .children
+            .iter()
+            .map(|(name, node)| (format!("{}/", name), String::new(), Some(node)))
+            .chain(
+                self.scripts
+                    .iter()
+                    .map(|(name, desc)| (name.clone(), desc.clone(), None)),
+            );
+        for (i, (label, description, child)) in entries.enumerate() {
+            let last = i + 1 == total;
+            let branch = if last { "└── " } else { "├── " };
+            out.push_str(prefix);
+            out.push_str(branch);
+            out.push_str(&label);
+            if !description.is_empty() {
+                out.push_str(&format!("  - {}", description));
+            }
+            out.push('\n');
+            if let Some(child) = child {
+                let next = format!("{}{}", prefix, if last { "    " } else { "│   " });
+                child.render(&next, out);
+            }
+        }
+    }
+}
+
+pub fn list(
+    plain: bool,
+    tree: bool,
+    format: Option<OutputFormat>,
+    repo: Option<String>,
+) -> Result<()> {
+    // 命令行选项优先于配置中的默认格式
+    let format = match (plain, tree, format) {
+        (true, _, _) => OutputFormat::Plain,
+        (_, true, _) => OutputFormat::Tree,
+        (_, _, Some(format)) => format,
+        _ => Config::load()?.output.format,
+    };
+    let scripts = RepoSet::discover()?.scripts(repo.as_deref())?;
     if scripts.is_empty() {
         println!("No script found");
         return Ok(());
     }
-    if plain {
-        for script in scripts {
-            println!("{}", script.name);
+    if format == OutputFormat::Plain {
+        // 被遮蔽的脚本无法直接通过名称运行，不在简洁模式中列出
+        for entry in scripts.iter().filter(|s| !s.shadowed) {
+            println!("{}", entry.script.name);
+        }
+        return Ok(());
+    }
+
+    let db = PluginDatabase::load()?;
+    let store = TrustStore::load()?;
+    let signing = Config::load()?.signing;
+    // 需要信任而尚未信任（或信任后被修改）的脚本加上标记；要求签名时按签名判断
+    let untrusted = |entry: &RepoScript| {
+        if !trust::required(&entry.repo) {
+            return None;
+        }
+        if signing.enforced() {
+            return match signing::verify_file(
+                &entry.script.path,
+                &signing::script_name(&entry.script),
+                &signing,
+            ) {
+                Ok(Verification::Verified(_)) => None,
+                Ok(Verification::Unsigned) => Some("(unsigned)"),
+                _ => Some("(bad signature)"),
+            };
+        }
+        match store.check(&entry.script.path) {
+            Ok(Trust::Trusted) => None,
+            Ok(Trust::Changed) => Some("(changed)"),
+            _ => Some("(untrusted)"),
+        }
+    };
+    // 元数据无效时仍然列出脚本，只给出警告
+    let load_metadata = |script: &Script| {
+        ScriptMetadata::for_script(script, &db).unwrap_or_else(|e| {
+            warn(format!("{:#}", e));
+            ScriptMetadata::default()
+        })
+    };
+    if format == OutputFormat::Tree {
+        // 每个仓库一棵树，按优先级排列
+        let mut trees: Vec<(String, TreeNode)> = Vec::new();
+        for entry in &scripts {
+            let title = format!("{} ({})", entry.repo.name(), entry.repo.path.display());
+            if trees.last().is_none_or(|(t, _)| *t != title) {
+                trees.push((title, TreeNode::default()));
+            }
+            let script = &entry.script;
+            let mut file_name = match script.extension.as_str() {
+                "" => script.base_name().to_string(),
+                ext => format!("{}.{}", script.base_name(), ext),
+            };
+            if entry.shadowed {
+                file_name.push(' ');
+                file_name.push_str(&paint("(shadowed)", Color::Dim));
+            }
+            if let Some(mark) = untrusted(entry) {
+                file_name.push(' ');
+                file_name.push_str(&paint(mark, Color::Yellow));
+            }
+            if let Some((_, root)) = trees.last_mut() {
+                root.insert(
+                    script.namespace(),
+                    file_name,
+                    load_metadata(script).description,
+                );
+            }
+        }
+        let mut out = String::new();
+        for (i, (title, root)) in trees.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            out.push_str(title);
+            out.push('\n');
+            root.render("", &mut out);
         }
+        print!("{}", out);
     } else {
         let scripts: Vec<ScriptListInfo> = scripts
             .iter()
             .enumerate()
-            .filter_map(|(index, entry)| {
-                Some(ScriptListInfo {
+            .map(|(index, entry)| {
+                let script = &entry.script;
+                let metadata = load_metadata(script);
+                let mut repo = entry.repo.name();
+                if entry.shadowed {
+                    repo.push_str(" (shadowed)");
+                }
+                if let Some(mark) = untrusted(entry) {
+                    repo.push(' ');
+                    repo.push_str(mark);
+                }
+                ScriptListInfo {
                     index,
-                    name: entry.name.clone(),
-                    extension: entry.extension.clone(),
-                })
+                    repo,
+                    namespace: script.namespace().to_string(),
+                    name: script.base_name().to_string(),
+                    extension: script.extension.clone(),
+                    description: metadata.description,
+                    tags: metadata.tags.join(", "),
+                }
             })
             .collect();
         let mut table = Table::new(scripts);
@@ -45,7 +217,12 @@ pub fn list(plain: bool) -> Result<()> {
     };
     Ok(())
 }
-pub fn add(path: PathBuf, force: bool) -> Result<()> {
+pub fn add(
+    path: PathBuf,
+    force: bool,
+    into: Option<String>,
+    repo: Option<String>,
+) -> Result<()> {
     // 检查源文件是否存在
     if !path.exists() {
         bail!("Target script does not exist: {}", path.display());
@@ -58,12 +235,16 @@ pub fn add(path: PathBuf, force: bool) -> Result<()> {
 
     let file_name = path.file_name().context("Failed to get file name")?;
 
-    let script_path = get_script_path().context("Failed to get script path")?;
+    let repos = RepoSet::discover()?;
+    let repo = repos.writable(repo.as_deref())?;
 
-    let target_path = script_path.join(file_name);
+    let target_dir = namespace_dir(&repo.path, into.as_deref().unwrap_or_default())?;
+    fs::create_dir_all(&target_dir)
+        .with_context(|| format!("Failed to create directory: {}", target_dir.display()))?;
+    let target_path = target_dir.join(file_name);
 
-    // 如果目标已存在且没有 force 标志，询问用户
-    if target_path.exists() && !force {
+    // 如果目标已存在且没有 force 标志，按配置询问用户
+    if target_path.exists() && !force && Config::load()?.confirm.overwrite {
         let message = format!(
             "Script '{}' already exists. Overwrite?",
             file_name.to_string_lossy()
@@ -86,26 +267,19 @@ pub fn add(path: PathBuf, force: bool) -> Result<()> {
 
     Ok(())
 }
-pub fn remove(name: String, yes: bool, extension: Option<String>) -> Result<()> {
-    let script_path = get_script_path()?;
-    let scripts = load_scripts(script_path)?;
-
-    // 查找匹配的脚本
-    let script = scripts
-        .into_iter()
-        .find(|s| {
-            s.name == name && (extension.is_none() || extension.as_ref() == Some(&s.extension))
-        })
-        .ok_or_else(|| {
-            if let Some(ext) = &extension {
-                anyhow::anyhow!("Script '{}.{}' not found", name, ext)
-            } else {
-                anyhow::anyhow!("Script '{}' not found", name)
-            }
-        })?;
+pub fn remove(
+    name: String,
+    yes: bool,
+    extension: Option<String>,
+    repo: Option<String>,
+) -> Result<()> {
+    // 查找匹配的脚本，共享仓库中的脚本不能删除
+    let RepoScript { repo, script, .. } =
+        RepoSet::discover()?.find(&name, extension.as_deref(), repo.as_deref())?;
+    repo.ensure_writable()?;
 
     // 确认删除
-    if !yes {
+    if !yes && Config::load()?.confirm.remove {
         let display_name = if script.extension.is_empty() {
             script.name.clone()
         } else {
@@ -122,6 +296,9 @@ pub fn remove(name: String, yes: bool, extension: Option<String>) -> Result<()>
     // 删除文件
     fs::remove_file(&script.path)
         .with_context(|| format!("Failed to delete script: {}", script.path.display()))?;
+    if let Some(parent) = script.path.parent() {
+        remove_empty_dirs(&repo.path, parent);
+    }
 
     let display_name = if script.extension.is_empty() {
         script.name
@@ -132,9 +309,120 @@ pub fn remove(name: String, yes: bool, extension: Option<String>) -> Result<()>
 
     Ok(())
 }
-pub fn new(name: String) -> Result<()> {
-    let script_path = get_script_path()?;
-    let path = script_path.join(name.clone());
+/// 显示脚本详情和元数据
+pub fn info(name: String, extension: Option<String>, repo: Option<String>) -> Result<()> {
+    let RepoScript {
+        repo,
+        script,
+        shadowed,
+    } = RepoSet::discover()?.find(&name, extension.as_deref(), repo.as_deref())?;
+    let db = PluginDatabase::load()?;
+    let metadata = ScriptMetadata::for_script(&script, &db)?;
+    let prefs = PluginPreferences::load()?;
+
+    println!("Script: {}", script.file_name());
+    if !metadata.description.is_empty() {
+        println!("Description: {}", metadata.description);
+    }
+    println!();
+    println!("Path: {}", script.path.display());
+    if shadowed {
+        println!("Repository: {} (shadowed)", repo.name());
+    } else {
+        println!("Repository: {}", repo.name());
+    }
+    // 要求签名时，有效的签名代替信任记录
+    let signing = Config::load()?.signing;
+    let signature = signing
+        .enforced()
+        .then(|| signing::verify_file(&script.path, &signing::script_name(&script), &signing))
+        .transpose()?;
+    if let Some(signature) = &signature {
+        println!("Signature: {}", signature);
+    }
+    match trust::status(&repo, &script.path)? {
+        Trust::Implicit => {}
+        _ if signature.as_ref().is_some_and(Verification::verified) => {}
+        status => println!("Trust: {}", status),
+    }
+    if let Some(interpreter) = &script.interpreter {
+        println!("Interpreter: {}", interpreter);
+    }
+    if !metadata.tags.is_empty() {
+        println!("Tags: {}", metadata.tags.join(", "));
+    }
+    if let Some(plugin) = prefs.pinned_for_script(&script.file_name()) {
+        println!("Pinned Plugin: {}", plugin);
+    }
+    if let Some(plugin) = &metadata.plugin {
+        println!("Plugin: {}", plugin);
+    }
+    if let Some(timeout) = metadata.timeout() {
+        println!("Timeout: {}", format_duration(timeout));
+    }
+    if let Some(retries) = metadata.retries {
+        println!("Retries: {}", retries);
+    }
+    if let Some(delay) = metadata.retry_delay() {
+        println!("Retry Delay: {}", format_duration(delay));
+    }
+    if let Some(backoff) = metadata.backoff {
+        println!("Backoff: x{}", backoff);
+    }
+    if !metadata.limits.is_empty() {
+        println!("Limits: {}", metadata.limits);
+    }
+    if let Some(sandbox) = &metadata.sandbox {
+        println!("Sandbox: {}", sandbox);
+    }
+    if metadata.exclusive || metadata.lock.is_some() {
+        println!(
+            "Lock: {} ({})",
+            metadata.lock.as_deref().unwrap_or(&script.name),
+            metadata.lock_wait().unwrap_or_default()
+        );
+    }
+    if !metadata.env.is_empty() {
+        println!();
+        println!("Environment:");
+        for (key, value) in &metadata.env {
+            println!("  {}={}", key, value);
+        }
+    }
+    if !metadata.args.is_empty() {
+        println!();
+        println!("Arguments ({}):", metadata.arg_style);
+        let width = metadata
+            .args
+            .iter()
+            .map(|a| a.label().len())
+            .max()
+            .unwrap_or(0);
+        for arg in &metadata.args {
+            println!("  {:width$}  {}", arg.label(), arg.summary(), width = width);
+        }
+    }
+    Ok(())
+}
+/// 删除脚本后清理变空的命名空间目录（不包括仓库根目录）
+fn remove_empty_dirs(root: &Path, dir: &Path) {
+    let mut dir = dir;
+    while dir != root && dir.starts_with(root) {
+        // 目录非空时删除失败，停止向上清理
+        if fs::remove_dir(dir).is_err() {
+            break;
+        }
+        let Some(parent) = dir.parent() else {
+            break;
+        };
+        dir = parent;
+    }
+}
+pub fn new(name: String, repo: Option<String>) -> Result<()> {
+    let repos = RepoSet::discover()?;
+    let repo = repos.writable(repo.as_deref())?;
+    let (namespace, file_name) = name.rsplit_once('/').unwrap_or(("", &name));
+    let path = namespace_dir(&repo.path, namespace)?.join(file_name);
     if path.exists() {
         let message = format!("Script '{}' already exists. Do you want to open it?", name);
         if !prompt_confirm(&message, true)? {
@@ -142,48 +430,79 @@ pub fn new(name: String) -> Result<()> {
             return Ok(());
         }
     } else {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
         fs::File::create(&path)
             .with_context(|| format!("Failed to create script: {}", path.display()))?;
         println!("Created new script:{}", name);
     }
-    let editor = env::var("EDITOR").unwrap_or_else(|_| {
-        if cfg!(windows) {
-            String::from("notepad")
-        } else {
-            String::from("nano")
-        }
-    });
+    open_in_editor(&name, &path)
+}
+pub fn edit(name: String, extension: Option<String>, repo: Option<String>) -> Result<()> {
+    let RepoScript { repo, script, .. } =
+        RepoSet::discover()?.find(&name, extension.as_deref(), repo.as_deref())?;
+    repo.ensure_writable()?;
+    open_in_editor(&name, &script.path)
+}
+/// 用配置的编辑器打开脚本
+fn open_in_editor(name: &str, path: &Path) -> Result<()> {
+    let editor = Config::load()?.editor();
+    // 编辑器可以带参数，如 "code --wait"
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().context("Editor command is empty")?;
     println!("Opening '{}' in {}...", name, editor);
-    CommandExecutor::new(&editor)
+    CommandExecutor::new(program)
+        .args(parts.map(String::from))
         .arg(path.to_string_lossy().to_string())
         .execute()?;
     Ok(())
 }
-pub fn edit(name: String, extension: Option<String>) -> Result<()> {
-    let script_path = get_script_path()?;
-    let scripts = load_scripts(script_path)?;
-    let script = scripts
-        .into_iter()
-        .find(|s| {
-            s.name == name && (extension.is_none() || extension.as_ref() == Some(&s.extension))
-        })
-        .ok_or_else(|| {
-            if let Some(ext) = &extension {
-                anyhow::anyhow!("Script '{}.{}' not found", name, ext)
-            } else {
-                anyhow::anyhow!("Script '{}' not found", name)
-            }
-        })?;
-    let editor = env::var("EDITOR").unwrap_or_else(|_| {
-        if cfg!(windows) {
-            String::from("notepad")
-        } else {
-            String::from("nano")
+/// 固定、查看或移除脚本使用的插件
+pub fn pin(
+    name: String,
+    plugin: Option<String>,
+    extension: Option<String>,
+    unset: bool,
+) -> Result<()> {
+    let script = RepoSet::discover()?
+        .find(&name, extension.as_deref(), None)?
+        .script;
+    let key = script.file_name();
+    let mut prefs = PluginPreferences::load()?;
+
+    if unset {
+        if prefs.scripts.remove(&key).is_none() {
+            bail!("Script '{}' is not pinned to a plugin", key);
         }
-    });
-    println!("Opening '{}' in {}...", name, editor);
-    CommandExecutor::new(&editor)
-        .arg(script.path.to_string_lossy().to_string())
-        .execute()?;
+        prefs.save()?;
+        println!("Plugin pin for '{}' removed", key);
+        return Ok(());
+    }
+
+    let Some(plugin) = plugin else {
+        match prefs.pinned_for_script(&key) {
+            Some(plugin) => println!("{}", plugin),
+            None => println!("Script '{}' is not pinned to a plugin", key),
+        }
+        return Ok(());
+    };
+
+    let db = PluginDatabase::load()?;
+    let found = db
+        .get_plugin(&plugin)
+        .ok_or_else(|| anyhow::anyhow!("Plugin '{}' not found", plugin))?;
+    let supports_interpreter = script
+        .interpreter
+        .as_deref()
+        .is_some_and(|i| found.matches_interpreter(i));
+    if !found.extensions.contains(&script.extension) && !supports_interpreter {
+        bail!("Plugin '{}' does not support script '{}'", plugin, key);
+    }
+
+    prefs.scripts.insert(key.clone(), plugin.clone());
+    prefs.save()?;
+    println!("Script '{}' pinned to plugin '{}'", key, plugin);
     Ok(())
 }
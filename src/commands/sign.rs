@@ -0,0 +1,82 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::core::{
+    config::Config,
+    repo::RepoSet,
+    signing::{self, KeyPair},
+};
+
+/// 签名脚本或插件文件，或生成、查看签名用的私钥
+///
+/// 目标是已存在的文件路径时直接签名，否则按脚本名称在仓库中查找
+pub fn sign(
+    targets: Vec<String>,
+    key: Option<PathBuf>,
+    generate: bool,
+    name: Option<String>,
+    show_key: bool,
+) -> Result<()> {
+    let key_path = match key {
+        Some(path) => path,
+        None => Config::load()?.signing.key_path()?,
+    };
+
+    if generate {
+        let name = name
+            .or_else(|| env::var("USER").ok())
+            .context("Could not determine the signer name; pass it with '--name'")?;
+        let pair = KeyPair::generate(&name)?;
+        pair.save(&key_path)?;
+        println!(
+            "Generated signing key for '{}': {}",
+            name,
+            key_path.display()
+        );
+        println!("Public key: {}", pair.public_key());
+        println!("To trust it, run on each machine:");
+        println!(
+            "  rune config set signing.trusted_keys.{} {}",
+            name.split(['@', '.', ' ']).next().unwrap_or(&name),
+            pair.public_key()
+        );
+        return Ok(());
+    }
+
+    let pair = KeyPair::load(&key_path)?;
+    if show_key {
+        println!("{}", pair.public_key());
+        return Ok(());
+    }
+
+    // 签名包含文件在仓库中的相对路径，复制或改名后的脚本无法通过验证
+    let repos = RepoSet::discover()?;
+    for target in targets {
+        let (path, name) = if Path::new(&target).is_file() {
+            let path = PathBuf::from(&target);
+            let name = repos
+                .repos()
+                .iter()
+                .find_map(|repo| signing::name_in(&repo.path, &path))
+                .unwrap_or_else(|| signing::file_name(&path));
+            (path, name)
+        } else {
+            let script = repos.find(&target, None, None)?.script;
+            let name = signing::script_name(&script);
+            (script.path, name)
+        };
+        let signature = pair.sign_file(&path, &name)?;
+        println!(
+            "Signed {} as '{}' by '{}' ({})",
+            path.display(),
+            name,
+            pair.name,
+            signature.display()
+        );
+    }
+    Ok(())
+}
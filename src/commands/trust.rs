@@ -0,0 +1,49 @@
+use anyhow::Result;
+
+use crate::core::{
+    repo::RepoSet,
+    trust::{self, TrustStore},
+};
+
+/// 信任脚本的当前内容，或撤销信任
+///
+/// 项目仓库和共享仓库中的脚本需要信任后才能运行，修改内容后需要重新信任
+pub fn trust(name: &str, extension: Option<&str>, repo: Option<&str>, revoke: bool) -> Result<()> {
+    let found = RepoSet::discover()?.find(name, extension, repo)?;
+    let script = &found.script;
+    let mut store = TrustStore::load()?;
+    if revoke {
+        if store.revoke(&script.path) {
+            store.save()?;
+            println!(
+                "Revoked trust in script '{}' ({})",
+                script.name,
+                script.path.display()
+            );
+        } else {
+            println!("Script '{}' was not trusted", script.name);
+        }
+        return Ok(());
+    }
+    if !trust::required(&found.repo) {
+        println!(
+            "Script '{}' is in your user repository and is always trusted",
+            script.name
+        );
+        return Ok(());
+    }
+
+    let hash = store.trust(&script.path)?;
+    store.save()?;
+    println!(
+        "Trusted script '{}' from repository '{}' ({})",
+        script.name,
+        found.repo.name(),
+        script.path.display()
+    );
+    println!("SHA-256: {}", hash);
+    if script.is_workflow() {
+        println!("The scripts run by its steps need to be trusted separately");
+    }
+    Ok(())
+}
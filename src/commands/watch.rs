@@ -0,0 +1,170 @@
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use anyhow::{Result, bail};
+
+use crate::{
+    commands::{
+        run::{self, Invocation, Prepared, RunOptions},
+        run_many::describe_outcome,
+    },
+    core::{
+        config::Config,
+        executor::ExitOutcome,
+        repo::RepoSet,
+        watch::{Watcher, display_path},
+    },
+    utils::cli::{Color, is_interactive, paint},
+};
+
+/// 检查文件变化和终止信号的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// `rune run --watch` 的选项
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    /// 除脚本文件外还要监视的文件或目录
+    pub paths: Vec<PathBuf>,
+    /// 每次运行前清屏
+    pub clear: bool,
+    /// 收到变化后等待这么久没有新变化才重新运行
+    pub debounce: Duration,
+}
+
+/// 正在后台运行的脚本
+struct Active {
+    cancel: Arc<AtomicBool>,
+    thread: JoinHandle<Result<ExitOutcome>>,
+}
+
+impl Active {
+    /// 终止运行并等待结束
+    fn stop(self) {
+        self.cancel.store(true, Ordering::SeqCst);
+        let _ = self.thread.join();
+    }
+}
+
+/// 运行脚本，并在脚本文件或指定路径变化时重新运行
+///
+/// 变化时仍在运行的脚本会先被终止（与终止信号的处理方式相同）。
+/// 脚本不读取终端输入，Ctrl-C 结束监视
+pub fn watch(options: RunOptions, watch: WatchOptions) -> Result<ExitOutcome> {
+    let mut interactive = !options.no_input && is_interactive();
+    #[cfg(unix)]
+    let guard = crate::core::signal::ForwardGuard::install()
+        .map_err(|e| anyhow::anyhow!(e).context("安装信号处理器失败"))?;
+
+    // 脚本本身无法找到时没有可监视的文件，直接报错
+    let script = RepoSet::discover()?
+        .find(
+            &options.name,
+            options.extension.as_deref(),
+            options.repo.as_deref(),
+        )?
+        .script;
+    let mut paths = vec![script.path];
+    paths.extend(watch.paths.iter().cloned());
+    let mut watcher = Watcher::new(&paths)?;
+
+    loop {
+        if watch.clear {
+            // 清屏并清除回滚缓冲区
+            print!("\x1b[2J\x1b[3J\x1b[H");
+            let _ = io::stdout().flush();
+        }
+        // 每次运行前重新解析，使脚本元数据和插件的修改生效；
+        // 解析失败（如元数据有误）与运行失败一样报告，并继续等待变化
+        let prepared = run::prepare(&options, interactive);
+        // 插件只在第一次运行时交互选择
+        interactive = false;
+        let mut active = match prepared {
+            Ok(Prepared::Ready(invocation)) => Some(start(*invocation, &options)),
+            Ok(Prepared::Help(usage)) => {
+                print!("{}", usage);
+                return Ok(ExitOutcome::SUCCESS);
+            }
+            Ok(Prepared::Workflow(_)) => bail!("--watch cannot be used with workflows"),
+            Err(e) => {
+                report(Ok(Err(e)));
+                None
+            }
+        };
+
+        // 等待文件变化；脚本结束后报告结果并继续等待
+        let changed = loop {
+            #[cfg(unix)]
+            if let Some(signal) = guard.take_pending() {
+                if let Some(active) = active.take() {
+                    active.stop();
+                }
+                return Ok(ExitOutcome::Signaled(signal));
+            }
+            if active.as_ref().is_some_and(|a| a.thread.is_finished())
+                && let Some(finished) = active.take()
+            {
+                report(finished.thread.join());
+            }
+            let changed = watcher.wait(POLL_INTERVAL)?;
+            if !changed.is_empty() {
+                break watcher.debounce(changed, watch.debounce)?;
+            }
+        };
+
+        let names: Vec<String> = changed.iter().map(|p| display_path(p)).collect();
+        match active.take() {
+            Some(active) => {
+                eprintln!(
+                    "{}",
+                    paint(
+                        &format!("Changed: {}; restarting {}", names.join(", "), options.name),
+                        Color::Dim
+                    )
+                );
+                active.stop();
+            }
+            None => eprintln!(
+                "{}",
+                paint(
+                    &format!("Changed: {}; running {}", names.join(", "), options.name),
+                    Color::Dim
+                )
+            ),
+        }
+    }
+}
+
+/// 在后台线程中运行脚本
+fn start(mut invocation: Invocation, options: &RunOptions) -> Active {
+    let cancel = Arc::new(AtomicBool::new(false));
+    invocation.executor = invocation
+        .executor
+        .interactive(false)
+        .cancel_flag(Arc::clone(&cancel));
+    let options = options.clone();
+    let thread = std::thread::spawn(move || run::execute(&invocation, &options, &Config::load()?));
+    Active { cancel, thread }
+}
+
+/// 报告一次运行的结果
+fn report(result: std::thread::Result<Result<ExitOutcome>>) {
+    let message = match result {
+        Ok(Ok(outcome)) if outcome.success() => paint("Finished successfully", Color::Green),
+        Ok(Ok(outcome)) => paint(&format!("Failed ({})", describe_outcome(outcome)), Color::Red),
+        Ok(Err(e)) => format!("{} {:#}", paint("Error:", Color::Red), e),
+        Err(_) => paint("Run panicked", Color::Red),
+    };
+    eprintln!(
+        "{} {}",
+        message,
+        paint("(waiting for changes, Ctrl-C to stop)", Color::Dim)
+    );
+}
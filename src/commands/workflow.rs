@@ -0,0 +1,297 @@
+use anyhow::{Context, Result, bail};
+use tabled::{builder::Builder, settings::Style};
+
+use crate::{
+    commands::{
+        run::{self, Prepared, RunOptions},
+        run_many::{self, Job, ManyOptions},
+    },
+    core::{
+        executor::ExitOutcome,
+        lock::ScriptLock,
+        params,
+        repo::RepoScript,
+        script::Script,
+        signing,
+        trust::{self, Trust},
+        workflow::{When, Workflow},
+    },
+    utils::{cli::is_interactive, fs::get_locks_path},
+};
+
+/// 运行工作流的选项
+#[derive(Debug, Clone, Default)]
+pub struct WorkflowOptions {
+    /// 同时运行的步骤数和失败时的处理方式
+    pub many: ManyOptions,
+    /// 从该步骤开始运行（包括所有依赖它的步骤）
+    pub from_step: Option<String>,
+    /// 只运行这些步骤
+    pub only: Vec<String>,
+}
+
+impl WorkflowOptions {
+    /// 是否选择了部分步骤
+    pub fn selects_steps(&self) -> bool {
+        self.from_step.is_some() || !self.only.is_empty()
+    }
+}
+
+/// 运行工作流
+///
+/// 所有步骤的脚本都先解析完成，任何一个无法运行时都不启动。
+/// 依赖关系允许时步骤同时运行，某个步骤失败时跳过依赖它的步骤
+pub(crate) fn run(
+    found: RepoScript,
+    options: &RunOptions,
+    workflow_options: WorkflowOptions,
+) -> Result<ExitOutcome> {
+    let workflow = Workflow::load(&found.script.path)?;
+    if params::wants_help(&options.args) {
+        print!("{}", usage(&found.script, &workflow));
+        return Ok(ExitOutcome::SUCCESS);
+    }
+    // 步骤中的脚本在运行前各自检查
+    let trust = trust::status(&found.repo, &found.script.path)?;
+    let signature = signing::status(&found.repo, &found.script)?;
+    run::check_trust(
+        &found.repo,
+        &found.script,
+        trust,
+        signature.as_ref(),
+        options.allow_unsigned,
+    )?;
+    let interactive = !options.no_input && is_interactive();
+    let jobs = plan(&found, &workflow, options, &workflow_options, interactive)?;
+
+    // 命令行中的单实例锁作用于整个工作流
+    let lock = if options.lock || options.lock_name.is_some() || options.lock_wait.is_some() {
+        let name = options.lock_name.as_deref().unwrap_or(&found.script.name);
+        Some(ScriptLock::acquire(
+            &get_locks_path()?,
+            name,
+            &found.script.name,
+            options.lock_wait.unwrap_or_default(),
+        )?)
+    } else {
+        None
+    };
+    // 每个步骤的脚本都继承锁，rune 被强制结束时由仍在运行的步骤继续持有
+    let jobs = match &lock {
+        Some(lock) => jobs
+            .into_iter()
+            .map(|mut job| {
+                job.invocation.executor = job.invocation.executor.hold_lock(lock);
+                job
+            })
+            .collect(),
+        None => jobs,
+    };
+    run_many::run_jobs(jobs, workflow_options.many, "Step")
+}
+
+/// 打印工作流的执行计划而不运行
+///
+/// `shell` 为 true 时按执行顺序输出每个步骤的命令行
+pub(crate) fn explain(
+    found: RepoScript,
+    options: &RunOptions,
+    shell: bool,
+    workflow_options: WorkflowOptions,
+) -> Result<()> {
+    let workflow = Workflow::load(&found.script.path)?;
+    let jobs = plan(&found, &workflow, options, &workflow_options, false)?;
+    let order = job_order(&jobs);
+
+    if shell {
+        for index in order {
+            let job = &jobs[index];
+            let command = run::shell_command(&job.invocation);
+            println!("# {}", job.label);
+            if job.invocation.executor.get_current_dir().is_some() {
+                println!("({})", command);
+            } else {
+                println!("{}", command);
+            }
+        }
+        return Ok(());
+    }
+
+    println!("Workflow: {}", found.script.path.display());
+    if !workflow.description.is_empty() {
+        println!("Description: {}", workflow.description);
+    }
+    println!("Repository: {}", found.repo.name());
+    match (
+        signing::status(&found.repo, &found.script)?,
+        trust::status(&found.repo, &found.script.path)?,
+    ) {
+        (Some(signature), _) => println!("Signature: {}", signature),
+        (None, Trust::Implicit) => {}
+        (None, Trust::Trusted) => println!("Trust: {}", Trust::Trusted),
+        (None, trust) => println!(
+            "Trust: {} (run 'rune trust {}' to allow it)",
+            trust, found.script.name
+        ),
+    }
+    println!();
+    let mut table = Builder::default();
+    table.push_record(["Step", "Script", "Plugin", "Needs", "Condition"]);
+    for index in order {
+        let job = &jobs[index];
+        let needs: Vec<&str> = job.needs.iter().map(|&i| jobs[i].label.as_str()).collect();
+        let step = &workflow.steps[workflow.index(&job.label).unwrap_or_default()];
+        let mut condition = Vec::new();
+        if job.when != When::Success {
+            condition.push(format!("when {}", job.when));
+        }
+        if let Some(check) = &step.condition {
+            let state = if job.enabled { "met" } else { "not met" };
+            condition.push(format!("if {} ({})", check, state));
+        }
+        table.push_record([
+            job.label.clone(),
+            job.invocation.script.file_name(),
+            job.invocation.plugin.name.clone(),
+            needs.join(", "),
+            condition.join(", "),
+        ]);
+    }
+    let mut table = table.build();
+    table.with(Style::rounded());
+    println!("{}", table);
+    Ok(())
+}
+
+/// 工作流的用法说明
+pub(crate) fn usage(script: &Script, workflow: &Workflow) -> String {
+    let mut out = String::new();
+    if !workflow.description.is_empty() {
+        out.push_str(&workflow.description);
+        out.push_str("\n\n");
+    }
+    out.push_str(&format!(
+        "Usage: rune run {} [--from-step <STEP> | --only <STEP>,...]\n\nSteps:\n",
+        script.name
+    ));
+    let width = workflow
+        .steps
+        .iter()
+        .map(|step| step.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    for step in &workflow.steps {
+        let mut notes = Vec::new();
+        if step.script != step.name {
+            notes.push(format!("runs {}", step.script));
+        }
+        if !step.needs.is_empty() {
+            notes.push(format!("needs {}", step.needs.join(", ")));
+        }
+        if step.when != When::Success {
+            notes.push(format!("when {}", step.when));
+        }
+        if let Some(condition) = &step.condition {
+            notes.push(format!("if {}", condition));
+        }
+        let line = format!("  {:<width$}  {}", step.name, notes.join("; "));
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// 解析选择的步骤，生成调度任务
+///
+/// 未选择的步骤视为已经完成，依赖它们的步骤不再等待
+fn plan(
+    found: &RepoScript,
+    workflow: &Workflow,
+    options: &RunOptions,
+    workflow_options: &WorkflowOptions,
+    interactive: bool,
+) -> Result<Vec<Job>> {
+    if !options.args.is_empty() {
+        bail!(
+            "Workflow '{}' does not take arguments; set 'args' on its steps instead",
+            found.script.name
+        );
+    }
+    let selected = workflow.select(
+        workflow_options.from_step.as_deref(),
+        &workflow_options.only,
+    )?;
+    let deps = workflow.dependencies();
+    // 工作流步骤下标到任务下标的映射
+    let mut indices = vec![None; workflow.steps.len()];
+    for (job, step) in (0..workflow.steps.len())
+        .filter(|&i| selected[i])
+        .enumerate()
+    {
+        indices[step] = Some(job);
+    }
+    let mut jobs = Vec::new();
+    for (i, step) in workflow.steps.iter().enumerate() {
+        if !selected[i] {
+            continue;
+        }
+        let step_options = RunOptions {
+            name: step.script.clone(),
+            extension: None,
+            plugin: None,
+            repo: None,
+            lock: false,
+            lock_name: None,
+            lock_wait: None,
+            args: step.args.clone(),
+            ..options.clone()
+        };
+        let prepared = run::prepare(&step_options, interactive)
+            .with_context(|| format!("Failed to prepare step '{}'", step.name))?;
+        let mut invocation = match prepared {
+            Prepared::Ready(invocation) => *invocation,
+            Prepared::Help(_) => bail!("Step '{}' passes '--help' to '{}'", step.name, step.script),
+            Prepared::Workflow(_) => bail!(
+                "Step '{}' runs workflow '{}'; workflows cannot be nested",
+                step.name,
+                step.script
+            ),
+        };
+        invocation.executor = invocation
+            .executor
+            .envs(workflow.env.iter().chain(step.env.iter()));
+        let enabled = step.condition.as_ref().is_none_or(|condition| {
+            condition.evaluate(|name| {
+                step.env
+                    .get(name)
+                    .or_else(|| workflow.env.get(name))
+                    .cloned()
+                    .or_else(|| std::env::var(name).ok())
+            })
+        });
+        jobs.push(Job {
+            label: step.name.clone(),
+            options: step_options,
+            invocation,
+            needs: deps[i].iter().filter_map(|&need| indices[need]).collect(),
+            when: step.when,
+            enabled,
+        });
+    }
+    Ok(jobs)
+}
+
+/// 任务按依赖关系排序后的下标
+fn job_order(jobs: &[Job]) -> Vec<usize> {
+    let mut order = Vec::with_capacity(jobs.len());
+    let mut done = vec![false; jobs.len()];
+    while order.len() < jobs.len() {
+        for (index, job) in jobs.iter().enumerate() {
+            if !done[index] && job.needs.iter().all(|&need| done[need]) {
+                done[index] = true;
+                order.push(index);
+            }
+        }
+    }
+    order
+}
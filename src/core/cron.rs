@@ -0,0 +1,369 @@
+//! cron 表达式
+//!
+//! 支持标准的五个字段（分 时 日 月 星期）、`*`、列表、范围、步长、
+//! 月份和星期的英文缩写，以及 `@daily` 等别名。按本地时间匹配
+
+use std::fmt;
+
+use anyhow::{Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::time::{civil_from_days, local_offset};
+
+/// 查找下一次运行时间时最多向后查找的时长（秒），超过后视为不会再运行
+const SEARCH_LIMIT: u64 = 5 * 366 * 86400;
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// 解析后的 cron 表达式
+///
+/// 每个字段用位图表示允许的值。日和星期都有限制时，满足其中一个即可（与 cron 一致）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct CronExpr {
+    source: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+/// 某一时刻的本地时间字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LocalTime {
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    /// 0 表示星期日
+    weekday: u32,
+}
+
+impl LocalTime {
+    fn at(secs: u64, offset: i64) -> Self {
+        let local = secs as i64 + offset;
+        let days = local.div_euclid(86400);
+        let rem = local.rem_euclid(86400);
+        let (_, month, day) = civil_from_days(days);
+        Self {
+            month: month as u32,
+            day: day as u32,
+            hour: (rem / 3600) as u32,
+            minute: (rem % 3600 / 60) as u32,
+            // 1970-01-01 是星期四
+            weekday: (days + 4).rem_euclid(7) as u32,
+        }
+    }
+}
+
+impl CronExpr {
+    /// 解析 cron 表达式
+    pub fn parse(input: &str) -> Result<Self> {
+        let source = input.split_whitespace().collect::<Vec<_>>().join(" ");
+        let expanded = match source.as_str() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            other if other.starts_with('@') => bail!("Unknown schedule '{}'", other),
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split(' ').collect();
+        if fields.len() != 5 {
+            bail!(
+                "Invalid cron expression '{}' (expected 5 fields: minute hour day month weekday)",
+                source
+            );
+        }
+        let field = |index: usize, name: &str, min: u32, max: u32, names: &[&str]| {
+            parse_field(fields[index], min, max, names)
+                .map_err(|e| anyhow!("Invalid {} field in '{}': {}", name, source, e))
+        };
+        let minutes = field(0, "minute", 0, 59, &[])?;
+        let hours = field(1, "hour", 0, 23, &[])?;
+        let days = field(2, "day", 1, 31, &[])?;
+        let months = field(3, "month", 1, 12, &MONTH_NAMES)?;
+        let mut weekdays = field(4, "weekday", 0, 7, &WEEKDAY_NAMES)?;
+        // 7 也表示星期日
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            minutes,
+            hours,
+            days,
+            months,
+            weekdays,
+            any_day: fields[2].starts_with('*'),
+            any_weekday: fields[4].starts_with('*'),
+            source,
+        })
+    }
+
+    /// `after` 之后（不含）的第一次运行时间，按本地时间计算
+    pub fn next_after(&self, after: u64) -> Option<u64> {
+        self.next_after_with(after, local_offset)
+    }
+
+    /// 使用指定的时区偏移函数计算下一次运行时间
+    fn next_after_with(&self, after: u64, offset: impl Fn(i64) -> i64) -> Option<u64> {
+        let mut secs = (after / 60 + 1) * 60;
+        while secs <= after.saturating_add(SEARCH_LIMIT) {
+            let local = LocalTime::at(secs, offset(secs as i64));
+            if self.matches_local(local) {
+                return Some(secs);
+            }
+            // 日期或小时不匹配时直接跳到下一个小时
+            secs += if self.hour_matches(local) {
+                60
+            } else {
+                u64::from(60 - local.minute) * 60
+            };
+        }
+        None
+    }
+
+    /// `(after, until]` 内的运行次数，按本地时间计算
+    ///
+    /// 逐小时计算，不需要逐次查找每一次运行
+    pub fn count_between(&self, after: u64, until: u64) -> usize {
+        self.count_between_with(after, until, local_offset)
+    }
+
+    fn count_between_with(&self, after: u64, until: u64, offset: impl Fn(i64) -> i64) -> usize {
+        let mut count = 0;
+        let mut secs = (after / 60 + 1) * 60;
+        while secs <= until {
+            let local = LocalTime::at(secs, offset(secs as i64));
+            let rest = 60 - local.minute;
+            if self.hour_matches(local) {
+                // 这个小时内从当前分钟到 `until` 为止的分钟
+                let span = u64::from(rest).min((until - secs) / 60 + 1);
+                let mask = ((1u64 << span) - 1) << local.minute;
+                count += (self.minutes & mask).count_ones() as usize;
+            }
+            secs += u64::from(rest) * 60;
+        }
+        count
+    }
+
+    fn hour_matches(&self, local: LocalTime) -> bool {
+        self.months & (1 << local.month) != 0
+            && self.day_matches(local)
+            && self.hours & (1 << local.hour) != 0
+    }
+
+    fn matches_local(&self, local: LocalTime) -> bool {
+        self.minutes & (1 << local.minute) != 0 && self.hour_matches(local)
+    }
+
+    fn day_matches(&self, local: LocalTime) -> bool {
+        let day = self.days & (1 << local.day) != 0;
+        let weekday = self.weekdays & (1 << local.weekday) != 0;
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+}
+
+/// 解析一个字段，返回允许值的位图
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64> {
+    let value = |input: &str| -> Result<u32> {
+        let lower = input.to_ascii_lowercase();
+        if let Some(index) = names.iter().position(|name| *name == lower) {
+            // 月份从 1 开始，星期从 0 开始
+            return Ok(index as u32 + min);
+        }
+        let value: u32 = input
+            .parse()
+            .map_err(|_| anyhow!("'{}' is not a number", input))?;
+        if !(min..=max).contains(&value) {
+            bail!("{} is out of range {}-{}", value, min, max);
+        }
+        Ok(value)
+    };
+
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .map_err(|_| anyhow!("'{}' is not a valid step", step))?;
+                if step == 0 {
+                    bail!("step must be greater than 0");
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            let (start, end) = (value(start)?, value(end)?);
+            if start > end {
+                bail!("range {} is reversed", range);
+            }
+            (start, end)
+        } else {
+            let start = value(range)?;
+            // `5/15` 表示从 5 开始每 15 个
+            (start, if step > 1 { max } else { start })
+        };
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+impl TryFrom<String> for CronExpr {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        Self::parse(&value)
+    }
+}
+
+impl From<CronExpr> for String {
+    fn from(expr: CronExpr) -> Self {
+        expr.source
+    }
+}
+
+impl fmt::Display for CronExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-01-01 00:00:00 UTC，星期一
+    const JAN_1: u64 = 1_704_067_200;
+
+    fn next_utc(expr: &str, after: u64) -> Option<u64> {
+        CronExpr::parse(expr).unwrap().next_after_with(after, |_| 0)
+    }
+
+    #[test]
+    fn test_parse_fields() {
+        let expr = CronExpr::parse("*/15 9-17 * jan,JUL mon-fri").unwrap();
+        assert_eq!(expr.minutes, 1 | 1 << 15 | 1 << 30 | 1 << 45);
+        assert_eq!(expr.hours, (9..=17).fold(0, |bits, h| bits | 1 << h));
+        assert_eq!(expr.months, 1 << 1 | 1 << 7);
+        assert_eq!(expr.weekdays, (1..=5).fold(0, |bits, d| bits | 1 << d));
+        assert!(expr.any_day && !expr.any_weekday);
+
+        assert_eq!(CronExpr::parse("0 0 * * 7").unwrap().weekdays, 1);
+        assert_eq!(
+            CronExpr::parse("5/20 * * * *").unwrap().minutes,
+            1 << 5 | 1 << 25 | 1 << 45
+        );
+        assert_eq!(CronExpr::parse("  @daily ").unwrap().to_string(), "@daily");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for input in [
+            "* * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "* * * foo *",
+            "@sometimes",
+        ] {
+            assert!(CronExpr::parse(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_next_after() {
+        // 每天 03:00
+        assert_eq!(next_utc("0 3 * * *", JAN_1), Some(JAN_1 + 3 * 3600));
+        assert_eq!(
+            next_utc("0 3 * * *", JAN_1 + 3 * 3600),
+            Some(JAN_1 + 86400 + 3 * 3600)
+        );
+        // 不足一分钟的部分不影响结果
+        assert_eq!(next_utc("* * * * *", JAN_1 + 59), Some(JAN_1 + 60));
+        // 2024-01-01 是星期一，下一个星期日是 01-07
+        assert_eq!(
+            next_utc("30 12 * * sun", JAN_1),
+            Some(JAN_1 + 6 * 86400 + 45000)
+        );
+        // 日和星期都有限制时满足其一即可：01-05 或星期二（01-02）
+        assert_eq!(next_utc("0 0 5 * tue", JAN_1), Some(JAN_1 + 86400));
+        // 2024 是闰年
+        assert_eq!(next_utc("0 0 29 2 *", JAN_1), Some(JAN_1 + 59 * 86400));
+        // 永远不会匹配
+        assert_eq!(next_utc("0 0 31 2 *", JAN_1), None);
+    }
+
+    #[test]
+    fn test_next_after_uses_offset() {
+        // UTC+8 的 03:00 是 UTC 前一天 19:00
+        let expr = CronExpr::parse("0 3 * * *").unwrap();
+        assert_eq!(
+            expr.next_after_with(JAN_1, |_| 8 * 3600),
+            Some(JAN_1 + 19 * 3600)
+        );
+    }
+
+    #[test]
+    fn test_count_between() {
+        for expr in [
+            "* * * * *",
+            "*/7 9-17 * * mon-fri",
+            "0 3 * * *",
+            "0 0 29 2 *",
+        ] {
+            let cron = CronExpr::parse(expr).unwrap();
+            for (after, until) in [
+                (JAN_1, JAN_1 + 59),
+                (JAN_1 + 59, JAN_1 + 3 * 3600 + 61),
+                (JAN_1 - 1, JAN_1 + 10 * 86400 + 7),
+                (JAN_1 + 1234, JAN_1 + 70 * 86400),
+            ] {
+                // 与逐次查找的结果一致
+                let mut expected = 0;
+                let mut cursor = after;
+                while let Some(at) = cron
+                    .next_after_with(cursor, |_| 0)
+                    .filter(|&at| at <= until)
+                {
+                    expected += 1;
+                    cursor = at;
+                }
+                assert_eq!(
+                    cron.count_between_with(after, until, |_| 0),
+                    expected,
+                    "{} in ({}, {}]",
+                    expr,
+                    after,
+                    until
+                );
+            }
+        }
+        assert_eq!(
+            CronExpr::parse("* * * * *").unwrap().count_between_with(
+                JAN_1,
+                JAN_1 + 365 * 86400,
+                |_| 8 * 3600
+            ),
+            365 * 1440
+        );
+    }
+}
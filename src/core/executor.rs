@@ -1,15 +1,209 @@
-use std::process::{Command, Stdio};
+use std::fmt;
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 
+use crate::core::limits::{LimitHit, ResourceLimits};
+use crate::core::lock::ScriptLock;
+use crate::core::sandbox::Sandbox;
+use crate::core::logs::{RunLog, SharedLog, Tee};
+use crate::utils::{
+    duration::{format_duration, parse_duration},
+    shell,
+};
+
+/// 收到终止信号后，等待脚本自行退出的默认宽限期
+pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// 第一次重试前的默认等待时间
+pub const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// 因超时被终止时的退出码，与 GNU `timeout` 一致
+pub const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// 子进程的退出结果
+///
+/// 区分正常退出与被信号终止，便于调用方按 shell 约定转换为退出码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitOutcome {
+    /// 进程正常退出，附带退出码
+    Exited(i32),
+    /// 进程被信号终止（仅 Unix），附带信号编号
+    Signaled(i32),
+    /// 运行超时，进程组已被终止
+    TimedOut,
+}
+
+impl ExitOutcome {
+    /// 成功退出（退出码 0）
+    pub const SUCCESS: ExitOutcome = ExitOutcome::Exited(0);
+
+    /// 是否成功退出
+    pub fn success(&self) -> bool {
+        matches!(self, ExitOutcome::Exited(0))
+    }
+
+    /// 按 shell 约定转换后的退出码
+    ///
+    /// 被信号终止时返回 128 + 信号编号，超时返回 [`TIMEOUT_EXIT_CODE`]
+    pub fn code(&self) -> i32 {
+        match *self {
+            ExitOutcome::Exited(code) => code,
+            ExitOutcome::Signaled(signal) => 128 + signal,
+            ExitOutcome::TimedOut => TIMEOUT_EXIT_CODE,
+        }
+    }
+
+    /// 终止进程的信号编号（如果有）
+    pub fn signal(&self) -> Option<i32> {
+        match *self {
+            ExitOutcome::Signaled(signal) => Some(signal),
+            ExitOutcome::Exited(_) | ExitOutcome::TimedOut => None,
+        }
+    }
+
+    /// 是否因超时被终止
+    pub fn timed_out(&self) -> bool {
+        matches!(self, ExitOutcome::TimedOut)
+    }
+
+    /// 从标准库的退出状态转换
+    pub fn from_status(status: ExitStatus) -> Self {
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            if let Some(signal) = status.signal() {
+                return ExitOutcome::Signaled(signal);
+            }
+        }
+        ExitOutcome::Exited(status.code().unwrap_or(1))
+    }
+
+    /// 以该结果终止当前进程
+    ///
+    /// 子进程被信号终止时，会先尝试以相同信号重新终止自身，
+    /// 使父进程（shell、CI）观察到一致的终止原因；失败时回退为 128 + N 退出码
+    pub fn exit(self) -> ! {
+        #[cfg(unix)]
+        if let ExitOutcome::Signaled(signal) = self {
+            use std::io::Write;
+            let _ = std::io::stdout().flush();
+            let _ = std::io::stderr().flush();
+            // SAFETY: 恢复默认处理方式后向自身发送信号，不涉及任何 Rust 内存
+            unsafe {
+                libc::signal(signal, libc::SIG_DFL);
+                libc::raise(signal);
+            }
+        }
+        std::process::exit(self.code())
+    }
+}
+
+impl fmt::Display for ExitOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ExitOutcome::Exited(code) => write!(f, "退出码: {}", code),
+            ExitOutcome::Signaled(signal) => write!(f, "被信号 {} 终止", signal),
+            ExitOutcome::TimedOut => write!(f, "运行超时"),
+        }
+    }
+}
+
+/// 一次运行的结果
+struct Attempt {
+    outcome: ExitOutcome,
+    /// 是否收到并转发了用户的终止信号
+    interrupted: bool,
+    /// 子进程使用的 CPU 时间，无法获取时为 None
+    cpu_time: Option<Duration>,
+}
+
+/// 失败后的重试策略
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// 最多重试的次数，0 表示不重试
+    pub retries: u32,
+    /// 第一次重试前的等待时间
+    pub delay: Duration,
+    /// 每次重试后等待时间的倍数，1 表示固定间隔
+    pub backoff: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            delay: DEFAULT_RETRY_DELAY,
+            backoff: 1.0,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 第 `retry` 次重试（从 1 开始）前的等待时间
+    pub fn delay_before(&self, retry: u32) -> Duration {
+        let factor = self.backoff.powi(retry.saturating_sub(1) as i32);
+        Duration::try_from_secs_f64(self.delay.as_secs_f64() * factor).unwrap_or(Duration::MAX)
+    }
+
+    /// 解析重试间隔
+    pub fn parse_delay(input: &str) -> Result<Duration> {
+        parse_duration(input).context("Invalid retry delay")
+    }
+
+    /// 检查退避倍数
+    pub fn check_backoff(backoff: f64) -> Result<()> {
+        if !backoff.is_finite() || backoff < 1.0 {
+            anyhow::bail!("Invalid backoff {} (must be at least 1)", backoff);
+        }
+        Ok(())
+    }
+}
+
 /// 命令执行器
 ///
 /// 用于执行外部命令，支持参数传递和错误处理
+#[derive(Clone)]
 pub struct CommandExecutor {
     /// 要执行的命令
     command: String,
     /// 命令参数列表
     args: Vec<String>,
+    /// 转发终止信号后等待进程组退出的时间，超时后发送 SIGKILL
+    grace_period: Duration,
+    /// 运行超时时间，超时后按终止信号的方式结束进程组
+    timeout: Option<Duration>,
+    /// 是否清空继承的环境变量
+    env_clear: bool,
+    /// 需要移除的环境变量
+    env_remove: Vec<String>,
+    /// 需要设置的环境变量
+    envs: Vec<(String, String)>,
+    /// 工作目录，None 表示继承当前目录
+    current_dir: Option<PathBuf>,
+    /// 输出日志文件，设置后输出同时写入该文件
+    log_file: Option<PathBuf>,
+    /// 日志文件的大小上限（字节），超过后轮转
+    log_max_size: u64,
+    /// 失败后的重试策略
+    retry: RetryPolicy,
+    /// 输出行前缀，设置后逐行转发输出并加上该前缀
+    output_prefix: Option<String>,
+    /// 取消标志，置位后按终止信号的方式结束进程组且不再重试
+    cancel: Option<Arc<AtomicBool>>,
+    /// 是否让命令使用终端：继承 stdin 并接管终端前台
+    interactive: bool,
+    /// 资源限制
+    limits: ResourceLimits,
+    /// 沙箱设置，None 表示不使用沙箱
+    sandbox: Option<Sandbox>,
+    /// 由命令继承的单实例锁文件描述符
+    #[cfg(unix)]
+    lock_fd: Option<std::os::fd::RawFd>,
 }
 
 impl CommandExecutor {
@@ -21,6 +215,22 @@ impl CommandExecutor {
         Self {
             command: command.into(),
             args: Vec::new(),
+            grace_period: DEFAULT_GRACE_PERIOD,
+            timeout: None,
+            env_clear: false,
+            env_remove: Vec::new(),
+            envs: Vec::new(),
+            current_dir: None,
+            log_file: None,
+            log_max_size: u64::MAX,
+            retry: RetryPolicy::default(),
+            output_prefix: None,
+            cancel: None,
+            interactive: true,
+            limits: ResourceLimits::default(),
+            sandbox: None,
+            #[cfg(unix)]
+            lock_fd: None,
         }
     }
 
@@ -44,41 +254,634 @@ impl CommandExecutor {
         self
     }
 
+    /// 设置转发终止信号后的宽限期
+    ///
+    /// 支持链式调用
+    pub fn grace_period(mut self, grace_period: Duration) -> Self {
+        self.grace_period = grace_period;
+        self
+    }
+
+    /// 设置环境变量
+    ///
+    /// 支持链式调用
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.envs.push((key.into(), value.into()));
+        self
+    }
+
+    /// 设置多个环境变量
+    ///
+    /// 支持链式调用
+    pub fn envs<I, K, V>(mut self, vars: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.envs
+            .extend(vars.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// 从继承的环境中移除变量
+    ///
+    /// 支持链式调用
+    pub fn env_remove(mut self, key: impl Into<String>) -> Self {
+        self.env_remove.push(key.into());
+        self
+    }
+
+    /// 不继承当前进程的环境变量
+    ///
+    /// 支持链式调用
+    pub fn env_clear(mut self) -> Self {
+        self.env_clear = true;
+        self
+    }
+
+    /// 设置工作目录
+    ///
+    /// 支持链式调用
+    pub fn current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
+    /// 设置运行超时时间
+    ///
+    /// 超时后向进程组发送 SIGTERM，宽限期过后仍未退出则发送 SIGKILL
+    ///
+    /// 支持链式调用
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// 设置失败（非零退出、被信号终止或超时）后的重试策略
+    ///
+    /// 超时时间对每次运行分别计算；用户中断的运行不会重试
+    ///
+    /// 支持链式调用
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// 把输出同时写入日志文件
+    ///
+    /// stdout/stderr 仍实时输出到终端，但脚本看到的是管道而不是终端；
+    /// 日志超过 `max_size` 字节时轮转，见 [`RunLog`]
+    ///
+    /// 支持链式调用
+    pub fn log_file(mut self, path: impl Into<PathBuf>, max_size: u64) -> Self {
+        self.log_file = Some(path.into());
+        self.log_max_size = max_size;
+        self
+    }
+
+    /// 为每行输出加上前缀，用于同时运行多个命令
+    ///
+    /// 命令的 stdin 不再继承终端，也不会接管终端前台
+    ///
+    /// 支持链式调用
+    pub fn prefix_output(mut self, prefix: impl Into<String>) -> Self {
+        self.output_prefix = Some(prefix.into());
+        self
+    }
+
+    /// 设置取消标志，由其他线程置位以结束正在运行的命令
+    ///
+    /// 支持链式调用
+    pub fn cancel_flag(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// 设置命令是否使用终端
+    ///
+    /// 为 false 时 stdin 重定向到空设备，终端前台仍由 rune 持有，
+    /// 终端发出的 Ctrl-C 由 rune 接收后转发给命令
+    ///
+    /// 支持链式调用
+    pub fn interactive(mut self, interactive: bool) -> Self {
+        self.interactive = interactive;
+        self
+    }
+
+    /// 设置资源限制，在命令 exec 之前以 `setrlimit` 设置
+    ///
+    /// 支持链式调用
+    pub fn limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// 在沙箱中运行命令，在命令 exec 之前进入沙箱（仅 Linux）
+    ///
+    /// 支持链式调用
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    /// 让命令继承单实例锁，rune 被强制结束时锁由命令继续持有，直到命令退出
+    ///
+    /// 只有命令本身继承锁文件的描述符，rune 启动的其他进程不会继承
+    ///
+    /// 支持链式调用
+    pub fn hold_lock(mut self, lock: &ScriptLock) -> Self {
+        #[cfg(unix)]
+        {
+            use std::os::fd::AsRawFd;
+            self.lock_fd = Some(lock.as_raw_fd());
+        }
+        #[cfg(not(unix))]
+        let _ = lock;
+        self
+    }
+
+    /// 要执行的命令
+    pub fn get_program(&self) -> &str {
+        &self.command
+    }
+
+    /// 命令参数列表
+    pub fn get_args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// 是否清空继承的环境变量
+    pub fn get_env_clear(&self) -> bool {
+        self.env_clear
+    }
+
+    /// 需要从继承的环境中移除的变量
+    pub fn get_env_remove(&self) -> &[String] {
+        &self.env_remove
+    }
+
+    /// 需要设置的环境变量，按设置顺序排列，后设置的值覆盖先设置的值
+    pub fn get_envs(&self) -> &[(String, String)] {
+        &self.envs
+    }
+
+    /// 工作目录，None 表示继承当前目录
+    pub fn get_current_dir(&self) -> Option<&std::path::Path> {
+        self.current_dir.as_deref()
+    }
+
+    /// 宽限期
+    pub fn get_grace_period(&self) -> Duration {
+        self.grace_period
+    }
+
+    /// 运行超时时间
+    pub fn get_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// 重试策略
+    pub fn get_retry(&self) -> RetryPolicy {
+        self.retry
+    }
+
+    /// 输出日志文件
+    pub fn get_log_file(&self) -> Option<&std::path::Path> {
+        self.log_file.as_deref()
+    }
+
+    /// 输出行前缀
+    pub fn get_output_prefix(&self) -> Option<&str> {
+        self.output_prefix.as_deref()
+    }
+
+    /// 资源限制
+    pub fn get_limits(&self) -> ResourceLimits {
+        self.limits
+    }
+
+    /// 沙箱设置
+    pub fn get_sandbox(&self) -> Option<&Sandbox> {
+        self.sandbox.as_ref()
+    }
+
+    /// 是否已被取消
+    fn cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(Ordering::SeqCst))
+    }
+
     /// 检查命令是否在 PATH 中可用
     ///
     /// # 错误
     /// 如果命令不存在，返回错误
     pub fn check_available(&self) -> Result<()> {
-        which::which(&self.command)
-            .with_context(|| format!("命令 '{}' 未在 PATH 中找到", self.command))?;
+        self.resolve()?;
         Ok(())
     }
 
-    /// 执行命令
+    /// 在 PATH 中查找命令的绝对路径
     ///
-    /// 命令将继承当前进程的 stdin/stdout/stderr，支持交互式程序
+    /// 查找使用 rune 自身的 PATH，因此即使清空了子进程的环境也能找到执行器
+    pub fn resolve(&self) -> Result<PathBuf> {
+        which::which(&self.command)
+            .with_context(|| format!("命令 '{}' 未在 PATH 中找到", self.command))
+    }
+
+    /// 执行命令并返回退出结果
+    ///
+    /// 命令将继承当前进程的 stdin/stdout/stderr，支持交互式程序；设置了日志文件时
+    /// stdout/stderr 改为经由 rune 转发。
+    /// 非零退出码不视为错误，由调用方根据 [`ExitOutcome`] 自行处理
+    ///
+    /// 在 Unix 上，命令运行在独立的进程组中；rune 收到 SIGINT/SIGTERM/SIGHUP 时
+    /// 会转发给整个进程组，宽限期过后仍未退出则发送 SIGKILL
     ///
     /// # 错误
     /// - 命令不存在
     /// - 命令执行失败
-    /// - 命令返回非零退出码
-    pub fn execute(&self) -> Result<()> {
-        self.check_available()?;
+    pub fn status(&self) -> Result<ExitOutcome> {
+        let program = self.resolve()?;
 
-        let status = Command::new(&self.command)
+        let mut command = Command::new(program);
+        command
             .args(&self.args)
             .stdin(Stdio::inherit())
             .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit())
-            .status()
-            .with_context(|| format!("执行命令失败: {}", self.command))?;
+            .stderr(Stdio::inherit());
+        if self.env_clear {
+            command.env_clear();
+        }
+        for key in &self.env_remove {
+            command.env_remove(key);
+        }
+        command.envs(self.envs.iter().map(|(k, v)| (k, v)));
+        if let Some(dir) = &self.current_dir {
+            command.current_dir(dir);
+        }
+        let log = match &self.log_file {
+            Some(path) => {
+                let mut log = RunLog::create(path, self.log_max_size)?;
+                log.note(&format!(
+                    "started: {}",
+                    shell::join(std::iter::once(&self.command).chain(&self.args))
+                ));
+                command.stdout(Stdio::piped()).stderr(Stdio::piped());
+                Some(SharedLog::new(log))
+            }
+            None => None,
+        };
+        if self.output_prefix.is_some() {
+            command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
+        let interactive = self.interactive && self.output_prefix.is_none();
+        if !interactive {
+            command.stdin(Stdio::null());
+        }
+        #[cfg(unix)]
+        let foreground = Self::prepare_group(&mut command, interactive);
+        if !self.limits.is_empty() {
+            self.limits.check()?;
+            #[cfg(unix)]
+            Self::prepare_limits(&mut command, self.limits);
+        }
+        if let Some(sandbox) = &self.sandbox {
+            Self::prepare_sandbox(&mut command, sandbox)?;
+        }
+        #[cfg(unix)]
+        if let Some(fd) = self.lock_fd {
+            Self::prepare_lock(&mut command, fd);
+        }
 
-        if !status.success() {
-            anyhow::bail!(
-                "命令 '{}' 退出码: {}",
+        let mut retry = 0;
+        loop {
+            #[cfg(unix)]
+            let attempt = self.supervise(&mut command, foreground, log.as_ref())?;
+            #[cfg(not(unix))]
+            let attempt = self.wait_once(&mut command, log.as_ref())?;
+
+            if !attempt.interrupted
+                && let Some(hit) = self.limits.hit(attempt.outcome, attempt.cpu_time)
+            {
+                self.warn_limit(hit, attempt.outcome, log.as_ref());
+            }
+            // 用户中断或取消的运行不再重试
+            if attempt.outcome.success()
+                || attempt.interrupted
+                || self.cancelled()
+                || retry >= self.retry.retries
+            {
+                return Ok(attempt.outcome);
+            }
+            retry += 1;
+            let delay = self.retry.delay_before(retry);
+            crate::utils::cli::warn(format!(
+                "命令 '{}' {}，{} 后重试（{}/{}）",
                 self.command,
-                status.code().unwrap_or(-1)
-            );
+                attempt.outcome,
+                format_duration(delay),
+                retry,
+                self.retry.retries
+            ));
+            if let Some(log) = &log {
+                log.note(&format!(
+                    "retrying in {} ({}/{})",
+                    format_duration(delay),
+                    retry,
+                    self.retry.retries
+                ));
+            }
+            self.sleep(delay);
+        }
+    }
+
+    /// 等待重试，期间被取消时提前返回
+    fn sleep(&self, delay: Duration) {
+        let until = std::time::Instant::now() + delay;
+        while !self.cancelled() {
+            let now = std::time::Instant::now();
+            if now >= until {
+                break;
+            }
+            std::thread::sleep((until - now).min(Duration::from_millis(50)));
+        }
+    }
+
+    /// 提示命令因超时被终止
+    fn warn_timeout(&self, timeout: Duration) {
+        crate::utils::cli::warn(format!(
+            "命令 '{}' 运行超过 {}，正在终止",
+            self.command,
+            format_duration(timeout)
+        ));
+    }
+
+    /// 提示失败与资源限制的关系
+    fn warn_limit(&self, hit: LimitHit, outcome: ExitOutcome, log: Option<&SharedLog>) {
+        match hit {
+            LimitHit::Cpu(_) => {
+                crate::utils::cli::warn(format!("命令 '{}' {}，已被终止", self.command, hit));
+            }
+            LimitHit::Possible => crate::utils::cli::warn(format!(
+                "命令 '{}' {}，{}（{}）",
+                self.command, outcome, hit, self.limits
+            )),
+        }
+        if let Some(log) = log {
+            match hit {
+                LimitHit::Cpu(cpu) => log.note(&format!(
+                    "cpu time limit exceeded ({})",
+                    format_duration(Duration::from_secs(cpu))
+                )),
+                LimitHit::Possible => {
+                    log.note(&format!("failed with resource limits {}", self.limits))
+                }
+            }
+        }
+    }
+
+    /// 启动命令并等待其退出，超时后直接结束进程
+    #[cfg(not(unix))]
+    fn wait_once(&self, command: &mut Command, log: Option<&SharedLog>) -> Result<Attempt> {
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("执行命令失败: {}", self.command))?;
+        let tee = (log.is_some() || self.output_prefix.is_some())
+            .then(|| Tee::start(&mut child, log, self.output_prefix.as_deref()));
+        let mut timed_out = false;
+        let mut interrupted = false;
+        let status = if self.timeout.is_some() || self.cancel.is_some() {
+            let deadline = self.timeout.map(|timeout| std::time::Instant::now() + timeout);
+            loop {
+                if let Some(status) = child.try_wait().context("等待子进程失败")? {
+                    break status;
+                }
+                if self.cancelled() {
+                    interrupted = true;
+                    let _ = child.kill();
+                    break child.wait().context("等待子进程失败")?;
+                }
+                if let (Some(timeout), Some(deadline)) = (self.timeout, deadline)
+                    && std::time::Instant::now() >= deadline
+                {
+                    self.warn_timeout(timeout);
+                    timed_out = true;
+                    let _ = child.kill();
+                    break child.wait().context("等待子进程失败")?;
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+        } else {
+            child.wait().context("等待子进程失败")?
+        };
+        let outcome = if timed_out {
+            ExitOutcome::TimedOut
+        } else {
+            ExitOutcome::from_status(status)
+        };
+        if let Some(tee) = tee {
+            tee.finish(outcome);
+        }
+        Ok(Attempt {
+            outcome,
+            interrupted,
+            cpu_time: None,
+        })
+    }
+
+    /// 让命令在独立进程组中启动，返回是否需要把终端前台交给它
+    ///
+    /// `interactive` 为 false 时命令不读取终端，不接管终端前台
+    #[cfg(unix)]
+    fn prepare_group(command: &mut Command, interactive: bool) -> bool {
+        use crate::core::signal;
+        use std::os::unix::process::CommandExt;
+
+        // 交互式运行时需要把终端前台交给脚本的进程组
+        let foreground = interactive && signal::owns_terminal();
+        command.process_group(0);
+        if foreground {
+            // SAFETY: set_foreground 只调用异步信号安全的函数
+            unsafe {
+                command.pre_exec(|| {
+                    let _ = signal::set_foreground(libc::getpid());
+                    Ok(())
+                });
+            }
+        }
+        foreground
+    }
+
+    /// 在命令 exec 之前设置资源限制
+    #[cfg(unix)]
+    fn prepare_limits(command: &mut Command, limits: ResourceLimits) {
+        use std::os::unix::process::CommandExt;
+
+        // SAFETY: apply 只调用异步信号安全的 getrlimit/setrlimit
+        unsafe {
+            command.pre_exec(move || limits.apply());
+        }
+    }
+
+    /// 在命令 exec 之前清除锁文件描述符的 `FD_CLOEXEC`，只影响命令自己的描述符
+    #[cfg(unix)]
+    fn prepare_lock(command: &mut Command, fd: std::os::fd::RawFd) {
+        use std::os::unix::process::CommandExt;
+
+        // SAFETY: 只调用异步信号安全的 fcntl
+        unsafe {
+            command.pre_exec(move || {
+                let flags = libc::fcntl(fd, libc::F_GETFD);
+                if flags == -1 || libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+
+    /// 检查内核支持，并在命令 exec 之前进入沙箱
+    #[cfg(target_os = "linux")]
+    fn prepare_sandbox(command: &mut Command, sandbox: &Sandbox) -> Result<()> {
+        use std::os::unix::process::CommandExt;
+
+        let setup = sandbox.prepare()?;
+        // SAFETY: enter 只调用异步信号安全的系统调用，不分配内存
+        unsafe {
+            command.pre_exec(move || setup.enter());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn prepare_sandbox(_command: &mut Command, _sandbox: &Sandbox) -> Result<()> {
+        anyhow::bail!("The sandbox is only supported on Linux")
+    }
+
+    /// 启动失败时的错误信息
+    fn spawn_context(&self) -> String {
+        if self.sandbox.is_some() {
+            format!("无法在沙箱中启动命令: {}", self.command)
+        } else {
+            format!("执行命令失败: {}", self.command)
+        }
+    }
+
+    /// 在独立进程组中启动命令并转发终止信号，直到其退出
+    #[cfg(unix)]
+    fn supervise(
+        &self,
+        command: &mut Command,
+        foreground: bool,
+        log: Option<&SharedLog>,
+    ) -> Result<Attempt> {
+        use crate::core::signal::{self, ForwardGuard};
+
+        let guard = ForwardGuard::install().context("安装信号处理器失败")?;
+        let mut child = command.spawn().with_context(|| self.spawn_context())?;
+        let pgid = child.id() as libc::pid_t;
+        let tee = (log.is_some() || self.output_prefix.is_some())
+            .then(|| Tee::start(&mut child, log, self.output_prefix.as_deref()));
+        if foreground {
+            // 与子进程中的调用互为补充，避免竞争
+            let _ = signal::set_foreground(pgid);
+        }
+
+        let attempt = self.wait_forwarding(pgid, foreground, &guard);
+
+        if foreground {
+            let _ = signal::set_foreground(signal::current_group());
+        }
+
+        let attempt = attempt?;
+        if let Some(tee) = tee {
+            tee.finish(attempt.outcome);
+        }
+        Ok(attempt)
+    }
+
+    /// 等待子进程退出，期间将收到的信号转发给其进程组
+    ///
+    /// `foreground` 为 true 时子进程是终端的前台进程组，它被 Ctrl-Z 停止后 rune 随之挂起
+    #[cfg(unix)]
+    fn wait_forwarding(
+        &self,
+        pgid: libc::pid_t,
+        foreground: bool,
+        guard: &crate::core::signal::ForwardGuard,
+    ) -> Result<Attempt> {
+        use crate::core::signal::{self, ChildState, kill_group};
+        use std::time::Instant;
+
+        const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+        let timeout_at = self.timeout.map(|timeout| Instant::now() + timeout);
+        let mut timed_out = false;
+        let mut interrupted = false;
+        let mut kill_deadline: Option<Instant> = None;
+        loop {
+            // 子进程是进程组的组长，pid 与 pgid 相同
+            match signal::poll_child(pgid, foreground).context("等待子进程失败")? {
+                ChildState::Exited(status, cpu_time) => {
+                    let outcome = if timed_out {
+                        ExitOutcome::TimedOut
+                    } else {
+                        ExitOutcome::from_status(status)
+                    };
+                    return Ok(Attempt {
+                        outcome,
+                        interrupted,
+                        cpu_time: Some(cpu_time),
+                    });
+                }
+                ChildState::Stopped => signal::suspend_job(pgid).context("恢复脚本失败")?,
+                ChildState::Running => {}
+            }
+
+            if let Some(signal) = guard.take_pending() {
+                interrupted = true;
+                kill_group(pgid, signal).context("转发信号失败")?;
+                kill_deadline.get_or_insert_with(|| Instant::now() + self.grace_period);
+            }
+
+            if !interrupted && self.cancelled() {
+                interrupted = true;
+                kill_group(pgid, libc::SIGTERM).context("终止进程组失败")?;
+                kill_deadline.get_or_insert_with(|| Instant::now() + self.grace_period);
+            }
+
+            if !timed_out && timeout_at.is_some_and(|at| Instant::now() >= at) {
+                timed_out = true;
+                if let Some(timeout) = self.timeout {
+                    self.warn_timeout(timeout);
+                }
+                kill_group(pgid, libc::SIGTERM).context("终止进程组失败")?;
+                kill_deadline.get_or_insert_with(|| Instant::now() + self.grace_period);
+            }
+
+            if kill_deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                kill_group(pgid, libc::SIGKILL).context("终止进程组失败")?;
+                kill_deadline = None;
+            }
+
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// 执行命令
+    ///
+    /// 命令将继承当前进程的 stdin/stdout/stderr，支持交互式程序
+    ///
+    /// # 错误
+    /// - 命令不存在
+    /// - 命令执行失败
+    /// - 命令返回非零退出码或被信号终止
+    pub fn execute(&self) -> Result<()> {
+        let outcome = self.status()?;
+        if !outcome.success() {
+            anyhow::bail!("命令 '{}' {}", self.command, outcome);
         }
 
         Ok(())
@@ -88,6 +891,7 @@ impl CommandExecutor {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serial_test::serial;
 
     #[test]
     fn test_command_executor_new() {
@@ -135,6 +939,7 @@ mod tests {
     }
 
     #[test]
+    #[serial]
     fn test_command_executor_execute_simple() {
         // Execute a simple echo command
         let result = CommandExecutor::new("echo")
@@ -153,6 +958,7 @@ mod tests {
     }
 
     #[test]
+    #[serial]
     fn test_command_executor_execute_failing_command() {
         // 使用一个会失败的命令（退出码非零）
         // false 命令在 Unix 系统上存在且总是返回 1
@@ -173,4 +979,437 @@ mod tests {
             assert!(result.is_err());
         }
     }
+
+    #[test]
+    fn test_exit_outcome_code() {
+        assert_eq!(ExitOutcome::Exited(0).code(), 0);
+        assert_eq!(ExitOutcome::Exited(3).code(), 3);
+        assert_eq!(ExitOutcome::Signaled(15).code(), 143);
+        assert!(ExitOutcome::SUCCESS.success());
+        assert!(!ExitOutcome::Exited(1).success());
+        assert!(!ExitOutcome::Signaled(9).success());
+        assert_eq!(ExitOutcome::Signaled(9).signal(), Some(9));
+        assert_eq!(ExitOutcome::Exited(9).signal(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[serial]
+    fn test_command_executor_status_exit_code() {
+        let outcome = CommandExecutor::new("sh")
+            .args(["-c", "exit 42"])
+            .status()
+            .unwrap();
+        assert_eq!(outcome, ExitOutcome::Exited(42));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[serial]
+    fn test_command_executor_status_signaled() {
+        let outcome = CommandExecutor::new("sh")
+            .args(["-c", "kill -TERM $$"])
+            .status()
+            .unwrap();
+        assert_eq!(outcome, ExitOutcome::Signaled(15));
+        assert_eq!(outcome.code(), 143);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[serial]
+    fn test_command_executor_log_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("run.log");
+        let outcome = CommandExecutor::new("sh")
+            .args(["-c", "echo hello; echo oops >&2; exit 3"])
+            .log_file(&path, 1024 * 1024)
+            .status()
+            .unwrap();
+        assert_eq!(outcome, ExitOutcome::Exited(3));
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.contains("[rune] started: sh -c"));
+        assert!(content.contains("[out] hello\n"));
+        assert!(content.contains("[err] oops\n"));
+        assert!(content.trim_end().ends_with("[rune] finished: exit 3"));
+    }
+
+    #[test]
+    fn test_command_executor_env_builders() {
+        let executor = CommandExecutor::new("echo")
+            .env("A", "1")
+            .envs([("B", "2")])
+            .env_remove("C")
+            .env_clear()
+            .current_dir("/tmp");
+        assert_eq!(
+            executor.envs,
+            vec![
+                ("A".to_string(), "1".to_string()),
+                ("B".to_string(), "2".to_string())
+            ]
+        );
+        assert_eq!(executor.env_remove, vec!["C"]);
+        assert!(executor.env_clear);
+        assert_eq!(executor.current_dir, Some(PathBuf::from("/tmp")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[serial]
+    fn test_command_executor_applies_env() {
+        let outcome = CommandExecutor::new("sh")
+            .args(["-c", r#"test "$RUNE_EXEC_TEST" = value && test -z "$HOME""#])
+            .env_remove("HOME")
+            .env("RUNE_EXEC_TEST", "value")
+            .status()
+            .unwrap();
+        assert!(outcome.success());
+
+        let outcome = CommandExecutor::new("sh")
+            .args(["-c", r#"test -z "$HOME" && test "$ONLY" = 1"#])
+            .env_clear()
+            .env("ONLY", "1")
+            .status()
+            .unwrap();
+        assert!(outcome.success());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[serial]
+    fn test_command_executor_applies_current_dir() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("marker"), "").unwrap();
+
+        let outcome = CommandExecutor::new("sh")
+            .args(["-c", "test -f marker"])
+            .current_dir(dir.path())
+            .status()
+            .unwrap();
+        assert!(outcome.success());
+    }
+
+    #[test]
+    fn test_command_executor_grace_period() {
+        let executor = CommandExecutor::new("echo");
+        assert_eq!(executor.grace_period, DEFAULT_GRACE_PERIOD);
+
+        let executor = executor.grace_period(Duration::from_secs(3));
+        assert_eq!(executor.grace_period, Duration::from_secs(3));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[serial]
+    fn test_command_executor_forwards_signal_to_group() {
+        // 子进程向 rune（测试进程）发送 SIGTERM，rune 应将其转发回脚本的进程组
+        let outcome = CommandExecutor::new("sh")
+            .args(["-c", "kill -TERM $PPID; sleep 5"])
+            .status()
+            .unwrap();
+        assert_eq!(outcome, ExitOutcome::Signaled(libc::SIGTERM));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[serial]
+    fn test_command_executor_escalates_to_sigkill() {
+        // 脚本忽略 SIGTERM，宽限期过后应被 SIGKILL 终止
+        let outcome = CommandExecutor::new("sh")
+            .args(["-c", "trap '' TERM; kill -TERM $PPID; sleep 5"])
+            .grace_period(Duration::from_millis(200))
+            .status()
+            .unwrap();
+        assert_eq!(outcome, ExitOutcome::Signaled(libc::SIGKILL));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[serial]
+    fn test_command_executor_timeout() {
+        let outcome = CommandExecutor::new("sleep")
+            .arg("5")
+            .timeout(Duration::from_millis(200))
+            .status()
+            .unwrap();
+        assert_eq!(outcome, ExitOutcome::TimedOut);
+        assert_eq!(outcome.code(), TIMEOUT_EXIT_CODE);
+        assert_eq!(outcome.signal(), None);
+
+        let outcome = CommandExecutor::new("true")
+            .timeout(Duration::from_secs(5))
+            .status()
+            .unwrap();
+        assert_eq!(outcome, ExitOutcome::SUCCESS);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[serial]
+    fn test_command_executor_retries() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let counter = temp_dir.path().join("attempts");
+        // 第三次运行时成功
+        let script = format!(
+            "echo x >> '{0}'; [ $(wc -l < '{0}') -ge 3 ]",
+            counter.display()
+        );
+        let retry = RetryPolicy {
+            retries: 5,
+            delay: Duration::from_millis(10),
+            backoff: 1.0,
+        };
+        let outcome = CommandExecutor::new("sh")
+            .args(["-c", &script])
+            .retry(retry)
+            .status()
+            .unwrap();
+        assert_eq!(outcome, ExitOutcome::SUCCESS);
+        assert_eq!(std::fs::read_to_string(&counter).unwrap().lines().count(), 3);
+
+        // 重试次数用完后返回最后一次的结果
+        let outcome = CommandExecutor::new("sh")
+            .args(["-c", "exit 7"])
+            .retry(RetryPolicy { retries: 2, ..retry })
+            .status()
+            .unwrap();
+        assert_eq!(outcome, ExitOutcome::Exited(7));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[serial]
+    fn test_command_executor_cancel_flag() {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let setter = {
+            let cancel = Arc::clone(&cancel);
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(200));
+                cancel.store(true, Ordering::SeqCst);
+            })
+        };
+        let started = std::time::Instant::now();
+        let outcome = CommandExecutor::new("sleep")
+            .arg("5")
+            .prefix_output("job | ")
+            .cancel_flag(Arc::clone(&cancel))
+            .retry(RetryPolicy {
+                retries: 3,
+                ..RetryPolicy::default()
+            })
+            .status()
+            .unwrap();
+        setter.join().unwrap();
+        // 取消后不再重试
+        assert_eq!(outcome, ExitOutcome::Signaled(libc::SIGTERM));
+        assert!(started.elapsed() < Duration::from_secs(3));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_executor_hold_lock() {
+        use crate::core::lock::LockWait;
+        use std::os::fd::AsRawFd;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path().to_path_buf();
+        let acquire = |wait| ScriptLock::acquire(&dir, "deploy", "deploy", wait);
+
+        // 正常结束时显式解锁，即使命令启动的进程仍持有描述符
+        let lock = acquire(LockWait::Fail).unwrap();
+        let executor = CommandExecutor::new("sh")
+            .args(["-c", "sleep 2 & exit 0"])
+            .hold_lock(&lock);
+        executor.status().unwrap();
+        drop(lock);
+        acquire(LockWait::Fail).unwrap();
+
+        // 模拟 rune 被强制结束：关闭描述符而不解锁，锁由命令继续持有
+        let lock = acquire(LockWait::Fail).unwrap();
+        let executor = CommandExecutor::new("sleep").arg("1").hold_lock(&lock);
+        let script = std::thread::spawn(move || executor.status().unwrap());
+        std::thread::sleep(Duration::from_millis(300));
+        let fd = lock.as_raw_fd();
+        std::mem::forget(lock);
+        unsafe { libc::close(fd) };
+        let err = acquire(LockWait::Fail).unwrap_err();
+        assert!(err.to_string().contains("is held by"), "{:#}", err);
+        assert!(script.join().unwrap().success());
+        acquire(LockWait::Timeout(Duration::from_secs(10))).unwrap();
+    }
+
+    /// 作业控制测试中子进程的角色
+    #[cfg(unix)]
+    const JOB_CONTROL_ROLE: &str = "RUNE_TEST_JOB_CONTROL";
+
+    /// 以指定角色重新运行本测试程序中的 `job_control_helper`
+    #[cfg(unix)]
+    fn job_control_command(role: &str) -> Command {
+        let mut command = Command::new(std::env::current_exe().unwrap());
+        command
+            .args([
+                "--exact",
+                "core::executor::tests::job_control_helper",
+                "--ignored",
+                "--nocapture",
+                "--quiet",
+            ])
+            .env(JOB_CONTROL_ROLE, role);
+        command
+    }
+
+    /// 作业控制测试的子进程
+    ///
+    /// `shell` 像交互式 shell 一样在前台运行 `rune`，它停止时报告并像 `fg` 一样恢复它；
+    /// `rune` 交互式运行一个读取终端输入的脚本
+    #[cfg(unix)]
+    #[test]
+    #[ignore]
+    fn job_control_helper() {
+        use crate::core::signal::{self, ChildState};
+        use std::os::unix::process::CommandExt;
+
+        match std::env::var(JOB_CONTROL_ROLE).as_deref() {
+            Ok("shell") => {
+                // 由 poll_child 回收
+                let pgid = job_control_command("rune")
+                    .process_group(0)
+                    .spawn()
+                    .unwrap()
+                    .id() as libc::pid_t;
+                signal::set_foreground(pgid).unwrap();
+                loop {
+                    match signal::poll_child(pgid, true).unwrap() {
+                        ChildState::Running => std::thread::sleep(Duration::from_millis(10)),
+                        ChildState::Stopped => {
+                            signal::set_foreground(signal::current_group()).unwrap();
+                            println!("[shell] job stopped");
+                            signal::set_foreground(pgid).unwrap();
+                            signal::kill_group(pgid, libc::SIGCONT).unwrap();
+                        }
+                        ChildState::Exited(status, _) => {
+                            println!("[shell] job exited with {}", status.code().unwrap_or(-1));
+                            return;
+                        }
+                    }
+                }
+            }
+            Ok("rune") => {
+                let outcome = CommandExecutor::new("sh")
+                    .args(["-c", "echo ready; read line; echo \"got $line\"; exit 3"])
+                    // 测试失败时不留下停止的进程
+                    .timeout(Duration::from_secs(30))
+                    .status()
+                    .unwrap();
+                std::process::exit(outcome.code());
+            }
+            _ => {}
+        }
+    }
+
+    /// 等待伪终端输出包含 `text`，超时返回已读到的输出
+    #[cfg(unix)]
+    fn wait_for_output(
+        chunks: &std::sync::mpsc::Receiver<Vec<u8>>,
+        output: &mut String,
+        text: &str,
+    ) -> Result<(), String> {
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        while !output.contains(text) {
+            let left = deadline.saturating_duration_since(std::time::Instant::now());
+            match chunks.recv_timeout(left) {
+                Ok(chunk) => output.push_str(&String::from_utf8_lossy(&chunk)),
+                Err(_) => return Err(format!("waiting for {:?}, got:\n{}", text, output)),
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_executor_job_control() {
+        use std::fs::File;
+        use std::io::{self, Read, Write};
+        use std::os::fd::{FromRawFd, OwnedFd};
+        use std::os::unix::process::CommandExt;
+
+        // 在新会话中以伪终端为控制终端运行 shell，shell 在前台运行 rune，rune 再运行脚本
+        let (mut master, slave) = unsafe {
+            let (mut master, mut slave) = (0, 0);
+            let result = libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+                std::ptr::null(),
+            );
+            assert_eq!(result, 0, "openpty: {}", io::Error::last_os_error());
+            (File::from_raw_fd(master), OwnedFd::from_raw_fd(slave))
+        };
+        let mut command = job_control_command("shell");
+        command
+            .stdin(slave.try_clone().unwrap())
+            .stdout(slave.try_clone().unwrap())
+            .stderr(slave);
+        // SAFETY: setsid 和 ioctl 都是异步信号安全的
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let mut shell = command.spawn().unwrap();
+        drop(command);
+
+        let (sender, chunks) = std::sync::mpsc::channel();
+        let mut reader = master.try_clone().unwrap();
+        std::thread::spawn(move || {
+            let mut buf = [0; 1024];
+            while let Ok(n) = reader.read(&mut buf) {
+                if n == 0 || sender.send(buf[..n].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut output = String::new();
+        let result = wait_for_output(&chunks, &mut output, "ready")
+            .and_then(|_| {
+                // Ctrl-Z 停止脚本，rune 随之挂起，shell 看到整个作业停止
+                master.write_all(b"\x1a").unwrap();
+                wait_for_output(&chunks, &mut output, "[shell] job stopped")
+            })
+            .and_then(|_| {
+                // 恢复后脚本重新成为前台进程组，可以继续读取终端
+                master.write_all(b"hello\n").unwrap();
+                wait_for_output(&chunks, &mut output, "got hello")
+            })
+            .and_then(|_| wait_for_output(&chunks, &mut output, "[shell] job exited with 3"));
+        if let Err(message) = result {
+            let _ = shell.kill();
+            panic!("{}", message);
+        }
+        assert!(shell.wait().unwrap().success());
+    }
+
+    #[test]
+    fn test_retry_policy_delay() {
+        let retry = RetryPolicy {
+            retries: 3,
+            delay: Duration::from_secs(2),
+            backoff: 2.0,
+        };
+        assert_eq!(retry.delay_before(1), Duration::from_secs(2));
+        assert_eq!(retry.delay_before(3), Duration::from_secs(8));
+        let fixed = RetryPolicy::default();
+        assert_eq!(fixed.delay_before(4), DEFAULT_RETRY_DELAY);
+
+        assert!(RetryPolicy::check_backoff(1.5).is_ok());
+        assert!(RetryPolicy::check_backoff(0.5).is_err());
+        assert!(RetryPolicy::check_backoff(f64::NAN).is_err());
+    }
 }
@@ -0,0 +1,494 @@
+use crate::{
+    core::{executor::ExitOutcome, limits::ResourceLimits, lock::LockWait, sandbox::SandboxPolicy},
+    utils::fs::get_history_path,
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// 一次 `rune run` 的记录
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// 递增的编号，用于 `rune rerun`
+    pub id: u64,
+    /// 开始时间（Unix 时间戳，秒）
+    pub timestamp: u64,
+    /// 脚本名称（包含命名空间）
+    pub script: String,
+    /// 脚本扩展名，没有扩展名时为空
+    #[serde(default)]
+    pub extension: String,
+    /// 脚本所在的仓库
+    pub repo: String,
+    /// 使用的插件
+    pub plugin: String,
+    /// 用户传给脚本的参数
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// 实际执行的命令行
+    #[serde(default)]
+    pub argv: Vec<String>,
+    /// 运行时的工作目录
+    pub cwd: PathBuf,
+    /// 运行时长（毫秒）
+    pub duration_ms: u64,
+    /// 退出码（被信号终止时为 128 + 信号编号）
+    pub exit_code: i32,
+    /// 终止进程的信号
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signal: Option<i32>,
+    /// 是否因超时被终止
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub timed_out: bool,
+    /// 运行脚本的用户
+    #[serde(default)]
+    pub user: String,
+    /// 输出日志文件，未记录日志时为 None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log: Option<PathBuf>,
+    /// 命令行指定的资源限制，`rune rerun` 时沿用
+    #[serde(default, skip_serializing_if = "ResourceLimits::is_empty")]
+    pub limits: ResourceLimits,
+    /// 命令行指定的沙箱策略，`rune rerun` 时沿用
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<SandboxPolicy>,
+    /// 命令行指定的超时、重试、日志和单实例锁设置，`rune rerun` 时沿用
+    #[serde(default, flatten)]
+    pub settings: RunSettings,
+}
+
+/// 命令行指定的运行设置，未指定的项由脚本元数据、插件和配置决定
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunSettings {
+    /// 超时时间（毫秒）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// 失败后的重试次数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    /// 第一次重试前的等待时间（毫秒）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_delay_ms: Option<u64>,
+    /// 每次重试后等待时间的倍数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backoff: Option<f64>,
+    /// `--log`（true）或 `--no-log`（false）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_output: Option<bool>,
+    /// 是否指定了 `--lock`
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub lock: bool,
+    /// 单实例锁的名称
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lock_name: Option<String>,
+    /// 锁被占用时的等待方式
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lock_wait: Option<LockWait>,
+}
+
+impl HistoryEntry {
+    /// 是否成功退出
+    pub fn success(&self) -> bool {
+        self.exit_code == 0 && self.signal.is_none() && !self.timed_out
+    }
+
+    /// 记录退出结果
+    pub fn set_outcome(&mut self, outcome: ExitOutcome) {
+        self.exit_code = outcome.code();
+        self.signal = outcome.signal();
+        self.timed_out = outcome.timed_out();
+    }
+
+    /// 退出状态的简短描述
+    pub fn status(&self) -> String {
+        if self.timed_out {
+            return "timeout".to_string();
+        }
+        match self.signal {
+            Some(signal) => format!("signal {}", signal),
+            None => format!("exit {}", self.exit_code),
+        }
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// 保留策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Retention {
+    /// 最多保留的记录数，0 表示不限制
+    pub max_entries: usize,
+    /// 最长保留时间（秒），None 表示不限制
+    pub max_age: Option<u64>,
+}
+
+/// 运行历史
+///
+/// 以 JSON Lines 格式保存在数据目录下的 `history.jsonl`，每行一条记录
+#[derive(Debug)]
+pub struct History {
+    path: PathBuf,
+    entries: Vec<HistoryEntry>,
+}
+
+impl History {
+    /// 从默认位置加载历史
+    pub fn load() -> Result<Self> {
+        Self::load_from(&get_history_path()?)
+    }
+
+    /// 从指定文件加载历史，文件不存在时返回空历史
+    ///
+    /// 无法解析的行（如写入中断留下的半行）会被跳过
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let entries = if path.exists() {
+            fs::read_to_string(path)
+                .with_context(|| format!("Failed to read history: {}", path.display()))?
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    /// 所有记录，按时间从旧到新
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    /// 按编号查找记录，None 表示最近一条
+    pub fn get(&self, id: Option<u64>) -> Option<&HistoryEntry> {
+        match id {
+            Some(id) => self.entries.iter().find(|e| e.id == id),
+            None => self.entries.last(),
+        }
+    }
+
+    /// 下一条记录的编号
+    pub fn next_id(&self) -> u64 {
+        self.entries.iter().map(|e| e.id).max().unwrap_or(0) + 1
+    }
+
+    /// 追加一条记录，并按保留策略清理旧记录
+    ///
+    /// 编号为 0 的记录由历史分配编号（后台运行使用启动时预留的编号）；
+    /// 没有需要清理的记录时只追加一行
+    pub fn record(
+        &mut self,
+        mut entry: HistoryEntry,
+        retention: Retention,
+        now: u64,
+    ) -> Result<u64> {
+        if entry.id == 0 {
+            entry.id = self.next_id();
+        }
+        let id = entry.id;
+        self.entries.push(entry);
+
+        let before = self.entries.len();
+        if let Some(max_age) = retention.max_age {
+            let cutoff = now.saturating_sub(max_age);
+            self.entries.retain(|e| e.timestamp >= cutoff || e.id == id);
+        }
+        if retention.max_entries > 0 && self.entries.len() > retention.max_entries {
+            let excess = self.entries.len() - retention.max_entries;
+            self.entries.drain(..excess);
+        }
+
+        if self.entries.len() == before {
+            self.append_last()?;
+        } else {
+            self.save()?;
+        }
+        Ok(id)
+    }
+
+    /// 清空历史
+    pub fn clear(&mut self) -> Result<()> {
+        self.entries.clear();
+        if self.path.exists() {
+            fs::remove_file(&self.path)
+                .with_context(|| format!("Failed to remove history: {}", self.path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// 把最后一条记录追加到文件
+    fn append_last(&self) -> Result<()> {
+        let Some(entry) = self.entries.last() else {
+            return Ok(());
+        };
+        self.ensure_parent()?;
+        let mut line = serde_json::to_string(entry).context("Failed to serialize history")?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .with_context(|| format!("Failed to write history: {}", self.path.display()))
+    }
+
+    /// 重写整个历史文件
+    ///
+    /// 先写入临时文件再重命名，避免中断时丢失历史
+    fn save(&self) -> Result<()> {
+        self.ensure_parent()?;
+        let mut content = String::new();
+        for entry in &self.entries {
+            content.push_str(&serde_json::to_string(entry).context("Failed to serialize history")?);
+            content.push('\n');
+        }
+        let temp = self.path.with_extension("jsonl.tmp");
+        fs::write(&temp, content)
+            .with_context(|| format!("Failed to write history: {}", temp.display()))?;
+        fs::rename(&temp, &self.path)
+            .with_context(|| format!("Failed to write history: {}", self.path.display()))
+    }
+
+    fn ensure_parent(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    fn entry(script: &str, timestamp: u64, exit_code: i32) -> HistoryEntry {
+        HistoryEntry {
+            id: 0,
+            timestamp,
+            script: script.to_string(),
+            extension: "sh".to_string(),
+            repo: "user".to_string(),
+            plugin: "bash".to_string(),
+            args: vec!["a b".to_string()],
+            argv: vec!["/bin/bash".to_string(), "deploy.sh".to_string()],
+            cwd: PathBuf::from("/tmp"),
+            duration_ms: 10,
+            exit_code,
+            signal: None,
+            timed_out: false,
+            user: "tester".to_string(),
+            log: None,
+            limits: ResourceLimits::default(),
+            sandbox: None,
+            settings: RunSettings::default(),
+        }
+    }
+
+    #[test]
+    fn test_history_record_and_load() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("data").join("history.jsonl");
+        let mut history = History::load_from(&path).unwrap();
+
+        assert_eq!(
+            history
+                .record(entry("deploy", 100, 0), Retention::default(), 100)
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            history
+                .record(entry("backup", 200, 1), Retention::default(), 200)
+                .unwrap(),
+            2
+        );
+
+        let loaded = History::load_from(&path).unwrap();
+        assert_eq!(loaded.entries().len(), 2);
+        assert_eq!(loaded.get(None).unwrap().script, "backup");
+        assert_eq!(loaded.get(Some(1)).unwrap().args, vec!["a b"]);
+        assert!(!loaded.get(Some(2)).unwrap().success());
+        assert!(loaded.get(Some(3)).is_none());
+    }
+
+    #[test]
+    fn test_history_keeps_reserved_id() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("history.jsonl");
+        let mut history = History::load_from(&path).unwrap();
+
+        let reserved = HistoryEntry {
+            id: 5,
+            ..entry("export", 100, 0)
+        };
+        assert_eq!(
+            history
+                .record(reserved, Retention::default(), 300)
+                .unwrap(),
+            5
+        );
+        assert_eq!(
+            history
+                .record(entry("deploy", 200, 0), Retention::default(), 300)
+                .unwrap(),
+            6
+        );
+        assert_eq!(History::load_from(&path).unwrap().get(Some(5)).unwrap().script, "export");
+    }
+
+    #[test]
+    fn test_history_keeps_limits_and_sandbox() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("history.jsonl");
+        let mut history = History::load_from(&path).unwrap();
+        let isolated = HistoryEntry {
+            limits: ResourceLimits::parse("mem=2G,cpu=60s").unwrap(),
+            sandbox: Some(SandboxPolicy {
+                writable: vec!["out".to_string()],
+                network: Some(false),
+                private_tmp: None,
+            }),
+            ..entry("build", 100, 0)
+        };
+        history
+            .record(isolated.clone(), Retention::default(), 100)
+            .unwrap();
+        history
+            .record(entry("deploy", 200, 0), Retention::default(), 200)
+            .unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert!(lines[0].contains("\"limits\""));
+        assert!(!lines[1].contains("\"limits\"") && !lines[1].contains("\"sandbox\""));
+
+        let loaded = History::load_from(&path).unwrap();
+        let restored = loaded.get(Some(1)).unwrap();
+        assert_eq!(restored.limits, isolated.limits);
+        assert_eq!(restored.sandbox, isolated.sandbox);
+        assert_eq!(loaded.get(Some(2)).unwrap().sandbox, None);
+    }
+
+    #[test]
+    fn test_history_keeps_run_settings() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("history.jsonl");
+        let mut history = History::load_from(&path).unwrap();
+        let settings = RunSettings {
+            timeout_ms: Some(30_000),
+            retries: Some(2),
+            retry_delay_ms: Some(1500),
+            backoff: Some(2.5),
+            log_output: Some(false),
+            lock: true,
+            lock_name: Some("db".to_string()),
+            lock_wait: Some(LockWait::Timeout(Duration::from_secs(300))),
+        };
+        let configured = HistoryEntry {
+            settings: settings.clone(),
+            ..entry("deploy", 100, 0)
+        };
+        history
+            .record(configured, Retention::default(), 100)
+            .unwrap();
+        history
+            .record(entry("deploy", 200, 0), Retention::default(), 200)
+            .unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert!(
+            lines[0].contains("\"lock_wait\":\"300000ms\""),
+            "{}",
+            lines[0]
+        );
+        assert!(!lines[1].contains("timeout_ms") && !lines[1].contains("lock"));
+
+        let loaded = History::load_from(&path).unwrap();
+        assert_eq!(loaded.get(Some(1)).unwrap().settings, settings);
+        assert_eq!(
+            loaded.get(Some(2)).unwrap().settings,
+            RunSettings::default()
+        );
+    }
+
+    #[test]
+    fn test_history_skips_corrupt_lines() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("history.jsonl");
+        let mut history = History::load_from(&path).unwrap();
+        history
+            .record(entry("deploy", 100, 0), Retention::default(), 100)
+            .unwrap();
+        let mut content = fs::read_to_string(&path).unwrap();
+        content.push_str("{\"id\": 2, \"timest");
+        fs::write(&path, content).unwrap();
+
+        let loaded = History::load_from(&path).unwrap();
+        assert_eq!(loaded.entries().len(), 1);
+        assert_eq!(loaded.next_id(), 2);
+    }
+
+    #[test]
+    fn test_history_retention() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("history.jsonl");
+        let mut history = History::load_from(&path).unwrap();
+        let retention = Retention {
+            max_entries: 3,
+            max_age: Some(1000),
+        };
+        for (i, timestamp) in [100, 2000, 2100, 2200, 2300].into_iter().enumerate() {
+            history
+                .record(
+                    entry(&format!("s{}", i), timestamp, 0),
+                    retention,
+                    timestamp,
+                )
+                .unwrap();
+        }
+
+        let loaded = History::load_from(&path).unwrap();
+        let ids: Vec<u64> = loaded.entries().iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![3, 4, 5]);
+
+        // 超过保留时间的记录被清理
+        let mut history = loaded;
+        history
+            .record(entry("late", 5000, 0), retention, 5000)
+            .unwrap();
+        let ids: Vec<u64> = History::load_from(&path)
+            .unwrap()
+            .entries()
+            .iter()
+            .map(|e| e.id)
+            .collect();
+        assert_eq!(ids, vec![6]);
+    }
+
+    #[test]
+    fn test_history_signal_status() {
+        let mut e = entry("deploy", 0, 0);
+        e.set_outcome(ExitOutcome::Signaled(15));
+        assert_eq!(e.exit_code, 143);
+        assert_eq!(e.status(), "signal 15");
+        assert!(!e.success());
+
+        e.set_outcome(ExitOutcome::TimedOut);
+        assert_eq!(e.exit_code, 124);
+        assert_eq!(e.signal, None);
+        assert_eq!(e.status(), "timeout");
+        assert!(!e.success());
+    }
+}
@@ -0,0 +1,390 @@
+//! 脚本运行时的资源限制
+//!
+//! 在插件定义和脚本元数据中以 `limits` 映射声明，或通过 `rune run --limit mem=2G,cpu=60s`
+//! 指定，启动脚本前在子进程中以 `setrlimit` 设置（软限制和硬限制相同，脚本无法自行提高）
+
+use std::{collections::BTreeMap, fmt, time::Duration};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::executor::ExitOutcome,
+    utils::{
+        duration::{format_duration, parse_duration},
+        size::parse_size,
+    },
+};
+
+/// 超出 CPU 时间的软限制后先收到 SIGXCPU，仍未退出时再过这么多秒由内核发送 SIGKILL
+const CPU_KILL_AFTER: u64 = 5;
+
+/// 资源限制，未设置的项不限制
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    try_from = "BTreeMap<String, LimitValue>",
+    into = "BTreeMap<String, String>"
+)]
+pub struct ResourceLimits {
+    /// CPU 时间（秒），`RLIMIT_CPU`
+    pub cpu: Option<u64>,
+    /// 虚拟内存（字节），`RLIMIT_AS`
+    pub memory: Option<u64>,
+    /// 打开的文件数，`RLIMIT_NOFILE`
+    pub nofile: Option<u64>,
+    /// 进程数，`RLIMIT_NPROC`（按用户计算，包括该用户的所有进程）
+    pub nproc: Option<u64>,
+}
+
+/// 限制值在 YAML/TOML 中可以写成数字或字符串
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LimitValue {
+    Number(u64),
+    Text(String),
+}
+
+/// 脚本失败与资源限制的关系
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitHit {
+    /// 超出 CPU 时间限制后被终止，附带限制（秒）
+    Cpu(u64),
+    /// 设置了内存、文件数或进程数限制，失败可能是分配内存、打开文件或创建进程失败
+    Possible,
+}
+
+impl ResourceLimits {
+    /// 解析 `mem=2G,cpu=60s,nofile=1024,nproc=64` 形式的限制
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut limits = Self::default();
+        for item in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+        {
+            let Some((name, value)) = item.split_once('=') else {
+                bail!(
+                    "Invalid limit '{}' (expected NAME=VALUE, e.g. mem=2G)",
+                    item
+                );
+            };
+            limits.set(name.trim(), value.trim())?;
+        }
+        if limits.is_empty() {
+            bail!("No limits given (e.g. mem=2G,cpu=60s,nofile=1024,nproc=64)");
+        }
+        Ok(limits)
+    }
+
+    /// 设置一项限制
+    ///
+    /// `mem` 为大小（如 `512M`、`2G`），`cpu` 为时长（不足一秒按一秒计），`nofile` 和 `nproc` 为个数
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        let context = || format!("Invalid limit '{}={}'", name, value);
+        let parsed = match name {
+            "mem" | "memory" => parse_size(value).with_context(context)?,
+            "cpu" => {
+                let cpu = parse_duration(value).with_context(context)?;
+                cpu.as_secs() + u64::from(cpu.subsec_nanos() > 0)
+            }
+            "nofile" | "nproc" => value
+                .parse()
+                .map_err(|_| anyhow::anyhow!("'{}' is not a number", value))
+                .with_context(context)?,
+            _ => bail!(
+                "Unknown resource limit '{}' (use mem, cpu, nofile or nproc)",
+                name
+            ),
+        };
+        if parsed == 0 {
+            bail!("{}: the limit must be greater than 0", context());
+        }
+        let slot = match name {
+            "mem" | "memory" => &mut self.memory,
+            "cpu" => &mut self.cpu,
+            "nofile" => &mut self.nofile,
+            _ => &mut self.nproc,
+        };
+        *slot = Some(parsed);
+        Ok(())
+    }
+
+    /// 是否没有任何限制
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// 逐项合并，`self` 中已设置的项优先
+    pub fn or(self, fallback: Self) -> Self {
+        Self {
+            cpu: self.cpu.or(fallback.cpu),
+            memory: self.memory.or(fallback.memory),
+            nofile: self.nofile.or(fallback.nofile),
+            nproc: self.nproc.or(fallback.nproc),
+        }
+    }
+
+    /// 判断失败的运行是否与资源限制有关
+    ///
+    /// 被 SIGXCPU 终止，或被 SIGKILL 终止且 `cpu_time`（子进程使用的 CPU 时间）已达到限制时，
+    /// 确定是超出了 CPU 时间；其他原因的 SIGKILL（如 OOM killer）不算。
+    /// 其他限制不会直接终止进程，只能提示失败可能与限制有关
+    pub fn hit(&self, outcome: ExitOutcome, cpu_time: Option<Duration>) -> Option<LimitHit> {
+        if outcome.success() || outcome.timed_out() {
+            return None;
+        }
+        #[cfg(unix)]
+        if let (Some(cpu), Some(signal)) = (self.cpu, outcome.signal()) {
+            let reached = cpu_time.is_some_and(|used| used >= Duration::from_secs(cpu));
+            if signal == libc::SIGXCPU || (signal == libc::SIGKILL && reached) {
+                return Some(LimitHit::Cpu(cpu));
+            }
+        }
+        (self.memory.is_some() || self.nofile.is_some() || self.nproc.is_some())
+            .then_some(LimitHit::Possible)
+    }
+
+    /// 检查限制能否设置：普通用户不能超过当前的硬限制
+    #[cfg(unix)]
+    pub fn check(&self) -> Result<()> {
+        for (name, resource, value) in self.entries() {
+            let Some(value) = value else { continue };
+            let hard = rlimit::get(resource)
+                .with_context(|| format!("Failed to read the current '{}' limit", name))?
+                .rlim_max;
+            if hard != libc::RLIM_INFINITY && value as libc::rlim_t > hard {
+                bail!(
+                    "Cannot set limit '{}={}': it exceeds the hard limit {}",
+                    name,
+                    self.value_text(name),
+                    hard
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn check(&self) -> Result<()> {
+        bail!("Resource limits are only supported on Unix")
+    }
+
+    /// 为当前进程设置限制，在子进程 exec 之前调用
+    ///
+    /// 只调用异步信号安全的函数，不分配内存
+    #[cfg(unix)]
+    pub fn apply(&self) -> std::io::Result<()> {
+        for (name, resource, value) in self.entries() {
+            let Some(value) = value else { continue };
+            let current = rlimit::get(resource)?;
+            let soft = value as libc::rlim_t;
+            // CPU 时间的硬限制稍大，先给脚本一个处理 SIGXCPU 的机会
+            let hard = if name == "cpu" {
+                soft.saturating_add(CPU_KILL_AFTER as libc::rlim_t)
+            } else {
+                soft
+            };
+            rlimit::set(resource, soft, hard.min(current.rlim_max))?;
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn entries(&self) -> [(&'static str, rlimit::Resource, Option<u64>); 4] {
+        [
+            ("mem", libc::RLIMIT_AS, self.memory),
+            ("cpu", libc::RLIMIT_CPU, self.cpu),
+            ("nofile", libc::RLIMIT_NOFILE, self.nofile),
+            ("nproc", libc::RLIMIT_NPROC, self.nproc),
+        ]
+    }
+
+    /// 限制值的文本形式，可以被 [`ResourceLimits::set`] 解析
+    fn value_text(&self, name: &str) -> String {
+        match name {
+            "mem" => self.memory.map(exact_size),
+            "cpu" => self.cpu.map(|cpu| format!("{}s", cpu)),
+            "nofile" => self.nofile.map(|n| n.to_string()),
+            _ => self.nproc.map(|n| n.to_string()),
+        }
+        .unwrap_or_default()
+    }
+
+    /// 已设置的限制名称
+    fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        [
+            ("mem", self.memory),
+            ("cpu", self.cpu),
+            ("nofile", self.nofile),
+            ("nproc", self.nproc),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.map(|_| name))
+    }
+}
+
+impl fmt::Display for ResourceLimits {
+    /// 与 `--limit` 相同的格式，如 `mem=2G,cpu=60s`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let items: Vec<String> = self
+            .names()
+            .map(|name| format!("{}={}", name, self.value_text(name)))
+            .collect();
+        write!(f, "{}", items.join(","))
+    }
+}
+
+impl fmt::Display for LimitHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            LimitHit::Cpu(cpu) => write!(
+                f,
+                "超出 CPU 时间限制 {}",
+                format_duration(Duration::from_secs(cpu))
+            ),
+            LimitHit::Possible => write!(f, "失败可能与资源限制有关"),
+        }
+    }
+}
+
+impl TryFrom<BTreeMap<String, LimitValue>> for ResourceLimits {
+    type Error = anyhow::Error;
+
+    fn try_from(map: BTreeMap<String, LimitValue>) -> Result<Self> {
+        let mut limits = Self::default();
+        for (name, value) in map {
+            let value = match value {
+                LimitValue::Number(n) => n.to_string(),
+                LimitValue::Text(text) => text,
+            };
+            limits.set(&name, &value)?;
+        }
+        Ok(limits)
+    }
+}
+
+impl From<ResourceLimits> for BTreeMap<String, String> {
+    fn from(limits: ResourceLimits) -> Self {
+        limits
+            .names()
+            .map(|name| (name.to_string(), limits.value_text(name)))
+            .collect()
+    }
+}
+
+/// 不丢失精度的大小表示（如 `2G`、`1536M`）
+fn exact_size(bytes: u64) -> String {
+    for (unit, size) in [("G", 1 << 30), ("M", 1 << 20), ("K", 1 << 10)] {
+        if bytes.is_multiple_of(size) {
+            return format!("{}{}", bytes / size, unit);
+        }
+    }
+    bytes.to_string()
+}
+
+/// `getrlimit`/`setrlimit` 的封装，资源参数的类型因平台而异
+#[cfg(unix)]
+mod rlimit {
+    use std::io;
+
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    pub type Resource = libc::__rlimit_resource_t;
+    #[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+    pub type Resource = libc::c_int;
+
+    pub fn get(resource: Resource) -> io::Result<libc::rlimit> {
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        // SAFETY: 只写入局部变量
+        if unsafe { libc::getrlimit(resource, &mut limit) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(limit)
+    }
+
+    pub fn set(resource: Resource, soft: libc::rlim_t, hard: libc::rlim_t) -> io::Result<()> {
+        let limit = libc::rlimit {
+            rlim_cur: soft.min(hard),
+            rlim_max: hard,
+        };
+        // SAFETY: 只读取局部变量
+        if unsafe { libc::setrlimit(resource, &limit) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_limits() {
+        let limits = ResourceLimits::parse("mem=2G, cpu=1m30s,nofile=1024").unwrap();
+        assert_eq!(limits.memory, Some(2 << 30));
+        assert_eq!(limits.cpu, Some(90));
+        assert_eq!(limits.nofile, Some(1024));
+        assert_eq!(limits.nproc, None);
+        assert_eq!(limits.to_string(), "mem=2G,cpu=90s,nofile=1024");
+        // 不足一秒的 CPU 时间按一秒计
+        assert_eq!(ResourceLimits::parse("cpu=500ms").unwrap().cpu, Some(1));
+
+        assert!(ResourceLimits::parse("").is_err());
+        assert!(ResourceLimits::parse("mem").is_err());
+        assert!(ResourceLimits::parse("disk=1G").is_err());
+        assert!(ResourceLimits::parse("nofile=0").is_err());
+        assert!(ResourceLimits::parse("nproc=many").is_err());
+    }
+
+    #[test]
+    fn test_limits_serde() {
+        let limits: ResourceLimits =
+            serde_yaml::from_str("mem: 1536M\ncpu: 60s\nnproc: 64\n").unwrap();
+        assert_eq!(limits.memory, Some(1536 << 20));
+        assert_eq!(limits.nproc, Some(64));
+
+        let toml = toml::to_string(&limits).unwrap();
+        assert!(toml.contains("mem = \"1536M\""));
+        assert_eq!(toml::from_str::<ResourceLimits>(&toml).unwrap(), limits);
+        assert!(serde_yaml::from_str::<ResourceLimits>("swap: 1G\n").is_err());
+    }
+
+    #[test]
+    fn test_limits_or() {
+        let cli = ResourceLimits::parse("cpu=10s").unwrap();
+        let plugin = ResourceLimits::parse("cpu=60s,mem=1G").unwrap();
+        assert_eq!(cli.or(plugin).to_string(), "mem=1G,cpu=10s");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_limit_hit() {
+        let limits = ResourceLimits::parse("cpu=60s").unwrap();
+        assert_eq!(
+            limits.hit(ExitOutcome::Signaled(libc::SIGXCPU), None),
+            Some(LimitHit::Cpu(60))
+        );
+        // 只限制 CPU 时间时，普通的失败与限制无关
+        assert_eq!(limits.hit(ExitOutcome::Exited(1), None), None);
+        assert_eq!(limits.hit(ExitOutcome::SUCCESS, None), None);
+
+        // SIGKILL 只在 CPU 时间确实达到限制时才算超出限制
+        let killed = ExitOutcome::Signaled(libc::SIGKILL);
+        assert_eq!(
+            limits.hit(killed, Some(Duration::from_millis(61_500))),
+            Some(LimitHit::Cpu(60))
+        );
+        assert_eq!(limits.hit(killed, Some(Duration::from_secs(2))), None);
+        assert_eq!(limits.hit(killed, None), None);
+
+        let limits = ResourceLimits::parse("mem=1G").unwrap();
+        assert_eq!(
+            limits.hit(ExitOutcome::Exited(1), None),
+            Some(LimitHit::Possible)
+        );
+        assert_eq!(limits.hit(ExitOutcome::TimedOut, None), None);
+    }
+}
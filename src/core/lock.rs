@@ -0,0 +1,356 @@
+use crate::utils::{
+    cli::warn,
+    duration::{format_duration, parse_duration},
+    time::{format_timestamp, now},
+};
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{Seek, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// 等待锁时的轮询间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 锁已被占用时的处理方式
+///
+/// 序列化为 `fail`、`forever` 或以毫秒表示的时长（如 `30000ms`）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum LockWait {
+    /// 立即失败
+    #[default]
+    Fail,
+    /// 一直等待
+    Forever,
+    /// 最多等待指定时间
+    Timeout(Duration),
+}
+
+impl LockWait {
+    /// 解析等待方式：`forever` 或时长（如 `30s`、`5m`）
+    pub fn parse(input: &str) -> Result<Self> {
+        if input.trim() == "forever" {
+            return Ok(LockWait::Forever);
+        }
+        parse_duration(input)
+            .map(LockWait::Timeout)
+            .context("Invalid lock wait (expected 'forever' or a duration such as 30s)")
+    }
+}
+
+impl TryFrom<String> for LockWait {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        match value.trim() {
+            "fail" => Ok(LockWait::Fail),
+            _ => Self::parse(&value),
+        }
+    }
+}
+
+impl From<LockWait> for String {
+    fn from(wait: LockWait) -> Self {
+        match wait {
+            LockWait::Fail => "fail".to_string(),
+            LockWait::Forever => "forever".to_string(),
+            LockWait::Timeout(timeout) => format!("{}ms", timeout.as_millis()),
+        }
+    }
+}
+
+impl fmt::Display for LockWait {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockWait::Fail => write!(f, "fail if held"),
+            LockWait::Forever => write!(f, "wait until released"),
+            LockWait::Timeout(timeout) => write!(f, "wait up to {}", format_duration(*timeout)),
+        }
+    }
+}
+
+/// 持有锁的进程，写在锁文件中供等待者显示
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockHolder {
+    /// rune 进程的 PID
+    pub pid: u32,
+    /// 获得锁的时间（Unix 时间戳，秒）
+    pub started: u64,
+    /// 运行的脚本
+    pub script: String,
+    /// 运行脚本的用户
+    #[serde(default)]
+    pub user: String,
+}
+
+impl fmt::Display for LockHolder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PID {}", self.pid)?;
+        if !self.user.is_empty() {
+            write!(f, " ({})", self.user)?;
+        }
+        write!(
+            f,
+            " running '{}' since {}",
+            self.script,
+            format_timestamp(self.started)
+        )
+    }
+}
+
+/// 脚本的单实例锁
+///
+/// 基于锁目录下 `<name>.lock` 文件的咨询锁。锁文件的描述符只由加锁的脚本继承
+/// （见 [`CommandExecutor::hold_lock`](crate::core::executor::CommandExecutor::hold_lock)），
+/// rune 被强制结束时锁仍由脚本持有，直到脚本退出；正常结束时显式解锁。
+/// 锁文件本身会保留，其中记录最近一次的持有者
+#[derive(Debug)]
+pub struct ScriptLock {
+    name: String,
+    path: PathBuf,
+    file: File,
+}
+
+impl ScriptLock {
+    /// 获取名为 `name` 的锁，`script` 记录在锁文件中
+    ///
+    /// # 错误
+    /// 锁已被占用且 `wait` 为 [`LockWait::Fail`]，或等待超时
+    pub fn acquire(dir: &Path, name: &str, script: &str, wait: LockWait) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+        let path = lock_path(dir, name);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("Failed to open lock file: {}", path.display()))?;
+
+        let started = Instant::now();
+        let mut announced = false;
+        loop {
+            match file.try_lock() {
+                Ok(()) => break,
+                Err(TryLockError::WouldBlock) => {}
+                Err(TryLockError::Error(e)) => {
+                    return Err(anyhow!(e))
+                        .with_context(|| format!("Failed to lock {}", path.display()));
+                }
+            }
+            let holder = describe_holder(&path);
+            match wait {
+                LockWait::Fail => bail!(
+                    "Cannot run '{}': lock '{}' is held by {}",
+                    script,
+                    name,
+                    holder
+                ),
+                LockWait::Timeout(timeout) if started.elapsed() >= timeout => bail!(
+                    "Timed out after {} waiting for lock '{}' held by {}",
+                    format_duration(timeout),
+                    name,
+                    holder
+                ),
+                _ => {}
+            }
+            if !announced {
+                warn(format!("Waiting for lock '{}' held by {}", name, holder));
+                announced = true;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+
+        let mut lock = Self {
+            name: name.to_string(),
+            path,
+            file,
+        };
+        lock.write_holder(script)?;
+        Ok(lock)
+    }
+
+    /// 锁名称
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn write_holder(&mut self, script: &str) -> Result<()> {
+        let holder = LockHolder {
+            pid: std::process::id(),
+            started: now(),
+            script: script.to_string(),
+            user: std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .unwrap_or_default(),
+        };
+        let content = serde_json::to_string(&holder).context("Failed to serialize lock holder")?;
+        self.file
+            .set_len(0)
+            .and_then(|_| self.file.rewind())
+            .and_then(|_| self.file.write_all(content.as_bytes()))
+            .with_context(|| format!("Failed to write lock file: {}", self.path.display()))
+    }
+}
+
+impl Drop for ScriptLock {
+    /// 显式解锁：脚本及其启动的进程可能仍持有继承的描述符
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+#[cfg(unix)]
+impl std::os::fd::AsRawFd for ScriptLock {
+    fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+/// 读取锁文件中记录的持有者
+pub fn read_holder(path: &Path) -> Option<LockHolder> {
+    let content = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// 持有者的描述，持有者尚未写入信息时给出通用描述
+fn describe_holder(path: &Path) -> String {
+    read_holder(path)
+        .map(|holder| holder.to_string())
+        .unwrap_or_else(|| "another process".to_string())
+}
+
+/// 锁文件路径
+///
+/// 锁名称中字母、数字、`-`、`_` 和 `.` 以外的字符（包括路径分隔符和 `%`）按 UTF-8 字节
+/// 转义为 `%XX`，不同的锁名称不会对应同一个文件
+pub fn lock_path(dir: &Path, name: &str) -> PathBuf {
+    let mut file_name = String::new();
+    for c in name.chars() {
+        if c.is_alphanumeric() || "-_.".contains(c) {
+            file_name.push(c);
+        } else {
+            let mut buf = [0; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                file_name.push_str(&format!("%{:02X}", byte));
+            }
+        }
+    }
+    dir.join(format!("{}.lock", file_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_lock_wait_parse() {
+        assert_eq!(LockWait::parse("forever").unwrap(), LockWait::Forever);
+        assert_eq!(
+            LockWait::parse("30s").unwrap(),
+            LockWait::Timeout(Duration::from_secs(30))
+        );
+        assert!(LockWait::parse("later").is_err());
+        assert_eq!(
+            LockWait::Timeout(Duration::from_secs(90)).to_string(),
+            "wait up to 1m30s"
+        );
+    }
+
+    #[test]
+    fn test_lock_path() {
+        let dir = Path::new("/locks");
+        assert_eq!(lock_path(dir, "db/backup"), dir.join("db%2Fbackup.lock"));
+        assert_eq!(lock_path(dir, "db_backup"), dir.join("db_backup.lock"));
+        assert_eq!(
+            lock_path(dir, "db%2Fbackup"),
+            dir.join("db%252Fbackup.lock")
+        );
+        assert_eq!(lock_path(dir, ".."), dir.join("...lock"));
+        assert_eq!(
+            lock_path(dir, "nightly-sync"),
+            dir.join("nightly-sync.lock")
+        );
+    }
+
+    #[test]
+    fn test_lock_exclusive() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("locks");
+        let lock = ScriptLock::acquire(&dir, "backup", "db/backup", LockWait::Fail).unwrap();
+        assert_eq!(lock.name(), "backup");
+
+        let holder = read_holder(&lock_path(&dir, "backup")).unwrap();
+        assert_eq!(holder.pid, std::process::id());
+        assert_eq!(holder.script, "db/backup");
+
+        let err = ScriptLock::acquire(&dir, "backup", "db/backup", LockWait::Fail)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Cannot run 'db/backup'"));
+        assert!(err.contains(&format!("PID {}", std::process::id())));
+
+        let err = ScriptLock::acquire(
+            &dir,
+            "backup",
+            "db/backup",
+            LockWait::Timeout(Duration::from_millis(150)),
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("Timed out"));
+
+        // 其他锁名称互不影响
+        ScriptLock::acquire(&dir, "restore", "db/restore", LockWait::Fail).unwrap();
+
+        drop(lock);
+        ScriptLock::acquire(&dir, "backup", "db/backup", LockWait::Fail).unwrap();
+    }
+
+    #[test]
+    fn test_lock_wait_until_released() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().to_path_buf();
+        let lock = ScriptLock::acquire(&dir, "sync", "sync", LockWait::Fail).unwrap();
+        let releaser = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            drop(lock);
+        });
+
+        let started = Instant::now();
+        ScriptLock::acquire(&dir, "sync", "sync", LockWait::Forever).unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(150));
+        releaser.join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_lock_not_held_by_other_children() {
+        use std::os::fd::AsRawFd;
+
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().to_path_buf();
+        let lock = ScriptLock::acquire(&dir, "deploy", "deploy", LockWait::Fail).unwrap();
+        // 持有锁期间启动的其他子进程不继承锁文件的描述符
+        let mut sibling = std::process::Command::new("sleep")
+            .arg("2")
+            .spawn()
+            .unwrap();
+
+        // 模拟 rune 被强制结束：关闭描述符而不解锁
+        let fd = lock.as_raw_fd();
+        std::mem::forget(lock);
+        unsafe { libc::close(fd) };
+
+        ScriptLock::acquire(&dir, "deploy", "deploy", LockWait::Fail).unwrap();
+        sibling.kill().unwrap();
+        sibling.wait().unwrap();
+    }
+}
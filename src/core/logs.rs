@@ -0,0 +1,498 @@
+use crate::{
+    core::executor::ExitOutcome,
+    utils::{
+        cli::warn,
+        time::{format_timestamp, format_timestamp_millis, now_millis},
+    },
+};
+use anyhow::{Context, Result};
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process::Child,
+    sync::{Arc, Mutex, MutexGuard},
+    thread::JoinHandle,
+};
+
+/// 没有换行的输出累积到该长度后单独记录为一行，避免无限占用内存
+const MAX_LINE: usize = 64 * 1024;
+
+/// 脚本的输出流
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    /// 日志中的流标签
+    fn label(self) -> &'static str {
+        match self {
+            Stream::Stdout => "out",
+            Stream::Stderr => "err",
+        }
+    }
+}
+
+/// 一次运行的日志文件
+///
+/// 每行格式为 `<本地时间> [out|err|rune] <内容>`。文件超过大小上限时
+/// 轮转为 `<file>.1`（覆盖上一次轮转的内容）并重新开始，因此单次运行
+/// 最多占用两倍上限的磁盘空间，且总是保留最新的输出
+#[derive(Debug)]
+pub struct RunLog {
+    path: PathBuf,
+    max_size: u64,
+    file: File,
+    written: u64,
+    /// 写入失败后不再尝试，只警告一次
+    failed: bool,
+}
+
+impl RunLog {
+    /// 创建日志文件，必要时创建父目录
+    pub fn create(path: &Path, max_size: u64) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create log file: {}", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_size,
+            file,
+            written: 0,
+            failed: false,
+        })
+    }
+
+    /// 记录 rune 自身的说明（如开始和结束）
+    pub fn note(&mut self, message: &str) {
+        self.write_record("rune", message.as_bytes());
+    }
+
+    /// 记录脚本输出的一行，行尾的换行符会被去掉
+    pub fn write_line(&mut self, stream: Stream, line: &[u8]) {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        self.write_record(stream.label(), line);
+    }
+
+    fn write_record(&mut self, label: &str, content: &[u8]) {
+        if self.failed {
+            return;
+        }
+        let record = format!(
+            "{} [{}] {}\n",
+            format_timestamp_millis(now_millis()),
+            label,
+            String::from_utf8_lossy(content)
+        );
+        if let Err(e) = self.append(record.as_bytes()) {
+            self.failed = true;
+            warn(format!(
+                "Failed to write log file {}: {}; output is no longer logged",
+                self.path.display(),
+                e
+            ));
+        }
+    }
+
+    fn append(&mut self, record: &[u8]) -> io::Result<()> {
+        if self.written > 0 && self.written + record.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(record)?;
+        self.written += record.len() as u64;
+        Ok(())
+    }
+
+    /// 把当前文件移到 `<file>.1`，然后重新开始写入
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        fs::rename(&self.path, rotated_path(&self.path))?;
+        self.file = File::create(&self.path)?;
+        self.written = 0;
+        let note = format!(
+            "{} [rune] log rotated, earlier output is in {}\n",
+            format_timestamp_millis(now_millis()),
+            rotated_path(&self.path).display()
+        );
+        self.file.write_all(note.as_bytes())?;
+        self.written += note.len() as u64;
+        Ok(())
+    }
+}
+
+/// 轮转后的日志文件路径
+pub fn rotated_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".1");
+    PathBuf::from(name)
+}
+
+/// 为一次运行生成日志文件路径
+///
+/// 文件名以本地时间开头，按名称排序即按时间排序
+pub fn new_log_path(dir: &Path, script: &str, timestamp: u64) -> PathBuf {
+    let time: String = format_timestamp(timestamp)
+        .chars()
+        .filter_map(|c| match c {
+            '0'..='9' => Some(c),
+            ' ' => Some('-'),
+            _ => None,
+        })
+        .collect();
+    let script: String = script
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "-_.".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    dir.join(format!("{}-{}-{}.log", time, std::process::id(), script))
+}
+
+/// 清理旧日志，最多保留 `max_files` 个运行日志（连同其轮转文件），0 表示不限制
+pub fn prune(dir: &Path, max_files: usize) -> Result<()> {
+    if max_files == 0 || !dir.is_dir() {
+        return Ok(());
+    }
+    let mut logs: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory: {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+        .collect();
+    if logs.len() <= max_files {
+        return Ok(());
+    }
+    logs.sort();
+    for path in &logs[..logs.len() - max_files] {
+        fs::remove_file(path)
+            .with_context(|| format!("Failed to remove log file: {}", path.display()))?;
+        let rotated = rotated_path(path);
+        if rotated.exists() {
+            fs::remove_file(&rotated)
+                .with_context(|| format!("Failed to remove log file: {}", rotated.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// 可在多个线程间共享的运行日志
+#[derive(Debug, Clone)]
+pub struct SharedLog(Arc<Mutex<RunLog>>);
+
+impl SharedLog {
+    pub fn new(log: RunLog) -> Self {
+        Self(Arc::new(Mutex::new(log)))
+    }
+
+    /// 记录 rune 自身的说明
+    pub fn note(&self, message: &str) {
+        self.lock().note(message);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, RunLog> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 转发子进程的 stdout/stderr
+///
+/// 输出实时转发到 rune 的 stdout/stderr：设置了前缀时逐行加上前缀，否则原样转发；
+/// 设置了日志时同时按行写入日志
+pub struct Tee {
+    log: Option<SharedLog>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Tee {
+    /// 接管子进程的输出管道，子进程需以 `Stdio::piped()` 启动
+    pub fn start(child: &mut Child, log: Option<&SharedLog>, prefix: Option<&str>) -> Self {
+        let mut threads = Vec::new();
+        if let Some(stdout) = child.stdout.take() {
+            let log = log.cloned();
+            let prefix = prefix.map(str::to_string);
+            threads.push(std::thread::spawn(move || {
+                pump(
+                    stdout,
+                    io::stdout(),
+                    Stream::Stdout,
+                    log.as_ref(),
+                    prefix.as_deref(),
+                )
+            }));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            let log = log.cloned();
+            let prefix = prefix.map(str::to_string);
+            threads.push(std::thread::spawn(move || {
+                pump(
+                    stderr,
+                    io::stderr(),
+                    Stream::Stderr,
+                    log.as_ref(),
+                    prefix.as_deref(),
+                )
+            }));
+        }
+        Self {
+            log: log.cloned(),
+            threads,
+        }
+    }
+
+    /// 等待输出转发完毕，并在日志中记录退出结果
+    ///
+    /// 脚本启动的后台进程仍持有管道时，会一直等到它们关闭输出
+    pub fn finish(self, outcome: ExitOutcome) {
+        for thread in self.threads {
+            let _ = thread.join();
+        }
+        let Some(log) = self.log else {
+            return;
+        };
+        let status = match outcome {
+            ExitOutcome::Exited(code) => format!("exit {}", code),
+            ExitOutcome::Signaled(signal) => format!("signal {}", signal),
+            ExitOutcome::TimedOut => "timeout".to_string(),
+        };
+        log.note(&format!("finished: {}", status));
+    }
+}
+
+/// 从管道读取输出，转发到终端并按行写入日志
+fn pump(
+    mut reader: impl Read,
+    mut echo: impl Write,
+    stream: Stream,
+    log: Option<&SharedLog>,
+    prefix: Option<&str>,
+) {
+    // 终端不可写（如管道已关闭）时继续读取，避免脚本因管道写满而阻塞
+    let emit = |echo: &mut dyn Write, line: &[u8]| {
+        if let Some(prefix) = prefix {
+            let mut out = Vec::with_capacity(prefix.len() + line.len() + 1);
+            out.extend_from_slice(prefix.as_bytes());
+            out.extend_from_slice(line);
+            if !line.ends_with(b"\n") {
+                out.push(b'\n');
+            }
+            let _ = echo.write_all(&out).and_then(|_| echo.flush());
+        }
+        if let Some(log) = log {
+            log.lock().write_line(stream, line);
+        }
+    };
+
+    let mut buf = [0u8; 8192];
+    let mut pending = Vec::new();
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        };
+        if prefix.is_none() {
+            let _ = echo.write_all(&buf[..n]).and_then(|_| echo.flush());
+            if log.is_none() {
+                continue;
+            }
+        }
+
+        pending.extend_from_slice(&buf[..n]);
+        while let Some(pos) = pending.iter().position(|&b| b == b'\n') {
+            emit(&mut echo, &pending[..=pos]);
+            pending.drain(..=pos);
+        }
+        if pending.len() >= MAX_LINE {
+            emit(&mut echo, &pending);
+            pending.clear();
+        }
+    }
+    if !pending.is_empty() {
+        emit(&mut echo, &pending);
+    }
+}
+
+/// 读取运行日志，包括轮转前的部分
+pub fn read_log(path: &Path) -> Result<String> {
+    let mut content = String::new();
+    let rotated = rotated_path(path);
+    if rotated.exists() {
+        content.push_str(
+            &fs::read_to_string(&rotated)
+                .with_context(|| format!("Failed to read log file: {}", rotated.display()))?,
+        );
+    }
+    content.push_str(
+        &fs::read_to_string(path)
+            .with_context(|| format!("Failed to read log file: {}", path.display()))?,
+    );
+    Ok(content)
+}
+
+/// 输出日志中 `offset` 之后的内容，返回新的位置
+///
+/// 文件变短说明日志已经轮转，先输出轮转文件中剩余的部分，再从头读取新文件；
+/// 文件不存在时不输出任何内容
+pub fn copy_since(path: &Path, offset: u64, out: &mut impl Write) -> Result<u64> {
+    let Ok(metadata) = fs::metadata(path) else {
+        return Ok(offset);
+    };
+    let mut offset = offset;
+    if metadata.len() < offset {
+        copy_from(&rotated_path(path), offset, out)?;
+        offset = 0;
+    }
+    Ok(offset + copy_from(path, offset, out)?)
+}
+
+/// 从 `offset` 开始输出文件内容，返回输出的字节数
+fn copy_from(path: &Path, offset: u64, out: &mut impl Write) -> Result<u64> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to read log file: {}", path.display()));
+        }
+    };
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| io::copy(&mut file, out))
+        .and_then(|copied| out.flush().map(|_| copied))
+        .with_context(|| format!("Failed to read log file: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_run_log_lines() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("logs").join("run.log");
+        let mut log = RunLog::create(&path, 1024).unwrap();
+        log.note("started: deploy");
+        log.write_line(Stream::Stdout, b"hello\r\n");
+        log.write_line(Stream::Stderr, b"oops\n");
+
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with(" [rune] started: deploy"));
+        assert!(lines[1].ends_with(" [out] hello"));
+        assert!(lines[2].ends_with(" [err] oops"));
+    }
+
+    #[test]
+    fn test_run_log_rotation() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("run.log");
+        let mut log = RunLog::create(&path, 200).unwrap();
+        for i in 0..20 {
+            log.write_line(Stream::Stdout, format!("line {}", i).as_bytes());
+        }
+
+        let rotated = rotated_path(&path);
+        assert!(rotated.exists());
+        assert!(fs::metadata(&path).unwrap().len() <= 200);
+        assert!(fs::metadata(&rotated).unwrap().len() <= 200);
+        let content = read_log(&path).unwrap();
+        assert!(content.contains("[out] line 19"));
+        assert!(!content.contains("[out] line 0\n"));
+        assert!(content.contains("log rotated"));
+    }
+
+    #[test]
+    fn test_copy_since_follows_rotation() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("export.log");
+        let mut out = Vec::new();
+
+        assert_eq!(copy_since(&path, 0, &mut out).unwrap(), 0);
+        fs::write(&path, "one\n").unwrap();
+        let offset = copy_since(&path, 0, &mut out).unwrap();
+        assert_eq!(offset, 4);
+
+        // 轮转前又写入了一行，轮转后新文件比已读取的位置短
+        fs::write(&path, "one\ntwo\n").unwrap();
+        fs::rename(&path, rotated_path(&path)).unwrap();
+        fs::write(&path, "3\n").unwrap();
+        assert_eq!(copy_since(&path, offset, &mut out).unwrap(), 2);
+        assert_eq!(String::from_utf8(out).unwrap(), "one\ntwo\n3\n");
+    }
+
+    #[test]
+    fn test_new_log_path() {
+        let dir = Path::new("/logs");
+        let path = new_log_path(dir, "ops/deploy app", 0);
+        let name = path.file_name().unwrap().to_str().unwrap();
+        assert!(name.ends_with("-ops_deploy_app.log"));
+        assert!(name.starts_with("19"));
+        assert_eq!(path.parent(), Some(dir));
+    }
+
+    #[test]
+    fn test_prune() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        for name in ["1-a.log", "2-b.log", "3-c.log"] {
+            fs::write(dir.join(name), "x").unwrap();
+        }
+        fs::write(dir.join("1-a.log.1"), "x").unwrap();
+        fs::write(dir.join("notes.txt"), "x").unwrap();
+
+        prune(dir, 2).unwrap();
+        assert!(!dir.join("1-a.log").exists());
+        assert!(!dir.join("1-a.log.1").exists());
+        assert!(dir.join("2-b.log").exists());
+        assert!(dir.join("3-c.log").exists());
+        assert!(dir.join("notes.txt").exists());
+
+        prune(dir, 0).unwrap();
+        assert!(dir.join("2-b.log").exists());
+    }
+
+    #[test]
+    fn test_pump_splits_lines() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("run.log");
+        let log = SharedLog::new(RunLog::create(&path, 1024).unwrap());
+        let mut echo = Vec::new();
+        pump(
+            &b"one\ntwo\npartial"[..],
+            &mut echo,
+            Stream::Stdout,
+            Some(&log),
+            None,
+        );
+
+        assert_eq!(echo, b"one\ntwo\npartial");
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[2].ends_with(" [out] partial"));
+    }
+
+    #[test]
+    fn test_pump_prefixes_lines() {
+        let mut echo = Vec::new();
+        pump(
+            &b"one\ntwo"[..],
+            &mut echo,
+            Stream::Stdout,
+            None,
+            Some("lint | "),
+        );
+        assert_eq!(echo, b"lint | one\nlint | two\n");
+    }
+}
@@ -0,0 +1,400 @@
+use crate::{
+    core::{
+        executor::RetryPolicy,
+        limits::ResourceLimits,
+        lock::LockWait,
+        params::{self, ArgSpec, ArgStyle},
+        plugin::PluginDatabase,
+        sandbox::{self, SandboxPolicy},
+        script::Script,
+        workflow::Workflow,
+    },
+    utils::duration::parse_duration,
+};
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path, time::Duration};
+
+/// 未声明注释语法时尝试的注释前缀
+pub const DEFAULT_COMMENT_PREFIXES: [&str; 3] = ["#", "//", "--"];
+
+/// front matter 的起止标记
+const FENCE: &str = "---";
+
+/// 脚本元数据
+///
+/// 从脚本开头注释中的 front matter 解析，例如：
+///
+/// ```text
+/// #!/usr/bin/env python3
+/// # ---
+/// # description: Back up the production database
+/// # tags: [db, backup]
+/// # timeout: 30m
+/// # ---
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptMetadata {
+    /// 脚本描述
+    #[serde(default)]
+    pub description: String,
+    /// 标签
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// 运行脚本使用的插件
+    #[serde(default)]
+    pub plugin: Option<String>,
+    /// 运行超时时间（如 30s、5m、1h30m）
+    #[serde(default)]
+    pub timeout: Option<String>,
+    /// 失败后的重试次数
+    #[serde(default)]
+    pub retries: Option<u32>,
+    /// 第一次重试前的等待时间
+    #[serde(default)]
+    pub retry_delay: Option<String>,
+    /// 每次重试后等待时间的倍数
+    #[serde(default)]
+    pub backoff: Option<f64>,
+    /// 是否禁止同时运行多个实例（锁名称为脚本名称）
+    #[serde(default)]
+    pub exclusive: bool,
+    /// 单实例锁的名称，设置后隐含 `exclusive: true`；多个脚本可共用同一个锁
+    #[serde(default)]
+    pub lock: Option<String>,
+    /// 锁被占用时的等待方式：`forever` 或最长等待时间，未设置时立即失败
+    #[serde(default)]
+    pub lock_wait: Option<String>,
+    /// 资源限制（如 `mem: 2G`、`cpu: 60s`），逐项覆盖插件的设置
+    #[serde(default)]
+    pub limits: ResourceLimits,
+    /// 沙箱策略（`true` 或包含 `writable`、`network`、`private_tmp` 的策略），逐项覆盖插件的设置
+    #[serde(default, deserialize_with = "sandbox::deserialize_policy")]
+    pub sandbox: Option<SandboxPolicy>,
+    /// 额外设置的环境变量，值支持模板占位符（{args} 除外）
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// 脚本声明的参数；声明后运行时会验证用户输入
+    #[serde(default)]
+    pub args: Vec<ArgSpec>,
+    /// 参数传给脚本的方式
+    #[serde(default)]
+    pub arg_style: ArgStyle,
+}
+
+impl ScriptMetadata {
+    /// 从脚本文件加载元数据
+    ///
+    /// `prefixes` 为可能的注释前缀（通常来自匹配插件的 `comment` 字段）；
+    /// 文件无法以文本读取或没有 front matter 时返回默认元数据
+    ///
+    /// # 错误
+    /// front matter 未闭合或内容无效
+    pub fn load(path: &Path, prefixes: &[&str]) -> Result<Self> {
+        let Ok(content) = fs::read_to_string(path) else {
+            return Ok(Self::default());
+        };
+        Self::parse(&content, prefixes)
+            .with_context(|| format!("Invalid metadata in {}", path.display()))
+    }
+
+    /// 加载脚本的元数据
+    ///
+    /// 注释前缀取自支持该脚本扩展名或解释器的插件，这些插件都没有声明时使用默认前缀
+    pub fn for_script(script: &Script, db: &PluginDatabase) -> Result<Self> {
+        // 工作流的描述和标签写在工作流文件中
+        if script.is_workflow() {
+            let workflow = Workflow::load(&script.path)?;
+            return Ok(Self {
+                description: workflow.description,
+                tags: workflow.tags,
+                ..Self::default()
+            });
+        }
+        let mut plugins = if script.extension.is_empty() {
+            Vec::new()
+        } else {
+            db.find_all_by_extension(&script.extension)
+        };
+        if let Some(interpreter) = &script.interpreter {
+            plugins.extend(db.find_all_by_interpreter(interpreter));
+        }
+        let mut prefixes: Vec<&str> = plugins
+            .iter()
+            .filter_map(|p| p.comment.as_deref())
+            .collect();
+        if prefixes.is_empty() {
+            prefixes.extend(DEFAULT_COMMENT_PREFIXES);
+        }
+        Self::load(&script.path, &prefixes)
+    }
+
+    /// 从脚本内容解析元数据
+    pub fn parse(content: &str, prefixes: &[&str]) -> Result<Self> {
+        let Some(block) = extract_front_matter(content, prefixes)? else {
+            return Ok(Self::default());
+        };
+        if block.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        let metadata: Self =
+            serde_yaml::from_str(&block).context("Failed to parse front matter")?;
+        metadata.check()?;
+        Ok(metadata)
+    }
+
+    /// 验证字段取值
+    fn check(&self) -> Result<()> {
+        if let Some(timeout) = &self.timeout {
+            parse_duration(timeout).context("Invalid timeout")?;
+        }
+        if let Some(delay) = &self.retry_delay {
+            RetryPolicy::parse_delay(delay)?;
+        }
+        if let Some(backoff) = self.backoff {
+            RetryPolicy::check_backoff(backoff)?;
+        }
+        if self.lock.as_deref().is_some_and(|l| l.trim().is_empty()) {
+            bail!("Lock name cannot be empty");
+        }
+        if let Some(wait) = &self.lock_wait {
+            LockWait::parse(wait)?;
+        }
+        for key in self.env.keys() {
+            if key.is_empty() || key.contains('=') {
+                bail!("Invalid environment variable name '{}'", key);
+            }
+        }
+        if let Some(sandbox) = &self.sandbox {
+            sandbox.validate()?;
+        }
+        params::check_specs(&self.args)?;
+        Ok(())
+    }
+
+    /// 解析后的超时时间
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout.as_deref().and_then(|t| parse_duration(t).ok())
+    }
+
+    /// 解析后的重试间隔
+    pub fn retry_delay(&self) -> Option<Duration> {
+        self.retry_delay
+            .as_deref()
+            .and_then(|d| RetryPolicy::parse_delay(d).ok())
+    }
+
+    /// 解析后的锁等待方式
+    pub fn lock_wait(&self) -> Option<LockWait> {
+        self.lock_wait
+            .as_deref()
+            .and_then(|w| LockWait::parse(w).ok())
+    }
+
+    /// 是否没有任何元数据
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// 从开头的注释行中提取 front matter 文本
+///
+/// 跳过 shebang 和空行，在连续的注释行中寻找 `<前缀> ---` 开始标记，
+/// 收集到下一个 `<前缀> ---` 为止，并去掉每行的注释前缀
+fn extract_front_matter(content: &str, prefixes: &[&str]) -> Result<Option<String>> {
+    // 较长的前缀优先，避免 "--" 被 "-" 之类的前缀截断
+    let mut prefixes: Vec<&str> = prefixes.iter().copied().filter(|p| !p.is_empty()).collect();
+    prefixes.sort_by_key(|p| std::cmp::Reverse(p.len()));
+
+    let mut lines = content.lines().enumerate().peekable();
+    if lines.peek().is_some_and(|(_, l)| l.starts_with("#!")) {
+        lines.next();
+    }
+
+    // 寻找开始标记
+    let prefix = loop {
+        let Some((_, line)) = lines.next() else {
+            return Ok(None);
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Some(prefix) = prefixes.iter().find(|p| line.starts_with(**p)) else {
+            // 注释块结束前没有 front matter
+            return Ok(None);
+        };
+        if line[prefix.len()..].trim() == FENCE {
+            break *prefix;
+        }
+    };
+
+    // 收集到结束标记
+    let mut block = String::new();
+    for (index, line) in lines {
+        let Some(body) = line.trim_start().strip_prefix(prefix) else {
+            bail!(
+                "Unterminated front matter: line {} is not a '{}' comment",
+                index + 1,
+                prefix
+            );
+        };
+        if body.trim() == FENCE {
+            return Ok(Some(block));
+        }
+        block.push_str(body.strip_prefix(' ').unwrap_or(body));
+        block.push('\n');
+    }
+    bail!(
+        "Unterminated front matter: missing closing '{} {}'",
+        prefix,
+        FENCE
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::params::ArgType;
+
+    const PREFIXES: &[&str] = &DEFAULT_COMMENT_PREFIXES;
+
+    #[test]
+    fn test_parse_full_metadata() {
+        let content = r#"#!/usr/bin/env python3
+# ---
+# description: Back up the database
+# tags: [db, backup]
+# plugin: python-uv
+# timeout: 30m
+# env:
+#   PGHOST: localhost
+# args:
+#   - name: target
+#     help: Database to back up
+#     required: true
+#   - name: retries
+#     type: int
+#     default: 3
+# arg_style: env
+# ---
+import sys
+"#;
+        let metadata = ScriptMetadata::parse(content, PREFIXES).unwrap();
+
+        assert_eq!(metadata.description, "Back up the database");
+        assert_eq!(metadata.tags, vec!["db", "backup"]);
+        assert_eq!(metadata.plugin.as_deref(), Some("python-uv"));
+        assert_eq!(metadata.timeout(), Some(Duration::from_secs(1800)));
+        assert_eq!(metadata.env.get("PGHOST").unwrap(), "localhost");
+        assert_eq!(metadata.args.len(), 2);
+        assert_eq!(metadata.args[0].name, "target");
+        assert_eq!(metadata.args[0].help, "Database to back up");
+        assert!(metadata.args[0].required);
+        assert_eq!(metadata.args[1].kind, ArgType::Int);
+        assert_eq!(metadata.args[1].default.as_deref(), Some("3"));
+        assert_eq!(metadata.arg_style, ArgStyle::Env);
+    }
+
+    #[test]
+    fn test_parse_other_comment_styles() {
+        let js = "// ---\n// description: Lint the code\n// ---\nconsole.log(1)\n";
+        assert_eq!(
+            ScriptMetadata::parse(js, PREFIXES).unwrap().description,
+            "Lint the code"
+        );
+
+        let lua = "-- ---\n-- description: Render\n-- ---\n";
+        assert_eq!(
+            ScriptMetadata::parse(lua, PREFIXES).unwrap().description,
+            "Render"
+        );
+
+        let bat = "REM ---\nREM description: Windows\nREM ---\n";
+        assert_eq!(
+            ScriptMetadata::parse(bat, &["REM"]).unwrap().description,
+            "Windows"
+        );
+    }
+
+    #[test]
+    fn test_parse_after_header_comments() {
+        let content =
+            "#!/bin/bash\n\n# Copyright 2024\n#\n# ---\n# description: Deploy\n# ---\necho\n";
+        assert_eq!(
+            ScriptMetadata::parse(content, PREFIXES)
+                .unwrap()
+                .description,
+            "Deploy"
+        );
+    }
+
+    #[test]
+    fn test_parse_without_front_matter() {
+        let content =
+            "#!/bin/bash\n# just a comment\necho hi\n# ---\n# description: too late\n# ---\n";
+        assert!(ScriptMetadata::parse(content, PREFIXES).unwrap().is_empty());
+        assert!(ScriptMetadata::parse("", PREFIXES).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_unterminated() {
+        let content = "# ---\n# description: x\necho hi\n";
+        let result = ScriptMetadata::parse(content, PREFIXES);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("Unterminated"));
+    }
+
+    #[test]
+    fn test_parse_invalid_fields() {
+        assert!(ScriptMetadata::parse("# ---\n# timeout: soon\n# ---\n", PREFIXES).is_err());
+        assert!(ScriptMetadata::parse("# ---\n# unknown: 1\n# ---\n", PREFIXES).is_err());
+        assert!(ScriptMetadata::parse("# ---\n# retry_delay: later\n# ---\n", PREFIXES).is_err());
+        assert!(ScriptMetadata::parse("# ---\n# backoff: 0.5\n# ---\n", PREFIXES).is_err());
+    }
+
+    #[test]
+    fn test_parse_lock_fields() {
+        let content = "# ---\n# exclusive: true\n# lock: db\n# lock_wait: 5m\n# ---\n";
+        let metadata = ScriptMetadata::parse(content, PREFIXES).unwrap();
+        assert!(metadata.exclusive);
+        assert_eq!(metadata.lock.as_deref(), Some("db"));
+        assert_eq!(
+            metadata.lock_wait(),
+            Some(LockWait::Timeout(Duration::from_secs(300)))
+        );
+
+        assert!(ScriptMetadata::parse("# ---\n# lock_wait: soon\n# ---\n", PREFIXES).is_err());
+        assert!(ScriptMetadata::parse("# ---\n# lock: ''\n# ---\n", PREFIXES).is_err());
+    }
+
+    #[test]
+    fn test_parse_retry_fields() {
+        let content = "# ---\n# retries: 3\n# retry_delay: 2s\n# backoff: 2\n# ---\n";
+        let metadata = ScriptMetadata::parse(content, PREFIXES).unwrap();
+        assert_eq!(metadata.retries, Some(3));
+        assert_eq!(metadata.retry_delay(), Some(Duration::from_secs(2)));
+        assert_eq!(metadata.backoff, Some(2.0));
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let content = "# ---\n# limits:\n#   mem: 512M\n#   cpu: 60s\n#   nproc: 32\n# ---\n";
+        let metadata = ScriptMetadata::parse(content, PREFIXES).unwrap();
+        assert_eq!(metadata.limits.to_string(), "mem=512M,cpu=60s,nproc=32");
+        assert!(ScriptMetadata::parse("# ---\n# limits:\n#   disk: 1G\n# ---\n", PREFIXES).is_err());
+    }
+
+    #[test]
+    fn test_parse_sandbox() {
+        let content = "# ---\n# sandbox:\n#   writable: [out]\n#   private_tmp: false\n# ---\n";
+        let metadata = ScriptMetadata::parse(content, PREFIXES).unwrap();
+        let sandbox = metadata.sandbox.unwrap();
+        assert_eq!(sandbox.writable, ["out"]);
+        assert_eq!(sandbox.private_tmp, Some(false));
+        let metadata = ScriptMetadata::parse("# ---\n# sandbox: false\n# ---\n", PREFIXES).unwrap();
+        assert_eq!(metadata.sandbox, None);
+        assert!(ScriptMetadata::parse("# ---\n# sandbox:\n#   net: false\n# ---\n", PREFIXES).is_err());
+    }
+}
@@ -1,3 +1,23 @@
+pub mod background;
+pub mod config;
+pub mod cron;
 pub mod executor;
+pub mod history;
+pub mod limits;
+pub mod lock;
+pub mod logs;
+pub mod metadata;
+pub mod params;
 pub mod plugin;
+pub mod preferences;
+pub mod repo;
+pub mod sandbox;
+pub mod schedule;
 pub mod script;
+#[cfg(unix)]
+pub mod signal;
+pub mod signing;
+pub mod template;
+pub mod trust;
+pub mod watch;
+pub mod workflow;
@@ -0,0 +1,628 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{collections::HashSet, fmt, path::Path};
+
+/// 参数类型
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArgType {
+    /// 任意字符串
+    #[default]
+    String,
+    /// 整数
+    Int,
+    /// 浮点数
+    Float,
+    /// 开关，只能通过 `--name` / `--no-name` 指定
+    Bool,
+    /// 已存在的文件或目录，以绝对路径传给脚本
+    Path,
+}
+
+impl fmt::Display for ArgType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ArgType::String => "string",
+            ArgType::Int => "int",
+            ArgType::Float => "float",
+            ArgType::Bool => "bool",
+            ArgType::Path => "path",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// 参数传给脚本的方式
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArgStyle {
+    /// 按声明顺序作为位置参数
+    #[default]
+    Positional,
+    /// 作为 `--name value` 形式的选项
+    Flags,
+    /// 作为 `RUNE_ARG_<NAME>` 环境变量
+    Env,
+}
+
+impl fmt::Display for ArgStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ArgStyle::Positional => "positional",
+            ArgStyle::Flags => "flags",
+            ArgStyle::Env => "env",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// 脚本参数声明
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArgSpec {
+    /// 参数名称
+    pub name: String,
+    /// 参数类型
+    #[serde(default, rename = "type")]
+    pub kind: ArgType,
+    /// 是否必须提供
+    #[serde(default)]
+    pub required: bool,
+    /// 默认值
+    #[serde(default, deserialize_with = "scalar_option")]
+    pub default: Option<String>,
+    /// 允许的取值
+    #[serde(default, deserialize_with = "scalar_list")]
+    pub choices: Vec<String>,
+    /// 帮助文本
+    #[serde(default)]
+    pub help: String,
+}
+
+/// 将 YAML 标量（字符串、数字、布尔值）读取为字符串
+fn scalar_to_string<E: serde::de::Error>(value: serde_yaml::Value) -> Result<String, E> {
+    match value {
+        serde_yaml::Value::String(s) => Ok(s),
+        serde_yaml::Value::Number(n) => Ok(n.to_string()),
+        serde_yaml::Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(E::custom("expected a string, number or boolean")),
+    }
+}
+
+fn scalar_option<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    match Option::<serde_yaml::Value>::deserialize(deserializer)? {
+        None | Some(serde_yaml::Value::Null) => Ok(None),
+        Some(value) => scalar_to_string(value).map(Some),
+    }
+}
+
+fn scalar_list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    Vec::<serde_yaml::Value>::deserialize(deserializer)?
+        .into_iter()
+        .map(scalar_to_string)
+        .collect()
+}
+
+impl ArgSpec {
+    /// 命令行选项名（如 `--dry-run`）
+    pub fn flag(&self) -> String {
+        format!("--{}", self.name.replace('_', "-"))
+    }
+
+    /// 环境变量名（如 `RUNE_ARG_DRY_RUN`）
+    pub fn env_var(&self) -> String {
+        format!("RUNE_ARG_{}", self.name.replace('-', "_").to_uppercase())
+    }
+
+    /// 用法说明中的参数标签（如 `<env>`、`[tag]`、`--dry-run`）
+    pub fn label(&self) -> String {
+        match (self.kind, self.required) {
+            (ArgType::Bool, _) => self.flag(),
+            (_, true) => format!("<{}>", self.name),
+            (_, false) => format!("[{}]", self.name),
+        }
+    }
+
+    /// 帮助文本及类型、可选值、默认值说明
+    pub fn summary(&self) -> String {
+        let mut text = self.help.clone();
+        let mut notes = Vec::new();
+        if !matches!(self.kind, ArgType::String | ArgType::Bool) {
+            notes.push(self.kind.to_string());
+        }
+        if !self.choices.is_empty() {
+            notes.push(format!("choices: {}", self.choices.join(", ")));
+        }
+        if let Some(default) = &self.default {
+            notes.push(format!("default: {}", default));
+        }
+        for note in notes {
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            text.push_str(&format!("[{}]", note));
+        }
+        text
+    }
+
+    /// 检查参数声明本身是否有效
+    fn check(&self) -> Result<()> {
+        if self.name.is_empty() {
+            bail!("Argument name cannot be empty");
+        }
+        if !self
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            bail!(
+                "Invalid argument name '{}' (use letters, digits, '-' and '_')",
+                self.name
+            );
+        }
+        if self.kind == ArgType::Bool && self.required {
+            bail!("Bool argument '{}' cannot be required", self.name);
+        }
+        if self.required && self.default.is_some() {
+            bail!(
+                "Argument '{}' cannot be both required and have a default",
+                self.name
+            );
+        }
+        // 路径是否存在只能在运行时检查
+        if self.kind != ArgType::Path {
+            for choice in &self.choices {
+                self.parse_value(choice)
+                    .with_context(|| format!("Invalid choice for argument '{}'", self.name))?;
+            }
+            if let Some(default) = &self.default {
+                self.validate(default)
+                    .with_context(|| format!("Invalid default for argument '{}'", self.name))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 按类型解析取值，返回规范化后的字符串
+    fn parse_value(&self, value: &str) -> Result<String> {
+        Ok(match self.kind {
+            ArgType::String => value.to_string(),
+            ArgType::Int => {
+                value
+                    .parse::<i64>()
+                    .map_err(|_| anyhow::anyhow!("'{}' is not an integer", value))?;
+                value.to_string()
+            }
+            ArgType::Float => {
+                value
+                    .parse::<f64>()
+                    .map_err(|_| anyhow::anyhow!("'{}' is not a number", value))?;
+                value.to_string()
+            }
+            ArgType::Bool => match value.to_ascii_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => "true".to_string(),
+                "false" | "no" | "off" | "0" => "false".to_string(),
+                _ => bail!("'{}' is not a boolean (use true or false)", value),
+            },
+            ArgType::Path => {
+                let path = Path::new(value);
+                if !path.exists() {
+                    bail!("Path '{}' does not exist", value);
+                }
+                path.canonicalize()
+                    .with_context(|| format!("Failed to resolve path '{}'", value))?
+                    .to_str()
+                    .ok_or_else(|| anyhow::anyhow!("Invalid path: non-UTF8 characters"))?
+                    .to_string()
+            }
+        })
+    }
+
+    /// 验证取值的类型和可选值范围
+    fn validate(&self, value: &str) -> Result<String> {
+        if !self.choices.is_empty() && !self.choices.iter().any(|c| c == value) {
+            bail!(
+                "'{}' is not one of the allowed values: {}",
+                value,
+                self.choices.join(", ")
+            );
+        }
+        self.parse_value(value)
+    }
+}
+
+/// 检查一组参数声明
+///
+/// # 错误
+/// - 参数名称无效或重复
+/// - 默认值或可选值与类型不符
+pub fn check_specs(specs: &[ArgSpec]) -> Result<()> {
+    let mut seen = HashSet::new();
+    for spec in specs {
+        spec.check()?;
+        if !seen.insert(spec.flag()) {
+            bail!("Duplicate argument '{}'", spec.name);
+        }
+    }
+    Ok(())
+}
+
+/// 用户参数中是否请求了帮助（`--` 之后的参数除外）
+pub fn wants_help(input: &[String]) -> bool {
+    input
+        .iter()
+        .take_while(|arg| *arg != "--")
+        .any(|arg| arg == "--help" || arg == "-h")
+}
+
+/// 解析后传给脚本的参数和环境变量
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ResolvedArgs {
+    /// 命令行参数
+    pub args: Vec<String>,
+    /// 环境变量
+    pub env: Vec<(String, String)>,
+}
+
+/// 按参数声明解析用户输入
+///
+/// 用户可以按声明顺序提供位置参数，也可以使用 `--name value`、`--name=value`；
+/// 布尔参数使用 `--name` / `--no-name`。`--` 之后的参数原样追加在末尾
+///
+/// # 错误
+/// - 未知选项或多余的位置参数
+/// - 取值类型不符或不在可选值中
+/// - 缺少必需参数
+pub fn resolve(specs: &[ArgSpec], style: ArgStyle, input: &[String]) -> Result<ResolvedArgs> {
+    let mut values: Vec<Option<String>> = vec![None; specs.len()];
+    let mut positional = Vec::new();
+    let mut passthrough = Vec::new();
+
+    let mut tokens = input.iter();
+    while let Some(token) = tokens.next() {
+        if token == "--" {
+            passthrough.extend(tokens.cloned());
+            break;
+        }
+        let Some(option) = token.strip_prefix("--") else {
+            // 负数作为取值，其他以 - 开头的参数视为未知选项
+            if token.len() > 1
+                && token.starts_with('-')
+                && !token[1..].starts_with(|c: char| c.is_ascii_digit() || c == '.')
+            {
+                bail!("Unknown option '{}'", token);
+            }
+            positional.push(token.clone());
+            continue;
+        };
+
+        let (key, inline) = match option.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (option, None),
+        };
+        let key = key.replace('_', "-");
+        let (index, negated) = match specs.iter().position(|s| s.flag() == format!("--{}", key)) {
+            Some(index) => (index, false),
+            None => key
+                .strip_prefix("no-")
+                .and_then(|key| {
+                    specs
+                        .iter()
+                        .position(|s| s.kind == ArgType::Bool && s.flag() == format!("--{}", key))
+                })
+                .map(|index| (index, true))
+                .ok_or_else(|| anyhow::anyhow!("Unknown option '--{}'", key))?,
+        };
+        let spec = &specs[index];
+
+        let value = match (spec.kind, negated, inline) {
+            (ArgType::Bool, true, None) => "false".to_string(),
+            (ArgType::Bool, true, Some(_)) => bail!("Option '--{}' does not take a value", key),
+            (ArgType::Bool, false, None) => "true".to_string(),
+            (_, _, Some(value)) => value.to_string(),
+            (_, _, None) => tokens
+                .next()
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("Missing value for '{}'", spec.flag()))?,
+        };
+        if values[index].is_some() {
+            bail!("Argument '{}' was given more than once", spec.name);
+        }
+        values[index] = Some(value);
+    }
+
+    // 位置参数按声明顺序填入尚未通过选项指定的非布尔参数
+    let mut slots = specs
+        .iter()
+        .enumerate()
+        .filter(|(i, s)| s.kind != ArgType::Bool && values[*i].is_none())
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>()
+        .into_iter();
+    for value in positional {
+        let index = slots
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Unexpected argument '{}'", value))?;
+        values[index] = Some(value);
+    }
+
+    // 验证取值，填充默认值
+    let mut resolved: Vec<Option<String>> = Vec::with_capacity(specs.len());
+    for (spec, value) in specs.iter().zip(values) {
+        let value = match value.or_else(|| spec.default.clone()) {
+            Some(value) => Some(
+                spec.validate(&value)
+                    .with_context(|| format!("Invalid value for argument '{}'", spec.name))?,
+            ),
+            None if spec.required => bail!("Missing required argument '{}'", spec.name),
+            None if spec.kind == ArgType::Bool => Some("false".to_string()),
+            None => None,
+        };
+        resolved.push(value);
+    }
+
+    let mut result = ResolvedArgs::default();
+    match style {
+        ArgStyle::Positional => {
+            // 未提供的可选参数以空字符串占位，末尾的省略
+            let len = resolved
+                .iter()
+                .rposition(Option::is_some)
+                .map_or(0, |i| i + 1);
+            result.args = resolved[..len]
+                .iter()
+                .map(|v| v.clone().unwrap_or_default())
+                .collect();
+        }
+        ArgStyle::Flags => {
+            for (spec, value) in specs.iter().zip(&resolved) {
+                match (spec.kind, value.as_deref()) {
+                    (ArgType::Bool, Some("true")) => result.args.push(spec.flag()),
+                    (ArgType::Bool, _) | (_, None) => {}
+                    (_, Some(value)) => {
+                        result.args.push(spec.flag());
+                        result.args.push(value.to_string());
+                    }
+                }
+            }
+        }
+        ArgStyle::Env => {
+            for (spec, value) in specs.iter().zip(resolved) {
+                if let Some(value) = value {
+                    result.env.push((spec.env_var(), value));
+                }
+            }
+        }
+    }
+    result.args.extend(passthrough);
+    Ok(result)
+}
+
+/// 生成脚本的用法说明
+pub fn usage(script: &str, description: &str, specs: &[ArgSpec]) -> String {
+    let mut out = String::new();
+    if !description.is_empty() {
+        out.push_str(description);
+        out.push_str("\n\n");
+    }
+
+    if specs.is_empty() {
+        out.push_str(&format!("Usage: rune run {} [-- <ARGS>...]\n", script));
+        return out;
+    }
+
+    let (switches, values): (Vec<&ArgSpec>, Vec<&ArgSpec>) =
+        specs.iter().partition(|s| s.kind == ArgType::Bool);
+    out.push_str(&format!("Usage: rune run {} --", script));
+    if !switches.is_empty() {
+        out.push_str(" [OPTIONS]");
+    }
+    for spec in &values {
+        out.push(' ');
+        out.push_str(&spec.label());
+    }
+    out.push('\n');
+
+    let mut rows: Vec<(String, String)> = Vec::new();
+    if !values.is_empty() {
+        rows.push(("Arguments:".to_string(), String::new()));
+        for spec in &values {
+            rows.push((spec.label(), spec.summary()));
+        }
+    }
+    rows.push(("Options:".to_string(), String::new()));
+    for spec in &switches {
+        rows.push((spec.flag(), spec.summary()));
+    }
+    for spec in &values {
+        rows.push((format!("{} <{}>", spec.flag(), spec.name), spec.summary()));
+    }
+    rows.push(("-h, --help".to_string(), "Print help".to_string()));
+
+    let width = rows
+        .iter()
+        .filter(|(label, help)| !(label.ends_with(':') && help.is_empty()))
+        .map(|(label, _)| label.len())
+        .max()
+        .unwrap_or(0);
+    for (label, help) in rows {
+        if label.ends_with(':') && help.is_empty() {
+            out.push_str(&format!("\n{}\n", label));
+        } else {
+            out.push_str(&format!("  {:width$}  {}\n", label, help, width = width));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn specs() -> Vec<ArgSpec> {
+        vec![
+            ArgSpec {
+                name: "env".to_string(),
+                required: true,
+                choices: strings(&["dev", "prod"]),
+                ..Default::default()
+            },
+            ArgSpec {
+                name: "replicas".to_string(),
+                kind: ArgType::Int,
+                default: Some("1".to_string()),
+                ..Default::default()
+            },
+            ArgSpec {
+                name: "tag".to_string(),
+                ..Default::default()
+            },
+            ArgSpec {
+                name: "dry_run".to_string(),
+                kind: ArgType::Bool,
+                ..Default::default()
+            },
+        ]
+    }
+
+    fn resolve_with(style: ArgStyle, input: &[&str]) -> Result<ResolvedArgs> {
+        resolve(&specs(), style, &strings(input))
+    }
+
+    #[test]
+    fn test_resolve_positional() {
+        let result = resolve_with(ArgStyle::Positional, &["prod", "3"]).unwrap();
+        assert_eq!(result.args, vec!["prod", "3", "", "false"]);
+        assert!(result.env.is_empty());
+    }
+
+    #[test]
+    fn test_resolve_options_and_defaults() {
+        let result = resolve_with(ArgStyle::Positional, &["--tag=v2", "--dry-run", "dev"]).unwrap();
+        assert_eq!(result.args, vec!["dev", "1", "v2", "true"]);
+
+        let result = resolve_with(ArgStyle::Positional, &["--env", "dev", "--no-dry-run"]).unwrap();
+        assert_eq!(result.args, vec!["dev", "1", "", "false"]);
+    }
+
+    #[test]
+    fn test_resolve_flags_style() {
+        let result =
+            resolve_with(ArgStyle::Flags, &["prod", "--dry-run", "--", "--extra"]).unwrap();
+        assert_eq!(
+            result.args,
+            vec!["--env", "prod", "--replicas", "1", "--dry-run", "--extra"]
+        );
+    }
+
+    #[test]
+    fn test_resolve_env_style() {
+        let result = resolve_with(ArgStyle::Env, &["prod", "--replicas", "-2"]).unwrap();
+        assert!(result.args.is_empty());
+        assert_eq!(
+            result.env,
+            vec![
+                ("RUNE_ARG_ENV".to_string(), "prod".to_string()),
+                ("RUNE_ARG_REPLICAS".to_string(), "-2".to_string()),
+                ("RUNE_ARG_DRY_RUN".to_string(), "false".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_errors() {
+        let err = |input: &[&str]| {
+            resolve_with(ArgStyle::Positional, input)
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(err(&[]).contains("Missing required argument 'env'"));
+        assert!(err(&["--replicas", "2"]).contains("Missing required argument"));
+        assert!(err(&["staging"]).contains("Invalid value for argument 'env'"));
+        assert!(err(&["dev", "many"]).contains("Invalid value for argument 'replicas'"));
+        assert!(err(&["dev", "--verbose"]).contains("Unknown option '--verbose'"));
+        assert!(err(&["dev", "-v"]).contains("Unknown option '-v'"));
+        assert!(err(&["dev", "1", "x", "y"]).contains("Unexpected argument 'y'"));
+        assert!(err(&["dev", "--replicas"]).contains("Missing value"));
+        assert!(err(&["dev", "--tag", "a", "--tag", "b"]).contains("more than once"));
+    }
+
+    #[test]
+    fn test_resolve_path_is_absolute() {
+        let temp_dir = TempDir::new().unwrap();
+        let specs = vec![ArgSpec {
+            name: "input".to_string(),
+            kind: ArgType::Path,
+            ..Default::default()
+        }];
+        let path = temp_dir.path().to_str().unwrap().to_string();
+        let result = resolve(&specs, ArgStyle::Positional, std::slice::from_ref(&path)).unwrap();
+        assert!(Path::new(&result.args[0]).is_absolute());
+
+        let missing = format!("{}/missing", path);
+        assert!(resolve(&specs, ArgStyle::Positional, &[missing]).is_err());
+    }
+
+    #[test]
+    fn test_wants_help() {
+        assert!(wants_help(&strings(&["dev", "--help"])));
+        assert!(wants_help(&strings(&["-h"])));
+        assert!(!wants_help(&strings(&["dev", "--", "--help"])));
+    }
+
+    #[test]
+    fn test_check_specs() {
+        assert!(check_specs(&specs()).is_ok());
+
+        let invalid = |spec: ArgSpec| check_specs(&[spec]).is_err();
+        assert!(invalid(ArgSpec {
+            name: "bad name".to_string(),
+            ..Default::default()
+        }));
+        assert!(invalid(ArgSpec {
+            name: "n".to_string(),
+            kind: ArgType::Int,
+            default: Some("x".to_string()),
+            ..Default::default()
+        }));
+        assert!(invalid(ArgSpec {
+            name: "n".to_string(),
+            kind: ArgType::Bool,
+            required: true,
+            ..Default::default()
+        }));
+
+        let duplicate = vec![
+            ArgSpec {
+                name: "dry-run".to_string(),
+                ..Default::default()
+            },
+            ArgSpec {
+                name: "dry_run".to_string(),
+                ..Default::default()
+            },
+        ];
+        assert!(check_specs(&duplicate).is_err());
+    }
+
+    #[test]
+    fn test_usage() {
+        let text = usage("deploy", "Deploy the app", &specs());
+        assert!(text.starts_with(
+            "Deploy the app\n\nUsage: rune run deploy -- [OPTIONS] <env> [replicas] [tag]\n"
+        ));
+        assert!(text.contains("[choices: dev, prod]"));
+        assert!(text.contains("[int] [default: 1]"));
+        assert!(text.contains("--dry-run"));
+        assert!(text.contains("--replicas <replicas>"));
+        assert_eq!(
+            usage("hello", "", &[]),
+            "Usage: rune run hello [-- <ARGS>...]\n"
+        );
+    }
+}
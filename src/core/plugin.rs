@@ -1,8 +1,20 @@
-use crate::utils::fs::get_plugin_db;
+use crate::{
+    core::{
+        executor::RetryPolicy,
+        limits::ResourceLimits,
+        sandbox::{self, SandboxPolicy},
+        template,
+    },
+    utils::{cli::warn, duration::parse_duration, fs::get_plugin_db},
+};
 use anyhow::{Context, Result};
 use bincode::config;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fs};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, fs,
+    time::Duration,
+};
 
 /// 插件定义结构体
 ///
@@ -13,11 +25,15 @@ pub struct Plugin {
     pub name: String,
     /// 执行器命令（如 python3, bash, node）
     pub executor: String,
-    /// 参数模板，{file} 会被替换为脚本路径
+    /// 参数模板，支持 {file}、{args}、{dir}、{name}、{ext}、{repo}、{env:VAR} 占位符
     #[serde(default = "default_arg_template")]
     pub arg_template: Vec<String>,
     /// 支持的文件扩展名列表
+    #[serde(default)]
     pub extensions: Vec<String>,
+    /// 可通过 shebang 匹配到此插件的解释器名称（执行器本身总是可以匹配）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub interpreters: Vec<String>,
     /// 插件描述
     #[serde(default)]
     pub description: String,
@@ -30,6 +46,121 @@ pub struct Plugin {
     /// 依赖的其他命令
     #[serde(default)]
     pub requires: Vec<String>,
+    /// 额外设置的环境变量，值支持模板占位符（{args} 除外）
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// 需要从继承的环境中移除的变量
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub env_remove: Vec<String>,
+    /// 是否继承 rune 的环境变量（为 false 时只保留 env 中声明的变量）
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub inherit_env: bool,
+    /// 脚本的工作目录
+    #[serde(default, skip_serializing_if = "WorkingDir::is_caller")]
+    pub cwd: WorkingDir,
+    /// 脚本的行注释前缀（如 `#`、`//`、`--`），用于解析脚本 front matter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    /// 默认的运行超时时间（如 30s、5m），可被脚本元数据和 `--timeout` 覆盖
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
+    /// 默认的失败重试次数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    /// 默认的第一次重试前的等待时间
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_delay: Option<String>,
+    /// 默认的重试间隔倍数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backoff: Option<f64>,
+    /// 默认的资源限制，脚本元数据和 `--limit` 可以逐项覆盖
+    #[serde(default, skip_serializing_if = "ResourceLimits::is_empty")]
+    pub limits: ResourceLimits,
+    /// 默认的沙箱策略，`true` 表示使用默认策略；脚本元数据和 `--sandbox` 只能收紧
+    #[serde(
+        default,
+        deserialize_with = "sandbox::deserialize_policy",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub sandbox: Option<SandboxPolicy>,
+    /// 验证通过的签名者（配置中受信任公钥的名称），由 `rune plugin add` 在验证签名后写入
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signer: Option<String>,
+}
+
+impl Default for Plugin {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            executor: String::new(),
+            arg_template: default_arg_template(),
+            extensions: Vec::new(),
+            interpreters: Vec::new(),
+            description: String::new(),
+            author: String::new(),
+            version: String::new(),
+            requires: Vec::new(),
+            env: BTreeMap::new(),
+            env_remove: Vec::new(),
+            inherit_env: true,
+            cwd: WorkingDir::Caller,
+            comment: None,
+            timeout: None,
+            retries: None,
+            retry_delay: None,
+            backoff: None,
+            limits: ResourceLimits::default(),
+            sandbox: None,
+            signer: None,
+        }
+    }
+}
+
+/// 脚本运行时的工作目录策略
+///
+/// 在插件定义中以字符串表示：`caller`（默认）、`script`，或一个路径（支持模板占位符）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum WorkingDir {
+    /// 调用 rune 时的当前目录
+    #[default]
+    Caller,
+    /// 脚本所在目录
+    Script,
+    /// 固定路径
+    Path(String),
+}
+
+impl WorkingDir {
+    fn is_caller(&self) -> bool {
+        *self == WorkingDir::Caller
+    }
+}
+
+impl From<String> for WorkingDir {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "" | "caller" => WorkingDir::Caller,
+            "script" => WorkingDir::Script,
+            _ => WorkingDir::Path(value),
+        }
+    }
+}
+
+impl From<WorkingDir> for String {
+    fn from(value: WorkingDir) -> Self {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for WorkingDir {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorkingDir::Caller => write!(f, "caller"),
+            WorkingDir::Script => write!(f, "script"),
+            WorkingDir::Path(path) => write!(f, "{}", path),
+        }
+    }
 }
 
 impl Plugin {
@@ -43,21 +174,11 @@ impl Plugin {
     ///
     /// # 错误
     /// - YAML 格式错误
-    /// - 缺少必填字段（name, executor, extensions）
+    /// - 缺少必填字段（name, executor, extensions 或 interpreters）
+    /// - 参数模板包含未知占位符
     pub fn from_yaml(yaml: &str) -> Result<Self> {
         let plugin: Plugin = serde_yaml::from_str(yaml).context("Failed to parse plugin YAML")?;
-
-        // 验证必填字段
-        if plugin.name.is_empty() {
-            anyhow::bail!("Plugin name cannot be empty");
-        }
-        if plugin.executor.is_empty() {
-            anyhow::bail!("Plugin executor cannot be empty");
-        }
-        if plugin.extensions.is_empty() {
-            anyhow::bail!("Plugin must support at least one extension");
-        }
-
+        plugin.check_fields()?;
         Ok(plugin)
     }
 
@@ -76,21 +197,11 @@ impl Plugin {
     ///
     /// # 错误
     /// - TOML 格式错误
-    /// - 缺少必填字段（name, executor, extensions）
+    /// - 缺少必填字段（name, executor, extensions 或 interpreters）
+    /// - 参数模板包含未知占位符
     pub fn from_toml(toml_str: &str) -> Result<Self> {
         let plugin: Plugin = toml::from_str(toml_str).context("Failed to parse plugin TOML")?;
-
-        // 验证必填字段
-        if plugin.name.is_empty() {
-            anyhow::bail!("Plugin name cannot be empty");
-        }
-        if plugin.executor.is_empty() {
-            anyhow::bail!("Plugin executor cannot be empty");
-        }
-        if plugin.extensions.is_empty() {
-            anyhow::bail!("Plugin must support at least one extension");
-        }
-
+        plugin.check_fields()?;
         Ok(plugin)
     }
 
@@ -105,22 +216,96 @@ impl Plugin {
         toml::to_string_pretty(self).context("Failed to serialize plugin to TOML")
     }
 
+    /// 验证必填字段和模板
+    fn check_fields(&self) -> Result<()> {
+        if self.name.is_empty() {
+            anyhow::bail!("Plugin name cannot be empty");
+        }
+        if self.executor.is_empty() {
+            anyhow::bail!("Plugin executor cannot be empty");
+        }
+        if self.extensions.is_empty() && self.interpreters.is_empty() {
+            anyhow::bail!("Plugin must support at least one extension or interpreter");
+        }
+        template::validate(&self.arg_template)?;
+        for (key, value) in &self.env {
+            if key.is_empty() || key.contains('=') {
+                anyhow::bail!("Invalid environment variable name '{}'", key);
+            }
+            template::validate_value(value)
+                .with_context(|| format!("Invalid value for environment variable '{}'", key))?;
+        }
+        if let WorkingDir::Path(path) = &self.cwd {
+            template::validate_value(path).context("Invalid working directory")?;
+        }
+        if let Some(comment) = &self.comment
+            && (comment.is_empty() || comment.chars().any(char::is_whitespace))
+        {
+            anyhow::bail!("Invalid comment prefix '{}'", comment);
+        }
+        if let Some(timeout) = &self.timeout {
+            parse_duration(timeout).context("Invalid timeout")?;
+        }
+        if let Some(delay) = &self.retry_delay {
+            RetryPolicy::parse_delay(delay)?;
+        }
+        if let Some(backoff) = self.backoff {
+            RetryPolicy::check_backoff(backoff)?;
+        }
+        if let Some(sandbox) = &self.sandbox {
+            sandbox.validate()?;
+        }
+        Ok(())
+    }
+
+    /// 解析后的默认超时时间
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout.as_deref().and_then(|t| parse_duration(t).ok())
+    }
+
+    /// 解析后的默认重试间隔
+    pub fn retry_delay(&self) -> Option<Duration> {
+        self.retry_delay
+            .as_deref()
+            .and_then(|d| RetryPolicy::parse_delay(d).ok())
+    }
+
+    /// 判断 shebang 中的解释器是否对应此插件
+    ///
+    /// 与 `interpreters` 中的名称或执行器的文件名比较，
+    /// 允许解释器带有版本后缀（如 `python3.11` 匹配 `python3` 和 `python`）
+    pub fn matches_interpreter(&self, interpreter: &str) -> bool {
+        let executor = std::path::Path::new(&self.executor)
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or(&self.executor);
+        self.interpreters
+            .iter()
+            .map(String::as_str)
+            .chain(std::iter::once(executor))
+            .any(|name| {
+                interpreter
+                    .strip_prefix(name)
+                    .is_some_and(|rest| rest.chars().all(|c| c.is_ascii_digit() || c == '.'))
+            })
+    }
+
     /// 验证插件的执行器和依赖是否可用
     ///
     /// 注意：此方法只会打印警告，不会返回错误
     pub fn validate(&self) -> Result<()> {
         // 检查执行器是否可用
         if which::which(&self.executor).is_err() {
-            eprintln!(
-                "警告: 执行器 '{}' 未在 PATH 中找到，插件可能无法正常工作。",
+            warn(format!(
+                "执行器 '{}' 未在 PATH 中找到，插件可能无法正常工作。",
                 self.executor
-            );
+            ));
         }
 
         // 检查依赖
         for req in &self.requires {
             if which::which(req).is_err() {
-                eprintln!("警告: 依赖命令 '{}' 未在 PATH 中找到。", req);
+                warn(format!("依赖命令 '{}' 未在 PATH 中找到。", req));
             }
         }
 
@@ -133,10 +318,18 @@ fn default_arg_template() -> Vec<String> {
     vec!["{file}".to_string()]
 }
 
+fn default_true() -> bool {
+    true
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
 /// 插件数据库
 ///
 /// 使用二进制格式存储所有已安装的插件
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone)]
 pub struct PluginDatabase {
     /// 插件名称 -> Plugin 映射
     plugins: HashMap<String, Plugin>,
@@ -145,7 +338,22 @@ pub struct PluginDatabase {
 }
 
 /// 当前数据库版本
-const DB_VERSION: u32 = 1;
+///
+/// v1 直接以 bincode 保存 Plugin 结构体，插件新增字段后无法读取旧数据库；
+/// v2 起每个插件以 TOML 文本保存，新增带默认值的字段不再需要迁移
+const DB_VERSION: u32 = 2;
+
+/// v2 起的数据库文件头，用于和 v1 格式区分
+const DB_MAGIC: [u8; 4] = *b"RUNE";
+
+/// 数据库的磁盘格式
+#[derive(Serialize, Deserialize)]
+struct StoredDatabase {
+    magic: [u8; 4],
+    version: u32,
+    /// TOML 格式的插件定义
+    plugins: Vec<String>,
+}
 
 impl PluginDatabase {
     /// 创建新的空数据库
@@ -158,7 +366,7 @@ impl PluginDatabase {
 
     /// 从磁盘加载插件数据库
     ///
-    /// 如果数据库不存在，会自动创建一个空数据库
+    /// 如果数据库不存在，会自动创建一个空数据库；v1 格式的数据库会被自动迁移
     pub fn load() -> Result<Self> {
         let db_path = get_plugin_db()?;
         if !db_path.exists() {
@@ -169,22 +377,53 @@ impl PluginDatabase {
         }
         // 读取数据库
         let data = fs::read(&db_path).context("Failed to read plugin database")?;
-        // 使用新版 bincode API
+        if !data.starts_with(&DB_MAGIC) {
+            let db = legacy::decode_v1(&data)?;
+            db.save()?;
+            return Ok(db);
+        }
+        Self::decode(&data)
+    }
+
+    /// 从 v2 格式的字节解码
+    fn decode(data: &[u8]) -> Result<Self> {
         let config = config::standard();
-        let (db, _): (Self, usize) = bincode::serde::decode_from_slice(&data, config)
+        let (stored, _): (StoredDatabase, usize) = bincode::serde::decode_from_slice(data, config)
             .context("Failed to deserialize plugin database")?;
         // 验证版本
-        if db.version != DB_VERSION {
+        if stored.version != DB_VERSION {
             anyhow::bail!(
                 "插件数据库版本不匹配。期望 {}, 实际 {}",
                 DB_VERSION,
-                db.version
+                stored.version
             );
         }
-
+        let mut db = Self::new();
+        for text in &stored.plugins {
+            let plugin: Plugin =
+                toml::from_str(text).context("Failed to deserialize plugin database")?;
+            db.plugins.insert(plugin.name.clone(), plugin);
+        }
         Ok(db)
     }
 
+    /// 编码为 v2 格式的字节
+    fn encode(&self) -> Result<Vec<u8>> {
+        let mut names: Vec<_> = self.plugins.keys().collect();
+        names.sort();
+        let plugins = names
+            .into_iter()
+            .map(|name| self.plugins[name].to_toml())
+            .collect::<Result<Vec<_>>>()?;
+        let stored = StoredDatabase {
+            magic: DB_MAGIC,
+            version: self.version,
+            plugins,
+        };
+        bincode::serde::encode_to_vec(&stored, config::standard())
+            .context("Failed to serialize plugin database")
+    }
+
     /// 保存数据库到磁盘
     pub fn save(&self) -> Result<()> {
         let db_path = get_plugin_db()?;
@@ -192,9 +431,7 @@ impl PluginDatabase {
         if let Some(parent) = db_path.parent() {
             fs::create_dir_all(parent)?;
         }
-        let config = config::standard();
-        let data = bincode::serde::encode_to_vec(self, config)
-            .context("Failed to serialize plugin database")?;
+        let data = self.encode()?;
         fs::write(&db_path, data).context("Failed to write plugin database")?;
 
         Ok(())
@@ -256,12 +493,90 @@ impl PluginDatabase {
         plugins
     }
 
+    /// 根据 shebang 中的解释器查找所有匹配的插件
+    ///
+    /// 返回的插件列表按名称排序
+    pub fn find_all_by_interpreter(&self, interpreter: &str) -> Vec<&Plugin> {
+        let mut plugins: Vec<_> = self
+            .plugins
+            .values()
+            .filter(|p| p.matches_interpreter(interpreter))
+            .collect();
+
+        plugins.sort_by(|a, b| a.name.cmp(&b.name));
+        plugins
+    }
+
     /// 获取所有插件的迭代器
     pub fn all_plugins(&self) -> impl Iterator<Item = &Plugin> {
         self.plugins.values()
     }
 }
 
+/// v1 数据库格式，仅用于迁移
+mod legacy {
+    use super::{DB_VERSION, Plugin, PluginDatabase};
+    use anyhow::{Context, Result};
+    use bincode::config;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+
+    /// v1 的插件结构（字段顺序与当时的 Plugin 一致）
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct PluginV1 {
+        pub name: String,
+        pub executor: String,
+        pub arg_template: Vec<String>,
+        pub extensions: Vec<String>,
+        pub description: String,
+        pub author: String,
+        pub version: String,
+        pub requires: Vec<String>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct DatabaseV1 {
+        pub plugins: HashMap<String, PluginV1>,
+        pub version: u32,
+    }
+
+    /// 解码 v1 数据库并转换为当前格式
+    pub(super) fn decode_v1(data: &[u8]) -> Result<PluginDatabase> {
+        let (db, _): (DatabaseV1, usize) =
+            bincode::serde::decode_from_slice(data, config::standard())
+                .context("Failed to deserialize plugin database")?;
+        if db.version != 1 {
+            anyhow::bail!(
+                "插件数据库版本不匹配。期望 {}, 实际 {}",
+                DB_VERSION,
+                db.version
+            );
+        }
+        let plugins = db
+            .plugins
+            .into_iter()
+            .map(|(name, p)| {
+                let plugin = Plugin {
+                    name: p.name,
+                    executor: p.executor,
+                    arg_template: p.arg_template,
+                    extensions: p.extensions,
+                    description: p.description,
+                    author: p.author,
+                    version: p.version,
+                    requires: p.requires,
+                    ..Default::default()
+                };
+                (name, plugin)
+            })
+            .collect();
+        Ok(PluginDatabase {
+            plugins,
+            version: DB_VERSION,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -380,6 +695,7 @@ extensions: []
             author: "Author".to_string(),
             version: "1.0".to_string(),
             requires: vec!["dep1".to_string()],
+            ..Default::default()
         };
 
         let yaml = plugin.to_yaml().unwrap();
@@ -413,6 +729,7 @@ extensions: []
             author: "".to_string(),
             version: "".to_string(),
             requires: vec!["nonexistent_dep_98765".to_string()],
+            ..Default::default()
         };
 
         // Should succeed even if executor doesn't exist
@@ -523,6 +840,7 @@ extensions: []
             author: "".to_string(),
             version: "".to_string(),
             requires: vec![],
+            ..Default::default()
         };
 
         let plugin2 = Plugin {
@@ -534,6 +852,7 @@ extensions: []
             author: "".to_string(),
             version: "".to_string(),
             requires: vec![],
+            ..Default::default()
         };
 
         db.add_plugin(plugin1).unwrap();
@@ -569,6 +888,7 @@ extensions: []
             author: "".to_string(),
             version: "".to_string(),
             requires: vec![],
+            ..Default::default()
         };
 
         db.add_plugin(plugin1).unwrap();
@@ -672,6 +992,27 @@ extensions = []
         assert!(result.unwrap_err().to_string().contains("Failed to parse plugin TOML"));
     }
 
+    #[test]
+    fn test_plugin_unknown_placeholder_rejected() {
+        let yaml = r#"
+name: r
+executor: Rscript
+arg_template: ["--vanilla", "{script}"]
+extensions: [R]
+"#;
+        let result = Plugin::from_yaml(yaml);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("Unknown placeholder"));
+
+        let toml = r#"
+name = "r"
+executor = "Rscript"
+arg_template = ["--vanilla", "{file}", "{args}", "--no-save"]
+extensions = ["R"]
+"#;
+        assert!(Plugin::from_toml(toml).is_ok());
+    }
+
     #[test]
     fn test_plugin_to_toml() {
         let plugin = Plugin {
@@ -683,6 +1024,7 @@ extensions = []
             author: "Author".to_string(),
             version: "1.0".to_string(),
             requires: vec!["dep1".to_string()],
+            ..Default::default()
         };
 
         let toml = plugin.to_toml().unwrap();
@@ -727,4 +1069,240 @@ extensions = []
         assert_eq!(toml_plugin2.extensions, yaml_plugin2.extensions);
         assert_eq!(toml_plugin2.version, yaml_plugin2.version);
     }
+
+    #[test]
+    fn test_plugin_env_and_cwd_defaults() {
+        let plugin = Plugin::from_yaml(sample_plugin_yaml()).unwrap();
+
+        assert!(plugin.env.is_empty());
+        assert!(plugin.env_remove.is_empty());
+        assert!(plugin.inherit_env);
+        assert_eq!(plugin.cwd, WorkingDir::Caller);
+    }
+
+    #[test]
+    fn test_plugin_env_and_cwd_from_toml() {
+        let toml = r#"
+name = "python"
+executor = "python3"
+extensions = ["py"]
+env_remove = ["PYTHONPATH"]
+inherit_env = false
+cwd = "script"
+
+[env]
+PYTHONUNBUFFERED = "1"
+PYTHONPATH = "{dir}/lib"
+"#;
+        let plugin = Plugin::from_toml(toml).unwrap();
+
+        assert_eq!(plugin.env.get("PYTHONUNBUFFERED").unwrap(), "1");
+        assert_eq!(plugin.env.get("PYTHONPATH").unwrap(), "{dir}/lib");
+        assert_eq!(plugin.env_remove, vec!["PYTHONPATH"]);
+        assert!(!plugin.inherit_env);
+        assert_eq!(plugin.cwd, WorkingDir::Script);
+    }
+
+    #[test]
+    fn test_plugin_env_roundtrip() {
+        let yaml = r#"
+name: node
+executor: node
+extensions: [js]
+env:
+  NODE_OPTIONS: --max-old-space-size=4096
+env_remove: [NODE_PATH]
+cwd: "{repo}/work"
+"#;
+        let original = Plugin::from_yaml(yaml).unwrap();
+        assert_eq!(original.cwd, WorkingDir::Path("{repo}/work".to_string()));
+
+        let from_toml = Plugin::from_toml(&original.to_toml().unwrap()).unwrap();
+        let from_yaml = Plugin::from_yaml(&original.to_yaml().unwrap()).unwrap();
+        for plugin in [from_toml, from_yaml] {
+            assert_eq!(plugin.env, original.env);
+            assert_eq!(plugin.env_remove, original.env_remove);
+            assert_eq!(plugin.inherit_env, original.inherit_env);
+            assert_eq!(plugin.cwd, original.cwd);
+        }
+    }
+
+    #[test]
+    fn test_plugin_default_fields_not_exported() {
+        let plugin = Plugin::from_yaml(sample_plugin_yaml()).unwrap();
+        let toml = plugin.to_toml().unwrap();
+
+        assert!(!toml.contains("env"));
+        assert!(!toml.contains("inherit_env"));
+        assert!(!toml.contains("cwd"));
+        assert!(!toml.contains("comment"));
+    }
+
+    #[test]
+    fn test_plugin_comment_prefix() {
+        let yaml = "name: lua\nexecutor: lua\nextensions: [lua]\ncomment: \"--\"\n";
+        let plugin = Plugin::from_yaml(yaml).unwrap();
+        assert_eq!(plugin.comment.as_deref(), Some("--"));
+
+        let invalid = "name: lua\nexecutor: lua\nextensions: [lua]\ncomment: \"- -\"\n";
+        assert!(Plugin::from_yaml(invalid).is_err());
+    }
+
+    #[test]
+    fn test_plugin_timeout_and_retries() {
+        let yaml = "name: ci\nexecutor: bash\nextensions: [sh]\ntimeout: 5m\nretries: 2\nretry_delay: 10s\nbackoff: 1.5\n";
+        let plugin = Plugin::from_yaml(yaml).unwrap();
+        assert_eq!(plugin.timeout(), Some(Duration::from_secs(300)));
+        assert_eq!(plugin.retries, Some(2));
+        assert_eq!(plugin.retry_delay(), Some(Duration::from_secs(10)));
+        assert_eq!(plugin.backoff, Some(1.5));
+
+        let invalid = "name: ci\nexecutor: bash\nextensions: [sh]\ntimeout: soon\n";
+        assert!(Plugin::from_yaml(invalid).is_err());
+        let invalid = "name: ci\nexecutor: bash\nextensions: [sh]\nbackoff: 0\n";
+        assert!(Plugin::from_yaml(invalid).is_err());
+    }
+
+    #[test]
+    fn test_plugin_limits() {
+        let yaml = "name: etl\nexecutor: python3\nextensions: [py]\nlimits:\n  mem: 2G\n  nofile: 1024\n";
+        let plugin = Plugin::from_yaml(yaml).unwrap();
+        assert_eq!(plugin.limits.memory, Some(2 << 30));
+        assert_eq!(plugin.limits.nofile, Some(1024));
+
+        // 数据库以 TOML 保存插件
+        let restored = Plugin::from_toml(&plugin.to_toml().unwrap()).unwrap();
+        assert_eq!(restored.limits, plugin.limits);
+
+        let invalid = "name: etl\nexecutor: python3\nextensions: [py]\nlimits:\n  mem: lots\n";
+        assert!(Plugin::from_yaml(invalid).is_err());
+    }
+
+    #[test]
+    fn test_plugin_sandbox() {
+        let yaml = "name: build\nexecutor: bash\nextensions: [sh]\nsandbox: true\n";
+        let plugin = Plugin::from_yaml(yaml).unwrap();
+        assert_eq!(plugin.sandbox, Some(SandboxPolicy::default()));
+        let restored = Plugin::from_toml(&plugin.to_toml().unwrap()).unwrap();
+        assert_eq!(restored.sandbox, plugin.sandbox);
+
+        let yaml = "name: build\nexecutor: bash\nextensions: [sh]\nsandbox:\n  writable: ['{dir}/out']\n  network: false\n";
+        let plugin = Plugin::from_yaml(yaml).unwrap();
+        let restored = Plugin::from_toml(&plugin.to_toml().unwrap()).unwrap();
+        assert_eq!(restored.sandbox, plugin.sandbox);
+        assert_eq!(restored.sandbox.unwrap().network, Some(false));
+
+        let invalid = "name: build\nexecutor: bash\nextensions: [sh]\nsandbox:\n  writable: ['{nope}']\n";
+        assert!(Plugin::from_yaml(invalid).is_err());
+    }
+
+    #[test]
+    fn test_plugin_invalid_env_rejected() {
+        let yaml = r#"
+name: python
+executor: python3
+extensions: [py]
+env:
+  ARGS: "{args}"
+"#;
+        assert!(Plugin::from_yaml(yaml).is_err());
+    }
+
+    #[test]
+    fn test_plugin_matches_interpreter() {
+        let plugin = Plugin {
+            name: "python".to_string(),
+            executor: "/usr/bin/python3".to_string(),
+            extensions: vec!["py".to_string()],
+            interpreters: vec!["python".to_string()],
+            ..Default::default()
+        };
+
+        assert!(plugin.matches_interpreter("python3"));
+        assert!(plugin.matches_interpreter("python"));
+        assert!(plugin.matches_interpreter("python3.11"));
+        assert!(!plugin.matches_interpreter("python3-config"));
+        assert!(!plugin.matches_interpreter("pypy3"));
+    }
+
+    #[test]
+    fn test_plugin_interpreters_only() {
+        let toml = r#"
+name = "bash"
+executor = "bash"
+interpreters = ["bash", "sh"]
+"#;
+        let plugin = Plugin::from_toml(toml).unwrap();
+        assert!(plugin.extensions.is_empty());
+        assert_eq!(plugin.interpreters, vec!["bash", "sh"]);
+    }
+
+    #[test]
+    fn test_plugin_database_find_by_interpreter() {
+        let mut db = PluginDatabase::new();
+        db.add_plugin(Plugin::from_yaml(sample_plugin_yaml()).unwrap())
+            .unwrap();
+        db.add_plugin(Plugin {
+            name: "python-uv".to_string(),
+            executor: "uv".to_string(),
+            extensions: vec!["py".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+
+        let matches = db.find_all_by_interpreter("python3");
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].name, "python");
+
+        let matches = db.find_all_by_interpreter("uv");
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].name, "python-uv");
+
+        assert!(db.find_all_by_interpreter("ruby").is_empty());
+    }
+
+    #[test]
+    fn test_plugin_database_encode_decode() {
+        let mut db = PluginDatabase::new();
+        let mut plugin = Plugin::from_yaml(sample_plugin_yaml()).unwrap();
+        plugin.env.insert("PYTHONUNBUFFERED".to_string(), "1".to_string());
+        db.add_plugin(plugin).unwrap();
+
+        let data = db.encode().unwrap();
+        assert!(data.starts_with(&DB_MAGIC));
+
+        let decoded = PluginDatabase::decode(&data).unwrap();
+        let plugin = decoded.get_plugin("python").unwrap();
+        assert_eq!(plugin.executor, "python3");
+        assert_eq!(plugin.env.get("PYTHONUNBUFFERED").unwrap(), "1");
+    }
+
+    #[test]
+    fn test_plugin_database_migrate_v1() {
+        let mut plugins = HashMap::new();
+        plugins.insert(
+            "bash".to_string(),
+            legacy::PluginV1 {
+                name: "bash".to_string(),
+                executor: "bash".to_string(),
+                arg_template: vec!["{file}".to_string()],
+                extensions: vec!["sh".to_string()],
+                description: "Bash".to_string(),
+                author: String::new(),
+                version: "1.0.0".to_string(),
+                requires: vec![],
+            },
+        );
+        let v1 = legacy::DatabaseV1 {
+            plugins,
+            version: 1,
+        };
+        let data = bincode::serde::encode_to_vec(&v1, config::standard()).unwrap();
+
+        let db = legacy::decode_v1(&data).unwrap();
+        assert_eq!(db.version, DB_VERSION);
+        let plugin = db.get_plugin("bash").unwrap();
+        assert_eq!(plugin.description, "Bash");
+        assert!(plugin.inherit_env);
+    }
 }
@@ -0,0 +1,95 @@
+use crate::utils::fs::get_preferences_path;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
+
+/// 插件选择偏好
+///
+/// 记录每个扩展名的默认插件和每个脚本固定使用的插件，
+/// 避免多个插件支持同一扩展名时每次运行都需要交互选择
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PluginPreferences {
+    /// 扩展名 -> 默认插件名称
+    #[serde(default)]
+    pub extensions: BTreeMap<String, String>,
+    /// 脚本文件名 -> 固定使用的插件名称
+    #[serde(default)]
+    pub scripts: BTreeMap<String, String>,
+}
+
+impl PluginPreferences {
+    /// 从默认位置加载偏好，文件不存在时返回空偏好
+    pub fn load() -> Result<Self> {
+        Self::load_from(&get_preferences_path()?)
+    }
+
+    /// 从指定文件加载偏好
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read preferences: {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to parse preferences: {}", path.display()))
+    }
+
+    /// 保存偏好到默认位置
+    pub fn save(&self) -> Result<()> {
+        self.save_to(&get_preferences_path()?)
+    }
+
+    /// 保存偏好到指定文件
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content = toml::to_string_pretty(self).context("Failed to serialize preferences")?;
+        fs::write(path, content)
+            .with_context(|| format!("Failed to write preferences: {}", path.display()))
+    }
+
+    /// 获取扩展名的默认插件
+    pub fn default_for_extension(&self, extension: &str) -> Option<&str> {
+        self.extensions.get(extension).map(String::as_str)
+    }
+
+    /// 获取脚本固定使用的插件
+    pub fn pinned_for_script(&self, script: &str) -> Option<&str> {
+        self.scripts.get(script).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_preferences_missing_file_is_empty() {
+        let temp_dir = TempDir::new().unwrap();
+        let prefs = PluginPreferences::load_from(&temp_dir.path().join("none.toml")).unwrap();
+
+        assert_eq!(prefs, PluginPreferences::default());
+    }
+
+    #[test]
+    fn test_preferences_roundtrip() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("plugin").join("preferences.toml");
+
+        let mut prefs = PluginPreferences::default();
+        prefs
+            .extensions
+            .insert("py".to_string(), "python-uv".to_string());
+        prefs
+            .scripts
+            .insert("legacy.py".to_string(), "python".to_string());
+        prefs.save_to(&path).unwrap();
+
+        let loaded = PluginPreferences::load_from(&path).unwrap();
+        assert_eq!(loaded.default_for_extension("py"), Some("python-uv"));
+        assert_eq!(loaded.pinned_for_script("legacy.py"), Some("python"));
+        assert_eq!(loaded.default_for_extension("rb"), None);
+    }
+}
@@ -0,0 +1,349 @@
+use crate::core::{
+    config::{Config, SharedRepo},
+    script::{Script, load_scripts},
+};
+use anyhow::{Result, bail};
+use std::{
+    collections::HashSet,
+    env, fmt,
+    path::{Path, PathBuf},
+};
+
+/// 项目仓库所在的目录名，rune 从当前目录向上查找 `.rune/scripts`
+pub const PROJECT_DIR: &str = ".rune";
+
+/// 脚本仓库层
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Layer {
+    /// 从当前目录向上找到的 `.rune/scripts`
+    Project,
+    /// 用户仓库（配置中的 `repos.user`、`RUNE_REPO` 或数据目录下的 scripts）
+    User,
+    /// 配置文件中列出的只读共享仓库
+    Shared(String),
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Layer::Project => write!(f, "project"),
+            Layer::User => write!(f, "user"),
+            Layer::Shared(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// 一个脚本仓库
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Repo {
+    /// 所属层
+    pub layer: Layer,
+    /// 仓库根目录
+    pub path: PathBuf,
+}
+
+impl Repo {
+    /// 仓库名称（`--repo` 使用的名称）
+    pub fn name(&self) -> String {
+        self.layer.to_string()
+    }
+
+    /// 是否只读
+    pub fn is_read_only(&self) -> bool {
+        matches!(self.layer, Layer::Shared(_))
+    }
+
+    /// 确认仓库可写
+    ///
+    /// # 错误
+    /// 共享仓库是只读的
+    pub fn ensure_writable(&self) -> Result<()> {
+        if self.is_read_only() {
+            bail!(
+                "Repository '{}' is read-only ({})",
+                self.name(),
+                self.path.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// 加载仓库中的脚本，目录不存在时返回空列表
+    pub fn load_scripts(&self) -> Result<Vec<Script>> {
+        if !self.path.is_dir() {
+            return Ok(Vec::new());
+        }
+        load_scripts(self.path.clone())
+    }
+}
+
+/// 仓库中的脚本
+#[derive(Debug)]
+pub struct RepoScript {
+    /// 脚本所在的仓库
+    pub repo: Repo,
+    /// 脚本
+    pub script: Script,
+    /// 是否被更高优先级仓库中的同名脚本遮蔽
+    pub shadowed: bool,
+}
+
+/// 从 `start` 向上查找项目仓库
+pub fn find_project_repo(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_DIR).join("scripts"))
+        .find(|candidate| candidate.is_dir())
+}
+
+/// 按优先级排列的脚本仓库：项目仓库、用户仓库、共享仓库
+///
+/// 查找脚本时使用第一个包含该脚本的仓库；列出脚本时，
+/// 与更高优先级仓库中文件名相同的脚本标记为被遮蔽
+#[derive(Debug, Clone)]
+pub struct RepoSet {
+    repos: Vec<Repo>,
+}
+
+impl RepoSet {
+    /// 根据当前目录、环境变量和配置文件发现所有仓库
+    pub fn discover() -> Result<Self> {
+        let project = env::current_dir()
+            .ok()
+            .and_then(|cwd| find_project_repo(&cwd));
+        let config = Config::load()?;
+        Ok(Self::from_parts(
+            project,
+            config.script_path()?,
+            &config.repos.shared,
+        ))
+    }
+
+    /// 由各层的目录构造仓库集合，重复的目录只保留优先级最高的一个
+    pub fn from_parts(project: Option<PathBuf>, user: PathBuf, shared: &[SharedRepo]) -> Self {
+        let mut repos = Vec::new();
+        if let Some(path) = project {
+            repos.push(Repo {
+                layer: Layer::Project,
+                path,
+            });
+        }
+        repos.push(Repo {
+            layer: Layer::User,
+            path: user,
+        });
+        for repo in shared {
+            repos.push(Repo {
+                layer: Layer::Shared(repo.name()),
+                path: repo.expanded_path(),
+            });
+        }
+
+        let mut seen = HashSet::new();
+        repos.retain(|repo| {
+            let key = repo
+                .path
+                .canonicalize()
+                .unwrap_or_else(|_| repo.path.clone());
+            seen.insert(key)
+        });
+        Self { repos }
+    }
+
+    /// 所有仓库，按优先级从高到低
+    pub fn repos(&self) -> &[Repo] {
+        &self.repos
+    }
+
+    /// 按名称获取仓库
+    pub fn get(&self, name: &str) -> Result<&Repo> {
+        self.repos.iter().find(|r| r.name() == name).ok_or_else(|| {
+            let names: Vec<String> = self.repos.iter().map(Repo::name).collect();
+            match name {
+                "project" => anyhow::anyhow!(
+                    "No project repository found (create '{}/scripts' in your project)",
+                    PROJECT_DIR
+                ),
+                _ => anyhow::anyhow!(
+                    "Unknown repository '{}' (available: {})",
+                    name,
+                    names.join(", ")
+                ),
+            }
+        })
+    }
+
+    /// 获取添加脚本的目标仓库，未指定时为用户仓库
+    pub fn writable(&self, name: Option<&str>) -> Result<&Repo> {
+        let repo = self.get(name.unwrap_or("user"))?;
+        repo.ensure_writable()?;
+        Ok(repo)
+    }
+
+    /// 加载脚本，`layer` 为 None 时加载所有仓库
+    pub fn scripts(&self, layer: Option<&str>) -> Result<Vec<RepoScript>> {
+        if let Some(layer) = layer {
+            self.get(layer)?;
+        }
+
+        let mut seen = HashSet::new();
+        let mut scripts = Vec::new();
+        for repo in &self.repos {
+            for script in repo.load_scripts()? {
+                let shadowed = !seen.insert(script.file_name());
+                if layer.is_none_or(|l| repo.name() == l) {
+                    scripts.push(RepoScript {
+                        repo: repo.clone(),
+                        script,
+                        shadowed,
+                    });
+                }
+            }
+        }
+        Ok(scripts)
+    }
+
+    /// 查找脚本
+    ///
+    /// 按优先级返回第一个匹配的脚本；指定 `layer` 时只在该仓库中查找
+    pub fn find(
+        &self,
+        name: &str,
+        extension: Option<&str>,
+        layer: Option<&str>,
+    ) -> Result<RepoScript> {
+        self.scripts(layer)?
+            .into_iter()
+            .find(|s| {
+                s.script.name == name && extension.is_none_or(|ext| s.script.extension == ext)
+            })
+            .ok_or_else(|| {
+                let display = match extension {
+                    Some(ext) => format!("{}.{}", name, ext),
+                    None => name.to_string(),
+                };
+                match layer {
+                    Some(layer) => {
+                        anyhow::anyhow!("Script '{}' not found in repository '{}'", display, layer)
+                    }
+                    None => anyhow::anyhow!("Script '{}' not found", display),
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    struct Layers {
+        _temp_dir: TempDir,
+        set: RepoSet,
+    }
+
+    fn layers() -> Layers {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let project = root.join("app/.rune/scripts");
+        let user = root.join("user");
+        let team = root.join("team");
+        for dir in [&project, &user, &team] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(project.join("deploy.sh"), "").unwrap();
+        fs::write(user.join("deploy.sh"), "").unwrap();
+        fs::write(user.join("backup.sh"), "").unwrap();
+        fs::write(team.join("backup.py"), "").unwrap();
+        fs::write(team.join("report.py"), "").unwrap();
+
+        let shared = vec![SharedRepo {
+            path: team,
+            name: None,
+        }];
+        let set = RepoSet::from_parts(Some(project), user, &shared);
+        Layers {
+            _temp_dir: temp_dir,
+            set,
+        }
+    }
+
+    #[test]
+    fn test_find_project_repo_walks_up() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path().join(".rune/scripts");
+        let nested = temp_dir.path().join("src/module");
+        fs::create_dir_all(&project).unwrap();
+        fs::create_dir_all(&nested).unwrap();
+
+        assert_eq!(find_project_repo(&nested), Some(project));
+    }
+
+    #[test]
+    fn test_repo_precedence_and_shadowing() {
+        let layers = layers();
+        let scripts = layers.set.scripts(None).unwrap();
+        let summary: Vec<(String, String, bool)> = scripts
+            .iter()
+            .map(|s| (s.repo.name(), s.script.file_name(), s.shadowed))
+            .collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                ("project".to_string(), "deploy.sh".to_string(), false),
+                ("user".to_string(), "backup.sh".to_string(), false),
+                ("user".to_string(), "deploy.sh".to_string(), true),
+                ("team".to_string(), "backup.py".to_string(), false),
+                ("team".to_string(), "report.py".to_string(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_repo_find() {
+        let layers = layers();
+        let set = &layers.set;
+
+        assert_eq!(
+            set.find("deploy", None, None).unwrap().repo.layer,
+            Layer::Project
+        );
+        assert_eq!(
+            set.find("deploy", None, Some("user")).unwrap().repo.layer,
+            Layer::User
+        );
+        assert_eq!(
+            set.find("backup", None, None).unwrap().repo.layer,
+            Layer::User
+        );
+        assert_eq!(
+            set.find("backup", Some("py"), None).unwrap().repo.name(),
+            "team"
+        );
+        assert!(set.find("report", None, Some("user")).is_err());
+        assert!(set.find("deploy", None, Some("missing")).is_err());
+    }
+
+    #[test]
+    fn test_repo_writable() {
+        let layers = layers();
+        let set = &layers.set;
+
+        assert_eq!(set.writable(None).unwrap().layer, Layer::User);
+        assert_eq!(set.writable(Some("project")).unwrap().layer, Layer::Project);
+        assert!(set.writable(Some("team")).is_err());
+    }
+
+    #[test]
+    fn test_repo_without_project() {
+        let temp_dir = TempDir::new().unwrap();
+        let set = RepoSet::from_parts(None, temp_dir.path().join("missing"), &[]);
+
+        assert_eq!(set.repos().len(), 1);
+        assert!(set.scripts(None).unwrap().is_empty());
+        let err = set.get("project").unwrap_err().to_string();
+        assert!(err.contains("No project repository found"));
+    }
+}
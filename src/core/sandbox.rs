@@ -0,0 +1,650 @@
+//! 沙箱运行
+//!
+//! 沙箱中的脚本只能读取文件系统，写入限于声明的可写路径（通过 Landlock 实现）；
+//! 私有的 `/tmp` 和禁止访问网络通过非特权用户命名空间中的挂载和网络命名空间实现。
+//! 策略在插件定义、脚本元数据和 `rune run --sandbox` 中声明，仅支持 Linux
+
+use std::{
+    env, fmt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::core::{
+    config::expand_home,
+    template::{self, TemplateContext},
+};
+
+/// 插件定义和脚本元数据中声明的沙箱策略
+///
+/// 写成 `sandbox: true` 时使用默认策略：只读文件系统、私有 `/tmp`、允许访问网络
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SandboxPolicy {
+    /// 可写的路径，支持模板占位符和 `~/`，相对路径相对于脚本的工作目录
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub writable: Vec<String>,
+    /// 是否允许访问网络，未设置时允许
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<bool>,
+    /// 是否使用私有的 `/tmp`，未设置时使用
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub private_tmp: Option<bool>,
+}
+
+/// `sandbox` 字段可以是布尔值或策略
+#[derive(Deserialize)]
+#[serde(
+    untagged,
+    expecting = "true, false or a sandbox policy with writable, network and private_tmp"
+)]
+enum SandboxSpec {
+    Enabled(bool),
+    Policy(SandboxPolicy),
+}
+
+/// 反序列化 `sandbox` 字段，`false` 表示不使用沙箱
+pub fn deserialize_policy<'de, D>(deserializer: D) -> Result<Option<SandboxPolicy>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Option::<SandboxSpec>::deserialize(deserializer)? {
+        Some(SandboxSpec::Enabled(true)) => Some(SandboxPolicy::default()),
+        Some(SandboxSpec::Policy(policy)) => Some(policy),
+        Some(SandboxSpec::Enabled(false)) | None => None,
+    })
+}
+
+impl SandboxPolicy {
+    /// 用更高优先级的策略收紧当前策略，结果不会比任何一方宽松
+    ///
+    /// 可写路径取交集，`stricter` 未声明可写路径时保持不变；
+    /// 任一方禁止访问网络即禁止，任一方使用私有 `/tmp` 即使用
+    pub fn tighten(self, stricter: Self) -> Self {
+        let writable = if stricter.writable.is_empty() {
+            self.writable
+        } else {
+            self.writable
+                .into_iter()
+                .filter(|path| stricter.writable.contains(path))
+                .collect()
+        };
+        let network = match (self.network, stricter.network) {
+            (Some(false), _) | (_, Some(false)) => Some(false),
+            (network, _) => network,
+        };
+        let private_tmp = match (self.private_tmp, stricter.private_tmp) {
+            (Some(false), other) => other.or(Some(false)),
+            (private_tmp, _) => private_tmp,
+        };
+        Self {
+            writable,
+            network,
+            private_tmp,
+        }
+    }
+
+    /// 检查可写路径中的模板占位符
+    pub fn validate(&self) -> Result<()> {
+        for path in &self.writable {
+            template::validate_value(path)
+                .with_context(|| format!("Invalid writable path '{}' in sandbox policy", path))?;
+        }
+        Ok(())
+    }
+
+    /// 展开可写路径，得到实际使用的沙箱设置
+    ///
+    /// `cwd` 为脚本的工作目录，None 表示 rune 的当前目录
+    pub fn resolve(&self, ctx: &TemplateContext, cwd: Option<&Path>) -> Result<Sandbox> {
+        let base = match cwd {
+            Some(dir) => dir.to_path_buf(),
+            None => env::current_dir().context("Failed to get current directory")?,
+        };
+        let writable = self
+            .writable
+            .iter()
+            .map(|path| {
+                let expanded = template::expand_value(path, ctx).map_err(|e| {
+                    anyhow::anyhow!("Invalid writable path '{}' in sandbox policy: {}", path, e)
+                })?;
+                Ok(base.join(expand_home(Path::new(&expanded))))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Sandbox {
+            writable,
+            network: self.network.unwrap_or(true),
+            private_tmp: self.private_tmp.unwrap_or(true),
+        })
+    }
+}
+
+impl fmt::Display for SandboxPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut items = vec!["read-only filesystem".to_string()];
+        if !self.writable.is_empty() {
+            items.push(format!("writable: {}", self.writable.join(" ")));
+        }
+        items.push(describe_tmp(self.private_tmp.unwrap_or(true)).to_string());
+        items.push(describe_network(self.network.unwrap_or(true)).to_string());
+        write!(f, "{}", items.join(", "))
+    }
+}
+
+/// 实际使用的沙箱设置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sandbox {
+    /// 可写的绝对路径
+    pub writable: Vec<PathBuf>,
+    /// 是否允许访问网络
+    pub network: bool,
+    /// 是否使用私有的 `/tmp`
+    pub private_tmp: bool,
+}
+
+impl Sandbox {
+    /// 检查路径在沙箱中是否可见：私有的 `/tmp` 会遮住原来 `/tmp` 下的文件
+    pub fn check_visible(&self, path: &Path, what: &str) -> Result<()> {
+        if self.private_tmp && path.starts_with("/tmp") {
+            anyhow::bail!(
+                "{} {} is under /tmp, which the sandbox replaces with a private /tmp; set 'private_tmp: false' in the sandbox policy",
+                what,
+                path.display()
+            );
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Sandbox {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut items = vec!["read-only filesystem".to_string()];
+        if !self.writable.is_empty() {
+            let paths: Vec<String> = self
+                .writable
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            items.push(format!("writable: {}", paths.join(" ")));
+        }
+        items.push(describe_tmp(self.private_tmp).to_string());
+        items.push(describe_network(self.network).to_string());
+        write!(f, "{}", items.join(", "))
+    }
+}
+
+fn describe_tmp(private: bool) -> &'static str {
+    if private {
+        "private /tmp"
+    } else {
+        "read-only /tmp"
+    }
+}
+
+fn describe_network(allowed: bool) -> &'static str {
+    if allowed {
+        "network allowed"
+    } else {
+        "no network"
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use linux::SandboxSetup;
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{
+        ffi::{CStr, CString},
+        fs, io,
+        os::unix::ffi::OsStrExt,
+        path::Path,
+    };
+
+    use anyhow::{Result, bail};
+
+    use super::Sandbox;
+    use crate::utils::cli::warn;
+
+    // Landlock 的 ABI 常量，见 linux/landlock.h
+    const CREATE_RULESET_VERSION: u32 = 1 << 0;
+    const RULE_PATH_BENEATH: libc::c_int = 1;
+    const ACCESS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_REMOVE_DIR: u64 = 1 << 4;
+    const ACCESS_REMOVE_FILE: u64 = 1 << 5;
+    const ACCESS_MAKE_CHAR: u64 = 1 << 6;
+    const ACCESS_MAKE_DIR: u64 = 1 << 7;
+    const ACCESS_MAKE_REG: u64 = 1 << 8;
+    const ACCESS_MAKE_SOCK: u64 = 1 << 9;
+    const ACCESS_MAKE_FIFO: u64 = 1 << 10;
+    const ACCESS_MAKE_BLOCK: u64 = 1 << 11;
+    const ACCESS_MAKE_SYM: u64 = 1 << 12;
+    /// ABI 2 起支持
+    const ACCESS_REFER: u64 = 1 << 13;
+    /// ABI 3 起支持
+    const ACCESS_TRUNCATE: u64 = 1 << 14;
+
+    /// 可以授予单个文件（而不是目录）的写权限
+    const FILE_ACCESS: u64 = ACCESS_WRITE_FILE | ACCESS_TRUNCATE;
+
+    /// 设备文件（如 `/dev/null`、终端）总是可以写入
+    const DEVICE_DIR: &CStr = c"/dev";
+    /// 共享内存（Python multiprocessing 等使用）总是可写
+    const SHM_DIR: &str = "/dev/shm";
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    /// 在父进程中准备好的沙箱设置，子进程 exec 之前调用 [`SandboxSetup::enter`]
+    ///
+    /// 所有需要分配内存的工作都在这里完成，子进程中只调用系统调用
+    pub struct SandboxSetup {
+        /// `unshare` 的参数，0 表示不需要新的命名空间
+        namespaces: libc::c_int,
+        uid_map: Vec<u8>,
+        gid_map: Vec<u8>,
+        private_tmp: bool,
+        /// Landlock 处理（默认禁止）的访问权限
+        handled: u64,
+        /// 允许写入的路径及其权限
+        rules: Vec<(CString, u64)>,
+    }
+
+    impl Sandbox {
+        /// 检查内核支持并准备沙箱设置
+        pub fn prepare(&self) -> Result<SandboxSetup> {
+            let abi = landlock_abi()?;
+            let mut handled = ACCESS_WRITE_FILE
+                | ACCESS_REMOVE_DIR
+                | ACCESS_REMOVE_FILE
+                | ACCESS_MAKE_CHAR
+                | ACCESS_MAKE_DIR
+                | ACCESS_MAKE_REG
+                | ACCESS_MAKE_SOCK
+                | ACCESS_MAKE_FIFO
+                | ACCESS_MAKE_BLOCK
+                | ACCESS_MAKE_SYM;
+            if abi >= 2 {
+                handled |= ACCESS_REFER;
+            }
+            if abi >= 3 {
+                handled |= ACCESS_TRUNCATE;
+            }
+
+            let mut rules = vec![(DEVICE_DIR.to_owned(), FILE_ACCESS & handled)];
+            if Path::new(SHM_DIR).is_dir() {
+                rules.push((CString::new(SHM_DIR)?, handled));
+            }
+            if self.private_tmp {
+                rules.push((c"/tmp".to_owned(), handled));
+            }
+            for path in &self.writable {
+                let Ok(metadata) = fs::metadata(path) else {
+                    warn(format!(
+                        "Sandbox: writable path {} does not exist, ignoring it",
+                        path.display()
+                    ));
+                    continue;
+                };
+                let access = if metadata.is_dir() {
+                    handled
+                } else {
+                    FILE_ACCESS & handled
+                };
+                rules.push((CString::new(path.as_os_str().as_bytes())?, access));
+            }
+
+            let mut namespaces = 0;
+            if self.private_tmp {
+                namespaces |= libc::CLONE_NEWNS;
+            }
+            if !self.network {
+                namespaces |= libc::CLONE_NEWNET;
+            }
+            if namespaces != 0 {
+                check_user_namespaces()?;
+                namespaces |= libc::CLONE_NEWUSER;
+            }
+            // SAFETY: 无参数的系统调用
+            let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+            Ok(SandboxSetup {
+                namespaces,
+                uid_map: format!("{} {} 1\n", uid, uid).into_bytes(),
+                gid_map: format!("{} {} 1\n", gid, gid).into_bytes(),
+                private_tmp: self.private_tmp,
+                handled,
+                rules,
+            })
+        }
+    }
+
+    impl SandboxSetup {
+        /// 让当前进程进入沙箱，在子进程 exec 之前调用
+        ///
+        /// 只调用异步信号安全的系统调用，不分配内存
+        pub fn enter(&self) -> io::Result<()> {
+            if self.namespaces != 0 {
+                // SAFETY: 只传入整数参数
+                check(unsafe { libc::unshare(self.namespaces) })?;
+                // 在新的用户命名空间中保持原来的用户和组
+                write_file(c"/proc/self/setgroups", b"deny")?;
+                write_file(c"/proc/self/uid_map", &self.uid_map)?;
+                write_file(c"/proc/self/gid_map", &self.gid_map)?;
+            }
+            if self.private_tmp {
+                // SAFETY: 参数都是有效的 C 字符串或空指针
+                unsafe {
+                    check(libc::mount(
+                        std::ptr::null(),
+                        c"/".as_ptr(),
+                        std::ptr::null(),
+                        libc::MS_REC | libc::MS_PRIVATE,
+                        std::ptr::null(),
+                    ))?;
+                    check(libc::mount(
+                        c"tmpfs".as_ptr(),
+                        c"/tmp".as_ptr(),
+                        c"tmpfs".as_ptr(),
+                        libc::MS_NOSUID | libc::MS_NODEV,
+                        c"mode=1777".as_ptr().cast(),
+                    ))?;
+                }
+            }
+            self.restrict_filesystem()
+        }
+
+        /// 通过 Landlock 禁止写入可写路径以外的文件
+        fn restrict_filesystem(&self) -> io::Result<()> {
+            let attr = RulesetAttr {
+                handled_access_fs: self.handled,
+            };
+            // SAFETY: 传入的结构体在调用期间有效，大小与其定义一致
+            let ruleset = unsafe {
+                libc::syscall(
+                    libc::SYS_landlock_create_ruleset,
+                    &attr as *const RulesetAttr,
+                    std::mem::size_of::<RulesetAttr>(),
+                    0u32,
+                )
+            };
+            if ruleset < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let ruleset = ruleset as libc::c_int;
+            let result = self.add_rules(ruleset).and_then(|_| {
+                // SAFETY: 只传入整数参数
+                check(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) })?;
+                // SAFETY: ruleset 是上面创建的文件描述符
+                let restricted =
+                    unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset, 0u32) };
+                if restricted < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+            // SAFETY: 关闭自己创建的文件描述符
+            unsafe { libc::close(ruleset) };
+            result
+        }
+
+        fn add_rules(&self, ruleset: libc::c_int) -> io::Result<()> {
+            for (path, access) in &self.rules {
+                // SAFETY: path 是有效的 C 字符串
+                let fd = unsafe { libc::open(path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+                if fd < 0 {
+                    // 父进程检查之后被删除的路径不再可写
+                    continue;
+                }
+                let attr = PathBeneathAttr {
+                    allowed_access: *access,
+                    parent_fd: fd,
+                };
+                // SAFETY: 传入的结构体在调用期间有效
+                let added = unsafe {
+                    libc::syscall(
+                        libc::SYS_landlock_add_rule,
+                        ruleset,
+                        RULE_PATH_BENEATH,
+                        &attr as *const PathBeneathAttr,
+                        0u32,
+                    )
+                };
+                // SAFETY: 关闭自己打开的文件描述符
+                unsafe { libc::close(fd) };
+                if added < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// 当前内核支持的 Landlock ABI 版本
+    fn landlock_abi() -> Result<i64> {
+        // SAFETY: 查询版本时不读取任何参数
+        let abi = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<RulesetAttr>(),
+                0usize,
+                CREATE_RULESET_VERSION,
+            )
+        };
+        if abi >= 1 {
+            return Ok(abi);
+        }
+        match io::Error::last_os_error().raw_os_error() {
+            Some(libc::ENOSYS) => bail!(
+                "The sandbox needs Landlock, which this kernel does not support (Linux 5.13 or later is required)"
+            ),
+            Some(libc::EOPNOTSUPP) => bail!(
+                "The sandbox needs Landlock, which is disabled on this system (add 'landlock' to the 'lsm=' kernel boot parameter)"
+            ),
+            _ => bail!(
+                "The sandbox needs Landlock, which is not available: {}",
+                io::Error::last_os_error()
+            ),
+        }
+    }
+
+    /// 检查是否可以创建非特权用户命名空间
+    fn check_user_namespaces() -> Result<()> {
+        // SAFETY: 无参数的系统调用
+        let root = unsafe { libc::geteuid() } == 0;
+        let settings = [
+            ("/proc/sys/user/max_user_namespaces", "0", true),
+            ("/proc/sys/kernel/unprivileged_userns_clone", "0", false),
+            (
+                "/proc/sys/kernel/apparmor_restrict_unprivileged_userns",
+                "1",
+                false,
+            ),
+        ];
+        for (path, blocked, applies_to_root) in settings {
+            if (applies_to_root || !root)
+                && fs::read_to_string(path).is_ok_and(|value| value.trim() == blocked)
+            {
+                let name = path.trim_start_matches("/proc/sys/").replace('/', ".");
+                bail!(
+                    "A private /tmp and network isolation need unprivileged user namespaces, which are disabled on this system ({} = {}); set 'private_tmp: false' and 'network: true' in the sandbox policy to use Landlock alone",
+                    name,
+                    blocked
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn write_file(path: &CStr, content: &[u8]) -> io::Result<()> {
+        // SAFETY: path 是有效的 C 字符串，content 在调用期间有效
+        unsafe {
+            let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let written = libc::write(fd, content.as_ptr().cast(), content.len());
+            let result = if written < 0 {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(())
+            };
+            libc::close(fd);
+            result
+        }
+    }
+
+    fn check(result: libc::c_int) -> io::Result<()> {
+        if result == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Holder {
+        #[serde(default, deserialize_with = "deserialize_policy")]
+        sandbox: Option<SandboxPolicy>,
+    }
+
+    fn parse(yaml: &str) -> Result<Option<SandboxPolicy>, serde_yaml::Error> {
+        serde_yaml::from_str::<Holder>(yaml).map(|holder| holder.sandbox)
+    }
+
+    #[test]
+    fn test_parse_policy() {
+        assert_eq!(parse("{}").unwrap(), None);
+        assert_eq!(parse("sandbox: false").unwrap(), None);
+        assert_eq!(
+            parse("sandbox: true").unwrap(),
+            Some(SandboxPolicy::default())
+        );
+
+        let policy = parse("sandbox:\n  writable: [out, '{dir}/cache']\n  network: false\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(policy.writable, ["out", "{dir}/cache"]);
+        assert_eq!(policy.network, Some(false));
+        assert_eq!(policy.private_tmp, None);
+        assert_eq!(
+            policy.to_string(),
+            "read-only filesystem, writable: out {dir}/cache, private /tmp, no network"
+        );
+
+        assert!(parse("sandbox:\n  writeable: [out]\n").is_err());
+        assert!(parse("sandbox: yes please").is_err());
+    }
+
+    #[test]
+    fn test_merge_and_resolve_policy() {
+        let cli = SandboxPolicy {
+            writable: vec!["out".to_string()],
+            network: Some(false),
+            private_tmp: None,
+        };
+        let plugin = SandboxPolicy {
+            writable: vec!["~/.cache".to_string(), "out".to_string()],
+            network: Some(true),
+            private_tmp: Some(false),
+        };
+        let merged = plugin.clone().tighten(cli);
+        assert_eq!(merged.writable, ["out"]);
+        assert_eq!(merged.network, Some(false));
+        assert_eq!(merged.private_tmp, Some(false));
+
+        // 元数据和命令行只能收紧插件的策略
+        let wider = SandboxPolicy {
+            writable: vec!["/".to_string(), "~/.cache".to_string()],
+            network: Some(true),
+            private_tmp: Some(false),
+        };
+        let strict = SandboxPolicy {
+            writable: vec!["~/.cache".to_string()],
+            network: Some(false),
+            private_tmp: None,
+        };
+        let merged = strict.clone().tighten(wider.clone());
+        assert_eq!(merged, strict);
+        let merged = SandboxPolicy::default().tighten(wider.clone());
+        assert_eq!(merged, SandboxPolicy::default());
+        let merged = wider.clone().tighten(SandboxPolicy::default());
+        assert_eq!(merged.writable, wider.writable);
+        assert_eq!(merged.network, Some(true));
+        assert_eq!(merged.private_tmp, Some(false));
+        let merged = wider.tighten(SandboxPolicy {
+            private_tmp: Some(true),
+            ..SandboxPolicy::default()
+        });
+        assert_eq!(merged.private_tmp, Some(true));
+
+        let file = PathBuf::from("/repo/etl/load.py");
+        let repo = PathBuf::from("/repo");
+        let ctx = TemplateContext {
+            file: &file,
+            repo: &repo,
+            args: &[],
+        };
+        let policy = SandboxPolicy {
+            writable: vec!["out".to_string(), "{dir}/cache".to_string()],
+            ..SandboxPolicy::default()
+        };
+        let sandbox = policy.resolve(&ctx, Some(Path::new("/work"))).unwrap();
+        assert_eq!(
+            sandbox.writable,
+            [PathBuf::from("/work/out"), PathBuf::from("/repo/etl/cache")]
+        );
+        assert!(sandbox.network);
+        assert!(sandbox.private_tmp);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_sandbox_blocks_writes() {
+        use crate::core::executor::{CommandExecutor, ExitOutcome};
+        use tempfile::TempDir;
+
+        let sandbox = |writable: Vec<PathBuf>| Sandbox {
+            writable,
+            network: true,
+            private_tmp: false,
+        };
+        // 内核不支持 Landlock 时跳过
+        if sandbox(Vec::new()).prepare().is_err() {
+            return;
+        }
+        let temp_dir = TempDir::new().unwrap();
+        let allowed = temp_dir.path().join("allowed");
+        let denied = temp_dir.path().join("denied");
+        std::fs::create_dir(&allowed).unwrap();
+        std::fs::create_dir(&denied).unwrap();
+
+        let write = |dir: &Path| {
+            CommandExecutor::new("sh")
+                .args(["-c", "exec 2> /dev/null; echo hi > \"$0/file\""])
+                .arg(dir.to_string_lossy())
+                .interactive(false)
+                .sandbox(sandbox(vec![allowed.clone()]))
+                .status()
+                .unwrap()
+        };
+        assert_eq!(write(&allowed), ExitOutcome::SUCCESS);
+        assert!(allowed.join("file").exists());
+        assert!(!write(&denied).success());
+        assert!(!denied.join("file").exists());
+    }
+}
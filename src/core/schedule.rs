@@ -0,0 +1,569 @@
+//! 定时任务
+//!
+//! 定时任务保存在数据目录下的 `schedules.toml`，由 `rune daemon` 按 cron 表达式触发。
+//! 守护进程把每个任务最后处理到的时间记录在 `schedule-state.json`，
+//! 重新启动时据此判断停止期间错过了哪些运行
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt, fs,
+    path::Path,
+    time::Instant,
+};
+
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::{cron::CronExpr, limits::ResourceLimits, sandbox::SandboxPolicy},
+    utils::{fs::get_schedules_path, time},
+};
+
+/// 一次补跑最多运行的次数（`missed = all`）
+pub const MAX_CATCH_UP: usize = 100;
+
+/// 计划时间过去多久仍未运行视为错过（秒）
+pub const MISSED_GRACE: u64 = 60;
+
+/// 错过的运行（守护进程未运行或系统休眠期间）的处理方式
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum MissedPolicy {
+    /// 不补跑
+    #[default]
+    Skip,
+    /// 无论错过多少次都只补跑一次
+    Once,
+    /// 每次错过的运行都补跑
+    All,
+}
+
+/// 上一次运行尚未结束时的处理方式
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum OverlapPolicy {
+    /// 跳过这次运行
+    #[default]
+    Skip,
+    /// 等上一次结束后再运行（最多排队一次）
+    Queue,
+    /// 同时运行
+    Parallel,
+    /// 终止上一次运行，然后运行
+    Replace,
+}
+
+impl fmt::Display for MissedPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MissedPolicy::Skip => "skip",
+            MissedPolicy::Once => "once",
+            MissedPolicy::All => "all",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for OverlapPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            OverlapPolicy::Skip => "skip",
+            OverlapPolicy::Queue => "queue",
+            OverlapPolicy::Parallel => "parallel",
+            OverlapPolicy::Replace => "replace",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// 一个定时任务
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Schedule {
+    /// 递增的编号，用于 `rune schedule remove`
+    pub id: u64,
+    /// 脚本名称
+    pub script: String,
+    /// 运行时间
+    pub cron: CronExpr,
+    /// 传给脚本的参数
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// 只在该仓库中查找脚本
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    /// 错过的运行的处理方式
+    #[serde(default)]
+    pub missed: MissedPolicy,
+    /// 上一次运行尚未结束时的处理方式
+    #[serde(default)]
+    pub overlap: OverlapPolicy,
+    /// 添加时间（Unix 时间戳，秒），此前的计划时间不算错过
+    pub created: u64,
+    /// 运行时的资源限制，同 `rune run --limit`
+    #[serde(default, skip_serializing_if = "ResourceLimits::is_empty")]
+    pub limits: ResourceLimits,
+    /// 运行时的沙箱策略，同 `rune run --sandbox`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<SandboxPolicy>,
+}
+
+/// 到期的一次运行
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fire {
+    /// 计划运行时间
+    pub at: u64,
+    /// 是否是补跑错过的运行
+    pub missed: bool,
+}
+
+/// 一段时间内到期的运行
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Due {
+    /// 需要运行的时间点，按时间排序
+    pub fires: Vec<Fire>,
+    /// 错过且按策略不再补跑的次数
+    pub dropped: usize,
+}
+
+impl Schedule {
+    /// `after` 之后的下一次计划运行时间
+    pub fn next_after(&self, after: u64) -> Option<u64> {
+        self.cron.next_after(after)
+    }
+
+    /// 计算 `(last, now]` 内到期的运行
+    ///
+    /// 计划时间早于 `now - grace` 的运行视为错过，按 [`MissedPolicy`] 处理
+    pub fn due(&self, last: u64, now: u64, grace: u64) -> Due {
+        self.due_with(
+            last,
+            now,
+            grace,
+            |after| self.next_after(after),
+            |after, until| self.cron.count_between(after, until),
+        )
+    }
+
+    /// `next` 返回下一次计划时间，`count` 返回 `(after, until]` 内的计划次数
+    fn due_with(
+        &self,
+        last: u64,
+        now: u64,
+        grace: u64,
+        next: impl Fn(u64) -> Option<u64>,
+        count: impl Fn(u64, u64) -> usize,
+    ) -> Due {
+        // 长时间停机后只逐次查找最近的运行，更早错过的运行只计数
+        let (mut cursor, mut total) = (last, 0);
+        if let Some(missed_end) = now
+            .checked_sub(grace.saturating_add(1))
+            .filter(|&end| end > last)
+        {
+            let mut span = 3600;
+            loop {
+                let start = missed_end.saturating_sub(span).max(last);
+                if start == last {
+                    break;
+                }
+                if count(start, missed_end) > MAX_CATCH_UP {
+                    total = count(last, start);
+                    cursor = start;
+                    break;
+                }
+                span = span.saturating_mul(2);
+            }
+        }
+
+        let mut missed = VecDeque::new();
+        let mut on_time = Vec::new();
+        while let Some(at) = next(cursor).filter(|&at| at <= now) {
+            if at.saturating_add(grace) < now {
+                missed.push_back(at);
+                total += 1;
+                // 只保留最近的几次
+                if missed.len() > MAX_CATCH_UP {
+                    missed.pop_front();
+                }
+            } else {
+                on_time.push(at);
+            }
+            cursor = at;
+        }
+
+        let catch_up: Vec<u64> = match self.missed {
+            MissedPolicy::Skip => Vec::new(),
+            // 按时的运行已经覆盖了错过的运行
+            MissedPolicy::Once if !on_time.is_empty() => Vec::new(),
+            MissedPolicy::Once => missed.back().copied().into_iter().collect(),
+            MissedPolicy::All => missed.into(),
+        };
+        let dropped = total - catch_up.len();
+        let fires = catch_up
+            .into_iter()
+            .map(|at| Fire { at, missed: true })
+            .chain(on_time.into_iter().map(|at| Fire { at, missed: false }))
+            .collect();
+        Due { fires, dropped }
+    }
+
+    /// 输出中使用的简短名称
+    pub fn label(&self) -> String {
+        format!("#{} {}", self.id, self.script)
+    }
+}
+
+/// 所有定时任务
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Schedules {
+    /// 下一个任务的编号，删除任务后编号不会重复使用
+    #[serde(default = "first_id")]
+    next_id: u64,
+    #[serde(default, rename = "schedule")]
+    schedules: Vec<Schedule>,
+}
+
+fn first_id() -> u64 {
+    1
+}
+
+impl Schedules {
+    /// 从默认位置加载定时任务
+    pub fn load() -> Result<Self> {
+        Self::load_from(&get_schedules_path()?)
+    }
+
+    /// 从指定文件加载定时任务，文件不存在时返回空列表
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self {
+                next_id: first_id(),
+                schedules: Vec::new(),
+            });
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read schedules: {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to parse schedules: {}", path.display()))
+    }
+
+    /// 保存到默认位置
+    pub fn save(&self) -> Result<()> {
+        self.save_to(&get_schedules_path()?)
+    }
+
+    /// 保存到指定文件
+    ///
+    /// 先写入临时文件再重命名，守护进程不会读到写了一半的文件
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content = toml::to_string_pretty(self).context("Failed to serialize schedules")?;
+        let temp = path.with_extension("toml.tmp");
+        fs::write(&temp, content)
+            .and_then(|_| fs::rename(&temp, path))
+            .with_context(|| format!("Failed to write schedules: {}", path.display()))
+    }
+
+    /// 所有定时任务，按编号排序
+    pub fn schedules(&self) -> &[Schedule] {
+        &self.schedules
+    }
+
+    /// 添加定时任务，返回分配的编号
+    pub fn add(&mut self, mut schedule: Schedule) -> u64 {
+        schedule.id = self.next_id.max(1);
+        self.next_id = schedule.id + 1;
+        self.schedules.push(schedule);
+        self.next_id - 1
+    }
+
+    /// 删除定时任务
+    pub fn remove(&mut self, id: u64) -> Result<Schedule> {
+        match self.schedules.iter().position(|s| s.id == id) {
+            Some(index) => Ok(self.schedules.remove(index)),
+            None => bail!("Schedule #{} not found (see 'rune schedule list')", id),
+        }
+    }
+}
+
+/// 守护进程的运行进度：每个任务最后处理到的时间
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduleState {
+    #[serde(default)]
+    last: BTreeMap<u64, u64>,
+}
+
+impl ScheduleState {
+    /// 从指定文件加载，文件不存在或无法解析时从头开始
+    pub fn load_from(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// 保存到指定文件
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content = serde_json::to_string(self).context("Failed to serialize schedule state")?;
+        fs::write(path, content)
+            .with_context(|| format!("Failed to write schedule state: {}", path.display()))
+    }
+
+    /// 任务最后处理到的时间
+    pub fn last(&self, id: u64) -> Option<u64> {
+        self.last.get(&id).copied()
+    }
+
+    /// 记录任务处理到的时间
+    pub fn set_last(&mut self, id: u64, at: u64) {
+        self.last.insert(id, at);
+    }
+
+    /// 删除已不存在的任务的记录
+    pub fn retain(&mut self, ids: impl Fn(u64) -> bool) {
+        self.last.retain(|&id, _| ids(id));
+    }
+}
+
+/// 守护进程使用的时钟
+///
+/// 默认使用系统时间；测试时可以从指定时间开始，并按倍速流逝
+#[derive(Debug, Clone, Copy)]
+pub struct Clock {
+    simulated: Option<(u64, Instant, f64)>,
+}
+
+impl Clock {
+    /// 系统时间
+    pub fn system() -> Self {
+        Self { simulated: None }
+    }
+
+    /// 从 `start` 开始、按 `speed` 倍速流逝的模拟时间
+    pub fn simulated(start: u64, speed: f64) -> Self {
+        Self {
+            simulated: Some((start, Instant::now(), speed)),
+        }
+    }
+
+    /// 是否是模拟时间
+    pub fn is_simulated(&self) -> bool {
+        self.simulated.is_some()
+    }
+
+    /// 当前时间（Unix 时间戳，秒）
+    pub fn now(&self) -> u64 {
+        match self.simulated {
+            Some((start, origin, speed)) => start + (origin.elapsed().as_secs_f64() * speed) as u64,
+            None => time::now(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn schedule(missed: MissedPolicy) -> Schedule {
+        Schedule {
+            id: 1,
+            script: "backup".to_string(),
+            cron: CronExpr::parse("*/10 * * * *").unwrap(),
+            args: Vec::new(),
+            repo: None,
+            missed,
+            overlap: OverlapPolicy::Skip,
+            created: 0,
+            limits: ResourceLimits::default(),
+            sandbox: None,
+        }
+    }
+
+    /// 每 600 秒一次，与时区无关
+    fn every_ten_minutes(after: u64) -> Option<u64> {
+        Some((after / 600 + 1) * 600)
+    }
+
+    fn count_ten_minutes(after: u64, until: u64) -> usize {
+        (until / 600).saturating_sub(after / 600) as usize
+    }
+
+    fn fires(due: &Due) -> Vec<(u64, bool)> {
+        due.fires.iter().map(|f| (f.at, f.missed)).collect()
+    }
+
+    #[test]
+    fn test_due_on_time() {
+        let schedule = schedule(MissedPolicy::Skip);
+        let due = schedule.due_with(590, 610, MISSED_GRACE, every_ten_minutes, count_ten_minutes);
+        assert_eq!(fires(&due), [(600, false)]);
+        assert_eq!(due.dropped, 0);
+
+        // 已经处理过的时间点不会再次触发
+        let due = schedule.due_with(
+            600,
+            1190,
+            MISSED_GRACE,
+            every_ten_minutes,
+            count_ten_minutes,
+        );
+        assert!(due.fires.is_empty());
+    }
+
+    #[test]
+    fn test_due_missed_policies() {
+        // 守护进程在 1000 停止，3050 重新启动：1200、1800、2400 错过，3000 按时
+        let due = |missed| {
+            schedule(missed).due_with(
+                1000,
+                3050,
+                MISSED_GRACE,
+                every_ten_minutes,
+                count_ten_minutes,
+            )
+        };
+
+        let skip = due(MissedPolicy::Skip);
+        assert_eq!(fires(&skip), [(3000, false)]);
+        assert_eq!(skip.dropped, 3);
+
+        let once = due(MissedPolicy::Once);
+        assert_eq!(fires(&once), [(3000, false)]);
+
+        let all = due(MissedPolicy::All);
+        assert_eq!(
+            fires(&all),
+            [(1200, true), (1800, true), (2400, true), (3000, false)]
+        );
+        assert_eq!(all.dropped, 0);
+
+        // 没有按时的运行时，once 补跑最近错过的一次
+        let once = schedule(MissedPolicy::Once).due_with(
+            1000,
+            2500,
+            MISSED_GRACE,
+            every_ten_minutes,
+            count_ten_minutes,
+        );
+        assert_eq!(fires(&once), [(2400, true)]);
+        assert_eq!(once.dropped, 2);
+    }
+
+    #[test]
+    fn test_due_grace_covers_slow_ticks() {
+        // 两次检查之间隔了 30 分钟（如模拟时钟倍速运行），期间的运行都算按时
+        let due = schedule(MissedPolicy::Skip).due_with(
+            0,
+            1800,
+            1800 + MISSED_GRACE,
+            every_ten_minutes,
+            count_ten_minutes,
+        );
+        assert_eq!(fires(&due), [(600, false), (1200, false), (1800, false)]);
+    }
+
+    #[test]
+    fn test_due_caps_catch_up() {
+        let due = schedule(MissedPolicy::All).due_with(
+            0,
+            600 * 1000 + 1,
+            0,
+            every_ten_minutes,
+            count_ten_minutes,
+        );
+        assert_eq!(due.fires.len(), MAX_CATCH_UP);
+        assert_eq!(due.fires.last().map(|f| f.at), Some(600 * 1000));
+        assert_eq!(due.dropped, 1000 - MAX_CATCH_UP);
+    }
+
+    #[test]
+    fn test_due_after_years_of_downtime() {
+        // 停机三年：只逐次查找最近的运行，其余错过的运行只计数
+        let now = 3 * 365 * 86400 + 300;
+        let calls = std::cell::Cell::new(0);
+        let next = |after| {
+            calls.set(calls.get() + 1);
+            every_ten_minutes(after)
+        };
+        let total = (now - 1 - MISSED_GRACE) / 600;
+        for missed in [MissedPolicy::All, MissedPolicy::Once, MissedPolicy::Skip] {
+            calls.set(0);
+            let due = schedule(missed).due_with(7, now, MISSED_GRACE, next, count_ten_minutes);
+            assert!(calls.get() <= 2 * MAX_CATCH_UP, "{} lookups", calls.get());
+            let kept = match missed {
+                MissedPolicy::All => MAX_CATCH_UP,
+                MissedPolicy::Once => 1,
+                MissedPolicy::Skip => 0,
+            };
+            assert_eq!(due.fires.len(), kept);
+            assert_eq!(due.dropped, total as usize - kept);
+            assert!(due.fires.iter().all(|fire| fire.missed));
+            assert_eq!(
+                due.fires.last().map(|f| f.at),
+                (kept > 0).then_some(total * 600)
+            );
+        }
+    }
+
+    #[test]
+    fn test_schedules_roundtrip() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("schedules.toml");
+
+        let mut schedules = Schedules::load_from(&path).unwrap();
+        let isolated = Schedule {
+            limits: ResourceLimits::parse("mem=512M").unwrap(),
+            sandbox: Some(SandboxPolicy {
+                writable: vec!["backups".to_string()],
+                network: Some(false),
+                private_tmp: None,
+            }),
+            ..schedule(MissedPolicy::Once)
+        };
+        assert_eq!(schedules.add(isolated), 1);
+        assert_eq!(schedules.add(schedule(MissedPolicy::All)), 2);
+        schedules.remove(2).unwrap();
+        assert!(schedules.remove(2).is_err());
+        schedules.save_to(&path).unwrap();
+
+        let mut loaded = Schedules::load_from(&path).unwrap();
+        assert_eq!(loaded, schedules);
+        assert_eq!(loaded.schedules()[0].cron.to_string(), "*/10 * * * *");
+        assert_eq!(loaded.schedules()[0].missed, MissedPolicy::Once);
+        assert_eq!(loaded.schedules()[0].limits.to_string(), "mem=512M");
+        // 删除的编号不会重复使用
+        assert_eq!(loaded.add(schedule(MissedPolicy::Skip)), 3);
+    }
+
+    #[test]
+    fn test_state_roundtrip() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("state.json");
+
+        assert_eq!(ScheduleState::load_from(&path), ScheduleState::default());
+        let mut state = ScheduleState::default();
+        state.set_last(1, 600);
+        state.set_last(2, 1200);
+        state.retain(|id| id == 1);
+        state.save_to(&path).unwrap();
+
+        let loaded = ScheduleState::load_from(&path);
+        assert_eq!(loaded.last(1), Some(600));
+        assert_eq!(loaded.last(2), None);
+    }
+
+    #[test]
+    fn test_simulated_clock() {
+        let clock = Clock::simulated(1000, 3600.0);
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        assert!(clock.now() >= 1072);
+        assert!(clock.is_simulated());
+        assert!(!Clock::system().is_simulated());
+    }
+}
@@ -1,15 +1,87 @@
-use anyhow::{Context, Result};
-use std::{fs, path::PathBuf};
+use crate::core::{
+    signing::SIGNATURE_EXTENSION,
+    workflow::{WORKFLOW_EXTENSION, is_workflow_file},
+};
+use anyhow::{Context, Result, bail};
+use ignore::WalkBuilder;
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+};
 
 /// 脚本信息结构体
 #[derive(Debug)]
 pub struct Script {
-    /// 脚本名称（不含扩展名）
+    /// 脚本名称（不含扩展名），子目录中的脚本带有命名空间前缀（如 `db/backup`）
     pub name: String,
     /// 文件扩展名
     pub extension: String,
     /// 脚本文件的完整路径
     pub path: PathBuf,
+    /// shebang 中声明的解释器名称（如 python3、bash）
+    pub interpreter: Option<String>,
+}
+
+impl Script {
+    /// 带扩展名的文件名（没有扩展名时即为脚本名称）
+    pub fn file_name(&self) -> String {
+        if self.extension.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{}.{}", self.name, self.extension)
+        }
+    }
+
+    /// 脚本所在的命名空间（顶层脚本为空字符串）
+    pub fn namespace(&self) -> &str {
+        self.name.rsplit_once('/').map_or("", |(ns, _)| ns)
+    }
+
+    /// 不含命名空间的脚本名称
+    pub fn base_name(&self) -> &str {
+        self.name.rsplit_once('/').map_or(&self.name, |(_, name)| name)
+    }
+
+    /// 是否为工作流文件（`*.rune.yaml`）
+    pub fn is_workflow(&self) -> bool {
+        self.extension == WORKFLOW_EXTENSION
+    }
+}
+
+/// 脚本仓库中的忽略规则文件，语法与 .gitignore 相同
+pub const IGNORE_FILE: &str = ".runeignore";
+
+/// 读取 shebang 时最多读取的字节数
+const SHEBANG_MAX_LEN: u64 = 512;
+
+/// 从 shebang 行中解析解释器名称
+///
+/// 支持 `#!/bin/bash`、`#!/usr/bin/env python3` 以及 `#!/usr/bin/env -S deno run` 等形式，
+/// 返回解释器路径的文件名部分
+pub fn parse_shebang(line: &str) -> Option<String> {
+    let mut parts = line.strip_prefix("#!")?.split_whitespace();
+    let mut program = parts.next()?;
+    if Path::new(program).file_name()?.to_str()? == "env" {
+        // 跳过 env 的选项（如 -S、-i）和变量赋值
+        program = parts.find(|p| !p.starts_with('-') && !p.contains('='))?;
+    }
+    Path::new(program)
+        .file_name()?
+        .to_str()
+        .map(|s| s.to_string())
+}
+
+/// 读取文件首行的 shebang 并解析解释器名称
+///
+/// 文件无法读取或没有 shebang 时返回 None
+pub fn read_shebang(path: &Path) -> Option<String> {
+    let file = fs::File::open(path).ok()?;
+    let mut line = String::new();
+    BufReader::new(file.take(SHEBANG_MAX_LEN))
+        .read_line(&mut line)
+        .ok()?;
+    parse_shebang(line.trim_end())
 }
 
 /// 从指定目录加载所有脚本
@@ -18,7 +90,9 @@ pub struct Script {
 /// * `path` - 脚本目录路径
 ///
 /// # 返回
-/// 返回脚本列表，只包含有扩展名的文件（排除 Makefile、Dockerfile 等）
+/// 返回脚本列表，包含有扩展名的文件，以及没有扩展名但带有 shebang 的文件
+/// （排除 Makefile、Dockerfile 等）。子目录中的脚本以相对路径作为命名空间，
+/// 隐藏文件和目录以及 `.runeignore` 中匹配的路径会被跳过
 ///
 /// # 示例
 /// ```no_run
@@ -27,34 +101,91 @@ pub struct Script {
 ///
 /// let scripts = load_scripts(PathBuf::from("~/.local/share/rune/scripts"))?;
 /// for script in scripts {
-///     println!("{}.{}", script.name, script.extension);
+///     println!("{}", script.file_name());
 /// }
 /// # Ok::<(), anyhow::Error>(())
 /// ```
 pub fn load_scripts(path: PathBuf) -> Result<Vec<Script>> {
-    // 读取目录中的所有条目
-    let entries = fs::read_dir(&path)
-        .context(format!("Failed to read directory: {:?}", path))?
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().map(|ft| ft.is_file()).unwrap_or(false));
-
-    // 过滤并转换为 Script 对象
-    let scripts: Vec<Script> = entries
-        .filter_map(|entry| {
-            let file_path = entry.path();
-            let name = file_path.file_stem()?.to_str()?.to_string();
-            // 只处理有扩展名的文件，过滤掉 Makefile、Dockerfile 等
-            let extension = file_path.extension()?.to_str()?.to_string();
-            Some(Script {
-                name,
-                extension,
-                path: file_path,
-            })
-        })
-        .collect();
+    if !path.is_dir() {
+        bail!("Failed to read directory: {:?}", path);
+    }
+
+    let walker = WalkBuilder::new(&path)
+        .standard_filters(false)
+        .hidden(true)
+        .add_custom_ignore_filename(IGNORE_FILE)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+
+    let mut scripts = Vec::new();
+    for entry in walker {
+        let entry = entry.context(format!("Failed to read directory: {:?}", path))?;
+        if !entry.file_type().is_some_and(|ft| ft.is_file()) {
+            continue;
+        }
+        let file_path = entry.into_path();
+        let Some(script) = script_from_path(&path, file_path) else {
+            continue;
+        };
+        scripts.push(script);
+    }
     Ok(scripts)
 }
 
+/// 获取命名空间在仓库中对应的目录
+///
+/// 命名空间是以 `/` 分隔的相对路径（如 `db` 或 `k8s/prod`），首尾的 `/` 会被忽略
+///
+/// # 错误
+/// 命名空间包含 `..`、`.` 开头的部分或绝对路径
+pub fn namespace_dir(root: &Path, namespace: &str) -> Result<PathBuf> {
+    let mut dir = root.to_path_buf();
+    for part in namespace.split('/').filter(|p| !p.is_empty()) {
+        if part.starts_with('.') || part.contains('\\') {
+            bail!("Invalid namespace '{}'", namespace);
+        }
+        dir.push(part);
+    }
+    Ok(dir)
+}
+
+/// 将仓库中的文件转换为脚本，不是脚本的文件返回 None
+fn script_from_path(root: &Path, file_path: PathBuf) -> Option<Script> {
+    let file_name = file_path.file_name()?.to_str()?;
+    let interpreter = read_shebang(&file_path);
+    // 工作流文件的扩展名包含两部分，如 deploy.rune.yaml
+    let (stem, extension) = if is_workflow_file(file_name) {
+        let stem = &file_name[..file_name.len() - WORKFLOW_EXTENSION.len() - 1];
+        (stem, WORKFLOW_EXTENSION.to_string())
+    } else {
+        // 没有扩展名的文件只有带 shebang 时才视为脚本，过滤掉 Makefile、Dockerfile 等
+        let extension = match file_path.extension() {
+            // 签名文件（deploy.sh.sig）不是脚本
+            Some(ext) if ext == SIGNATURE_EXTENSION => return None,
+            Some(ext) => ext.to_str()?.to_string(),
+            None if interpreter.is_some() => String::new(),
+            None => return None,
+        };
+        (file_path.file_stem()?.to_str()?, extension)
+    };
+
+    // 命名空间由相对于仓库根目录的子目录组成，统一使用 / 分隔
+    let mut parts = Vec::new();
+    if let Some(parent) = file_path.parent().and_then(|p| p.strip_prefix(root).ok()) {
+        for component in parent.components() {
+            parts.push(component.as_os_str().to_str()?);
+        }
+    }
+    parts.push(stem);
+
+    Some(Script {
+        name: parts.join("/"),
+        extension,
+        path: file_path,
+        interpreter,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -118,6 +249,76 @@ mod tests {
         assert_eq!(scripts[0].extension, "sh");
     }
 
+    #[test]
+    fn test_load_scripts_in_subdirectories() {
+        let temp_dir = TempDir::new().unwrap();
+        let script_dir = temp_dir.path();
+
+        fs::write(script_dir.join("top.sh"), "#!/bin/bash").unwrap();
+        fs::create_dir_all(script_dir.join("db")).unwrap();
+        fs::write(script_dir.join("db/backup.sh"), "#!/bin/bash").unwrap();
+        fs::create_dir_all(script_dir.join("k8s/prod")).unwrap();
+        fs::write(script_dir.join("k8s/prod/rollout.py"), "print(1)").unwrap();
+
+        let scripts = load_scripts(script_dir.to_path_buf()).unwrap();
+        let names: Vec<&str> = scripts.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["db/backup", "k8s/prod/rollout", "top"]);
+
+        let rollout = &scripts[1];
+        assert_eq!(rollout.namespace(), "k8s/prod");
+        assert_eq!(rollout.base_name(), "rollout");
+        assert_eq!(rollout.file_name(), "k8s/prod/rollout.py");
+        assert_eq!(scripts[2].namespace(), "");
+        assert_eq!(scripts[2].base_name(), "top");
+    }
+
+    #[test]
+    fn test_load_workflows() {
+        let temp_dir = TempDir::new().unwrap();
+        let script_dir = temp_dir.path();
+        fs::create_dir_all(script_dir.join("ci")).unwrap();
+        fs::write(script_dir.join("ci/release.rune.yaml"), "steps: []").unwrap();
+        fs::write(script_dir.join("compose.yaml"), "services: {}").unwrap();
+
+        let scripts = load_scripts(script_dir.to_path_buf()).unwrap();
+        assert_eq!(scripts[0].name, "ci/release");
+        assert_eq!(scripts[0].extension, "rune.yaml");
+        assert_eq!(scripts[0].file_name(), "ci/release.rune.yaml");
+        assert!(scripts[0].is_workflow());
+        assert_eq!(scripts[1].name, "compose");
+        assert!(!scripts[1].is_workflow());
+    }
+
+    #[test]
+    fn test_namespace_dir() {
+        let root = Path::new("/repo");
+        assert_eq!(namespace_dir(root, "db/").unwrap(), root.join("db"));
+        assert_eq!(namespace_dir(root, "/k8s/prod").unwrap(), root.join("k8s/prod"));
+        assert_eq!(namespace_dir(root, "").unwrap(), root);
+        assert!(namespace_dir(root, "../etc").is_err());
+        assert!(namespace_dir(root, "db/.git").is_err());
+    }
+
+    #[test]
+    fn test_load_scripts_respects_runeignore_and_hidden() {
+        let temp_dir = TempDir::new().unwrap();
+        let script_dir = temp_dir.path();
+
+        fs::write(script_dir.join(IGNORE_FILE), "lib/\n*_test.py\n!keep_test.py\n").unwrap();
+        fs::create_dir_all(script_dir.join("lib")).unwrap();
+        fs::write(script_dir.join("lib/helpers.py"), "").unwrap();
+        fs::write(script_dir.join("report.py"), "").unwrap();
+        fs::write(script_dir.join("report_test.py"), "").unwrap();
+        fs::write(script_dir.join("keep_test.py"), "").unwrap();
+        fs::create_dir_all(script_dir.join(".git")).unwrap();
+        fs::write(script_dir.join(".git/hook.sh"), "").unwrap();
+        fs::write(script_dir.join(".hidden.sh"), "").unwrap();
+
+        let scripts = load_scripts(script_dir.to_path_buf()).unwrap();
+        let names: Vec<&str> = scripts.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["keep_test", "report"]);
+    }
+
     #[test]
     fn test_load_scripts_ignores_files_without_extensions() {
         let temp_dir = TempDir::new().unwrap();
@@ -130,6 +331,8 @@ mod tests {
         // 创建无扩展名的文件（应该被忽略）
         fs::write(script_dir.join("README"), "Documentation").unwrap();
         fs::write(script_dir.join("Makefile"), "all:\n\techo test").unwrap();
+        // 签名文件也不是脚本
+        fs::write(script_dir.join("backup.sh.sig"), "signer = \"alice\"").unwrap();
 
         let scripts = load_scripts(script_dir.to_path_buf()).unwrap();
 
@@ -138,6 +341,38 @@ mod tests {
         assert!(scripts.iter().all(|s| !s.extension.is_empty()));
     }
 
+    #[test]
+    fn test_load_scripts_includes_files_with_shebang() {
+        let temp_dir = TempDir::new().unwrap();
+        let script_dir = temp_dir.path();
+
+        fs::write(script_dir.join("rotate-logs"), "#!/usr/bin/env python3\nprint('x')").unwrap();
+        fs::write(script_dir.join("cleanup"), "#!/bin/bash -e\necho x").unwrap();
+        fs::write(script_dir.join("Makefile"), "all:\n\techo test").unwrap();
+
+        let scripts = load_scripts(script_dir.to_path_buf()).unwrap();
+
+        assert_eq!(scripts.len(), 2);
+        let rotate = scripts.iter().find(|s| s.name == "rotate-logs").unwrap();
+        assert_eq!(rotate.extension, "");
+        assert_eq!(rotate.interpreter.as_deref(), Some("python3"));
+        let cleanup = scripts.iter().find(|s| s.name == "cleanup").unwrap();
+        assert_eq!(cleanup.interpreter.as_deref(), Some("bash"));
+    }
+
+    #[test]
+    fn test_parse_shebang() {
+        assert_eq!(parse_shebang("#!/bin/bash").as_deref(), Some("bash"));
+        assert_eq!(parse_shebang("#!/bin/sh -eu").as_deref(), Some("sh"));
+        assert_eq!(parse_shebang("#! /usr/bin/python3.11").as_deref(), Some("python3.11"));
+        assert_eq!(parse_shebang("#!/usr/bin/env python3").as_deref(), Some("python3"));
+        assert_eq!(parse_shebang("#!/usr/bin/env -S deno run --allow-all").as_deref(), Some("deno"));
+        assert_eq!(parse_shebang("#!/usr/bin/env LANG=C perl -w").as_deref(), Some("perl"));
+        assert_eq!(parse_shebang("#!/usr/bin/env"), None);
+        assert_eq!(parse_shebang("# comment"), None);
+        assert_eq!(parse_shebang(""), None);
+    }
+
     #[test]
     fn test_load_scripts_duplicate_names_different_extensions() {
         let temp_dir = TempDir::new().unwrap();
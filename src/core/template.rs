@@ -0,0 +1,360 @@
+use anyhow::{Result, bail};
+use std::{env, path::Path};
+
+/// 参数模板中支持的占位符
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Placeholder {
+    /// `{file}` - 脚本的完整路径
+    File,
+    /// `{args}` - 用户传入的参数（展开为多个参数）
+    Args,
+    /// `{dir}` - 脚本所在目录
+    Dir,
+    /// `{name}` - 脚本名称（不含扩展名）
+    Name,
+    /// `{ext}` - 脚本扩展名
+    Ext,
+    /// `{repo}` - 脚本仓库根目录
+    Repo,
+    /// `{env:VAR}` - 环境变量，未设置时为空字符串
+    Env(String),
+}
+
+impl Placeholder {
+    fn parse(key: &str) -> Result<Self> {
+        Ok(match key {
+            "file" => Placeholder::File,
+            "args" => Placeholder::Args,
+            "dir" => Placeholder::Dir,
+            "name" => Placeholder::Name,
+            "ext" => Placeholder::Ext,
+            "repo" => Placeholder::Repo,
+            _ => match key.strip_prefix("env:") {
+                Some(var) if !var.is_empty() => Placeholder::Env(var.to_string()),
+                _ => bail!(
+                    "Unknown placeholder '{{{}}}' (supported: {{file}}, {{args}}, {{dir}}, {{name}}, {{ext}}, {{repo}}, {{env:VAR}}; use '{{{{' and '}}}}' for literal braces)",
+                    key
+                ),
+            },
+        })
+    }
+}
+
+/// 模板片段
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+/// 解析单个模板参数
+///
+/// `{{` 和 `}}` 分别表示字面量 `{` 和 `}`
+fn parse(template: &str) -> Result<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut key = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => key.push(c),
+                        None => bail!("Unclosed '{{' in argument template '{}'", template),
+                    }
+                }
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Placeholder(Placeholder::parse(&key)?));
+            }
+            '}' => bail!(
+                "Unmatched '}}' in argument template '{}' (use '}}}}' for a literal brace)",
+                template
+            ),
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+    Ok(segments)
+}
+
+/// 判断模板参数是否恰好是 `{args}`
+fn is_args(segments: &[Segment]) -> bool {
+    matches!(segments, [Segment::Placeholder(Placeholder::Args)])
+}
+
+/// 验证参数模板
+///
+/// # 错误
+/// - 未知占位符
+/// - 未闭合或未转义的花括号
+/// - `{args}` 没有单独作为一个参数，或出现多次
+pub fn validate(template: &[String]) -> Result<()> {
+    let mut args_count = 0;
+    for arg in template {
+        let segments = parse(arg)?;
+        if is_args(&segments) {
+            args_count += 1;
+        } else if segments.contains(&Segment::Placeholder(Placeholder::Args)) {
+            bail!(
+                "'{{args}}' must be a standalone element in the argument template, found in '{}'",
+                arg
+            );
+        }
+    }
+    if args_count > 1 {
+        bail!("'{{args}}' can appear at most once in the argument template");
+    }
+    Ok(())
+}
+
+/// 模板展开所需的上下文
+pub struct TemplateContext<'a> {
+    /// 脚本文件路径
+    pub file: &'a Path,
+    /// 脚本仓库根目录
+    pub repo: &'a Path,
+    /// 用户传入的参数
+    pub args: &'a [String],
+}
+
+impl TemplateContext<'_> {
+    fn resolve(&self, placeholder: &Placeholder) -> Result<String> {
+        Ok(match placeholder {
+            Placeholder::File => path_str(self.file)?.to_string(),
+            Placeholder::Dir => path_str(self.file.parent().unwrap_or(Path::new("")))?.to_string(),
+            Placeholder::Name => self
+                .file
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            Placeholder::Ext => self
+                .file
+                .extension()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            Placeholder::Repo => path_str(self.repo)?.to_string(),
+            Placeholder::Env(var) => env::var(var).unwrap_or_default(),
+            Placeholder::Args => self.args.join(" "),
+        })
+    }
+}
+
+fn path_str(path: &Path) -> Result<&str> {
+    path.to_str()
+        .ok_or_else(|| anyhow::anyhow!("Invalid script path: non-UTF8 characters"))
+}
+
+/// 验证单个值模板（用于环境变量、工作目录等）
+///
+/// 值模板展开为单个字符串，因此不允许使用 `{args}`
+pub fn validate_value(value: &str) -> Result<()> {
+    if parse(value)?.contains(&Segment::Placeholder(Placeholder::Args)) {
+        bail!("'{{args}}' is only allowed in arg_template, found in '{}'", value);
+    }
+    Ok(())
+}
+
+/// 将片段渲染为字符串
+fn render(segments: &[Segment], ctx: &TemplateContext) -> Result<String> {
+    let mut expanded = String::new();
+    for segment in segments {
+        match segment {
+            Segment::Literal(s) => expanded.push_str(s),
+            Segment::Placeholder(p) => expanded.push_str(&ctx.resolve(p)?),
+        }
+    }
+    Ok(expanded)
+}
+
+/// 展开参数模板
+///
+/// 模板中的 `{args}` 会被替换为用户参数（每个参数单独一项）；
+/// 如果模板不包含 `{args}`，用户参数追加在末尾
+pub fn expand(template: &[String], ctx: &TemplateContext) -> Result<Vec<String>> {
+    validate(template)?;
+
+    let mut cmd_args = Vec::new();
+    let mut args_used = false;
+    for arg in template {
+        let segments = parse(arg)?;
+        if is_args(&segments) {
+            cmd_args.extend(ctx.args.iter().cloned());
+            args_used = true;
+            continue;
+        }
+        cmd_args.push(render(&segments, ctx)?);
+    }
+
+    if !args_used {
+        cmd_args.extend(ctx.args.iter().cloned());
+    }
+    Ok(cmd_args)
+}
+
+/// 展开单个值模板
+pub fn expand_value(value: &str, ctx: &TemplateContext) -> Result<String> {
+    validate_value(value)?;
+    render(&parse(value)?, ctx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+    use std::path::PathBuf;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn expand_with(template: &[&str], args: &[&str]) -> Result<Vec<String>> {
+        let file = PathBuf::from("/repo/tools/report.py");
+        let repo = PathBuf::from("/repo");
+        let args = strings(args);
+        let ctx = TemplateContext {
+            file: &file,
+            repo: &repo,
+            args: &args,
+        };
+        expand(&strings(template), &ctx)
+    }
+
+    #[test]
+    fn test_expand_default_template_appends_args() {
+        let result = expand_with(&["{file}"], &["a", "b"]).unwrap();
+        assert_eq!(result, vec!["/repo/tools/report.py", "a", "b"]);
+    }
+
+    #[test]
+    fn test_expand_args_in_middle() {
+        let result =
+            expand_with(&["--vanilla", "{file}", "{args}", "--no-save"], &["x", "y"]).unwrap();
+        assert_eq!(
+            result,
+            vec!["--vanilla", "/repo/tools/report.py", "x", "y", "--no-save"]
+        );
+    }
+
+    #[test]
+    fn test_expand_args_empty() {
+        let result = expand_with(&["{file}", "{args}", "--end"], &[]).unwrap();
+        assert_eq!(result, vec!["/repo/tools/report.py", "--end"]);
+    }
+
+    #[test]
+    fn test_expand_path_placeholders() {
+        let result = expand_with(
+            &[
+                "{dir}",
+                "{name}",
+                "{ext}",
+                "{repo}",
+                "--out={name}.{ext}.log",
+            ],
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            result,
+            vec![
+                "/repo/tools",
+                "report",
+                "py",
+                "/repo",
+                "--out=report.py.log"
+            ]
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_expand_env_placeholder() {
+        unsafe {
+            env::set_var("RUNE_TEMPLATE_TEST_VAR", "value");
+            env::remove_var("RUNE_TEMPLATE_TEST_UNSET");
+        }
+        let result = expand_with(
+            &[
+                "--a={env:RUNE_TEMPLATE_TEST_VAR}",
+                "--b={env:RUNE_TEMPLATE_TEST_UNSET}",
+            ],
+            &[],
+        )
+        .unwrap();
+        assert_eq!(result, vec!["--a=value", "--b="]);
+        unsafe {
+            env::remove_var("RUNE_TEMPLATE_TEST_VAR");
+        }
+    }
+
+    #[test]
+    fn test_expand_escaped_braces() {
+        let result = expand_with(&["-e", "{{\"file\": \"{file}\"}}"], &[]).unwrap();
+        assert_eq!(result, vec!["-e", "{\"file\": \"/repo/tools/report.py\"}"]);
+    }
+
+    #[test]
+    fn test_validate_unknown_placeholder() {
+        let result = validate(&strings(&["{script}"]));
+        assert!(result.is_err());
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("Unknown placeholder '{script}'")
+        );
+    }
+
+    #[test]
+    fn test_validate_empty_env_name() {
+        assert!(validate(&strings(&["{env:}"])).is_err());
+    }
+
+    #[test]
+    fn test_validate_unbalanced_braces() {
+        assert!(validate(&strings(&["{file"])).is_err());
+        assert!(validate(&strings(&["file}"])).is_err());
+    }
+
+    #[test]
+    fn test_validate_args_must_be_standalone() {
+        let result = validate(&strings(&["--args={args}"]));
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("standalone"));
+    }
+
+    #[test]
+    fn test_expand_value() {
+        let file = PathBuf::from("/repo/tools/report.py");
+        let repo = PathBuf::from("/repo");
+        let ctx = TemplateContext {
+            file: &file,
+            repo: &repo,
+            args: &[],
+        };
+        assert_eq!(expand_value("{dir}/lib", &ctx).unwrap(), "/repo/tools/lib");
+        assert!(expand_value("{args}", &ctx).is_err());
+        assert!(validate_value("{args}").is_err());
+        assert!(validate_value("{repo}/cache").is_ok());
+    }
+
+    #[test]
+    fn test_validate_args_only_once() {
+        assert!(validate(&strings(&["{args}", "{file}", "{args}"])).is_err());
+    }
+}
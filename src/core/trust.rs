@@ -0,0 +1,185 @@
+use crate::{
+    core::repo::{Layer, Repo},
+    utils::fs::get_trust_path,
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// 脚本的信任状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trust {
+    /// 用户仓库中的脚本，不需要信任
+    Implicit,
+    /// 已通过 `rune trust` 信任，内容未改变
+    Trusted,
+    /// 从未被信任
+    Untrusted,
+    /// 信任之后内容被修改
+    Changed,
+}
+
+impl Trust {
+    /// 是否可以运行
+    pub fn allowed(self) -> bool {
+        matches!(self, Trust::Implicit | Trust::Trusted)
+    }
+}
+
+impl fmt::Display for Trust {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trust::Implicit => write!(f, "trusted (user repository)"),
+            Trust::Trusted => write!(f, "trusted"),
+            Trust::Untrusted => write!(f, "untrusted"),
+            Trust::Changed => write!(f, "changed since trusted"),
+        }
+    }
+}
+
+/// 仓库中的脚本是否需要信任后才能运行
+///
+/// 用户仓库中的脚本由用户自己编写；项目仓库和共享仓库中的脚本来自他人，
+/// 首次运行前和每次修改后都需要用户确认
+pub fn required(repo: &Repo) -> bool {
+    !matches!(repo.layer, Layer::User)
+}
+
+/// 信任记录
+///
+/// 以脚本的绝对路径和内容的 SHA-256 识别脚本，修改内容后信任随之失效。
+/// 保存在插件目录下的 `trust.toml`（与 `plugin.dat` 相邻）
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrustStore {
+    /// 脚本绝对路径 -> 信任时内容的 SHA-256
+    #[serde(default)]
+    pub scripts: BTreeMap<PathBuf, String>,
+}
+
+impl TrustStore {
+    /// 从默认位置加载信任记录，文件不存在时返回空记录
+    pub fn load() -> Result<Self> {
+        Self::load_from(&get_trust_path()?)
+    }
+
+    /// 从指定文件加载信任记录
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read trust store: {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to parse trust store: {}", path.display()))
+    }
+
+    /// 保存信任记录到默认位置
+    pub fn save(&self) -> Result<()> {
+        self.save_to(&get_trust_path()?)
+    }
+
+    /// 保存信任记录到指定文件
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content = toml::to_string_pretty(self).context("Failed to serialize trust store")?;
+        fs::write(path, content)
+            .with_context(|| format!("Failed to write trust store: {}", path.display()))
+    }
+
+    /// 脚本当前内容的信任状态
+    pub fn check(&self, path: &Path) -> Result<Trust> {
+        let (path, hash) = identify(path)?;
+        Ok(match self.scripts.get(&path) {
+            Some(trusted) if *trusted == hash => Trust::Trusted,
+            Some(_) => Trust::Changed,
+            None => Trust::Untrusted,
+        })
+    }
+
+    /// 信任脚本的当前内容，返回内容的 SHA-256
+    pub fn trust(&mut self, path: &Path) -> Result<String> {
+        let (path, hash) = identify(path)?;
+        self.scripts.insert(path, hash.clone());
+        Ok(hash)
+    }
+
+    /// 撤销对脚本的信任，返回之前是否信任过
+    pub fn revoke(&mut self, path: &Path) -> bool {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.scripts.remove(&path).is_some()
+    }
+}
+
+/// 仓库中脚本的信任状态，不需要信任的仓库返回 [`Trust::Implicit`]
+pub fn status(repo: &Repo, path: &Path) -> Result<Trust> {
+    if !required(repo) {
+        return Ok(Trust::Implicit);
+    }
+    TrustStore::load()?.check(path)
+}
+
+/// 脚本的绝对路径和内容的 SHA-256
+fn identify(path: &Path) -> Result<(PathBuf, String)> {
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {}", path.display()))?;
+    let content = fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok((path, format!("{:x}", Sha256::digest(&content))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_trust_is_revoked_by_changes() {
+        let temp_dir = TempDir::new().unwrap();
+        let script = temp_dir.path().join("deploy.sh");
+        fs::write(&script, "echo deploy\n").unwrap();
+
+        let mut store = TrustStore::default();
+        assert_eq!(store.check(&script).unwrap(), Trust::Untrusted);
+        assert_eq!(
+            store.trust(&script).unwrap(),
+            "a1b6fafd9142ebdcce0d5c6e70cd43370623b45b01757b83c6263e8bd67dc326"
+        );
+        assert_eq!(store.check(&script).unwrap(), Trust::Trusted);
+
+        fs::write(&script, "echo deploy; rm -rf ~\n").unwrap();
+        assert_eq!(store.check(&script).unwrap(), Trust::Changed);
+        store.trust(&script).unwrap();
+        assert_eq!(store.check(&script).unwrap(), Trust::Trusted);
+
+        assert!(store.revoke(&script));
+        assert!(!store.revoke(&script));
+        assert_eq!(store.check(&script).unwrap(), Trust::Untrusted);
+    }
+
+    #[test]
+    fn test_trust_store_roundtrip() {
+        let temp_dir = TempDir::new().unwrap();
+        let script = temp_dir.path().join("build.py");
+        fs::write(&script, "print('build')\n").unwrap();
+        let path = temp_dir.path().join("plugin").join("trust.toml");
+
+        let mut store = TrustStore::default();
+        store.trust(&script).unwrap();
+        store.save_to(&path).unwrap();
+
+        let loaded = TrustStore::load_from(&path).unwrap();
+        assert_eq!(loaded, store);
+        assert_eq!(loaded.check(&script).unwrap(), Trust::Trusted);
+        assert_eq!(
+            TrustStore::load_from(&temp_dir.path().join("none.toml")).unwrap(),
+            TrustStore::default()
+        );
+    }
+}
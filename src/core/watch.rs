@@ -0,0 +1,512 @@
+use anyhow::{Context, Result, bail};
+use ignore::{Match, WalkBuilder, gitignore::Gitignore};
+use std::{
+    cmp::Reverse,
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::core::script::IGNORE_FILE;
+
+/// 默认的防抖时间：收到变化后等待这么久没有新变化才重新运行
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// 监视目录时遵循的忽略规则文件，同一目录中 `.runeignore` 优先
+const IGNORE_FILES: [&str; 2] = [IGNORE_FILE, ".gitignore"];
+
+/// 监视的目标
+#[derive(Debug, Clone)]
+enum Target {
+    /// 单个文件，监视其所在目录，以便发现编辑器“写入临时文件再改名”式的保存
+    File(PathBuf),
+    /// 目录及其所有子目录（遵循 .gitignore 和 .runeignore，跳过隐藏文件和目录）
+    Dir(PathBuf, IgnoreRules),
+}
+
+impl Target {
+    /// 变化的路径是否属于该目标
+    fn matches(&self, path: &Path) -> bool {
+        match self {
+            Target::File(file) => path == file,
+            Target::Dir(dir, rules) => {
+                path.starts_with(dir) && !is_noise(path) && !rules.is_ignored(path)
+            }
+        }
+    }
+}
+
+/// 隐藏文件、编辑器的交换文件和备份文件等不触发重新运行
+fn is_noise(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| {
+            name.starts_with('.')
+                || name.ends_with('~')
+                || (name.starts_with('#') && name.ends_with('#'))
+                || [".swp", ".swx", ".tmp", ".bak"]
+                    .iter()
+                    .any(|suffix| name.ends_with(suffix))
+                // vim 保存前用来检查目录是否可写的临时文件
+                || name == "4913"
+        })
+}
+
+/// 监视的目录及其子目录中 .gitignore 和 .runeignore 的规则
+#[derive(Debug, Clone, Default)]
+struct IgnoreRules(Vec<Gitignore>);
+
+impl IgnoreRules {
+    fn load(dir: &Path) -> Self {
+        let mut rules: Vec<Gitignore> = subdirectories(dir)
+            .iter()
+            .flat_map(|sub| IGNORE_FILES.map(|name| sub.join(name)))
+            .filter(|file| file.is_file())
+            .map(|file| Gitignore::new(file).0)
+            .collect();
+        // 子目录中的规则优先（排序是稳定的，同一目录中仍是 .runeignore 优先）
+        rules.sort_by_key(|rules| Reverse(rules.path().components().count()));
+        Self(rules)
+    }
+
+    /// 路径是否被忽略，离路径最近的规则文件中第一个匹配的规则决定结果
+    fn is_ignored(&self, path: &Path) -> bool {
+        let is_dir = path.is_dir();
+        for rules in self.0.iter().filter(|rules| path.starts_with(rules.path())) {
+            match rules.matched_path_or_any_parents(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
+}
+
+/// 是否是忽略规则文件，修改后需要重新加载规则
+fn is_ignore_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| IGNORE_FILES.contains(&name))
+}
+
+/// 文件变化监视器
+///
+/// Linux 上使用 inotify，其他平台定期比较文件的修改时间
+pub struct Watcher {
+    targets: Vec<Target>,
+    backend: Backend,
+}
+
+impl Watcher {
+    /// 监视文件或目录
+    ///
+    /// # 错误
+    /// 路径不存在或无法监视
+    pub fn new(paths: &[PathBuf]) -> Result<Self> {
+        let mut targets = Vec::new();
+        for path in paths {
+            let resolved = fs::canonicalize(path)
+                .with_context(|| format!("Cannot watch '{}'", path.display()))?;
+            targets.push(if resolved.is_dir() {
+                let rules = IgnoreRules::load(&resolved);
+                Target::Dir(resolved, rules)
+            } else {
+                Target::File(resolved)
+            });
+        }
+        if targets.is_empty() {
+            bail!("Nothing to watch");
+        }
+        let backend = Backend::new(&targets)?;
+        Ok(Self { targets, backend })
+    }
+
+    /// 等待文件变化，最多等待 `timeout`
+    ///
+    /// 返回发生变化的路径（已排序去重），超时返回空列表
+    pub fn wait(&mut self, timeout: Duration) -> Result<Vec<PathBuf>> {
+        let changed = self.backend.wait(&self.targets, timeout)?;
+        for target in &mut self.targets {
+            if let Target::Dir(dir, rules) = target
+                && changed
+                    .iter()
+                    .any(|path| path.starts_with(&*dir) && is_ignore_file(path))
+            {
+                *rules = IgnoreRules::load(dir);
+            }
+        }
+        Ok(changed
+            .into_iter()
+            .filter(|path| self.targets.iter().any(|t| t.matches(path)))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect())
+    }
+
+    /// 收到变化后继续收集，直到 `quiet` 时间内没有新变化，避免一次保存触发多次运行
+    pub fn debounce(&mut self, first: Vec<PathBuf>, quiet: Duration) -> Result<Vec<PathBuf>> {
+        let mut changed: BTreeSet<PathBuf> = first.into_iter().collect();
+        loop {
+            let more = self.wait(quiet)?;
+            if more.is_empty() {
+                return Ok(changed.into_iter().collect());
+            }
+            changed.extend(more);
+        }
+    }
+}
+
+/// 需要监视的目录（不递归），文件目标为其所在目录
+fn watched_dirs(target: &Target) -> Vec<PathBuf> {
+    match target {
+        Target::File(file) => file.parent().map(Path::to_path_buf).into_iter().collect(),
+        Target::Dir(dir, _) => subdirectories(dir),
+    }
+}
+
+/// 目录及其子目录，遵循 .gitignore 和 .runeignore 并跳过隐藏目录
+fn subdirectories(dir: &Path) -> Vec<PathBuf> {
+    let mut walker = WalkBuilder::new(dir);
+    walker.standard_filters(false).hidden(true);
+    for name in IGNORE_FILES.iter().rev() {
+        // 后添加的规则文件优先
+        walker.add_custom_ignore_filename(name);
+    }
+    walker
+        .build()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_some_and(|ft| ft.is_dir()))
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+use inotify::Backend;
+#[cfg(not(target_os = "linux"))]
+use polling::Backend;
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use super::{Target, subdirectories, watched_dirs};
+    use anyhow::{Context, Result};
+    use std::{
+        collections::HashMap,
+        ffi::{CString, OsStr},
+        io,
+        os::{
+            fd::{AsRawFd, FromRawFd, OwnedFd},
+            unix::ffi::OsStrExt,
+        },
+        path::{Path, PathBuf},
+        time::Duration,
+    };
+
+    /// 关心的事件：内容修改、创建、删除和改名
+    const MASK: u32 = libc::IN_MODIFY
+        | libc::IN_CLOSE_WRITE
+        | libc::IN_ATTRIB
+        | libc::IN_CREATE
+        | libc::IN_DELETE
+        | libc::IN_MOVED_FROM
+        | libc::IN_MOVED_TO;
+
+    pub struct Backend {
+        fd: OwnedFd,
+        /// watch 描述符对应的目录
+        dirs: HashMap<i32, PathBuf>,
+    }
+
+    impl Backend {
+        pub fn new(targets: &[Target]) -> Result<Self> {
+            // SAFETY: inotify_init1 只返回新的文件描述符或 -1
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error()).context("Failed to initialize inotify");
+            }
+            let mut backend = Self {
+                // SAFETY: fd 是刚创建的有效描述符，由 OwnedFd 负责关闭
+                fd: unsafe { OwnedFd::from_raw_fd(fd) },
+                dirs: HashMap::new(),
+            };
+            for target in targets {
+                for dir in watched_dirs(target) {
+                    backend
+                        .add(&dir)
+                        .with_context(|| format!("Cannot watch '{}'", dir.display()))?;
+                }
+            }
+            Ok(backend)
+        }
+
+        fn add(&mut self, dir: &Path) -> io::Result<()> {
+            let path = CString::new(dir.as_os_str().as_bytes())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            // SAFETY: path 是以 NUL 结尾的有效字符串
+            let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), path.as_ptr(), MASK) };
+            if wd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            self.dirs.insert(wd, dir.to_path_buf());
+            Ok(())
+        }
+
+        pub fn wait(&mut self, targets: &[Target], timeout: Duration) -> Result<Vec<PathBuf>> {
+            let mut poll = libc::pollfd {
+                fd: self.fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let millis = timeout.as_millis().min(i32::MAX as u128) as i32;
+            // SAFETY: poll 只读写传入的 pollfd
+            let ready = unsafe { libc::poll(&mut poll, 1, millis) };
+            if ready < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    return Ok(Vec::new());
+                }
+                return Err(err).context("Failed to wait for file changes");
+            }
+            if ready == 0 {
+                return Ok(Vec::new());
+            }
+
+            let mut changed = Vec::new();
+            let mut buf = vec![0u8; 64 * 1024];
+            loop {
+                // SAFETY: 读取到 buf 的有效范围内
+                let n =
+                    unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+                if n <= 0 {
+                    break;
+                }
+                let mut offset = 0;
+                while offset < n as usize {
+                    // SAFETY: 内核保证缓冲区中是完整的事件，头部可能未对齐
+                    let event: libc::inotify_event =
+                        unsafe { std::ptr::read_unaligned(buf[offset..].as_ptr().cast()) };
+                    let header = std::mem::size_of::<libc::inotify_event>();
+                    let name = &buf[offset + header..offset + header + event.len as usize];
+                    offset += header + event.len as usize;
+
+                    let Some(dir) = self.dirs.get(&event.wd).cloned() else {
+                        continue;
+                    };
+                    if event.mask & libc::IN_IGNORED != 0 {
+                        self.dirs.remove(&event.wd);
+                        continue;
+                    }
+                    let name = name.split(|&b| b == 0).next().unwrap_or_default();
+                    let path = dir.join(OsStr::from_bytes(name));
+                    // 新建的子目录也需要监视
+                    if event.mask & libc::IN_ISDIR != 0
+                        && event.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0
+                        && targets
+                            .iter()
+                            .any(|t| matches!(t, Target::Dir(..)) && t.matches(&path))
+                    {
+                        for sub in subdirectories(&path) {
+                            let _ = self.add(&sub);
+                        }
+                    }
+                    changed.push(path);
+                }
+            }
+            Ok(changed)
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod polling {
+    use super::{Target, watched_dirs};
+    use anyhow::Result;
+    use std::{
+        collections::HashMap,
+        fs,
+        path::PathBuf,
+        time::{Duration, Instant, SystemTime},
+    };
+
+    /// 两次扫描之间的间隔
+    const INTERVAL: Duration = Duration::from_millis(250);
+
+    type Snapshot = HashMap<PathBuf, (Option<SystemTime>, u64)>;
+
+    pub struct Backend {
+        snapshot: Snapshot,
+    }
+
+    impl Backend {
+        pub fn new(targets: &[Target]) -> Result<Self> {
+            Ok(Self {
+                snapshot: scan(targets),
+            })
+        }
+
+        pub fn wait(&mut self, targets: &[Target], timeout: Duration) -> Result<Vec<PathBuf>> {
+            let deadline = Instant::now() + timeout;
+            loop {
+                let snapshot = scan(targets);
+                let mut changed: Vec<PathBuf> = snapshot
+                    .iter()
+                    .filter(|(path, meta)| self.snapshot.get(*path) != Some(meta))
+                    .map(|(path, _)| path.clone())
+                    .collect();
+                changed.extend(
+                    self.snapshot
+                        .keys()
+                        .filter(|path| !snapshot.contains_key(*path))
+                        .cloned(),
+                );
+                self.snapshot = snapshot;
+                let now = Instant::now();
+                if !changed.is_empty() || now >= deadline {
+                    return Ok(changed);
+                }
+                std::thread::sleep(INTERVAL.min(deadline - now));
+            }
+        }
+    }
+
+    fn scan(targets: &[Target]) -> Snapshot {
+        let mut snapshot = HashMap::new();
+        for target in targets {
+            for dir in watched_dirs(target) {
+                let Ok(entries) = fs::read_dir(&dir) else {
+                    continue;
+                };
+                for entry in entries.flatten() {
+                    if let Ok(meta) = entry.metadata() {
+                        snapshot.insert(entry.path(), (meta.modified().ok(), meta.len()));
+                    }
+                }
+            }
+        }
+        snapshot
+    }
+}
+
+/// 变化路径的简短显示：当前目录下的路径显示为相对路径
+pub fn display_path(path: &Path) -> String {
+    std::env::current_dir()
+        .ok()
+        .and_then(|cwd| fs::canonicalize(cwd).ok())
+        .and_then(|cwd| path.strip_prefix(cwd).ok().map(Path::to_path_buf))
+        .unwrap_or_else(|| path.to_path_buf())
+        .display()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use tempfile::TempDir;
+
+    fn soon() -> Instant {
+        Instant::now() + Duration::from_secs(3)
+    }
+
+    /// 等待变化直到 `deadline`
+    fn wait_until(watcher: &mut Watcher, deadline: Instant) -> Vec<PathBuf> {
+        while Instant::now() < deadline {
+            let changed = watcher.wait(Duration::from_millis(50)).unwrap();
+            if !changed.is_empty() {
+                return changed;
+            }
+        }
+        Vec::new()
+    }
+
+    #[test]
+    fn test_watch_file_replaced_by_rename() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = fs::canonicalize(temp_dir.path()).unwrap();
+        let script = dir.join("build.sh");
+        fs::write(&script, "echo 1").unwrap();
+        fs::write(dir.join("other.txt"), "").unwrap();
+        let mut watcher = Watcher::new(std::slice::from_ref(&script)).unwrap();
+
+        // 同一目录中的其他文件不触发
+        fs::write(dir.join("other.txt"), "changed").unwrap();
+        assert!(watcher.wait(Duration::from_millis(300)).unwrap().is_empty());
+
+        // 编辑器式保存：写入临时文件再改名
+        fs::write(dir.join(".build.sh.swp"), "echo 2").unwrap();
+        fs::rename(dir.join(".build.sh.swp"), &script).unwrap();
+        let changed = wait_until(&mut watcher, soon());
+        assert_eq!(changed, vec![script.clone()]);
+
+        // 再次修改仍能发现
+        std::thread::sleep(Duration::from_millis(20));
+        fs::write(&script, "echo 3").unwrap();
+        assert_eq!(wait_until(&mut watcher, soon()), vec![script]);
+    }
+
+    #[test]
+    fn test_watch_directory_recursively() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = fs::canonicalize(temp_dir.path()).unwrap();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        let mut watcher = Watcher::new(std::slice::from_ref(&dir)).unwrap();
+
+        fs::write(dir.join("src/main.rs"), "fn main() {}").unwrap();
+        let changed = wait_until(&mut watcher, soon());
+        assert!(changed.contains(&dir.join("src/main.rs")), "{:?}", changed);
+        let changed = watcher
+            .debounce(changed, Duration::from_millis(300))
+            .unwrap();
+        assert!(changed.contains(&dir.join("src/main.rs")));
+
+        // 隐藏文件（如编辑器的交换文件）被忽略
+        fs::write(dir.join("src/.main.rs.swp"), "").unwrap();
+        assert!(watcher.wait(Duration::from_millis(300)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_watch_directory_skips_ignored_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = fs::canonicalize(temp_dir.path()).unwrap();
+        fs::create_dir_all(dir.join("out")).unwrap();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join(".gitignore"), "*.log\nout/\n").unwrap();
+        fs::write(dir.join(IGNORE_FILE), "cache.json\n").unwrap();
+        fs::write(dir.join("src/.gitignore"), "!keep.log\n").unwrap();
+        let mut watcher = Watcher::new(std::slice::from_ref(&dir)).unwrap();
+
+        // 脚本写入的输出、日志和缓存，以及编辑器的临时文件都不触发
+        fs::write(dir.join("run.log"), "output").unwrap();
+        fs::write(dir.join("out/result.txt"), "output").unwrap();
+        fs::write(dir.join("cache.json"), "{}").unwrap();
+        fs::write(dir.join("src/debug.log"), "output").unwrap();
+        fs::write(dir.join("src/4913"), "").unwrap();
+        fs::write(dir.join("src/#main.rs#"), "").unwrap();
+        assert!(watcher.wait(Duration::from_millis(300)).unwrap().is_empty());
+
+        // 子目录中的规则可以重新包含被忽略的文件
+        fs::write(dir.join("src/keep.log"), "kept").unwrap();
+        assert_eq!(
+            wait_until(&mut watcher, soon()),
+            vec![dir.join("src/keep.log")]
+        );
+
+        // 修改规则后重新加载
+        fs::write(dir.join(IGNORE_FILE), "").unwrap();
+        assert!(watcher.wait(Duration::from_millis(300)).unwrap().is_empty());
+        fs::write(dir.join("cache.json"), "{\"a\": 1}").unwrap();
+        assert_eq!(
+            wait_until(&mut watcher, soon()),
+            vec![dir.join("cache.json")]
+        );
+    }
+
+    #[test]
+    fn test_watch_missing_path() {
+        let err = Watcher::new(&[PathBuf::from("/nonexistent/rune-watch")])
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("Cannot watch"));
+    }
+}
@@ -0,0 +1,478 @@
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fmt, fs,
+    path::Path,
+};
+
+/// 工作流文件的扩展名，如 `deploy.rune.yaml`
+pub const WORKFLOW_EXTENSION: &str = "rune.yaml";
+
+/// 工作流：由多个脚本步骤组成的流水线
+///
+/// 步骤按 `needs` 声明的依赖关系组成有向无环图，没有依赖关系的步骤可以同时运行，例如：
+///
+/// ```yaml
+/// description: Build and deploy
+/// env:
+///   DEPLOY_ENV: staging
+/// steps:
+///   - script: lint
+///   - script: test
+///     args: [--fast]
+///   - script: build
+///     needs: [lint, test]
+///   - name: notify
+///     script: chat/notify
+///     needs: [build]
+///     when: failure
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Workflow {
+    /// 工作流描述
+    #[serde(default)]
+    pub description: String,
+    /// 标签
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// 所有步骤共用的环境变量
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// 步骤，按文件中的顺序
+    pub steps: Vec<Step>,
+}
+
+/// 工作流中的一个步骤
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    /// 步骤名称，默认为脚本名称
+    #[serde(default)]
+    pub name: String,
+    /// 运行的脚本
+    pub script: String,
+    /// 传给脚本的参数
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// 额外的环境变量，覆盖工作流的同名变量
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// 需要先完成的步骤
+    #[serde(default)]
+    pub needs: Vec<String>,
+    /// 根据依赖步骤的结果决定是否运行
+    #[serde(default)]
+    pub when: When,
+    /// 运行条件，不满足时跳过该步骤
+    #[serde(default, rename = "if")]
+    pub condition: Option<Condition>,
+}
+
+/// 步骤在什么情况下运行
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum When {
+    /// 所有依赖步骤都成功时
+    #[default]
+    Success,
+    /// 有依赖步骤失败时（如发送通知、回滚）
+    Failure,
+    /// 无论依赖步骤是否成功
+    Always,
+}
+
+impl fmt::Display for When {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            When::Success => write!(f, "success"),
+            When::Failure => write!(f, "failure"),
+            When::Always => write!(f, "always"),
+        }
+    }
+}
+
+/// 基于环境变量的运行条件
+///
+/// 支持 `$VAR`（已设置且不为空、`0` 或 `false`）、`!$VAR`、
+/// `$VAR == value` 和 `$VAR != value`，值可以用引号包围
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Condition {
+    source: String,
+    var: String,
+    test: Test,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Test {
+    Truthy,
+    Falsy,
+    Equals(String),
+    NotEquals(String),
+}
+
+impl Condition {
+    /// 解析条件表达式
+    pub fn parse(input: &str) -> Result<Self> {
+        let source = input.trim().to_string();
+        let invalid = || {
+            anyhow::anyhow!(
+                "Invalid condition '{}' (expected $VAR, !$VAR, $VAR == value or $VAR != value)",
+                source
+            )
+        };
+        let (negated, rest) = match source.strip_prefix('!') {
+            Some(rest) => (true, rest.trim_start()),
+            None => (false, source.as_str()),
+        };
+        let rest = rest.strip_prefix('$').ok_or_else(invalid)?;
+        let end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let (var, rest) = rest.split_at(end);
+        if var.is_empty() {
+            return Err(invalid());
+        }
+        let rest = rest.trim();
+        let test = if rest.is_empty() {
+            if negated { Test::Falsy } else { Test::Truthy }
+        } else {
+            if negated {
+                return Err(invalid());
+            }
+            let (equals, value) = if let Some(value) = rest.strip_prefix("==") {
+                (true, value)
+            } else if let Some(value) = rest.strip_prefix("!=") {
+                (false, value)
+            } else {
+                return Err(invalid());
+            };
+            let value = unquote(value.trim());
+            if equals {
+                Test::Equals(value)
+            } else {
+                Test::NotEquals(value)
+            }
+        };
+        Ok(Self {
+            var: var.to_string(),
+            source,
+            test,
+        })
+    }
+
+    /// 使用 `lookup` 查找变量并求值
+    pub fn evaluate(&self, lookup: impl Fn(&str) -> Option<String>) -> bool {
+        let value = lookup(&self.var);
+        match &self.test {
+            Test::Truthy => value.is_some_and(|v| is_truthy(&v)),
+            Test::Falsy => !value.is_some_and(|v| is_truthy(&v)),
+            Test::Equals(expected) => value.unwrap_or_default() == *expected,
+            Test::NotEquals(expected) => value.unwrap_or_default() != *expected,
+        }
+    }
+}
+
+impl TryFrom<String> for Condition {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        Self::parse(&value)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+fn unquote(value: &str) -> String {
+    for quote in ['\'', '"'] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|v| v.strip_suffix(quote))
+        {
+            return inner.to_string();
+        }
+    }
+    value.to_string()
+}
+
+fn is_truthy(value: &str) -> bool {
+    !matches!(value.trim(), "" | "0" | "false")
+}
+
+impl Workflow {
+    /// 从文件加载工作流
+    ///
+    /// # 错误
+    /// 文件无法读取、格式无效，或步骤的依赖关系有误
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read workflow: {}", path.display()))?;
+        Self::parse(&content).with_context(|| format!("Invalid workflow {}", path.display()))
+    }
+
+    /// 从 YAML 文本解析工作流，未命名的步骤以脚本名称命名
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut workflow: Self = serde_yaml::from_str(content).context("Failed to parse YAML")?;
+        for step in &mut workflow.steps {
+            if step.name.is_empty() {
+                step.name = step.script.clone();
+            }
+        }
+        workflow.check()?;
+        Ok(workflow)
+    }
+
+    /// 验证步骤名称和依赖关系
+    fn check(&self) -> Result<()> {
+        if self.steps.is_empty() {
+            bail!("Workflow has no steps");
+        }
+        let mut names = HashSet::new();
+        for step in &self.steps {
+            if step.script.trim().is_empty() {
+                bail!("Step '{}' has no script", step.name);
+            }
+            if step.name.contains(',') {
+                bail!("Step name '{}' cannot contain ','", step.name);
+            }
+            if !names.insert(step.name.as_str()) {
+                bail!(
+                    "Duplicate step name '{}' (use 'name' to tell the steps apart)",
+                    step.name
+                );
+            }
+            for key in self.env.keys().chain(step.env.keys()) {
+                if key.is_empty() || key.contains('=') {
+                    bail!("Invalid environment variable name '{}'", key);
+                }
+            }
+        }
+        for step in &self.steps {
+            for need in &step.needs {
+                if need == &step.name {
+                    bail!("Step '{}' cannot depend on itself", step.name);
+                }
+                if !names.contains(need.as_str()) {
+                    bail!("Step '{}' needs unknown step '{}'", step.name, need);
+                }
+            }
+        }
+        self.order()?;
+        Ok(())
+    }
+
+    /// 步骤的下标
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.steps.iter().position(|step| step.name == name)
+    }
+
+    /// 每个步骤依赖的步骤下标
+    pub fn dependencies(&self) -> Vec<Vec<usize>> {
+        self.steps
+            .iter()
+            .map(|step| {
+                step.needs
+                    .iter()
+                    .filter_map(|need| self.index(need))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// 按依赖关系排序的步骤下标，依赖关系允许时保持文件中的顺序
+    ///
+    /// # 错误
+    /// 依赖关系中存在环
+    pub fn order(&self) -> Result<Vec<usize>> {
+        let deps = self.dependencies();
+        let mut remaining: Vec<usize> = deps.iter().map(Vec::len).collect();
+        let mut dependents: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (i, needs) in deps.iter().enumerate() {
+            for &need in needs {
+                dependents.entry(need).or_default().push(i);
+            }
+        }
+
+        let mut ready: VecDeque<usize> = (0..deps.len()).filter(|&i| remaining[i] == 0).collect();
+        let mut order = Vec::with_capacity(deps.len());
+        while let Some(i) = ready.pop_front() {
+            order.push(i);
+            for &dependent in dependents.get(&i).into_iter().flatten() {
+                remaining[dependent] -= 1;
+                if remaining[dependent] == 0 {
+                    ready.push_back(dependent);
+                }
+            }
+        }
+        if order.len() < deps.len() {
+            let cycle: Vec<&str> = (0..deps.len())
+                .filter(|i| !order.contains(i))
+                .map(|i| self.steps[i].name.as_str())
+                .collect();
+            bail!(
+                "Steps depend on each other in a cycle: {}",
+                cycle.join(", ")
+            );
+        }
+        Ok(order)
+    }
+
+    /// 选择要运行的步骤
+    ///
+    /// `from` 为该步骤以及所有直接或间接依赖它的步骤，`only` 为列出的步骤；
+    /// 都未指定时选择全部步骤。未选择的步骤视为已经完成
+    pub fn select(&self, from: Option<&str>, only: &[String]) -> Result<Vec<bool>> {
+        let lookup = |name: &str| {
+            self.index(name).with_context(|| {
+                let names: Vec<&str> = self.steps.iter().map(|s| s.name.as_str()).collect();
+                format!("Unknown step '{}' (steps: {})", name, names.join(", "))
+            })
+        };
+
+        if !only.is_empty() {
+            let mut selected = vec![false; self.steps.len()];
+            for name in only {
+                selected[lookup(name)?] = true;
+            }
+            return Ok(selected);
+        }
+
+        let Some(from) = from else {
+            return Ok(vec![true; self.steps.len()]);
+        };
+        let mut selected = vec![false; self.steps.len()];
+        selected[lookup(from)?] = true;
+        let deps = self.dependencies();
+        for i in self.order()? {
+            if deps[i].iter().any(|&need| selected[need]) {
+                selected[i] = true;
+            }
+        }
+        Ok(selected)
+    }
+}
+
+/// 文件名是否为工作流文件
+pub fn is_workflow_file(file_name: &str) -> bool {
+    file_name
+        .strip_suffix(WORKFLOW_EXTENSION)
+        .is_some_and(|stem| stem.len() > 1 && stem.ends_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PIPELINE: &str = r#"
+description: Build and deploy
+env:
+  DEPLOY_ENV: staging
+steps:
+  - script: lint
+  - script: test
+    args: [--fast]
+  - script: build
+    needs: [lint, test]
+  - name: deploy
+    script: ops/deploy
+    needs: [build]
+    if: $DEPLOY_ENV == "prod"
+  - name: notify
+    script: chat/notify
+    needs: [deploy]
+    when: failure
+"#;
+
+    #[test]
+    fn test_parse_workflow() {
+        let workflow = Workflow::parse(PIPELINE).unwrap();
+        assert_eq!(workflow.description, "Build and deploy");
+        assert_eq!(workflow.steps.len(), 5);
+        assert_eq!(workflow.steps[0].name, "lint");
+        assert_eq!(workflow.steps[1].args, vec!["--fast"]);
+        assert_eq!(workflow.steps[3].name, "deploy");
+        assert_eq!(workflow.steps[4].when, When::Failure);
+        assert_eq!(workflow.dependencies()[2], vec![0, 1]);
+        assert_eq!(workflow.order().unwrap(), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_invalid_workflows() {
+        let cases = [
+            ("steps: []", "no steps"),
+            (
+                "steps:\n  - script: a\n  - script: a",
+                "Duplicate step name",
+            ),
+            ("steps:\n  - script: a\n    needs: [b]", "unknown step"),
+            ("steps:\n  - script: a\n    needs: [a]", "itself"),
+            (
+                "steps:\n  - script: a\n    needs: [b]\n  - script: b\n    needs: [a]",
+                "cycle: a, b",
+            ),
+            ("steps:\n  - script: a\n    if: DEPLOY", "Invalid condition"),
+            ("steps:\n  - script: a\n    when: later", "unknown variant"),
+            ("steps:\n  - script: a\n    retries: 2", "unknown field"),
+        ];
+        for (yaml, expected) in cases {
+            let err = format!("{:#}", Workflow::parse(yaml).unwrap_err());
+            assert!(err.contains(expected), "{}: {}", yaml, err);
+        }
+    }
+
+    #[test]
+    fn test_select_steps() {
+        let workflow = Workflow::parse(PIPELINE).unwrap();
+        assert_eq!(workflow.select(None, &[]).unwrap(), vec![true; 5]);
+        assert_eq!(
+            workflow.select(Some("build"), &[]).unwrap(),
+            vec![false, false, true, true, true]
+        );
+        assert_eq!(
+            workflow.select(Some("test"), &[]).unwrap(),
+            vec![false, true, true, true, true]
+        );
+        assert_eq!(
+            workflow
+                .select(None, &["lint".to_string(), "deploy".to_string()])
+                .unwrap(),
+            vec![true, false, false, true, false]
+        );
+        assert!(workflow.select(Some("package"), &[]).is_err());
+    }
+
+    #[test]
+    fn test_condition() {
+        let env = |name: &str| match name {
+            "DEPLOY_ENV" => Some("prod".to_string()),
+            "DRY_RUN" => Some("0".to_string()),
+            _ => None,
+        };
+        let check = |input: &str| Condition::parse(input).unwrap().evaluate(env);
+        assert!(check("$DEPLOY_ENV"));
+        assert!(check("$DEPLOY_ENV == prod"));
+        assert!(check("$DEPLOY_ENV == 'prod'"));
+        assert!(!check("$DEPLOY_ENV != \"prod\""));
+        assert!(!check("$DRY_RUN"));
+        assert!(check("!$DRY_RUN"));
+        assert!(check("!$MISSING"));
+        assert!(check("$MISSING == ''"));
+        assert!(Condition::parse("DEPLOY_ENV").is_err());
+        assert!(Condition::parse("!$A == b").is_err());
+        assert!(Condition::parse("$A > b").is_err());
+    }
+
+    #[test]
+    fn test_is_workflow_file() {
+        assert!(is_workflow_file("deploy.rune.yaml"));
+        assert!(!is_workflow_file("deploy.yaml"));
+        assert!(!is_workflow_file(".rune.yaml"));
+        assert!(!is_workflow_file("deployrune.yaml"));
+    }
+}
@@ -1,82 +1,418 @@
-mod commands;
-mod core;
-mod utils;
-
-use clap::Parser;
-use commands::{Cli, Commands};
-
-use crate::commands::{plugin, run, script};
+use clap::{CommandFactory, Parser};
+use rune::commands::{
+    self, Cli, Commands, background, config,
+    daemon::{self, DaemonOptions},
+    history, plugin, run,
+    run_many::{self, ManyOptions},
+    schedule, script,
+    watch::WatchOptions,
+    workflow::WorkflowOptions,
+};
+use rune::core::{
+    config::Config,
+    executor::{DEFAULT_GRACE_PERIOD, ExitOutcome},
+    limits::ResourceLimits,
+    lock::LockWait,
+    sandbox::SandboxPolicy,
+    watch::DEFAULT_DEBOUNCE,
+};
+use rune::utils::{
+    cli::{Color, paint, set_color},
+    time::parse_time,
+};
 
 fn main() {
     let cli = Cli::parse();
-    match cli.command {
+    // 配置无效时先不使用颜色，错误由读取配置的命令报告
+    let color = Config::load().map(|c| c.output.color).unwrap_or_default();
+    set_color(color.enabled());
+    let result = match cli.command {
         Commands::Run {
             name,
             extension,
             plugin,
+            repo,
+            grace_period,
+            no_input,
+            dry_run,
+            no_history,
+            log,
+            no_log,
+            timeout,
+            retries,
+            retry_delay,
+            backoff,
+            lock,
+            lock_name,
+            lock_wait,
+            limit,
+            sandbox,
+            sandbox_write,
+            no_network,
+            allow_unsigned,
+            parallel,
+            fail_fast,
+            from_step,
+            only,
+            watch,
+            clear,
+            debounce,
+            detach,
+            background_id,
+            help,
+            args,
+        } => match name {
+            Some(name) if help => run::help(name, extension, repo).map(|_| ExitOutcome::SUCCESS),
+            Some(name) => {
+                let options = run::RunOptions {
+                    name,
+                    extension,
+                    plugin,
+                    repo,
+                    grace_period,
+                    no_input,
+                    no_history,
+                    log: (log || no_log).then_some(log),
+                    timeout,
+                    retries,
+                    retry_delay,
+                    backoff,
+                    lock,
+                    lock_name,
+                    lock_wait: lock_wait
+                        .map(|wait| wait.map_or(LockWait::Forever, LockWait::Timeout)),
+                    limits: merge_limits(limit),
+                    sandbox: sandbox_policy(sandbox, sandbox_write, no_network),
+                    allow_unsigned,
+                    background: background_id,
+                    args,
+                };
+                let mut names = name_list(&options.name);
+                let many = ManyOptions {
+                    jobs: parallel.unwrap_or(0),
+                    fail_fast,
+                };
+                if names.len() > 1 {
+                    if detach {
+                        Err(anyhow::anyhow!("--detach can only be used with a single script"))
+                    } else if watch.is_some() {
+                        Err(anyhow::anyhow!("--watch can only be used with a single script"))
+                    } else if from_step.is_some() || !only.is_empty() {
+                        Err(anyhow::anyhow!(
+                            "--from-step and --only can only be used with a single workflow"
+                        ))
+                    } else if dry_run {
+                        names
+                            .into_iter()
+                            .try_for_each(|name| {
+                                run::explain(
+                                    run::RunOptions { name, ..options.clone() },
+                                    false,
+                                    WorkflowOptions::default(),
+                                )
+                            })
+                            .map(|_| ExitOutcome::SUCCESS)
+                    } else {
+                        run_many::run_many(names, options, many)
+                    }
+                } else {
+                    let options = run::RunOptions {
+                        name: names.pop().unwrap_or(options.name),
+                        ..options
+                    };
+                    let steps = WorkflowOptions {
+                        many,
+                        from_step,
+                        only,
+                    };
+                    if dry_run {
+                        run::explain(options, false, steps).map(|_| ExitOutcome::SUCCESS)
+                    } else if detach {
+                        background::detach(options).map(|_| ExitOutcome::SUCCESS)
+                    } else if let Some(paths) = watch {
+                        let watch = WatchOptions {
+                            paths,
+                            clear,
+                            debounce: debounce.unwrap_or(DEFAULT_DEBOUNCE),
+                        };
+                        commands::watch::watch(options, watch)
+                    } else {
+                        run::run(options, steps)
+                    }
+                }
+            }
+            None => print_run_help().map(|_| ExitOutcome::SUCCESS),
+        },
+        Commands::RunMany {
+            names,
+            repo,
+            parallel,
+            fail_fast,
+            grace_period,
+            no_input,
+            no_history,
+            log,
+            no_log,
+            timeout,
+            retries,
+            limit,
+            sandbox,
+            sandbox_write,
+            no_network,
+            allow_unsigned,
+            args,
+        } => {
+            let options = run::RunOptions {
+                name: String::new(),
+                extension: None,
+                plugin: None,
+                repo,
+                grace_period,
+                no_input,
+                no_history,
+                log: (log || no_log).then_some(log),
+                timeout,
+                retries,
+                retry_delay: None,
+                backoff: None,
+                lock: false,
+                lock_name: None,
+                lock_wait: None,
+                limits: merge_limits(limit),
+                sandbox: sandbox_policy(sandbox, sandbox_write, no_network),
+                allow_unsigned,
+                background: None,
+                args,
+            };
+            let many = ManyOptions {
+                jobs: parallel,
+                fail_fast,
+            };
+            run_many::run_many(names, options, many)
+        }
+        Commands::Explain {
+            name,
+            extension,
+            plugin,
+            repo,
+            shell,
             args,
         } => {
-            if let Err(e) = run::run(name, extension, plugin, args) {
-                eprintln!("Error: {:#}", e)
+            let options = run::RunOptions {
+                name,
+                extension,
+                plugin,
+                repo,
+                grace_period: DEFAULT_GRACE_PERIOD.as_secs(),
+                no_input: true,
+                no_history: true,
+                log: None,
+                timeout: None,
+                retries: None,
+                retry_delay: None,
+                backoff: None,
+                lock: false,
+                lock_name: None,
+                lock_wait: None,
+                limits: ResourceLimits::default(),
+                sandbox: None,
+                allow_unsigned: false,
+                background: None,
+                args,
+            };
+            run::explain(options, shell, WorkflowOptions::default()).map(|_| ExitOutcome::SUCCESS)
+        }
+        Commands::History {
+            script,
+            status,
+            since,
+            until,
+            limit,
+            plain,
+            clear,
+            yes,
+        } => if clear {
+            history::clear(yes)
+        } else {
+            let filter = history::HistoryFilter {
+                script,
+                status,
+                since,
+                until,
+                limit,
+            };
+            history::list(filter, plain)
+        }
+        .map(|_| ExitOutcome::SUCCESS),
+        Commands::Rerun {
+            id,
+            here,
+            dry_run,
+            no_input,
+        } => history::rerun(id, here, dry_run, no_input),
+        Commands::Logs { id, path, follow } => {
+            history::logs(id, path, follow).map(|_| ExitOutcome::SUCCESS)
+        }
+        Commands::Trust {
+            name,
+            extension,
+            repo,
+            revoke,
+        } => commands::trust::trust(&name, extension.as_deref(), repo.as_deref(), revoke)
+            .map(|_| ExitOutcome::SUCCESS),
+        Commands::Sign {
+            targets,
+            key,
+            generate,
+            name,
+            show_key,
+        } => commands::sign::sign(targets, key, generate, name, show_key)
+            .map(|_| ExitOutcome::SUCCESS),
+        Commands::Ps { plain } => background::ps(plain).map(|_| ExitOutcome::SUCCESS),
+        Commands::Kill { id, signal } => background::kill(id, signal).map(|_| ExitOutcome::SUCCESS),
+        Commands::Schedule { command } => match command {
+            commands::ScheduleCommands::Add {
+                script,
+                cron,
+                repo,
+                missed,
+                overlap,
+                limit,
+                sandbox,
+                sandbox_write,
+                no_network,
+                args,
+            } => {
+                let options = schedule::ScheduleOptions {
+                    missed,
+                    overlap,
+                    limits: merge_limits(limit),
+                    sandbox: sandbox_policy(sandbox, sandbox_write, no_network),
+                };
+                schedule::add(script, cron, repo, options, args)
             }
+            commands::ScheduleCommands::List { plain } => schedule::list(plain),
+            commands::ScheduleCommands::Remove { id, yes } => schedule::remove(id, yes),
         }
+        .map(|_| ExitOutcome::SUCCESS),
+        Commands::Daemon { clock, speed, once } => clock
+            .as_deref()
+            .map(parse_time)
+            .transpose()
+            .and_then(|clock| daemon::daemon(DaemonOptions { clock, speed, once })),
         Commands::Plugin { command } => match command {
-            commands::PluginCommands::Add { path, force } => {
-                if let Err(e) = plugin::add(path, force) {
-                    eprintln!("Error: {:#}", e)
-                }
-            }
-            commands::PluginCommands::Remove { name, yes } => {
-                if let Err(e) = plugin::remove(name, yes) {
-                    eprintln!("Error: {:#}", e)
-                }
-            }
-            commands::PluginCommands::List { plain } => {
-                if let Err(e) = plugin::list(plain) {
-                    eprintln!("Error: {:#}", e)
-                }
-            }
-            commands::PluginCommands::Info { name } => {
-                if let Err(e) = plugin::info(name) {
-                    eprintln!("Error: {:#}", e)
-                }
-            }
+            commands::PluginCommands::Add {
+                path,
+                force,
+                allow_unsigned,
+            } => plugin::add(path, force, allow_unsigned),
+            commands::PluginCommands::Remove { name, yes } => plugin::remove(name, yes),
+            commands::PluginCommands::List { plain, format } => plugin::list(plain, format),
+            commands::PluginCommands::Info { name } => plugin::info(name),
+            commands::PluginCommands::Default {
+                extension,
+                plugin,
+                unset,
+            } => plugin::default(extension, plugin, unset),
             commands::PluginCommands::Export { name, output, format } => {
-                if let Err(e) = plugin::export(name, output, format) {
-                    eprintln!("Error: {:#}", e)
-                }
+                plugin::export(name, output, format)
             }
-        },
+        }
+        .map(|_| ExitOutcome::SUCCESS),
         Commands::Script { command } => match command {
-            commands::ScriptCommands::Add { path, force } => {
-                if let Err(e) = script::add(path, force) {
-                    eprintln!("Error: {:#}", e)
-                }
-            }
+            commands::ScriptCommands::Add {
+                path,
+                force,
+                into,
+                repo,
+            } => script::add(path, force, into, repo),
             commands::ScriptCommands::Remove {
                 name,
                 yes,
                 extension,
-            } => {
-                if let Err(e) = script::remove(name, yes, extension) {
-                    eprintln!("Error: {:#}", e)
-                }
-            }
-            commands::ScriptCommands::List { plain } => {
-                if let Err(e) = script::list(plain) {
-                    eprintln!("Error: {:#}", e)
-                }
-            }
-            commands::ScriptCommands::New { name } => {
-                if let Err(e) = script::new(name) {
-                    eprintln!("Error: {:#}", e)
-                }
-            }
-            commands::ScriptCommands::Edit { name, extension } => {
-                if let Err(e) = script::edit(name, extension) {
-                    eprintln!("Error: {:#}", e)
-                }
-            }
-        },
+                repo,
+            } => script::remove(name, yes, extension, repo),
+            commands::ScriptCommands::List {
+                plain,
+                tree,
+                format,
+                repo,
+            } => script::list(plain, tree, format, repo),
+            commands::ScriptCommands::Info {
+                name,
+                extension,
+                repo,
+            } => script::info(name, extension, repo),
+            commands::ScriptCommands::New { name, repo } => script::new(name, repo),
+            commands::ScriptCommands::Edit {
+                name,
+                extension,
+                repo,
+            } => script::edit(name, extension, repo),
+            commands::ScriptCommands::Pin {
+                name,
+                plugin,
+                extension,
+                unset,
+            } => script::pin(name, plugin, extension, unset),
+        }
+        .map(|_| ExitOutcome::SUCCESS),
+        Commands::Config { command } => match command {
+            commands::ConfigCommands::Get { key } => config::get(key),
+            commands::ConfigCommands::Set {
+                key,
+                value,
+                unset,
+                project,
+            } => config::set(key, value, unset, project),
+            commands::ConfigCommands::List => config::list(),
+            commands::ConfigCommands::Path { project } => config::path(project),
+        }
+        .map(|_| ExitOutcome::SUCCESS),
+    };
+
+    // 脚本的退出码（或终止信号）原样传递给调用方，rune 自身的错误统一以 1 退出
+    match result {
+        Ok(outcome) => outcome.exit(),
+        Err(e) => {
+            eprintln!("{} {:#}", paint("Error:", Color::Red), e);
+            std::process::exit(1)
+        }
+    }
+}
+
+/// 拆分以逗号分隔的脚本名称
+fn name_list(names: &str) -> Vec<String> {
+    names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// 合并多个 `--limit`，后面的同名限制覆盖前面的
+fn merge_limits(limits: Vec<ResourceLimits>) -> ResourceLimits {
+    limits
+        .into_iter()
+        .fold(ResourceLimits::default(), |merged, limit| limit.or(merged))
+}
+
+/// 由 `--sandbox`、`--sandbox-write` 和 `--no-network` 得到命令行的沙箱策略
+fn sandbox_policy(sandbox: bool, writable: Vec<String>, no_network: bool) -> Option<SandboxPolicy> {
+    (sandbox || !writable.is_empty() || no_network).then(|| SandboxPolicy {
+        writable,
+        network: no_network.then_some(false),
+        private_tmp: None,
+    })
+}
+
+/// 打印 `rune run` 子命令的帮助
+fn print_run_help() -> anyhow::Result<()> {
+    let mut command = Cli::command();
+    command.build();
+    if let Some(run) = command.find_subcommand_mut("run") {
+        run.print_help()?;
     }
+    Ok(())
 }
@@ -1,5 +1,58 @@
 use anyhow::{Context, Result};
-use std::io::{self, Write};
+use std::{
+    fmt,
+    io::{self, IsTerminal, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// 是否输出 ANSI 颜色，由配置中的 `output.color` 决定
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// 终端颜色
+#[derive(Debug, Clone, Copy)]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    Dim,
+}
+
+/// 启用或禁用颜色输出
+pub fn set_color(enabled: bool) {
+    COLOR.store(enabled, Ordering::Relaxed);
+}
+
+/// 为文本加上颜色，禁用颜色时原样返回
+pub fn paint(text: &str, color: Color) -> String {
+    if !COLOR.load(Ordering::Relaxed) {
+        return text.to_string();
+    }
+    let code = match color {
+        Color::Red => "31",
+        Color::Green => "32",
+        Color::Yellow => "33",
+        Color::Blue => "34",
+        Color::Magenta => "35",
+        Color::Cyan => "36",
+        Color::Dim => "2",
+    };
+    format!("\x1b[{}m{}\x1b[0m", code, text)
+}
+
+/// 向 stderr 打印警告
+pub fn warn(message: impl fmt::Display) {
+    eprintln!("{} {}", paint("警告:", Color::Yellow), message);
+}
+
+/// 标准输入是否连接到终端
+///
+/// 在 CI、管道或 cron 中运行时返回 false，此时不应提示用户输入
+pub fn is_interactive() -> bool {
+    io::stdin().is_terminal()
+}
 
 pub fn prompt_confirm(prompt: &str, default_yes: bool) -> Result<bool> {
     let hint = if default_yes { "[Y/n" } else { "[y/N]" };
@@ -1,22 +1,81 @@
+use crate::core::config::Config;
 use anyhow::{Result, anyhow};
 use std::{env, path::PathBuf};
 
+/// 用户仓库目录，可通过配置 `repos.user` 或 RUNE_REPO 覆盖
 pub fn get_script_path() -> Result<PathBuf> {
-    match env::var("RUNE_REPO") {
-        Ok(v) => Ok(PathBuf::from(v)),
-        Err(_) => Ok(get_data_home()?.join("scripts")),
-    }
+    Config::load()?.script_path()
 }
+/// 插件目录，可通过配置 `plugins.dir` 或 RUNE_PLUGIN 覆盖
 pub fn get_plugin_path() -> Result<PathBuf> {
-    match env::var("RUNE_PLUGIN") {
-        Ok(v) => Ok(PathBuf::from(v)),
-        Err(_) => Ok(get_data_home()?.join("plugin")),
-    }
+    Config::load()?.plugin_path()
 }
 pub fn get_plugin_db() -> Result<PathBuf> {
     Ok(get_plugin_path()?.join("plugin.dat"))
 }
-fn get_data_home() -> Result<PathBuf> {
+pub fn get_preferences_path() -> Result<PathBuf> {
+    Ok(get_plugin_path()?.join("preferences.toml"))
+}
+/// 脚本信任记录文件路径
+pub fn get_trust_path() -> Result<PathBuf> {
+    Ok(get_plugin_path()?.join("trust.toml"))
+}
+/// 运行历史文件路径
+pub fn get_history_path() -> Result<PathBuf> {
+    Ok(get_data_home()?.join("history.jsonl"))
+}
+/// 运行日志目录
+pub fn get_logs_path() -> Result<PathBuf> {
+    Ok(get_data_home()?.join("logs"))
+}
+/// 脚本锁目录
+pub fn get_locks_path() -> Result<PathBuf> {
+    Ok(get_data_home()?.join("locks"))
+}
+/// 后台运行登记目录
+pub fn get_runs_path() -> Result<PathBuf> {
+    Ok(get_data_home()?.join("runs"))
+}
+/// 定时任务文件路径
+pub fn get_schedules_path() -> Result<PathBuf> {
+    Ok(get_data_home()?.join("schedules.toml"))
+}
+/// 守护进程记录的定时任务运行进度
+pub fn get_schedule_state_path() -> Result<PathBuf> {
+    Ok(get_data_home()?.join("schedule-state.json"))
+}
+/// 配置文件路径，可通过 RUNE_CONFIG 覆盖
+pub fn get_config_path() -> Result<PathBuf> {
+    match env::var("RUNE_CONFIG") {
+        Ok(v) => Ok(PathBuf::from(v)),
+        Err(_) => Ok(get_config_home()?.join("config.toml")),
+    }
+}
+/// 默认的签名私钥文件路径
+pub fn get_signing_key_path() -> Result<PathBuf> {
+    Ok(get_config_home()?.join("signing.key"))
+}
+fn get_config_home() -> Result<PathBuf> {
+    #[cfg(target_os = "windows")]
+    {
+        get_data_home()
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        env::var("XDG_CONFIG_HOME")
+            .ok()
+            .map(|p| PathBuf::from(p).join("rune"))
+            .or_else(|| {
+                env::var("HOME")
+                    .ok()
+                    .map(|p| PathBuf::from(p).join(".config").join("rune"))
+            })
+            .ok_or_else(|| {
+                anyhow!("Could not determine config directory: XDG_CONFIG_HOME or HOME not set")
+            })
+    }
+}
+pub(crate) fn get_data_home() -> Result<PathBuf> {
     #[cfg(target_os = "windows")]
     {
         env::var("APPDATA")
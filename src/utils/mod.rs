@@ -1,2 +1,6 @@
 pub mod cli;
+pub mod duration;
 pub mod fs;
+pub mod shell;
+pub mod size;
+pub mod time;
@@ -0,0 +1,46 @@
+/// 按 POSIX shell 规则转义单个参数
+///
+/// 只包含安全字符的参数原样返回，其他参数用单引号包裹
+pub fn quote(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+/// 转义并用空格连接多个参数
+pub fn join<I, S>(args: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    args.into_iter()
+        .map(|arg| quote(arg.as_ref()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("plain-arg_1.txt"), "plain-arg_1.txt");
+        assert_eq!(quote("--name=value"), "--name=value");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("two words"), "'two words'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+        assert_eq!(quote("$HOME"), "'$HOME'");
+        assert_eq!(quote("~/x"), "'~/x'");
+    }
+
+    #[test]
+    fn test_join() {
+        assert_eq!(
+            join(["/usr/bin/python3", "a b", "c"]),
+            "/usr/bin/python3 'a b' c"
+        );
+    }
+}
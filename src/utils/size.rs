@@ -0,0 +1,78 @@
+use anyhow::{Result, bail};
+
+/// 解析大小字符串，返回字节数
+///
+/// 支持 `B`、`K`/`KB`、`M`/`MB`、`G`/`GB` 单位（按 1024 进位，不区分大小写），纯数字按字节处理
+pub fn parse_size(input: &str) -> Result<u64> {
+    let input = input.trim();
+    if input.is_empty() {
+        bail!("Size cannot be empty");
+    }
+    let digits = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    if digits == 0 {
+        bail!("Invalid size '{}' (examples: 512K, 10MB, 1G)", input);
+    }
+    let value: u64 = input[..digits].parse()?;
+    let multiplier: u64 = match input[digits..].trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        unit => bail!(
+            "Invalid size unit '{}' in '{}' (use B, K, M or G)",
+            unit,
+            input
+        ),
+    };
+    match value.checked_mul(multiplier) {
+        Some(size) => Ok(size),
+        None => bail!("Size '{}' is too large", input),
+    }
+}
+
+/// 将字节数格式化为便于阅读的字符串（如 `512B`、`1.5K`、`10M`）
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["K", "M", "G"];
+    if bytes < 1024 {
+        return format!("{}B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if value.fract() == 0.0 || value >= 10.0 {
+        format!("{:.0}{}", value, UNITS[unit])
+    } else {
+        format!("{:.1}{}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("100").unwrap(), 100);
+        assert_eq!(parse_size("512B").unwrap(), 512);
+        assert_eq!(parse_size("4k").unwrap(), 4096);
+        assert_eq!(parse_size("10MB").unwrap(), 10 * 1024 * 1024);
+        assert_eq!(parse_size("1 G").unwrap(), 1 << 30);
+        assert!(parse_size("").is_err());
+        assert!(parse_size("MB").is_err());
+        assert!(parse_size("5T").is_err());
+        assert!(parse_size("99999999999999G").is_err());
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512B");
+        assert_eq!(format_size(1536), "1.5K");
+        assert_eq!(format_size(10 * 1024 * 1024), "10M");
+        assert_eq!(format_size(3 << 30), "3G");
+    }
+}
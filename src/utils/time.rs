@@ -0,0 +1,179 @@
+use crate::utils::duration::parse_duration;
+use anyhow::{Result, anyhow, bail};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 当前时间（Unix 时间戳，秒）
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// 当前时间（Unix 时间戳，毫秒）
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// 将 Unix 时间戳格式化为本地时间 `YYYY-MM-DD HH:MM:SS`
+pub fn format_timestamp(secs: u64) -> String {
+    format_with_offset(secs, local_offset(secs as i64))
+}
+
+/// 将毫秒时间戳格式化为本地时间 `YYYY-MM-DD HH:MM:SS.mmm`
+pub fn format_timestamp_millis(millis: u64) -> String {
+    format!("{}.{:03}", format_timestamp(millis / 1000), millis % 1000)
+}
+
+/// 解析时间点，返回 Unix 时间戳
+///
+/// 支持相对时长（如 `2h`、`7d`，表示距今多久之前）、
+/// 本地日期 `YYYY-MM-DD` 和 `YYYY-MM-DD HH:MM`
+pub fn parse_time(input: &str) -> Result<u64> {
+    let input = input.trim();
+    if let Ok(ago) = parse_duration(input) {
+        return Ok(now().saturating_sub(ago.as_secs()));
+    }
+
+    let (date, time) = match input.split_once([' ', 'T']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (input, None),
+    };
+    let invalid = || {
+        anyhow!(
+            "Invalid time '{}' (expected e.g. '2h', '7d' or 'YYYY-MM-DD')",
+            input
+        )
+    };
+    let mut parts = date.splitn(3, '-').map(|p| p.parse::<i64>());
+    let (Some(Ok(year)), Some(Ok(month)), Some(Ok(day))) =
+        (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(invalid());
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+    let (hour, minute) = match time {
+        Some(time) => {
+            let (h, m) = time.split_once(':').ok_or_else(invalid)?;
+            let (h, m) = (
+                h.parse::<i64>().map_err(|_| invalid())?,
+                m.parse::<i64>().map_err(|_| invalid())?,
+            );
+            if !(0..24).contains(&h) || !(0..60).contains(&m) {
+                return Err(invalid());
+            }
+            (h, m)
+        }
+        None => (0, 0),
+    };
+
+    let local = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60;
+    let utc = local - local_offset(local);
+    if utc < 0 {
+        bail!("Time '{}' is before 1970", input);
+    }
+    Ok(utc as u64)
+}
+
+/// 按指定的 UTC 偏移（秒）格式化时间戳
+fn format_with_offset(secs: u64, offset: i64) -> String {
+    let local = secs as i64 + offset;
+    let (days, rem) = (local.div_euclid(86400), local.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// 本地时区在指定时刻相对 UTC 的偏移（秒）
+#[cfg(unix)]
+pub(crate) fn local_offset(secs: i64) -> i64 {
+    let time = secs as libc::time_t;
+    // SAFETY: localtime_r 只写入传入的 tm 结构体
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&time, &mut tm).is_null() {
+            return 0;
+        }
+        tm.tm_gmtoff as i64
+    }
+}
+
+/// 非 Unix 平台使用 UTC
+#[cfg(not(unix))]
+pub(crate) fn local_offset(_secs: i64) -> i64 {
+    0
+}
+
+/// 1970-01-01 起的天数转换为公历日期
+pub(crate) fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// 公历日期转换为 1970-01-01 起的天数
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_civil_roundtrip() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(civil_from_days(days_from_civil(2024, 2, 29)), (2024, 2, 29));
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+    }
+
+    #[test]
+    fn test_format_with_offset() {
+        assert_eq!(format_with_offset(0, 0), "1970-01-01 00:00:00");
+        assert_eq!(format_with_offset(1_700_000_000, 0), "2023-11-14 22:13:20");
+        assert_eq!(
+            format_with_offset(1_700_000_000, 8 * 3600),
+            "2023-11-15 06:13:20"
+        );
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let ago = parse_time("2h").unwrap();
+        assert!(now() - ago >= 7200 && now() - ago < 7260);
+
+        let date = parse_time("2023-11-15").unwrap();
+        assert!(format_timestamp(date).starts_with("2023-11-15 00:00"));
+        let time = parse_time("2023-11-15 06:30").unwrap();
+        assert_eq!(time - date, 6 * 3600 + 30 * 60);
+
+        assert!(parse_time("yesterday").is_err());
+        assert!(parse_time("2023-13-01").is_err());
+    }
+}
@@ -1,7 +1,6 @@
 mod common;
 
 use anyhow::Result;
-use common::TestEnv;
 
 #[test]
 fn test_plugin_from_yaml_valid() -> Result<()> {
@@ -37,7 +37,7 @@ fn test_load_scripts_without_extensions() -> Result<()> {
 
 #[test]
 fn test_empty_script_directory() -> Result<()> {
-    let env = TestEnv::new()?;
+    let _env = TestEnv::new()?;
 
     // Script directory exists but is empty
     // Should return empty Vec, not error
@@ -0,0 +1,201 @@
+mod common;
+
+use anyhow::Result;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+/// A project with its own `.rune/scripts` and an isolated rune home
+struct Project {
+    _temp_dir: TempDir,
+    home: PathBuf,
+    root: PathBuf,
+    scripts: PathBuf,
+}
+
+impl Project {
+    fn new() -> Result<Self> {
+        let temp_dir = TempDir::new()?;
+        let home = temp_dir.path().join("home");
+        let root = temp_dir.path().join("project");
+        let scripts = root.join(".rune").join("scripts");
+        fs::create_dir_all(&home)?;
+        fs::create_dir_all(&scripts)?;
+        let project = Self {
+            home,
+            root,
+            scripts,
+            _temp_dir: temp_dir,
+        };
+
+        let plugin = project.home.join("bash.yaml");
+        fs::write(&plugin, common::sample_shell_plugin())?;
+        let output = project.rune(&["plugin", "add", &plugin.to_string_lossy()])?;
+        assert!(output.status.success(), "{}", stderr(&output));
+        Ok(project)
+    }
+
+    fn write(&self, name: &str, content: &str) -> Result<()> {
+        fs::write(self.scripts.join(name), content)?;
+        Ok(())
+    }
+
+    /// Run rune in the project directory, isolated from the user's config and data
+    fn rune(&self, args: &[&str]) -> Result<Output> {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_rune"));
+        for (key, _) in std::env::vars_os() {
+            if key.to_string_lossy().starts_with("RUNE_") {
+                command.env_remove(key);
+            }
+        }
+        Ok(command
+            .args(args)
+            .current_dir(&self.root)
+            .env("HOME", &self.home)
+            .env("XDG_CONFIG_HOME", self.home.join("config"))
+            .env("XDG_DATA_HOME", self.home.join("data"))
+            .env("RUNE_REPO", self.home.join("scripts"))
+            .env("RUNE_PLUGIN", self.home.join("plugins"))
+            .output()?)
+    }
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// Assert that rune refused to run `script` because it is untrusted or changed
+fn assert_refused(output: &Output, script: &str, reason: &str) {
+    assert!(!output.status.success(), "ran: {}", stdout(output));
+    let message = stderr(output);
+    assert!(
+        message.contains(&format!("Script '{}'", script)) && message.contains(reason),
+        "unexpected error: {}",
+        message
+    );
+}
+
+#[test]
+fn test_run_requires_trust() -> Result<()> {
+    let project = Project::new()?;
+    project.write("deploy.sh", "echo deployed > deployed.txt\n")?;
+
+    let output = project.rune(&["run", "--no-input", "deploy"])?;
+    assert_refused(&output, "deploy", "is not trusted");
+    assert!(!project.root.join("deployed.txt").exists());
+
+    let output = project.rune(&["trust", "deploy"])?;
+    assert!(output.status.success(), "{}", stderr(&output));
+    let output = project.rune(&["run", "--no-input", "deploy"])?;
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(project.root.join("deployed.txt").exists());
+
+    project.write("deploy.sh", "echo changed > changed.txt\n")?;
+    let output = project.rune(&["run", "--no-input", "deploy"])?;
+    assert_refused(&output, "deploy", "has changed since it was trusted");
+    assert!(!project.root.join("changed.txt").exists());
+
+    // --allow-unsigned only skips signatures, never trust
+    let output = project.rune(&["run", "--no-input", "--allow-unsigned", "deploy"])?;
+    assert_refused(&output, "deploy", "has changed since it was trusted");
+    Ok(())
+}
+
+#[test]
+fn test_detach_requires_trust() -> Result<()> {
+    let project = Project::new()?;
+    project.write("deploy.sh", "echo deployed > deployed.txt\n")?;
+
+    let output = project.rune(&["run", "--no-input", "--detach", "deploy"])?;
+    assert_refused(&output, "deploy", "is not trusted");
+    assert!(
+        !stderr(&output).contains("Background run"),
+        "{}",
+        stderr(&output)
+    );
+
+    project.rune(&["trust", "deploy"])?;
+    project.write("deploy.sh", "echo changed > changed.txt\n")?;
+    let output = project.rune(&["run", "--no-input", "--detach", "deploy"])?;
+    assert_refused(&output, "deploy", "has changed since it was trusted");
+
+    // Refused before a background run was started
+    assert!(
+        !stderr(&output).contains("Background run"),
+        "{}",
+        stderr(&output)
+    );
+    let output = project.rune(&["ps"])?;
+    assert!(!stdout(&output).contains("deploy"), "{}", stdout(&output));
+    assert!(!project.root.join("changed.txt").exists());
+    Ok(())
+}
+
+#[test]
+fn test_workflow_requires_trust() -> Result<()> {
+    let project = Project::new()?;
+    project.write("build.sh", "echo built >> steps.txt\n")?;
+    project.write("release.rune.yaml", "steps:\n  - script: build\n")?;
+
+    let output = project.rune(&["run", "--no-input", "release"])?;
+    assert_refused(&output, "release", "is not trusted");
+
+    // Trusting the workflow does not trust its steps
+    project.rune(&["trust", "release"])?;
+    let output = project.rune(&["run", "--no-input", "release"])?;
+    assert_refused(&output, "build", "is not trusted");
+    assert!(!project.root.join("steps.txt").exists());
+
+    project.rune(&["trust", "build"])?;
+    let output = project.rune(&["run", "--no-input", "release"])?;
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(project.root.join("steps.txt").exists());
+
+    project.write(
+        "release.rune.yaml",
+        "steps:\n  - script: build\n  - script: build\n    name: again\n",
+    )?;
+    let output = project.rune(&["run", "--no-input", "release"])?;
+    assert_refused(&output, "release", "has changed since it was trusted");
+    Ok(())
+}
+
+#[test]
+fn test_run_many_allow_unsigned() -> Result<()> {
+    let project = Project::new()?;
+    project.write("lint.sh", "echo linted > linted.txt\n")?;
+    project.rune(&["trust", "lint"])?;
+
+    // Enforce signing with a key that never signed the script
+    let key = project.home.join("signing.key");
+    let output = project.rune(&[
+        "sign",
+        "--generate",
+        "--name",
+        "alice",
+        "--key",
+        &key.to_string_lossy(),
+    ])?;
+    assert!(output.status.success(), "{}", stderr(&output));
+    let public_key = stdout(&output)
+        .lines()
+        .find_map(|line| line.strip_prefix("Public key: "))
+        .map(str::to_string)
+        .expect("public key is printed");
+    let output = project.rune(&["config", "set", "signing.trusted_keys.alice", &public_key])?;
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = project.rune(&["run-many", "--no-input", "lint"])?;
+    assert_refused(&output, "lint", "failed signature verification");
+    assert!(!project.root.join("linted.txt").exists());
+
+    let output = project.rune(&["run-many", "--no-input", "--allow-unsigned", "lint"])?;
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(project.root.join("linted.txt").exists());
+    Ok(())
+}